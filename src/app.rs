@@ -13,20 +13,30 @@ use axum_extra::extract::cookie::CookieJar;
 #[cfg(feature = "web")]
 // use local_ip_address::local_ip; // Add this import
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 // #[cfg(feature = "web")]
 // use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::services::ServeDir;
 // use actix_web::post;
+use crate::cell::Spill;
+use crate::chart::{Chart, ChartDefinition, Palette};
 use crate::downloader;
-use crate::graph::{GraphOptions, GraphType, create_graph};
+use crate::graph::{
+    GraphFormat, GraphOptions, GraphType, MarkerStyle, Series, Theme, create_series_graph,
+    named_palette, parse_color, parse_tick_format, parse_trendline,
+};
+use crate::journal;
 use crate::login::{
     self, serve_change_password_page, serve_forgot_password_page, serve_reset_password_page,
 };
+use crate::plot;
+use crate::recovery;
 use crate::saving;
-use crate::spreadsheet::{FunctionName, Operand, ParsedRHS, Spreadsheet};
+use crate::snapshot;
+use crate::spreadsheet::Spreadsheet;
 
 /// Application state shared across all requests
 ///
@@ -51,6 +61,123 @@ pub struct AppState {
 
     /// Last modified timestamp
     pub last_modified: Mutex<std::time::SystemTime>,
+
+    /// The last drawing of every saved chart of the sheet, by chart id
+    pub charts: Mutex<HashMap<u32, DrawnChart>>,
+
+    /// Writes of sheets to their files, made one at a time
+    pub saves: Arc<SaveQueue>,
+
+    /// Journal of the sheet's file, opened on the first edit and locked after the sheet
+    pub journal: Mutex<Option<journal::Journal>>,
+}
+
+/// Writes sheets to their files one at a time, away from the async worker threads
+///
+/// Edits queue the sheet with [`SaveQueue::push`]; a blocking task writes queued sheets
+/// until none are left. Only the latest state of each file waits to be written, and
+/// these saves keep no backups, so the backups of a sheet are the versions it had when
+/// it was last saved explicitly with [`SaveQueue::save`].
+///
+/// Once a sheet is on disk its recovery file holds nothing more, so a background save
+/// removes it, and the queue remembers the version written so that autosave can skip it.
+#[derive(Default)]
+pub struct SaveQueue {
+    /// The sheets waiting to be written
+    pending: Mutex<PendingSaves>,
+    /// Held while a file is written, so that two saves never overlap
+    writing: Mutex<()>,
+    /// The file and version of the last sheet written
+    written: Mutex<Option<(String, u64)>>,
+}
+
+/// Sheets waiting in a [`SaveQueue`]
+#[derive(Default)]
+struct PendingSaves {
+    /// The sheets to write with their versions, by file
+    sheets: BTreeMap<String, (Spreadsheet, u64)>,
+    /// Whether a task is writing them
+    running: bool,
+}
+
+impl SaveQueue {
+    /// Queues a sheet to be written to `path`, replacing a state still waiting for it
+    fn push(self: &Arc<Self>, path: String, version: u64, sheet: Spreadsheet) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.sheets.insert(path, (sheet, version));
+        if !pending.running {
+            pending.running = true;
+            let queue = Arc::clone(self);
+            tokio::task::spawn_blocking(move || queue.write_pending());
+        }
+    }
+
+    /// Writes queued sheets until none are left
+    fn write_pending(&self) {
+        loop {
+            let (path, (sheet, version)) = {
+                let mut pending = self.pending.lock().unwrap();
+                match pending.sheets.pop_first() {
+                    Some(next) => next,
+                    None => {
+                        pending.running = false;
+                        return;
+                    }
+                }
+            };
+            let _writing = self.writing.lock().unwrap();
+            match saving::save_spreadsheet_with_backups(&sheet, &path, 0) {
+                Ok(()) => {
+                    self.wrote(&path, version);
+                    let database_dir = std::path::Path::new(login::DATABASE_DIR);
+                    let _ = recovery::discard(&recovery::recovery_path(Some(&path), database_dir));
+                }
+                Err(e) => eprintln!("Failed to save {}: {}", path, e),
+            }
+        }
+    }
+
+    /// Saves a sheet to `path` now, rotating its backups, once no other save is running
+    async fn save(
+        self: &Arc<Self>,
+        sheet: Spreadsheet,
+        path: String,
+        version: u64,
+    ) -> std::io::Result<()> {
+        let queue = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            let _writing = queue.writing.lock().unwrap();
+            saving::save_spreadsheet(&sheet, &path)?;
+            queue.wrote(&path, version);
+            Ok(())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
+    /// Records that `version` of the sheet is in the file at `path`
+    fn wrote(&self, path: &str, version: u64) {
+        *self.written.lock().unwrap() = Some((path.to_string(), version));
+    }
+
+    /// Whether `version` of the sheet, or a later one, has been written to `path`
+    fn has_written(&self, path: &str, version: u64) -> bool {
+        matches!(&*self.written.lock().unwrap(), Some((written, at)) if written == path && *at >= version)
+    }
+}
+
+/// The last drawing of a saved chart
+pub struct DrawnChart {
+    /// Definition the chart was drawn from
+    pub definition: ChartDefinition,
+    /// Digest of the cells the chart read, from [`Spreadsheet::chart_signature`]
+    pub signature: u64,
+    /// Number of times the chart has been drawn; clients compare it to know when to
+    /// reload the image
+    pub revision: u64,
+    /// Format and data of the image, or why the chart could not be drawn; `None` while
+    /// the chart is stale and waits to be drawn again
+    pub image: Option<Result<(GraphFormat, Vec<u8>), String>>,
 }
 
 /// Data structure for cell updates from the client
@@ -70,11 +197,13 @@ struct CellResponse {
     /// Status message indicating success or the error that occurred
     status: String,
     /// The calculated cell value (if successful)
-    value: Option<i32>,
+    value: Option<f64>,
     /// Current version of the sheet after update
     version: u64,
     /// Whether the client needs to refresh due to conflict
     needs_refresh: bool,
+    /// Saved charts whose images are out of date because the update changed cells they read
+    stale_charts: Vec<u32>,
 }
 
 /// Query parameters for saving a spreadsheet
@@ -109,23 +238,6 @@ struct FileNameQuery {
     name: String,
 }
 
-/// Request data for graph generation
-#[derive(Debug, Deserialize)]
-struct GraphRequest {
-    /// Cell range for X-axis values (e.g., "A1:A10")
-    x_range: String,
-    /// Cell range for Y-axis values (e.g., "B1:B10")
-    y_range: String,
-    /// Title for the graph
-    title: String,
-    /// Label for the X-axis
-    x_label: String,
-    /// Label for the Y-axis
-    y_label: String,
-    /// Type of graph ("Line", "Bar", "Scatter", "Area")
-    graph_type: String,
-}
-
 /// Data structure for listing spreadsheets
 #[derive(Debug, Serialize, Deserialize)]
 struct SheetEntry {
@@ -160,153 +272,1264 @@ struct SheetDataQuery {
     cols: Option<i16>,
 }
 
-/// Main application entry point
+/// Startup options for the web server
+pub struct ServerOptions {
+    /// How often unsaved changes are written to a recovery file; `None` disables autosave
+    pub autosave_interval: Option<Duration>,
+    /// Whether to load the most recent recovery file found at startup into the live sheet
+    pub restore_recovery: bool,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions {
+            autosave_interval: Some(Duration::from_secs(30)),
+            restore_recovery: false,
+        }
+    }
+}
+
+/// Main application entry point
+///
+/// Initializes the database, creates the default spreadsheet, and starts the web server
+/// with the default [`ServerOptions`].
+///
+/// # Arguments
+/// * `rows` - Number of rows for the initial spreadsheet
+/// * `cols` - Number of columns for the initial spreadsheet
+///
+/// # Returns
+/// * `Result<(), Box<dyn std::error::Error>>` - Success or error
+pub async fn run(rows: i16, cols: i16) -> Result<(), Box<dyn std::error::Error>> {
+    run_with_options(rows, cols, ServerOptions::default()).await
+}
+
+/// Starts the web server with the given options
+///
+/// Sets up both public and authenticated routes for the application. Recovery files
+/// left by an earlier run are reported, and restored if the options ask for it, before
+/// the periodic autosave is started.
+///
+/// # Arguments
+/// * `rows` - Number of rows for the initial spreadsheet
+/// * `cols` - Number of columns for the initial spreadsheet
+/// * `options` - Autosave and recovery settings
+///
+/// # Returns
+/// * `Result<(), Box<dyn std::error::Error>>` - Success or error
+pub async fn run_with_options(
+    rows: i16,
+    cols: i16,
+    options: ServerOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Initialize the database
+    login::init_database()?;
+
+    // Create spreadsheet
+    let mut sheet =
+        Spreadsheet::spreadsheet_create(rows, cols).expect("Failed to create spreadsheet");
+    let mut original_path = None;
+
+    // Report unsaved changes left by an earlier run
+    let recoveries = recovery::find_recoveries(std::path::Path::new(login::DATABASE_DIR));
+    for found in &recoveries {
+        let sheet_name = found
+            .sheet_path
+            .as_ref()
+            .map_or(String::from("unsaved sheet"), |path| {
+                path.display().to_string()
+            });
+        println!(
+            "Found unsaved changes to {} in {}",
+            sheet_name,
+            found.path.display()
+        );
+    }
+    if let Some(newest) = recoveries.first() {
+        if options.restore_recovery {
+            sheet = Box::new(recovery::restore(newest)?);
+            original_path = newest
+                .sheet_path
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string());
+            println!("Restored {}", newest.path.display());
+        } else {
+            println!("Start with --restore to continue from the most recent one");
+        }
+    }
+
+    // Setup app state
+    let app_state = Arc::new(AppState {
+        sheet: Mutex::new(sheet),
+        original_path: Mutex::new(original_path),
+        public_sheets: Mutex::new(HashSet::new()),
+        version: Mutex::new(0), // Initialize version to 0
+        last_modified: Mutex::new(std::time::SystemTime::now()),
+        charts: Mutex::new(HashMap::new()),
+        saves: Arc::new(SaveQueue::default()),
+        journal: Mutex::new(None),
+    });
+
+    if let Some(interval) = options.autosave_interval {
+        tokio::spawn(autosave(Arc::clone(&app_state), interval));
+    }
+
+    // 1) Build the public (no‐auth) routes
+    let public = Router::new()
+        .route("/", get(serve_landing))
+        .route(
+            "/login",
+            get(login::serve_login_page).post(login::handle_login),
+        )
+        .route(
+            "/signup",
+            get(login::serve_signup_page).post(login::handle_signup),
+        )
+        .route("/logout", get(login::handle_logout))
+        .route(
+            "/forgot-password",
+            get(serve_forgot_password_page).post(login::handle_forgot_password),
+        )
+        .route(
+            "/reset-password",
+            get(serve_reset_password_page).post(login::handle_reset_password),
+        )
+        .route(
+            "/change-password",
+            get(serve_change_password_page).post(login::handle_change_password),
+        )
+        // Public routes for accessing sheets
+        .route("/:username/:sheet_name", get(load_user_file))
+        // Add these API endpoints to public routes for public sheets
+        .route("/api/sheet", get(get_sheet_data))
+        .route("/api/cell/:cell_name", get(get_cell))
+        .route("/api/update_cell", post(update_cell))
+        .route("/api/sheet_info", get(get_sheet_info))
+        .route("/api/save", post(save_spreadsheet))
+        .route("/api/export", post(export_spreadsheet))
+        .route("/api/load", post(load_spreadsheet))
+        .route("/api/graph", post(generate_graph))
+        .route("/api/charts", get(list_charts).post(create_chart))
+        .route(
+            "/api/charts/:id",
+            get(get_chart).put(update_chart).delete(delete_chart),
+        )
+        .route("/api/charts/:id/image", get(chart_image))
+        .route("/api/download/csv", get(download_csv))
+        .route("/api/download/xlsx", get(download_xlsx))
+        .route("/api/sheet_status", get(get_sheet_status))
+        .route("/api/undo", get(undo).post(undo))
+        .route("/api/journal", get(get_journal))
+        .route("/api/snapshots", get(list_snapshots))
+        .route("/api/snapshots/:snapshot", get(get_snapshot))
+        .route("/api/diff", get(diff_snapshots))
+        .nest_service("/static", ServeDir::new("static"));
+
+    // 2) Build the protected routes and apply auth‐middleware
+    let protected = Router::new()
+        // spreadsheet endpoints
+        .route("/sheet", get(serve_sheet))
+        // .route("/api/sheet", get(get_sheet_data))
+        // .route("/api/cell/:cell_name", get(get_cell))
+        // .route("/api/sheet_info", get(get_sheet_info))
+        .route("/api/save_with_name", post(save_spreadsheet_with_name))
+        .route("/api/journal/restore", post(restore_journal))
+        .route("/api/snapshots/create", post(create_snapshot))
+        .route("/api/snapshots/:snapshot/restore", post(restore_snapshot))
+        // user file routes
+        .route("/:username", get(login::list_files))
+        .route(
+            "/:username/create",
+            get(login::serve_create_sheet_form).post(login::handle_create_sheet),
+        )
+        .route("/:username/:sheet_name/status", post(change_sheet_status))
+        .route(
+            "/:username/:sheet_name/delete",
+            post(login::handle_delete_sheet),
+        )
+        // only these get require_auth
+        .layer(middleware::from_fn(login::require_auth));
+
+    // 3) Merge and attach shared state
+    let app = Router::new()
+        .merge(public)
+        .merge(protected)
+        .with_state(app_state);
+
+    // Start server
+    let listener = TcpListener::bind("127.0.0.1:8000").await?;
+    // let local_ip = local_ip().unwrap_or_else(|_| "127.0.0.1".parse().unwrap());
+    // println!("Listening on http://{}:8000", local_ip);
+    println!("Listening on http://localhost:8000");
+
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
+
+/// Periodically writes the live sheet to its recovery file
+///
+/// The sheet is only written when its version has changed since the last autosave and
+/// that version is not already in the sheet's file, so an idle server does not touch the
+/// disk and a restart does not report changes that were saved. Failures are logged and
+/// retried on the next tick.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `interval` - Time between autosaves
+async fn autosave(state: Arc<AppState>, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    let mut saved_version = *state.version.lock().unwrap();
+    loop {
+        ticker.tick().await;
+        let version = *state.version.lock().unwrap();
+        if version == saved_version {
+            continue;
+        }
+        let sheet_path = state.original_path.lock().unwrap().clone();
+        // Changes already saved to the sheet's file need no recovery file
+        let saved = |version| {
+            sheet_path
+                .as_deref()
+                .is_some_and(|path| state.saves.has_written(path, version))
+        };
+        if saved(version) {
+            saved_version = version;
+            continue;
+        }
+        let sheet = state.sheet.lock().unwrap().clone();
+        let path = recovery::recovery_path(
+            sheet_path.as_deref(),
+            std::path::Path::new(login::DATABASE_DIR),
+        );
+        let recovery_file = path.clone();
+        let written =
+            tokio::task::spawn_blocking(move || recovery::write_recovery(&sheet, &path)).await;
+        match written {
+            Ok(Ok(())) => {
+                saved_version = version;
+                // The sheet may have been saved while the recovery file was written
+                if saved(version) {
+                    let _ = recovery::discard(&recovery_file);
+                }
+            }
+            Ok(Err(e)) => eprintln!("Autosave failed: {}", e),
+            Err(e) => eprintln!("Autosave failed: {}", e),
+        }
+    }
+}
+
+/// Removes the recovery files made obsolete by saving the live sheet
+///
+/// # Arguments
+/// * `previous_path` - Where the sheet was saved before, if anywhere
+/// * `saved_path` - Where it has just been saved
+fn discard_recoveries(previous_path: Option<&str>, saved_path: &str) {
+    let database_dir = std::path::Path::new(login::DATABASE_DIR);
+    for path in [previous_path, Some(saved_path)] {
+        let _ = recovery::discard(&recovery::recovery_path(path, database_dir));
+    }
+}
+
+/// Returns the name recorded in the journal for the user making a request
+fn journal_user(jar: &CookieJar) -> String {
+    jar.get("session")
+        .and_then(|cookie| crate::login::validate_session(cookie.value()))
+        .unwrap_or_else(|| String::from("anonymous"))
+}
+
+/// Appends edits of the live sheet to its journal
+///
+/// Sheets that have not been saved to a file have no journal, so their edits are not
+/// recorded. The journal is kept open in the state, so it is only read again when the
+/// sheet moves to another file or an append fails. Failures are logged rather than failing
+/// the edit.
+///
+/// # Arguments
+/// * `state` - Application state holding the open journal
+/// * `sheet_path` - File the live sheet belongs to, if any
+/// * `user` - Who made the edits
+/// * `edits` - The edits, in the order they were made
+fn record_edits(state: &AppState, sheet_path: Option<&str>, user: &str, edits: &[journal::Edit]) {
+    let Some(sheet_path) = sheet_path else {
+        return;
+    };
+    if edits.is_empty() {
+        return;
+    }
+    let path = journal::journal_path(sheet_path);
+    let mut open = state.journal.lock().unwrap();
+    if open.as_ref().is_none_or(|journal| journal.path() != path) {
+        *open = None;
+        match journal::Journal::open(&path) {
+            Ok(journal) => *open = Some(journal),
+            Err(e) => {
+                eprintln!("Failed to write journal of {}: {}", sheet_path, e);
+                return;
+            }
+        }
+    }
+    if let Some(journal) = open.as_mut()
+        && let Err(e) = journal.append(user, edits)
+    {
+        eprintln!("Failed to write journal of {}: {}", sheet_path, e);
+        *open = None;
+    }
+}
+
+/// Returns true if the user making a request may edit the live sheet
+///
+/// Sheets not saved to a file can be edited by anyone; saved sheets by their owner, and
+/// by everyone else only if they are public.
+///
+/// # Arguments
+/// * `state` - Application state holding the public sheets
+/// * `jar` - Cookie jar containing session information
+/// * `sheet_path` - File the live sheet belongs to, if any
+fn can_edit(state: &AppState, jar: &CookieJar, sheet_path: Option<&str>) -> bool {
+    let Some(path) = sheet_path else {
+        return true;
+    };
+    // Extract username/sheet_name from path "database/username/sheet_name.bin.gz"
+    let path_parts: Vec<&str> = path.split('/').collect();
+    if path_parts.len() < 3 {
+        return true;
+    }
+    let username = path_parts[1];
+    let sheet_name = path_parts[2].trim_end_matches(".bin.gz");
+
+    let current_user = jar
+        .get("session")
+        .and_then(|cookie| crate::login::validate_session(cookie.value()));
+    if current_user.as_deref() == Some(username) {
+        return true;
+    }
+    let public_sheets = state.public_sheets.lock().unwrap();
+    public_sheets.contains(&format!("{}/{}", username, sheet_name))
+}
+
+/// Returns true if the user making a request may read the history of the live sheet
+///
+/// The history holds the old contents of the sheet and who changed it, so it is shown to
+/// the same users who may edit the sheet: its owner, and everyone if it is public.
+///
+/// # Arguments
+/// * `state` - Application state holding the public sheets
+/// * `jar` - Cookie jar containing session information
+/// * `sheet_path` - File the live sheet belongs to, if any
+fn can_view_history(state: &AppState, jar: &CookieJar, sheet_path: Option<&str>) -> bool {
+    can_edit(state, jar, sheet_path)
+}
+
+/// Saves the live sheet to its file without blocking the request
+///
+/// Sheets not saved to a file are left alone. The save goes through the queue of
+/// [`SaveQueue`], so it keeps no backup.
+///
+/// # Arguments
+/// * `state` - Application state holding the save queue
+/// * `version` - Version of the live sheet
+/// * `sheet` - The live sheet
+/// * `sheet_path` - File the live sheet belongs to, if any
+fn save_in_background(
+    state: &AppState,
+    version: u64,
+    sheet: &Spreadsheet,
+    sheet_path: Option<String>,
+) {
+    let Some(path) = sheet_path else {
+        return;
+    };
+    // Save a copy so the sheet stays unlocked while the file is written
+    state.saves.push(path, version, sheet.clone());
+}
+
+/// Request body for restoring the live sheet to an earlier point
+#[derive(Deserialize)]
+struct RestoreRequest {
+    /// Journal position to go back to; 0 is before the first edit
+    position: Option<u64>,
+    /// Time to go back to, in RFC 3339 format (e.g. "2024-05-01T12:00:00Z")
+    timestamp: Option<String>,
+}
+
+/// Get the edit journal of the live sheet
+///
+/// Only the owner may read the journal of a private sheet.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+///
+/// # Returns
+/// * JSON list of journal entries, oldest first
+async fn get_journal(State(state): State<Arc<AppState>>, jar: CookieJar) -> impl IntoResponse {
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_view_history(&state, &jar, original_path.as_deref()) {
+        return (StatusCode::FORBIDDEN, "Not authorized to view this sheet").into_response();
+    }
+    let Some(path) = original_path else {
+        return Json(serde_json::json!({ "status": "ok", "entries": [] })).into_response();
+    };
+    match journal::read_journal(&journal::journal_path(&path)) {
+        Ok(entries) => {
+            Json(serde_json::json!({ "status": "ok", "entries": entries })).into_response()
+        }
+        Err(e) => Json(SaveResponse {
+            status: "error".to_string(),
+            message: Some(e.to_string()),
+        })
+        .into_response(),
+    }
+}
+
+/// Restore the live sheet to an earlier point of its journal
+///
+/// The edits made after the requested position or time are rolled back. The edits that
+/// undo them are themselves appended to the journal, so the history stays complete.
+/// Only the owner of the sheet may restore it.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `username` - The authenticated username
+/// * `request` - The position or time to restore to
+///
+/// # Returns
+/// * JSON response with the status, the number of cells changed and the new version
+async fn restore_journal(
+    State(state): State<Arc<AppState>>,
+    username: axum::extract::Extension<String>,
+    Json(request): Json<RestoreRequest>,
+) -> impl IntoResponse {
+    let error = |message: String| {
+        Json(SaveResponse {
+            status: "error".to_string(),
+            message: Some(message),
+        })
+        .into_response()
+    };
+
+    let point = match (request.position, request.timestamp) {
+        (Some(position), None) => journal::RestorePoint::Position(position),
+        (None, Some(timestamp)) => match chrono::DateTime::parse_from_rfc3339(&timestamp) {
+            Ok(time) => journal::RestorePoint::Time(time.timestamp_millis().max(0) as u64),
+            Err(e) => return error(format!("Invalid timestamp: {}", e)),
+        },
+        _ => return error("Give either a position or a timestamp".to_string()),
+    };
+
+    let original_path = state.original_path.lock().unwrap().clone();
+    let Some(path) = original_path else {
+        return error("The sheet has not been saved, so it has no journal".to_string());
+    };
+    if path.split('/').nth(1) != Some(username.0.as_str()) {
+        return error("Only the owner can restore this sheet".to_string());
+    }
+
+    let journal_path = journal::journal_path(&path);
+    let entries = match journal::read_journal(&journal_path) {
+        Ok(entries) => entries,
+        Err(e) => return error(e.to_string()),
+    };
+
+    let (edits, new_version, to_save) = {
+        let mut version = state.version.lock().unwrap();
+        let mut sheet = state.sheet.lock().unwrap();
+        let mut restored = sheet.clone();
+        let edits = match journal::roll_back(&mut restored, &entries, point) {
+            Ok(edits) => edits,
+            Err(e) => return error(format!("Restore failed: {}", e)),
+        };
+        *sheet = restored;
+        if !edits.is_empty() {
+            *version += 1;
+            *state.last_modified.lock().unwrap() = std::time::SystemTime::now();
+            record_edits(&state, Some(&path), &username.0, &edits);
+        }
+        let to_save = (!edits.is_empty()).then(|| (**sheet).clone());
+        (edits, *version, to_save)
+    };
+    if let Some(sheet) = to_save
+        && let Err(e) = state.saves.save(sheet, path, new_version).await
+    {
+        return error(format!("Restored, but saving failed: {}", e));
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "changed": edits.len(),
+        "version": new_version,
+    }))
+    .into_response()
+}
+
+/// Request body for taking a snapshot of the live sheet
+#[derive(Deserialize)]
+struct SnapshotRequest {
+    /// Name of the snapshot, e.g. "Q3 close"
+    name: String,
+}
+
+/// Query parameters for comparing two versions of the live sheet
+#[derive(Deserialize)]
+struct DiffQuery {
+    /// Id or name of the older snapshot
+    from: String,
+    /// Id or name of the newer snapshot; the live sheet if missing
+    to: Option<String>,
+}
+
+/// Returns a JSON error response with the given message
+fn snapshot_error(message: String) -> Response {
+    Json(SaveResponse {
+        status: "error".to_string(),
+        message: Some(message),
+    })
+    .into_response()
+}
+
+/// Returns the file the live sheet is saved to, which its snapshots belong to
+fn snapshot_sheet_path(state: &AppState) -> Result<String, String> {
+    state
+        .original_path
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| "The sheet has not been saved, so it has no snapshots".to_string())
+}
+
+/// Loads a snapshot of the live sheet by its id or name
+fn load_snapshot(sheet_path: &str, key: &str) -> Result<(snapshot::Snapshot, Spreadsheet), String> {
+    let snapshots = snapshot::list(sheet_path).map_err(|e| e.to_string())?;
+    let found = snapshot::find(&snapshots, key)
+        .ok_or_else(|| format!("No snapshot {}", key))?
+        .clone();
+    let sheet = snapshot::open(sheet_path, &found).map_err(|e| e.to_string())?;
+    Ok((found, sheet))
+}
+
+/// List the snapshots of the live sheet
+///
+/// Only the owner may list the snapshots of a private sheet.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+///
+/// # Returns
+/// * JSON list of snapshots, oldest first
+async fn list_snapshots(State(state): State<Arc<AppState>>, jar: CookieJar) -> impl IntoResponse {
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_view_history(&state, &jar, original_path.as_deref()) {
+        return (StatusCode::FORBIDDEN, "Not authorized to view this sheet").into_response();
+    }
+    let Some(path) = original_path else {
+        return Json(serde_json::json!({ "status": "ok", "snapshots": [] })).into_response();
+    };
+    match snapshot::list(&path) {
+        Ok(snapshots) => {
+            Json(serde_json::json!({ "status": "ok", "snapshots": snapshots })).into_response()
+        }
+        Err(e) => snapshot_error(e.to_string()),
+    }
+}
+
+/// Take a named snapshot of the live sheet
+///
+/// Only the owner of the sheet may take snapshots of it.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `username` - The authenticated username
+/// * `request` - The name of the snapshot
+///
+/// # Returns
+/// * JSON response with the status and the new snapshot
+async fn create_snapshot(
+    State(state): State<Arc<AppState>>,
+    username: axum::extract::Extension<String>,
+    Json(request): Json<SnapshotRequest>,
+) -> impl IntoResponse {
+    let path = match snapshot_sheet_path(&state) {
+        Ok(path) => path,
+        Err(e) => return snapshot_error(e),
+    };
+    if path.split('/').nth(1) != Some(username.0.as_str()) {
+        return snapshot_error("Only the owner can take snapshots of this sheet".to_string());
+    }
+
+    let sheet = state.sheet.lock().unwrap().clone();
+    match snapshot::create(&sheet, &path, &request.name) {
+        Ok(snapshot) => {
+            Json(serde_json::json!({ "status": "ok", "snapshot": snapshot })).into_response()
+        }
+        Err(e) => snapshot_error(format!("Snapshot failed: {}", e)),
+    }
+}
+
+/// Open a snapshot of the live sheet read-only
+///
+/// Only the owner may open the snapshots of a private sheet.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+/// * `key` - Id or name of the snapshot
+///
+/// # Returns
+/// * JSON with the snapshot, its size and the formula and value of its non-empty cells
+async fn get_snapshot(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    let path = match snapshot_sheet_path(&state) {
+        Ok(path) => path,
+        Err(e) => return snapshot_error(e),
+    };
+    if !can_view_history(&state, &jar, Some(&path)) {
+        return (StatusCode::FORBIDDEN, "Not authorized to view this sheet").into_response();
+    }
+    let loaded = load_snapshot(&path, &key);
+    let (found, sheet) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => return snapshot_error(e),
+    };
+
+    let mut cells = Vec::new();
+    for row in 1..=sheet.rows {
+        for col in 1..=sheet.cols {
+            let (formula, value) = snapshot::cell_contents(&sheet, row, col);
+            if !formula.is_empty() || !value.is_empty() {
+                cells.push(serde_json::json!({
+                    "name": Spreadsheet::get_cell_name(row, col),
+                    "formula": formula,
+                    "value": value,
+                }));
+            }
+        }
+    }
+    Json(serde_json::json!({
+        "status": "ok",
+        "snapshot": found,
+        "rows": sheet.rows,
+        "cols": sheet.cols,
+        "read_only": true,
+        "cells": cells,
+    }))
+    .into_response()
+}
+
+/// Compare two snapshots of the live sheet, or a snapshot with the live sheet
+///
+/// Only the owner may compare the snapshots of a private sheet.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+/// * `query` - The snapshots to compare
+///
+/// # Returns
+/// * JSON list of the cells that were added, removed or changed, row by row
+async fn diff_snapshots(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Query(query): Query<DiffQuery>,
+) -> impl IntoResponse {
+    let path = match snapshot_sheet_path(&state) {
+        Ok(path) => path,
+        Err(e) => return snapshot_error(e),
+    };
+    if !can_view_history(&state, &jar, Some(&path)) {
+        return (StatusCode::FORBIDDEN, "Not authorized to view this sheet").into_response();
+    }
+    let old = match load_snapshot(&path, &query.from) {
+        Ok((_, sheet)) => sheet,
+        Err(e) => return snapshot_error(e),
+    };
+    let new = match &query.to {
+        Some(key) => match load_snapshot(&path, key) {
+            Ok((_, sheet)) => sheet,
+            Err(e) => return snapshot_error(e),
+        },
+        None => *state.sheet.lock().unwrap().clone(),
+    };
+    Json(serde_json::json!({
+        "status": "ok",
+        "changes": snapshot::diff(&old, &new),
+    }))
+    .into_response()
+}
+
+/// Replace the live sheet with a snapshot of it
+///
+/// The formula changes are appended to the journal and the sheet is saved. Only the
+/// owner of the sheet may restore it.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `username` - The authenticated username
+/// * `key` - Id or name of the snapshot
+///
+/// # Returns
+/// * JSON response with the status, the number of cells changed and the new version
+async fn restore_snapshot(
+    State(state): State<Arc<AppState>>,
+    username: axum::extract::Extension<String>,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    let path = match snapshot_sheet_path(&state) {
+        Ok(path) => path,
+        Err(e) => return snapshot_error(e),
+    };
+    if path.split('/').nth(1) != Some(username.0.as_str()) {
+        return snapshot_error("Only the owner can restore this sheet".to_string());
+    }
+    let restored = match load_snapshot(&path, &key) {
+        Ok((_, sheet)) => sheet,
+        Err(e) => return snapshot_error(e),
+    };
+
+    let (edits, new_version) = {
+        let mut version = state.version.lock().unwrap();
+        let mut sheet = state.sheet.lock().unwrap();
+        let changes = snapshot::diff(&sheet, &restored);
+        let edits: Vec<journal::Edit> = changes
+            .into_iter()
+            .filter(|change| change.old_formula != change.new_formula)
+            .map(|change| journal::Edit {
+                cell: change.cell,
+                old: change.old_formula,
+                new: change.new_formula,
+            })
+            .collect();
+        **sheet = restored.clone();
+        *version += 1;
+        *state.last_modified.lock().unwrap() = std::time::SystemTime::now();
+        record_edits(&state, Some(&path), &username.0, &edits);
+        (edits, *version)
+    };
+    if let Err(e) = state.saves.save(restored, path, new_version).await {
+        return snapshot_error(format!("Restored, but saving failed: {}", e));
+    }
+
+    Json(serde_json::json!({
+        "status": "ok",
+        "changed": edits.len(),
+        "version": new_version,
+    }))
+    .into_response()
+}
+
+/// Generate a graph based on spreadsheet data
+///
+/// Creates and returns a graph image based on data ranges from the spreadsheet, as PNG,
+/// SVG or PDF depending on the requested format.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `payload` - Graph configuration including ranges, series, labels, graph type and format
+///
+/// # Returns
+/// * The image of the requested graph with its `Content-Type`, or an error message
+async fn generate_graph(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<ChartDefinition>,
+) -> impl IntoResponse {
+    let sheet = state.sheet.lock().unwrap();
+    match draw_chart(&sheet, &payload) {
+        Ok((format, img_data)) => {
+            ([("Content-Type", format.content_type())], img_data).into_response()
+        }
+        Err(message) => (StatusCode::BAD_REQUEST, message).into_response(),
+    }
+}
+
+/// Draws a chart definition from the cells of a sheet
+///
+/// # Arguments
+/// * `sheet` - The spreadsheet holding the chart's data
+/// * `payload` - Graph configuration including ranges, series, labels, graph type and format
+///
+/// # Returns
+/// * The format and data of the image, or a message saying why the definition is invalid
+///   or cannot be drawn
+fn draw_chart(
+    sheet: &Spreadsheet,
+    payload: &ChartDefinition,
+) -> Result<(GraphFormat, Vec<u8>), String> {
+    let graph_type = parse_graph_type(&payload.graph_type).unwrap_or(GraphType::Line);
+
+    let mut series = Vec::new();
+    if !payload.y_range.is_empty() {
+        series.push(Series::new(&payload.y_range));
+    }
+    for request in &payload.series {
+        let color = match request
+            .color
+            .as_deref()
+            .map(|text| (text, parse_color(text)))
+        {
+            None => None,
+            Some((_, Some(color))) => Some(color),
+            Some((text, None)) => return Err(format!("Invalid colour '{}'", text)),
+        };
+        let series_type = match request
+            .graph_type
+            .as_deref()
+            .map(|name| (name, parse_graph_type(name)))
+        {
+            None => None,
+            Some((_, Some(series_type))) => Some(series_type),
+            Some((name, None)) => return Err(format!("Unknown graph type '{}'", name)),
+        };
+        series.push(Series {
+            name: request.name.clone(),
+            color,
+            graph_type: series_type,
+            secondary_axis: request.secondary_axis,
+            ..Series::new(&request.range)
+        });
+    }
+
+    let format = match payload
+        .format
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        None | Some("png") => GraphFormat::Png,
+        Some("svg") => GraphFormat::Svg,
+        Some("pdf") => GraphFormat::Pdf,
+        Some(other) => return Err(format!("Unknown graph format '{}'", other)),
+    };
+
+    let palette = match &payload.palette {
+        None => None,
+        Some(Palette::Named(name)) => match named_palette(name) {
+            Some(palette) => Some(palette.to_vec()),
+            None => return Err(format!("Unknown palette '{}'", name)),
+        },
+        Some(Palette::Colors(colors)) => {
+            let mut palette = Vec::with_capacity(colors.len());
+            for text in colors {
+                match parse_color(text) {
+                    Some(color) => palette.push(color),
+                    None => return Err(format!("Invalid colour '{}'", text)),
+                }
+            }
+            Some(palette)
+        }
+    };
+    let marker = match payload
+        .marker
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        None | Some("none") => None,
+        Some("circle") => Some(MarkerStyle::Circle),
+        Some("square") => Some(MarkerStyle::Square),
+        Some("triangle") => Some(MarkerStyle::Triangle),
+        Some("cross") => Some(MarkerStyle::Cross),
+        Some(other) => return Err(format!("Unknown marker '{}'", other)),
+    };
+    let theme = match payload
+        .theme
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        None | Some("light") => Theme::Light,
+        Some("dark") => Theme::Dark,
+        Some(other) => return Err(format!("Unknown theme '{}'", other)),
+    };
+    let tick_format = match payload.tick_format.as_deref() {
+        None => None,
+        Some(text) => match parse_tick_format(text) {
+            Some(tick_format) => Some(tick_format),
+            None => return Err(format!("Unknown tick format '{}'", text)),
+        },
+    };
+    let trendline = match payload.trendline.as_deref() {
+        None => None,
+        Some(text) => match parse_trendline(text) {
+            Some(trendline) => Some(trendline),
+            None => return Err(format!("Unknown trendline '{}'", text)),
+        },
+    };
+
+    let defaults = GraphOptions::default();
+    let options = GraphOptions {
+        title: payload.title.clone(),
+        x_label: payload.x_label.clone(),
+        y_label: payload.y_label.clone(),
+        width: payload.width.unwrap_or(defaults.width),
+        height: payload.height.unwrap_or(defaults.height),
+        graph_type,
+        format,
+        y2_label: payload.y2_label.clone(),
+        header: payload.header,
+        legend: payload.legend.unwrap_or(true),
+        bins: payload.bins,
+        palette: palette.unwrap_or(defaults.palette),
+        line_width: payload.line_width.unwrap_or(defaults.line_width),
+        marker,
+        marker_size: payload.marker_size.unwrap_or(defaults.marker_size),
+        x_grid: payload.x_grid.unwrap_or(true),
+        y_grid: payload.y_grid.unwrap_or(true),
+        log_scale: payload.log_scale,
+        x_min: payload.x_min,
+        x_max: payload.x_max,
+        y_min: payload.y_min,
+        y_max: payload.y_max,
+        tick_format: tick_format.unwrap_or(defaults.tick_format),
+        data_labels: payload.data_labels,
+        theme,
+        title_size: payload.title_size.unwrap_or(defaults.title_size),
+        trendline,
+        trendline_equation: payload.trendline_equation.unwrap_or(true),
+    };
+
+    create_series_graph(sheet, &payload.x_range, &series, options)
+        .map(|img_data| (format, img_data))
+        .map_err(|e| format!("Failed to create graph: {}", e))
+}
+
+/// Parses the name of a graph type as used in graph requests
+fn parse_graph_type(name: &str) -> Option<GraphType> {
+    match name {
+        "Line" => Some(GraphType::Line),
+        "Bar" => Some(GraphType::Bar),
+        "Scatter" => Some(GraphType::Scatter),
+        "Area" => Some(GraphType::Area),
+        "GroupedBar" => Some(GraphType::GroupedBar),
+        "StackedBar" => Some(GraphType::StackedBar),
+        "Pie" => Some(GraphType::Pie),
+        "Donut" => Some(GraphType::Donut),
+        "Histogram" => Some(GraphType::Histogram),
+        "BoxPlot" => Some(GraphType::BoxPlot),
+        "Heatmap" => Some(GraphType::Heatmap),
+        _ => None,
+    }
+}
+
+/// Marks every saved chart whose cells or definition changed since its last drawing as stale
+///
+/// A stale chart gets a new revision and loses its image, which is drawn again when it is
+/// next requested. Nothing is drawn here, so edits do not wait for charts to render.
+/// Drawings of charts no longer in the sheet are dropped.
+///
+/// # Arguments
+/// * `state` - Application state holding the drawings
+/// * `sheet` - The live sheet, locked by the caller
+///
+/// # Returns
+/// * The ids of the charts that became stale, whose images clients should reload
+fn mark_stale_charts(state: &AppState, sheet: &Spreadsheet) -> Vec<u32> {
+    let mut drawn = state.charts.lock().unwrap();
+    drawn.retain(|id, _| sheet.chart(*id).is_some());
+
+    let mut stale = Vec::new();
+    for chart in &sheet.charts {
+        let signature = sheet.chart_signature(&chart.definition);
+        let revision = match drawn.get(&chart.id) {
+            Some(last) if last.signature == signature && last.definition == chart.definition => {
+                continue;
+            }
+            Some(last) => last.revision + 1,
+            None => 1,
+        };
+        drawn.insert(
+            chart.id,
+            DrawnChart {
+                definition: chart.definition.clone(),
+                signature,
+                revision,
+                image: None,
+            },
+        );
+        stale.push(chart.id);
+    }
+    stale
+}
+
+/// Draws a chart from a copy of the live sheet on a blocking thread
+///
+/// # Arguments
+/// * `sheet` - Copy of the sheet holding the chart's data
+/// * `definition` - The chart to draw
+///
+/// # Returns
+/// * The signature of the cells drawn, and the image or why it could not be drawn
+async fn draw_detached(
+    sheet: Spreadsheet,
+    definition: ChartDefinition,
+) -> (u64, Result<(GraphFormat, Vec<u8>), String>) {
+    tokio::task::spawn_blocking(move || {
+        let signature = sheet.chart_signature(&definition);
+        (signature, draw_chart(&sheet, &definition))
+    })
+    .await
+    .unwrap_or_else(|e| (0, Err(format!("Failed to create graph: {}", e))))
+}
+
+/// Keeps a drawing of a chart just saved in the live sheet, under a new revision
+fn keep_drawing(
+    state: &AppState,
+    id: u32,
+    definition: ChartDefinition,
+    signature: u64,
+    image: Result<(GraphFormat, Vec<u8>), String>,
+) {
+    let mut drawn = state.charts.lock().unwrap();
+    let revision = drawn.get(&id).map_or(1, |last| last.revision + 1);
+    drawn.insert(
+        id,
+        DrawnChart {
+            definition,
+            signature,
+            revision,
+            image: Some(image),
+        },
+    );
+}
+
+/// Describes a saved chart and its last drawing for the chart API
+fn chart_summary(chart: &Chart, drawn: Option<&DrawnChart>) -> serde_json::Value {
+    serde_json::json!({
+        "id": chart.id,
+        "definition": chart.definition,
+        "revision": drawn.map_or(0, |drawn| drawn.revision),
+        "error": drawn
+            .and_then(|drawn| drawn.image.as_ref())
+            .and_then(|image| image.as_ref().err()),
+    })
+}
+
+/// Records a change to the charts of the live sheet
+///
+/// Bumps the version so other clients reload the sheet, marks the changed charts stale and
+/// saves the sheet to its file.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `version` - The locked version counter
+/// * `sheet` - The locked live sheet
+/// * `sheet_path` - File the live sheet belongs to, if any
+fn charts_changed(
+    state: &AppState,
+    version: &mut u64,
+    sheet: &Spreadsheet,
+    sheet_path: Option<String>,
+) {
+    *version += 1;
+    *state.last_modified.lock().unwrap() = std::time::SystemTime::now();
+    mark_stale_charts(state, sheet);
+    save_in_background(state, *version, sheet, sheet_path);
+}
+
+/// List the charts saved in the live sheet
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+///
+/// # Returns
+/// * JSON list of the charts with their id, definition, revision and any drawing error
+async fn list_charts(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let sheet = state.sheet.lock().unwrap();
+    mark_stale_charts(&state, &sheet);
+    let drawn = state.charts.lock().unwrap();
+    let charts: Vec<serde_json::Value> = sheet
+        .charts
+        .iter()
+        .map(|chart| chart_summary(chart, drawn.get(&chart.id)))
+        .collect();
+    Json(charts)
+}
+
+/// Save a new chart in the live sheet
 ///
-/// Initializes the database, creates the default spreadsheet, and starts the web server.
-/// Sets up both public and authenticated routes for the application.
+/// The definition is drawn once to check it before it is saved.
 ///
 /// # Arguments
-/// * `rows` - Number of rows for the initial spreadsheet
-/// * `cols` - Number of columns for the initial spreadsheet
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+/// * `definition` - The chart's type, ranges and options, as sent to `/api/graph`
 ///
 /// # Returns
-/// * `Result<(), Box<dyn std::error::Error>>` - Success or error
-pub async fn run(rows: i16, cols: i16) -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize the database
-    login::init_database()?;
-
-    // Create spreadsheet
-    let sheet = Spreadsheet::spreadsheet_create(rows, cols).expect("Failed to create spreadsheet");
+/// * The new chart with its id, or an error message
+async fn create_chart(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Json(definition): Json<ChartDefinition>,
+) -> impl IntoResponse {
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_edit(&state, &jar, original_path.as_deref()) {
+        return (StatusCode::FORBIDDEN, "Not authorized to edit this sheet").into_response();
+    }
 
-    // Setup app state
-    let app_state = Arc::new(AppState {
-        sheet: Mutex::new(sheet),
-        original_path: Mutex::new(None),
-        public_sheets: Mutex::new(HashSet::new()),
-        version: Mutex::new(0), // Initialize version to 0
-        last_modified: Mutex::new(std::time::SystemTime::now()),
-    });
+    let sheet_copy = state.sheet.lock().unwrap().as_ref().clone();
+    let (signature, image) = draw_detached(sheet_copy, definition.clone()).await;
+    if let Err(message) = image {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
 
-    // 1) Build the public (no‐auth) routes
-    let public = Router::new()
-        .route("/", get(serve_landing))
-        .route(
-            "/login",
-            get(login::serve_login_page).post(login::handle_login),
-        )
-        .route(
-            "/signup",
-            get(login::serve_signup_page).post(login::handle_signup),
-        )
-        .route("/logout", get(login::handle_logout))
-        .route(
-            "/forgot-password",
-            get(serve_forgot_password_page).post(login::handle_forgot_password),
-        )
-        .route(
-            "/reset-password",
-            get(serve_reset_password_page).post(login::handle_reset_password),
-        )
-        .route(
-            "/change-password",
-            get(serve_change_password_page).post(login::handle_change_password),
-        )
-        // Public routes for accessing sheets
-        .route("/:username/:sheet_name", get(load_user_file))
-        // Add these API endpoints to public routes for public sheets
-        .route("/api/sheet", get(get_sheet_data))
-        .route("/api/cell/:cell_name", get(get_cell))
-        .route("/api/update_cell", post(update_cell))
-        .route("/api/sheet_info", get(get_sheet_info))
-        .route("/api/save", post(save_spreadsheet))
-        .route("/api/export", post(export_spreadsheet))
-        .route("/api/load", post(load_spreadsheet))
-        .route("/api/graph", post(generate_graph))
-        .route("/api/download/csv", get(download_csv))
-        .route("/api/download/xlsx", get(download_xlsx))
-        .route("/api/sheet_status", get(get_sheet_status))
-        .route("/api/undo", get(undo).post(undo))
-        .nest_service("/static", ServeDir::new("static"));
+    let mut version = state.version.lock().unwrap();
+    let mut sheet = state.sheet.lock().unwrap();
+    let id = sheet.add_chart(definition.clone());
+    keep_drawing(&state, id, definition, signature, image);
+    charts_changed(&state, &mut version, &sheet, original_path);
+
+    let drawn = state.charts.lock().unwrap();
+    let chart = sheet.chart(id).unwrap();
+    (
+        StatusCode::CREATED,
+        Json(chart_summary(chart, drawn.get(&id))),
+    )
+        .into_response()
+}
 
-    // 2) Build the protected routes and apply auth‐middleware
-    let protected = Router::new()
-        // spreadsheet endpoints
-        .route("/sheet", get(serve_sheet))
-        // .route("/api/sheet", get(get_sheet_data))
-        // .route("/api/cell/:cell_name", get(get_cell))
-        // .route("/api/sheet_info", get(get_sheet_info))
-        .route("/api/save_with_name", post(save_spreadsheet_with_name))
-        // user file routes
-        .route("/:username", get(login::list_files))
-        .route(
-            "/:username/create",
-            get(login::serve_create_sheet_form).post(login::handle_create_sheet),
-        )
-        .route("/:username/:sheet_name/status", post(change_sheet_status))
-        .route(
-            "/:username/:sheet_name/delete",
-            post(login::handle_delete_sheet),
-        )
-        // only these get require_auth
-        .layer(middleware::from_fn(login::require_auth));
+/// Get a chart saved in the live sheet
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `id` - Id of the chart
+///
+/// # Returns
+/// * The chart with its definition, revision and any drawing error
+async fn get_chart(State(state): State<Arc<AppState>>, Path(id): Path<u32>) -> impl IntoResponse {
+    let sheet = state.sheet.lock().unwrap();
+    mark_stale_charts(&state, &sheet);
+    let drawn = state.charts.lock().unwrap();
+    match sheet.chart(id) {
+        Some(chart) => Json(chart_summary(chart, drawn.get(&id))).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("No chart {}", id)).into_response(),
+    }
+}
 
-    // 3) Merge and attach shared state
-    let app = Router::new()
-        .merge(public)
-        .merge(protected)
-        .with_state(app_state);
+/// Replace the definition of a chart saved in the live sheet
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `jar` - Cookie jar containing session information
+/// * `id` - Id of the chart
+/// * `definition` - The chart's new type, ranges and options
+///
+/// # Returns
+/// * The updated chart, or an error message
+async fn update_chart(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    Path(id): Path<u32>,
+    Json(definition): Json<ChartDefinition>,
+) -> impl IntoResponse {
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_edit(&state, &jar, original_path.as_deref()) {
+        return (StatusCode::FORBIDDEN, "Not authorized to edit this sheet").into_response();
+    }
 
-    // Start server
-    let listener = TcpListener::bind("127.0.0.1:8000").await?;
-    // let local_ip = local_ip().unwrap_or_else(|_| "127.0.0.1".parse().unwrap());
-    // println!("Listening on http://{}:8000", local_ip);
-    println!("Listening on http://localhost:8000");
+    let sheet_copy = {
+        let sheet = state.sheet.lock().unwrap();
+        if sheet.chart(id).is_none() {
+            return (StatusCode::NOT_FOUND, format!("No chart {}", id)).into_response();
+        }
+        sheet.as_ref().clone()
+    };
+    let (signature, image) = draw_detached(sheet_copy, definition.clone()).await;
+    if let Err(message) = image {
+        return (StatusCode::BAD_REQUEST, message).into_response();
+    }
 
-    axum::serve(listener, app).await?;
+    let mut version = state.version.lock().unwrap();
+    let mut sheet = state.sheet.lock().unwrap();
+    // The chart may have been deleted while it was drawn
+    if !sheet.replace_chart(id, definition.clone()) {
+        return (StatusCode::NOT_FOUND, format!("No chart {}", id)).into_response();
+    }
+    keep_drawing(&state, id, definition, signature, image);
+    charts_changed(&state, &mut version, &sheet, original_path);
 
-    Ok(())
+    let drawn = state.charts.lock().unwrap();
+    let chart = sheet.chart(id).unwrap();
+    Json(chart_summary(chart, drawn.get(&id))).into_response()
 }
 
-/// Generate a graph based on spreadsheet data
-///
-/// Creates and returns a graph image (PNG format) based on data ranges from the spreadsheet.
+/// Delete a chart saved in the live sheet
 ///
 /// # Arguments
 /// * `state` - Application state containing the spreadsheet
-/// * `payload` - Graph configuration including ranges, labels, and graph type
+/// * `jar` - Cookie jar containing session information
+/// * `id` - Id of the chart
 ///
 /// # Returns
-/// * A PNG image of the requested graph, or an error message
-async fn generate_graph(
+/// * No content, or an error message
+async fn delete_chart(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<GraphRequest>,
+    jar: CookieJar,
+    Path(id): Path<u32>,
 ) -> impl IntoResponse {
-    let sheet = state.sheet.lock().unwrap();
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_edit(&state, &jar, original_path.as_deref()) {
+        return (StatusCode::FORBIDDEN, "Not authorized to edit this sheet").into_response();
+    }
 
-    let graph_type = match payload.graph_type.as_str() {
-        "Line" => GraphType::Line,
-        "Bar" => GraphType::Bar,
-        "Scatter" => GraphType::Scatter,
-        "Area" => GraphType::Area,
-        _ => GraphType::Line,
-    };
+    let mut version = state.version.lock().unwrap();
+    let mut sheet = state.sheet.lock().unwrap();
+    if !sheet.remove_chart(id) {
+        return (StatusCode::NOT_FOUND, format!("No chart {}", id)).into_response();
+    }
+    charts_changed(&state, &mut version, &sheet, original_path);
+    StatusCode::NO_CONTENT.into_response()
+}
 
-    let options = GraphOptions {
-        title: payload.title,
-        x_label: payload.x_label,
-        y_label: payload.y_label,
-        width: 800,
-        height: 600,
-        graph_type,
+/// Get the image of a chart saved in the live sheet
+///
+/// A stale chart is drawn again first, from a copy of the sheet on a blocking thread, so
+/// the sheet stays unlocked while it renders.
+///
+/// # Arguments
+/// * `state` - Application state containing the spreadsheet
+/// * `id` - Id of the chart
+///
+/// # Returns
+/// * The image with its `Content-Type`, or why the chart could not be drawn
+async fn chart_image(State(state): State<Arc<AppState>>, Path(id): Path<u32>) -> impl IntoResponse {
+    let (sheet_copy, definition, revision) = {
+        let sheet = state.sheet.lock().unwrap();
+        mark_stale_charts(&state, &sheet);
+        let drawn = state.charts.lock().unwrap();
+        match drawn.get(&id) {
+            None => return (StatusCode::NOT_FOUND, format!("No chart {}", id)).into_response(),
+            Some(DrawnChart {
+                image: Some(image), ..
+            }) => return chart_image_response(image),
+            Some(last) => (
+                sheet.as_ref().clone(),
+                last.definition.clone(),
+                last.revision,
+            ),
+        }
     };
 
-    match create_graph(&sheet, &payload.x_range, &payload.y_range, options) {
-        Ok(img_data) => ([("Content-Type", "image/png")], img_data).into_response(),
-        Err(e) => (
-            StatusCode::BAD_REQUEST,
-            format!("Failed to create graph: {}", e),
-        )
-            .into_response(),
+    let (_, image) = draw_detached(sheet_copy, definition).await;
+    let response = chart_image_response(&image);
+    // Keep the drawing unless the chart went stale again while it was drawn
+    if let Some(last) = state.charts.lock().unwrap().get_mut(&id)
+        && last.revision == revision
+    {
+        last.image = Some(image);
+    }
+    response
+}
+
+/// Serves the image of a chart, or why it could not be drawn
+fn chart_image_response(image: &Result<(GraphFormat, Vec<u8>), String>) -> Response {
+    match image {
+        Ok((format, img_data)) => {
+            ([("Content-Type", format.content_type())], img_data.clone()).into_response()
+        }
+        Err(message) => (StatusCode::BAD_REQUEST, message.clone()).into_response(),
     }
 }
 
@@ -336,14 +1559,17 @@ async fn serve_sheet(
     Query(params): Query<SheetQuery>,
     State(state): State<Arc<AppState>>,
 ) -> Html<&'static str> {
-    if let (Some(rows), Some(cols)) = (params.rows, params.cols) {
-        if rows > 0 && rows <= 1000 && cols > 0 && cols <= 18278 {
-            let new_sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16)
-                .expect("Failed to create spreadsheet with specified dimensions");
+    if let (Some(rows), Some(cols)) = (params.rows, params.cols)
+        && rows > 0
+        && rows <= 1000
+        && cols > 0
+        && cols <= 18278
+    {
+        let new_sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16)
+            .expect("Failed to create spreadsheet with specified dimensions");
 
-            let mut current_sheet = state.sheet.lock().unwrap();
-            *current_sheet = new_sheet;
-        }
+        let mut current_sheet = state.sheet.lock().unwrap();
+        *current_sheet = new_sheet;
     }
 
     Html(include_str!("./static/sheet.html"))
@@ -385,8 +1611,8 @@ async fn get_sheet_data(
     // Parse pagination parameters with defaults
     let start_row = params.start_row.unwrap_or(1).max(1);
     let start_col = params.start_col.unwrap_or(1).max(1);
-    let page_rows = params.rows.unwrap_or(50).min(100).max(1); // Limit to 100 rows
-    let page_cols = params.cols.unwrap_or(50).min(100).max(1); // Limit to 100 cols
+    let page_rows = params.rows.unwrap_or(50).clamp(1, 100); // Limit to 100 rows
+    let page_cols = params.cols.unwrap_or(50).clamp(1, 100); // Limit to 100 cols
 
     // Calculate end bounds respecting sheet dimensions
     let end_row = (start_row + page_rows - 1).min(total_rows);
@@ -402,17 +1628,31 @@ async fn get_sheet_data(
                 let index = ((r - 1) * total_cols + (c - 1)) as usize;
 
                 // Make sure index is within bounds of the cells array
-                if index < sheet.cells.len() {
-                    if let Some(cell) = &sheet.cells[index] {
-                        cell_data.push(serde_json::json!({
-                            "row": r,
-                            "col": c,
-                            "name": Spreadsheet::get_cell_name(r, c),
-                            "value": cell.value,
-                            "formula": formula_to_string(&cell.formula),
-                            "error": cell.error,
-                        }));
-                    }
+                if index < sheet.cells.len()
+                    && let Some(cell) = &sheet.cells[index]
+                {
+                    // Cells filled by a dynamic array formula are read-only views of its result
+                    let spill_anchor = match cell.spill {
+                        Spill::Member(anchor_row, anchor_col) => {
+                            Some(Spreadsheet::get_cell_name(anchor_row, anchor_col))
+                        }
+                        _ => None,
+                    };
+                    cell_data.push(serde_json::json!({
+                        "row": r,
+                        "col": c,
+                        "name": Spreadsheet::get_cell_name(r, c),
+                        "value": cell.number(),
+                        "text": cell.text(),
+                        "formula": Spreadsheet::formula_to_string(&cell.formula),
+                        "error": cell.error,
+                        "error_text": cell.error_text(),
+                        "read_only": spill_anchor.is_some(),
+                        "spill_anchor": spill_anchor,
+                        "sparkline": sheet
+                            .sparkline_values(r, c)
+                            .map(|values| plot::sparkline_svg(&values, 90, 18)),
+                    }));
                 }
             }
         }
@@ -451,8 +1691,9 @@ async fn get_cell(
         if let Some(cell) = &sheet.cells[index] {
             return Json(serde_json::json!({
                 "name": cell_name,
-                "value": cell.value,
-                "formula": formula_to_string(&cell.formula),  // Convert to string
+                "value": cell.number(),
+                "text": cell.text(),
+                "formula": Spreadsheet::formula_to_string(&cell.formula),  // Convert to string
                 "error": cell.error,
             }))
             .into_response();
@@ -482,37 +1723,15 @@ async fn update_cell(
     let original_path = state.original_path.lock().unwrap().clone();
 
     // Check permission for editing if not logged in or not owner
-    if let Some(path) = &original_path {
-        // Extract username/sheet_name from path "database/username/sheet_name.bin.gz"
-        let path_parts: Vec<&str> = path.split('/').collect();
-        if path_parts.len() >= 3 {
-            let username = path_parts[1];
-            let sheet_name = path_parts[2].trim_end_matches(".bin.gz");
-
-            // Get current user from session cookie
-            let current_user = jar
-                .get("session")
-                .and_then(|cookie| crate::login::validate_session(cookie.value()));
-
-            let is_owner = current_user.as_deref() == Some(username);
-
-            // If not owner, check if sheet is public
-            if !is_owner {
-                let public_sheets = state.public_sheets.lock().unwrap();
-                let sheet_key = format!("{}/{}", username, sheet_name);
-
-                // If it's not in public sheets, return unauthorized
-                if !public_sheets.contains(&sheet_key) {
-                    return Json(CellResponse {
-                        status: "Error: Not authorized to edit this sheet".to_string(),
-                        value: None,
-                        version: 0,
-                        needs_refresh: false,
-                    })
-                    .into_response();
-                }
-            }
-        }
+    if !can_edit(&state, &jar, original_path.as_deref()) {
+        return Json(CellResponse {
+            status: "Error: Not authorized to edit this sheet".to_string(),
+            value: None,
+            version: 0,
+            needs_refresh: false,
+            stale_charts: Vec::new(),
+        })
+        .into_response();
     }
 
     // Check for version conflicts
@@ -526,6 +1745,7 @@ async fn update_cell(
             value: None,
             version: *current_version,
             needs_refresh: true,
+            stale_charts: Vec::new(),
         })
         .into_response();
     }
@@ -534,6 +1754,7 @@ async fn update_cell(
     let mut sheet = state.sheet.lock().unwrap();
     let mut status = String::new();
     let mut was_updated = false;
+    let user = journal_user(&jar);
 
     // Parse the cell name and update the cell
     if let Some((row, col)) = sheet.spreadsheet_parse_cell_name(&payload.cell) {
@@ -550,6 +1771,8 @@ async fn update_cell(
                     .map(|c| c.value)
             };
 
+            let undo_len = sheet.undo_stack.len();
+
             // Update the cell
             sheet.spreadsheet_set_cell_value(row, col, parsed_rhs, &mut status);
             // println!("Updated cell {}: {}", payload.cell, status);
@@ -561,10 +1784,16 @@ async fn update_cell(
                     value: None,
                     version: *current_version,
                     needs_refresh: false,
+                    stale_charts: Vec::new(),
                 })
                 .into_response();
             }
 
+            // Record every cell the command changed in the sheet's journal
+            let edits = journal::edits_since(&sheet, undo_len);
+            let formula_changed = !edits.is_empty();
+            record_edits(&state, original_path.as_deref(), &user, &edits);
+
             // Check if value actually changed
             let new_value = {
                 let index = ((row - 1) * sheet.cols + (col - 1)) as usize;
@@ -575,8 +1804,8 @@ async fn update_cell(
                     .map(|c| c.value)
             };
 
-            // If value changed
-            if current_value != new_value {
+            // If the value or the formula changed (text constants all have the value 0)
+            if current_value != new_value || formula_changed {
                 was_updated = true;
 
                 // Increment version
@@ -591,6 +1820,7 @@ async fn update_cell(
                 value: None,
                 version: *current_version,
                 needs_refresh: false,
+                stale_charts: Vec::new(),
             })
             .into_response();
         }
@@ -600,51 +1830,51 @@ async fn update_cell(
             value: None,
             version: *current_version,
             needs_refresh: false,
+            stale_charts: Vec::new(),
         })
         .into_response();
     }
 
     // Auto-save if the sheet was updated
     if was_updated {
-        if let Some(path) = &original_path {
-            // Create a clone of the sheet for saving to avoid deadlocks
-            let sheet_clone = {
-                let sheet_ref = &*sheet;
-                bincode::serialize(sheet_ref)
-                    .ok()
-                    .and_then(|data| bincode::deserialize(&data).ok())
-            };
-
-            if let Some(sheet_to_save) = sheet_clone {
-                // Spawn a task to save in the background to avoid blocking
-                let path_clone = path.clone();
-                tokio::spawn(async move {
-                    let _ = saving::save_spreadsheet(&sheet_to_save, &path_clone);
-                });
-            }
-        }
+        save_in_background(&state, *current_version, &sheet, original_path);
     }
+    let stale_charts = mark_stale_charts(&state, &sheet);
 
     // Prepare response with current cell value and version
     if let Some((row, col)) = sheet.spreadsheet_parse_cell_name(&payload.cell) {
         let index = ((row - 1) * sheet.cols + (col - 1)) as usize;
         if let Some(cell) = &sheet.cells.get(index).and_then(|c| c.as_ref()) {
+            // A blocked array formula reports why it could not spill
+            if let Spill::Blocked { .. } = cell.spill {
+                return Json(CellResponse {
+                    status: "Error: #SPILL! - the spill range is not empty".to_string(),
+                    value: None,
+                    version: *current_version,
+                    needs_refresh: false,
+                    stale_charts,
+                })
+                .into_response();
+            }
+
             // Check if the cell has an error flag set
             if cell.error {
                 return Json(CellResponse {
                     status: "Error: Invalid calculation result".to_string(),
-                    value: Some(cell.value),
+                    value: Some(cell.number()),
                     version: *current_version,
                     needs_refresh: false,
+                    stale_charts,
                 })
                 .into_response();
             }
 
             Json(CellResponse {
                 status: "ok".to_string(),
-                value: Some(cell.value),
+                value: Some(cell.number()),
                 version: *current_version,
                 needs_refresh: false,
+                stale_charts,
             })
             .into_response()
         } else {
@@ -653,6 +1883,7 @@ async fn update_cell(
                 value: None,
                 version: *current_version,
                 needs_refresh: false,
+                stale_charts,
             })
             .into_response()
         }
@@ -662,6 +1893,7 @@ async fn update_cell(
             value: None,
             version: *current_version,
             needs_refresh: false,
+            stale_charts,
         })
         .into_response()
     }
@@ -681,41 +1913,54 @@ async fn save_spreadsheet(
     Query(params): Query<SaveQuery>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    // Get the sheet and original path
-    let sheet = state.sheet.lock().unwrap();
-    let mut original_path = state.original_path.lock().unwrap();
-
-    // Get filename from query params or use original path if none provided
-    let filename = if params.filename.is_empty() {
-        // Try to use the original path
-        match original_path.as_ref() {
-            Some(path) => path.clone(),
-            None => {
-                return Json(SaveResponse {
-                    status: "error".to_string(),
-                    message: Some(
-                        "No filename provided and no original path available".to_string(),
-                    ),
-                })
-                .into_response();
+    // Take a copy of the sheet and settle the path, then write the file with both unlocked
+    let (sheet_to_save, version, previous_path, filename) = {
+        let version = state.version.lock().unwrap();
+        let sheet = state.sheet.lock().unwrap();
+        let mut original_path = state.original_path.lock().unwrap();
+        let previous_path = original_path.clone();
+
+        // Get filename from query params or use original path if none provided
+        let filename = if params.filename.is_empty() {
+            // Try to use the original path
+            match original_path.as_ref() {
+                Some(path) => path.clone(),
+                None => {
+                    return Json(SaveResponse {
+                        status: "error".to_string(),
+                        message: Some(
+                            "No filename provided and no original path available".to_string(),
+                        ),
+                    })
+                    .into_response();
+                }
             }
-        }
-    } else {
-        // For new sheets, update the original path with the provided filename
-        let new_filename = params.filename.clone();
-        *original_path = Some(new_filename.clone());
-        new_filename
+        } else {
+            // For new sheets, update the original path with the provided filename
+            let new_filename = params.filename.clone();
+            *original_path = Some(new_filename.clone());
+            new_filename
+        };
+        ((**sheet).clone(), *version, previous_path, filename)
     };
 
-    match saving::save_spreadsheet(&sheet, &filename) {
-        Ok(_) => Json(SaveResponse {
-            status: "ok".to_string(),
-            message: None,
-        })
-        .into_response(),
+    match state
+        .saves
+        .save(sheet_to_save, filename.clone(), version)
+        .await
+    {
+        Ok(_) => {
+            discard_recoveries(previous_path.as_deref(), &filename);
+            Json(SaveResponse {
+                status: "ok".to_string(),
+                message: None,
+            })
+            .into_response()
+        }
         Err(e) => {
             // If save fails, don't keep the path for new sheets
-            if original_path.as_ref().unwrap() == &filename && params.filename == filename {
+            let mut original_path = state.original_path.lock().unwrap();
+            if original_path.as_ref() == Some(&filename) && params.filename == filename {
                 *original_path = None;
             }
 
@@ -745,7 +1990,11 @@ async fn save_spreadsheet_with_name(
     username: axum::extract::Extension<String>,
     Form(query): Form<FileNameQuery>,
 ) -> impl IntoResponse {
-    let sheet = state.sheet.lock().unwrap();
+    let (sheet, version) = {
+        let version = state.version.lock().unwrap();
+        let sheet = state.sheet.lock().unwrap().as_ref().clone();
+        (sheet, *version)
+    };
 
     // Create user directory if it doesn't exist
     let user_dir = format!("database/{}", username.0);
@@ -763,18 +2012,23 @@ async fn save_spreadsheet_with_name(
     let path = format!("{}/{}", user_dir, filename);
 
     // Update original path
-    let mut original_path = state.original_path.lock().unwrap();
-    *original_path = Some(path.clone());
+    let previous_path = state.original_path.lock().unwrap().replace(path.clone());
 
     // Save the file
-    match saving::save_spreadsheet(&sheet, &path) {
-        Ok(_) => Json(SaveResponse {
-            status: "ok".to_string(),
-            message: None,
-        })
-        .into_response(),
+    match state.saves.save(sheet, path.clone(), version).await {
+        Ok(_) => {
+            discard_recoveries(previous_path.as_deref(), &path);
+            Json(SaveResponse {
+                status: "ok".to_string(),
+                message: None,
+            })
+            .into_response()
+        }
         Err(e) => {
-            *original_path = None;
+            let mut original_path = state.original_path.lock().unwrap();
+            if original_path.as_ref() == Some(&path) {
+                *original_path = None;
+            }
             Json(SaveResponse {
                 status: "error".to_string(),
                 message: Some(e.to_string()),
@@ -1055,19 +2309,36 @@ async fn load_spreadsheet(
     }
 }
 
+/// Query parameters for downloading the spreadsheet as CSV
+#[derive(Deserialize)]
+struct CsvDownloadQuery {
+    /// Write formulas rather than values (default false)
+    formulas: Option<bool>,
+    /// Write a header row of column letters (default true)
+    header: Option<bool>,
+}
+
 /// Download the current spreadsheet as CSV
 ///
 /// Converts the spreadsheet to CSV format and returns it as a downloadable file.
 ///
 /// # Arguments
 /// * `state` - Application state containing the spreadsheet
+/// * `query` - Whether to write formulas and a header row
 ///
 /// # Returns
 /// * CSV file for download or error response
-async fn download_csv(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+async fn download_csv(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CsvDownloadQuery>,
+) -> impl IntoResponse {
     let sheet = state.sheet.lock().unwrap();
+    let options = downloader::CsvExportOptions {
+        formulas: query.formulas.unwrap_or(false),
+        header: query.header.unwrap_or(true),
+    };
 
-    match downloader::to_csv(&sheet) {
+    match downloader::to_csv_with_options(&sheet, &options) {
         Ok(csv_content) => Response::builder()
             .status(StatusCode::OK)
             .header(header::CONTENT_TYPE, "text/csv")
@@ -1126,7 +2397,7 @@ async fn download_xlsx(State(state): State<Arc<AppState>>) -> impl IntoResponse
 
 /// Serialize a spreadsheet to a memory buffer
 ///
-/// Compresses and serializes a spreadsheet to a memory buffer.
+/// Encodes a spreadsheet in the native file format into a memory buffer.
 ///
 /// # Arguments
 /// * `spreadsheet` - The spreadsheet to serialize
@@ -1135,22 +2406,13 @@ async fn download_xlsx(State(state): State<Arc<AppState>>) -> impl IntoResponse
 /// # Returns
 /// * `std::io::Result<()>` - Success or error
 fn serialize_to_memory(spreadsheet: &Spreadsheet, buffer: &mut Vec<u8>) -> std::io::Result<()> {
-    use bincode::serialize_into;
-    use flate2::Compression;
-    use flate2::write::GzEncoder;
-
-    let encoder = GzEncoder::new(buffer, Compression::default());
-    let mut writer = std::io::BufWriter::new(encoder);
-
-    serialize_into(&mut writer, spreadsheet)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-
+    buffer.extend(saving::to_bytes(spreadsheet)?);
     Ok(())
 }
 
 /// Deserialize a spreadsheet from a memory buffer
 ///
-/// Decompresses and deserializes a spreadsheet from a memory buffer.
+/// Decodes a spreadsheet saved in any version of the native file format.
 ///
 /// # Arguments
 /// * `buffer` - The buffer containing the serialized spreadsheet
@@ -1158,84 +2420,7 @@ fn serialize_to_memory(spreadsheet: &Spreadsheet, buffer: &mut Vec<u8>) -> std::
 /// # Returns
 /// * `std::io::Result<Box<Spreadsheet>>` - Deserialized spreadsheet or error
 fn deserialize_from_memory(buffer: &[u8]) -> std::io::Result<Box<Spreadsheet>> {
-    use bincode::deserialize_from;
-    use flate2::read::GzDecoder;
-    use std::io::Cursor;
-
-    let cursor = Cursor::new(buffer);
-    let decoder = GzDecoder::new(cursor);
-    let mut reader = std::io::BufReader::new(decoder);
-
-    let spreadsheet: Spreadsheet = deserialize_from(&mut reader)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-
-    Ok(Box::new(spreadsheet))
-}
-
-/// Convert a formula to a displayable string
-///
-/// Converts the internal formula representation to a string that can be displayed
-/// in the UI or saved to a file.
-///
-/// # Arguments
-/// * `formula` - The formula to convert
-///
-/// # Returns
-/// * A string representation of the formula
-fn formula_to_string(formula: &ParsedRHS) -> String {
-    match formula {
-        ParsedRHS::Function {
-            name,
-            args: (arg1, arg2),
-        } => {
-            let func_name = match name {
-                FunctionName::Min => "MIN",
-                FunctionName::Max => "MAX",
-                FunctionName::Avg => "AVG",
-                FunctionName::Sum => "SUM",
-                FunctionName::Stdev => "STDEV",
-                FunctionName::Copy => "COPY",
-            };
-
-            let cell1 = match arg1 {
-                Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-                Operand::Number(n) => n.to_string(),
-            };
-
-            let cell2 = match arg2 {
-                Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-                Operand::Number(n) => n.to_string(),
-            };
-
-            format!("{}({}:{})", func_name, cell1, cell2)
-        }
-        ParsedRHS::Arithmetic { lhs, operator, rhs } => {
-            let left = match lhs {
-                Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-                Operand::Number(n) => n.to_string(),
-            };
-
-            let right = match rhs {
-                Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-                Operand::Number(n) => n.to_string(),
-            };
-
-            format!("{}{}{}", left, operator, right)
-        }
-        ParsedRHS::Sleep(operand) => {
-            let value = match operand {
-                Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-                Operand::Number(n) => n.to_string(),
-            };
-
-            format!("SLEEP({})", value)
-        }
-        ParsedRHS::SingleValue(operand) => match operand {
-            Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-            Operand::Number(n) => n.to_string(),
-        },
-        ParsedRHS::None => String::new(),
-    }
+    saving::from_bytes(buffer).map(Box::new)
 }
 
 /// Get information about the current spreadsheet
@@ -1278,13 +2463,22 @@ async fn get_sheet_status(State(state): State<Arc<AppState>>) -> impl IntoRespon
 }
 
 /// Add an endpoint for undo
-async fn undo(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+///
+/// The undo is journaled and saved to the sheet's file like any other edit, so the file
+/// and the journal stay in step. Only users who may edit the sheet can undo.
+async fn undo(State(state): State<Arc<AppState>>, jar: CookieJar) -> impl IntoResponse {
+    let original_path = state.original_path.lock().unwrap().clone();
+    if !can_edit(&state, &jar, original_path.as_deref()) {
+        return Json(serde_json::json!({
+            "status": "Error: Not authorized to edit this sheet",
+            "version": 0,
+            "stale_charts": []
+        }))
+        .into_response();
+    }
+
+    let mut version = state.version.lock().unwrap();
     let mut sheet = state.sheet.lock().unwrap();
-    // debug before
-    // println!(
-    //     "DEBUG /undo handler: undo_stack length before undo: {}",
-    //     sheet.undo_stack.len()
-    // );
 
     let mut status = String::new();
     if sheet.undo_stack.is_empty() {
@@ -1292,22 +2486,26 @@ async fn undo(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     } else {
         // perform undo
         sheet.spreadsheet_undo(&mut status);
-        // println!("DEBUG /undo handler: performed undo, status: {}", status);
-        // println!(
-        //     "DEBUG /undo handler: undo_stack length after undo: {}",
-        //     sheet.undo_stack.len()
-        // );
-    }
 
-    // bump version & timestamp
-    {
-        let mut version = state.version.lock().unwrap();
+        // The undo stack now holds the formulas the undo replaced
+        let edits = journal::edits_since(&sheet, 0);
+        record_edits(
+            &state,
+            original_path.as_deref(),
+            &journal_user(&jar),
+            &edits,
+        );
+
+        // bump version & timestamp
         *version += 1;
         *state.last_modified.lock().unwrap() = std::time::SystemTime::now();
+        save_in_background(&state, *version, &sheet, original_path);
     }
 
     Json(serde_json::json!({
         "status": status,
-        "version": *state.version.lock().unwrap()
+        "version": *version,
+        "stale_charts": mark_stale_charts(&state, &sheet)
     }))
+    .into_response()
 }
@@ -0,0 +1,257 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Chart Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod chart_tests {
+    use cop::chart::{CellRange, ChartDefinition, Palette, read_ranges};
+    use cop::journal;
+    use cop::saving;
+    use cop::spreadsheet::Spreadsheet;
+
+    fn definition(json: &str) -> ChartDefinition {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn line_chart(x_range: &str, y_range: &str) -> ChartDefinition {
+        definition(&format!(
+            r#"{{"x_range":"{}","y_range":"{}","title":"","x_label":"","y_label":"","graph_type":"Line"}}"#,
+            x_range, y_range
+        ))
+    }
+
+    /// Months in A1:A4 under a header, sales in B1:B4 and costs in C1:C4
+    fn sales_sheet() -> Box<Spreadsheet> {
+        let mut sheet = Spreadsheet::spreadsheet_create(6, 4).unwrap();
+        for (cell, formula) in [
+            ("A1", "\"Month\""),
+            ("A2", "\"Jan\""),
+            ("A3", "\"Feb\""),
+            ("A4", "\"Mar\""),
+            ("B1", "\"Sales\""),
+            ("B2", "10"),
+            ("B3", "20"),
+            ("B4", "B3+5"),
+            ("C1", "\"Costs\""),
+            ("C2", "4"),
+            ("C3", "6"),
+            ("C4", "8"),
+        ] {
+            journal::set_formula(&mut sheet, cell, formula).unwrap();
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_chart_ids() {
+        let mut sheet = sales_sheet();
+        let first = sheet.add_chart(line_chart("A1:A4", "B1:B4"));
+        let second = sheet.add_chart(line_chart("A1:A4", "C1:C4"));
+        assert_eq!((first, second), (1, 2));
+
+        assert!(sheet.replace_chart(first, line_chart("A1:A4", "B1:C4")));
+        assert_eq!(sheet.chart(first).unwrap().definition.y_range, "B1:C4");
+        assert!(!sheet.replace_chart(7, line_chart("A1:A4", "B1:B4")));
+
+        assert!(sheet.remove_chart(second));
+        assert!(!sheet.remove_chart(second));
+        assert!(sheet.chart(second).is_none());
+        // Ids are not reused while a higher one is in use
+        assert!(sheet.remove_chart(first));
+        assert_eq!(sheet.add_chart(line_chart("A1:A4", "B1:B4")), 1);
+        assert_eq!(sheet.add_chart(line_chart("A1:A4", "B1:B4")), 2);
+        sheet.remove_chart(1);
+        assert_eq!(sheet.add_chart(line_chart("A1:A4", "B1:B4")), 3);
+    }
+
+    #[test]
+    fn test_signature() {
+        let mut sheet = sales_sheet();
+        let chart = line_chart("A1:A4", "B1:B4");
+        let signature = sheet.chart_signature(&chart);
+
+        // Cells outside the ranges do not matter
+        journal::set_formula(&mut sheet, "C2", "40").unwrap();
+        journal::set_formula(&mut sheet, "D1", "1").unwrap();
+        assert_eq!(sheet.chart_signature(&chart), signature);
+
+        // A cell the chart reads, directly or through a formula
+        journal::set_formula(&mut sheet, "B3", "21").unwrap();
+        let changed = sheet.chart_signature(&chart);
+        assert_ne!(changed, signature);
+        journal::set_formula(&mut sheet, "B3", "20").unwrap();
+        assert_eq!(sheet.chart_signature(&chart), signature);
+
+        // Text of a category, and an error
+        journal::set_formula(&mut sheet, "A2", "\"January\"").unwrap();
+        assert_ne!(sheet.chart_signature(&chart), signature);
+        journal::set_formula(&mut sheet, "A2", "\"Jan\"").unwrap();
+        journal::set_formula(&mut sheet, "B2", "1/0").unwrap();
+        assert_ne!(sheet.chart_signature(&chart), signature);
+    }
+
+    #[test]
+    fn test_palette_formats() {
+        let named = definition(
+            r#"{"x_range":"A1:A4","title":"","x_label":"","y_label":"","graph_type":"Bar","palette":"pastel"}"#,
+        );
+        assert_eq!(named.palette, Some(Palette::Named("pastel".to_string())));
+        let listed = definition(
+            r##"{"x_range":"A1:A4","title":"","x_label":"","y_label":"","graph_type":"Bar","palette":["#ff0000","#00ff00"]}"##,
+        );
+        assert_eq!(
+            listed.palette,
+            Some(Palette::Colors(vec![
+                "#ff0000".to_string(),
+                "#00ff00".to_string()
+            ]))
+        );
+
+        // JSON keeps the plain forms, the binary format tells them apart by a tag
+        let json = serde_json::to_value(&listed).unwrap();
+        assert_eq!(json["palette"], serde_json::json!(["#ff0000", "#00ff00"]));
+        for chart in [named, listed] {
+            let bytes = bincode::serialize(&chart).unwrap();
+            assert_eq!(
+                bincode::deserialize::<ChartDefinition>(&bytes).unwrap(),
+                chart
+            );
+        }
+    }
+
+    #[test]
+    fn test_saved_with_sheet() {
+        let mut sheet = sales_sheet();
+        let mut chart = line_chart("A1:A4", "B1:C4");
+        chart.palette = Some(Palette::Named("colorblind".to_string()));
+        chart.width = Some(640);
+        sheet.add_chart(chart);
+        sheet.add_chart(line_chart("", "C2:C4"));
+        sheet.remove_chart(1);
+
+        let loaded = saving::from_bytes(&saving::to_bytes(&sheet).unwrap()).unwrap();
+        assert_eq!(loaded.charts, sheet.charts);
+        assert_eq!(loaded.charts[0].id, 2);
+    }
+
+    #[test]
+    fn test_read_ranges() {
+        let mut sheet = sales_sheet();
+        let data = read_ranges(&sheet, "A1:A4", &["B1:C4"], false, true).unwrap();
+        assert!(data.header);
+        assert_eq!(data.x, vec![1.0, 2.0, 3.0]);
+        assert_eq!(data.categories, vec!["Jan", "Feb", "Mar"]);
+        let headers: Vec<_> = data.columns.iter().map(|c| c.header.as_deref()).collect();
+        assert_eq!(headers, vec![Some("Sales"), Some("Costs")]);
+        assert_eq!(data.columns[0].values, vec![10.0, 20.0, 25.0]);
+        assert!(data.columns.iter().all(|column| column.block));
+        assert_eq!(data.columns[1].range.to_string(), "C1:C4");
+
+        let data = read_ranges(&sheet, "", &["B2:B4", "C2:C4"], false, true).unwrap();
+        assert!(!data.header);
+        assert_eq!(data.x, vec![1.0, 2.0, 3.0]);
+        assert_eq!(data.columns[1].source, 1);
+
+        // Fractional values are read as they are, not rounded
+        journal::set_formula(&mut sheet, "D2", "SLOPE(B2:B4, C2:C4)").unwrap();
+        journal::set_formula(&mut sheet, "D3", "C3").unwrap();
+        let data = read_ranges(&sheet, "D2:D3", &["D2:D3"], false, true).unwrap();
+        assert_eq!(data.x, vec![3.75, 6.0]);
+        assert_eq!(data.columns[0].values, vec![3.75, 6.0]);
+
+        let error = read_ranges(&sheet, "A2:A4", &["B2:B3"], false, true).unwrap_err();
+        assert_eq!(error, "X range A2:A4 has 3 values but Y range B2:B3 has 2");
+        let error = read_ranges(&sheet, "A2:A4", &[], false, true).unwrap_err();
+        assert_eq!(error, "At least one Y range is required");
+        let error = CellRange::parse(&sheet, "B4:B1", "Y range").unwrap_err();
+        assert_eq!(
+            error,
+            "Y range B4:B1 must run from its first to its last cell"
+        );
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_excel_export() {
+        use cop::downloader::to_xlsx;
+        use cop::loader::from_workbook;
+
+        let mut sheet = sales_sheet();
+        let plain = to_xlsx(&sheet).unwrap();
+        sheet.add_chart(line_chart("A1:A4", "B1:C4"));
+        let mut heatmap = line_chart("", "B2:C4");
+        heatmap.graph_type = "Heatmap".to_string();
+        sheet.add_chart(heatmap);
+        sheet.add_chart(line_chart("A1:A4", "Z1:Z9"));
+
+        // Only the line chart has an Excel equivalent and readable ranges
+        let xlsx = to_xlsx(&sheet).unwrap();
+        assert!(xlsx.len() > plain.len());
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("charts.xlsx");
+        std::fs::write(&path, xlsx).unwrap();
+        let sheets = from_workbook(&path).unwrap();
+        assert_eq!(journal::formula_text(&sheets[0].sheet, 4, 2), "B3+5");
+    }
+
+    /// Returns the XML of every chart in an XLSX workbook, in archive order
+    #[cfg(feature = "web")]
+    fn chart_xml(xlsx: Vec<u8>) -> Vec<String> {
+        use std::io::Read;
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(xlsx)).unwrap();
+        let mut charts = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).unwrap();
+            if file.name().starts_with("xl/charts/chart") {
+                let mut xml = String::new();
+                file.read_to_string(&mut xml).unwrap();
+                charts.push(xml);
+            }
+        }
+        charts
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_excel_trendline_export() {
+        use cop::downloader::to_xlsx;
+
+        let mut sheet = sales_sheet();
+        sheet.add_chart(line_chart("A1:A4", "B1:C4"));
+        let charts = chart_xml(to_xlsx(&sheet).unwrap());
+        assert_eq!(charts.len(), 1);
+        assert!(!charts[0].contains("<c:trendline>"));
+
+        let mut sheet = sales_sheet();
+        let mut trend = line_chart("A1:A4", "B1:C4");
+        trend.trendline = Some("polynomial:2".to_string());
+        sheet.add_chart(trend);
+        let charts = chart_xml(to_xlsx(&sheet).unwrap());
+        assert_eq!(charts.len(), 1);
+        // One polynomial trendline of order 2 per series, with its equation and R²
+        assert_eq!(charts[0].matches("<c:trendline>").count(), 2);
+        assert!(charts[0].contains(r#"<c:trendlineType val="poly"/><c:order val="2"/>"#));
+        assert!(charts[0].contains(r#"<c:dispRSqr val="1"/><c:dispEq val="1"/>"#));
+
+        let mut sheet = sales_sheet();
+        let mut trend = line_chart("A1:A4", "B1:C4");
+        trend.trendline = Some("moving_average:3".to_string());
+        sheet.add_chart(trend);
+        let charts = chart_xml(to_xlsx(&sheet).unwrap());
+        assert!(charts[0].contains(r#"<c:trendlineType val="movingAvg"/><c:period val="3"/>"#));
+
+        // Trendlines Excel cannot draw are left out rather than failing the export
+        let mut sheet = sales_sheet();
+        let mut trend = line_chart("A1:A4", "B1:C4");
+        trend.trendline = Some("polynomial:9".to_string());
+        sheet.add_chart(trend);
+        let charts = chart_xml(to_xlsx(&sheet).unwrap());
+        assert_eq!(charts.len(), 1);
+        assert!(!charts[0].contains("<c:trendline>"));
+    }
+}
@@ -0,0 +1,263 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== CLI Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use cop::cli::{CommandResult, Session};
+    use cop::spreadsheet::Spreadsheet;
+
+    fn session(rows: i16, cols: i16) -> Session {
+        Session::new(Spreadsheet::spreadsheet_create(rows, cols).unwrap())
+    }
+
+    fn run(session: &mut Session, command: &str) -> (CommandResult, String) {
+        let mut status = String::new();
+        let result = session.execute(command, &mut status);
+        (result, status)
+    }
+
+    fn value(session: &Session, cell_name: &str) -> i32 {
+        let (row, col) = session
+            .sheet
+            .spreadsheet_parse_cell_name(cell_name)
+            .unwrap();
+        let index = (row - 1) as usize * session.sheet.cols as usize + (col - 1) as usize;
+        session.sheet.cells[index].as_ref().unwrap().value
+    }
+
+    #[test]
+    fn test_existing_commands() {
+        let mut session = session(30, 30);
+        assert_eq!(run(&mut session, "A1=5").1, "ok");
+        assert_eq!(run(&mut session, "B1=A1+1").1, "ok");
+        assert_eq!(value(&session, "B1"), 6);
+        assert_eq!(run(&mut session, "B1=B1").1, "Cycle Detected");
+        assert_eq!(run(&mut session, "A1=5++").1, "invalid command");
+        assert_eq!(run(&mut session, "").1, "invalid command");
+        assert_eq!(run(&mut session, "jump").1, "invalid command");
+
+        run(&mut session, "A1=7");
+        assert_eq!(run(&mut session, "UNDO").1, "ok");
+        assert_eq!(value(&session, "A1"), 5);
+        assert_eq!(run(&mut session, "UNDO").1, "ok");
+        assert_eq!(value(&session, "A1"), 7);
+
+        run(&mut session, "s");
+        run(&mut session, "d");
+        assert_eq!((session.sheet.view_row, session.sheet.view_col), (10, 10));
+        run(&mut session, "w");
+        assert_eq!(session.sheet.view_row, 0);
+        assert_eq!(run(&mut session, "scroll_to C5").1, "ok");
+        assert_eq!((session.sheet.view_row, session.sheet.view_col), (4, 2));
+        assert_eq!(run(&mut session, "goto A1").1, "ok");
+        assert_eq!((session.sheet.view_row, session.sheet.view_col), (0, 0));
+        assert_eq!(run(&mut session, "goto ZZ99").1, "invalid cell");
+
+        run(&mut session, "disable_output");
+        assert!(!session.show_output);
+        run(&mut session, "enable_output");
+        assert!(session.show_output);
+
+        assert!(matches!(
+            run(&mut session, "help").0,
+            CommandResult::Output(_)
+        ));
+        assert_eq!(run(&mut session, "q").0, CommandResult::Quit);
+    }
+
+    #[test]
+    fn test_show_cell() {
+        let mut session = session(10, 10);
+        run(&mut session, "A1=5");
+        run(&mut session, "B1=A1*2");
+        run(&mut session, "C1=SUM(A1:B1)");
+
+        let (result, status) = run(&mut session, "show A1");
+        assert_eq!(status, "ok");
+        let CommandResult::Output(text) = result else {
+            panic!("show should print the cell");
+        };
+        assert!(text.starts_with("A1\n"));
+        assert!(text.contains("formula:    5\n"));
+        assert!(text.contains("value:      5\n"));
+        assert!(text.contains("dependents: B1, C1"));
+
+        let CommandResult::Output(text) = run(&mut session, "show C1").0 else {
+            panic!("show should print the cell");
+        };
+        assert!(text.contains("formula:    SUM(A1:B1)"));
+        assert!(text.contains("value:      15"));
+        assert!(text.contains("dependents: (none)"));
+
+        run(&mut session, "D1=1/0");
+        let CommandResult::Output(text) = run(&mut session, "show D1").0 else {
+            panic!("show should print the cell");
+        };
+        assert!(text.contains("value:      ERR"));
+
+        run(&mut session, "E1=SPARKLINE(A1:C1)");
+        let CommandResult::Output(text) = run(&mut session, "show E1").0 else {
+            panic!("show should print the cell");
+        };
+        assert!(text.contains("value:      15  ▁▅█"));
+
+        assert_eq!(run(&mut session, "show K1").1, "invalid cell");
+    }
+
+    #[test]
+    fn test_clear_range() {
+        let mut session = session(10, 10);
+        run(&mut session, "A1=1");
+        run(&mut session, "A2=2");
+        run(&mut session, "B1=3");
+        run(&mut session, "C1=SUM(A1:B2)");
+        assert_eq!(value(&session, "C1"), 6);
+
+        assert_eq!(run(&mut session, "clear A1:B2").1, "ok");
+        assert_eq!(value(&session, "A1"), 0);
+        assert_eq!(value(&session, "B1"), 0);
+        assert_eq!(value(&session, "C1"), 0);
+
+        // The whole range comes back with a single UNDO
+        run(&mut session, "UNDO");
+        assert_eq!(value(&session, "A1"), 1);
+        assert_eq!(value(&session, "A2"), 2);
+        assert_eq!(value(&session, "C1"), 6);
+
+        assert_eq!(run(&mut session, "clear C1").1, "ok");
+        assert_eq!(value(&session, "C1"), 0);
+        assert_eq!(run(&mut session, "clear B2:A1").1, "invalid range");
+        assert_eq!(run(&mut session, "clear").1, "invalid range");
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut session = session(10, 10);
+        run(&mut session, "A1=4");
+        run(&mut session, "B2=A1*3");
+        run(&mut session, "J10=7");
+        run(&mut session, "C1=SUM(F6:J10)");
+
+        assert_eq!(run(&mut session, "resize 5 5").1, "ok, 2 formulas dropped");
+        assert_eq!((session.sheet.rows, session.sheet.cols), (5, 5));
+        assert_eq!(value(&session, "B2"), 12);
+        run(&mut session, "A1=5");
+        assert_eq!(value(&session, "B2"), 15);
+        assert_eq!(value(&session, "C1"), 0);
+
+        assert_eq!(run(&mut session, "resize 20 30").1, "ok");
+        assert_eq!((session.sheet.rows, session.sheet.cols), (20, 30));
+        assert_eq!(value(&session, "B2"), 15);
+
+        assert_eq!(run(&mut session, "resize 0 5").1, "invalid dimensions");
+        assert_eq!(run(&mut session, "resize 5").1, "invalid dimensions");
+        assert_eq!(run(&mut session, "resize five 5").1, "invalid dimensions");
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let native = dir.path().join("sheet.bin.gz");
+        let csv = dir.path().join("sheet.csv");
+        let native = native.to_str().unwrap();
+        let csv = csv.to_str().unwrap();
+
+        let mut session = session(5, 5);
+        run(&mut session, "A1=5");
+        run(&mut session, "B1=A1*2");
+        assert_eq!(run(&mut session, &format!("save {}", native)).1, "ok");
+        assert_eq!(run(&mut session, &format!("export {}", csv)).1, "ok");
+        assert!(std::fs::read_to_string(csv).unwrap().contains("5,10"));
+
+        run(&mut session, "A1=1");
+        assert_eq!(run(&mut session, &format!("load {}", native)).1, "ok");
+        assert_eq!(value(&session, "A1"), 5);
+        run(&mut session, "A1=6");
+        assert_eq!(value(&session, "B1"), 12);
+
+        assert_eq!(run(&mut session, &format!("import {}", csv)).1, "ok");
+        assert!(session.sheet.rows > 0);
+
+        let missing = dir.path().join("missing.bin.gz");
+        let status = run(&mut session, &format!("load {}", missing.display())).1;
+        assert!(status.starts_with("load failed"));
+        let status = run(
+            &mut session,
+            &format!("export {}", dir.path().join("x.txt").display()),
+        )
+        .1;
+        assert!(status.starts_with("export failed"));
+        assert_eq!(run(&mut session, "save").1, "invalid command");
+    }
+
+    #[test]
+    fn test_plot() {
+        let mut session = session(10, 10);
+        for (cell, value) in [("A1", "1"), ("A2", "2"), ("B1", "5"), ("B2", "9")] {
+            run(&mut session, &format!("{}={}", cell, value));
+        }
+
+        let (result, status) = run(&mut session, "plot line A1:A2 B1:B2 --width 10 --height 4");
+        assert_eq!(status, "ok");
+        let CommandResult::Output(text) = result else {
+            panic!("plot should print the chart");
+        };
+        assert_eq!(text.lines().count(), 7);
+        assert!(text.ends_with("● B1:B2"));
+
+        let CommandResult::Output(text) = run(&mut session, "plot bar B1:B2 --ascii").0 else {
+            panic!("plot should print the chart");
+        };
+        assert!(text.starts_with("1 |"));
+        let CommandResult::Output(text) = run(&mut session, "plot sparkline B1:B2").0 else {
+            panic!("plot should print the chart");
+        };
+        assert_eq!(text, "B1:B2  ▁█  5..9");
+
+        assert_eq!(run(&mut session, "plot pie B1:B2").1, "invalid command");
+        assert_eq!(
+            run(&mut session, "plot line B1:B2 --height x").1,
+            "invalid height"
+        );
+        assert_eq!(
+            run(&mut session, "plot line B1:B2 --colour").1,
+            "unknown option --colour"
+        );
+        assert_eq!(
+            run(&mut session, "plot line A1:A2 B1:B1").1,
+            "plot failed: X range A1:A2 has 2 values but Y range B1:B1 has 1"
+        );
+    }
+
+    #[test]
+    fn test_completions() {
+        let mut session = session(20, 10);
+        run(&mut session, "B3=1");
+        run(&mut session, "B12=2");
+        run(&mut session, "C1=5");
+
+        assert_eq!(session.completions("sh", 2), (0, vec!["show".to_string()]));
+        assert_eq!(
+            session.completions("A1=SU", 5),
+            (3, vec!["SUM(".to_string()])
+        );
+        assert_eq!(
+            session.completions("A1=st", 5),
+            (3, vec!["STDEV(".to_string()])
+        );
+        let (start, candidates) = session.completions("A1=MAX(b", 8);
+        assert_eq!(start, 7);
+        assert_eq!(candidates, vec!["B3".to_string(), "B12".to_string()]);
+        let (start, candidates) = session.completions("A1=MAX(B3:B1", 12);
+        assert_eq!(start, 10);
+        assert_eq!(candidates, vec!["B12".to_string()]);
+        assert_eq!(session.completions("show C", 6).1, vec!["C1".to_string()]);
+        assert!(session.completions("A1=", 3).1.is_empty());
+        assert!(session.completions("A1=ZZZZZ", 8).1.is_empty());
+    }
+}
@@ -10,3 +10,870 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod graph_tests {
+    use cop::graph::{
+        GraphFormat, GraphOptions, GraphType, MarkerStyle, PALETTE, Series, Theme, TickFormat,
+        TrendCurve, Trendline, create_graph, create_series_graph, fit_trendline, moving_average,
+        named_palette, parse_color, parse_tick_format, parse_trendline,
+    };
+    use cop::spreadsheet::Spreadsheet;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn sample_sheet() -> Box<Spreadsheet> {
+        let mut sheet = Spreadsheet::spreadsheet_create(10, 4).unwrap();
+        for row in 1..=10 {
+            for (col, text) in [
+                ("A", row.to_string()),
+                ("B", (row * row % 7).to_string()),
+                ("C", (row * 3).to_string()),
+                ("D", format!("C{}-B{}", row, row)),
+            ] {
+                let cell = format!("{}{}", col, row);
+                let (_, r, c, formula) = sheet.is_valid_command(&cell, &text);
+                let mut status = String::new();
+                sheet.spreadsheet_set_cell_value(r, c, formula, &mut status);
+            }
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_every_type_renders_png_in_memory() {
+        let sheet = sample_sheet();
+        for graph_type in [
+            GraphType::Line,
+            GraphType::Bar,
+            GraphType::Scatter,
+            GraphType::Area,
+        ] {
+            let options = GraphOptions {
+                width: 320,
+                height: 240,
+                graph_type,
+                ..GraphOptions::default()
+            };
+            let png = create_graph(&sheet, "A1:A10", "B1:B10", options).unwrap();
+            let image = image::load_from_memory(&png).unwrap();
+            assert_eq!(
+                (image.width(), image.height()),
+                (320, 240),
+                "{:?}",
+                graph_type
+            );
+        }
+        assert!(!std::path::Path::new("temp_graph.png").exists());
+    }
+
+    #[test]
+    fn test_concurrent_renders() {
+        let sheet = Arc::new(sample_sheet());
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let sheet = Arc::clone(&sheet);
+                thread::spawn(move || {
+                    let options = GraphOptions {
+                        title: format!("Graph {}", i),
+                        width: 200 + i,
+                        height: 150,
+                        ..GraphOptions::default()
+                    };
+                    let png = create_graph(&sheet, "A1:A10", "B1:B10", options).unwrap();
+                    image::load_from_memory(&png).unwrap().width()
+                })
+            })
+            .collect();
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.join().unwrap(), 200 + i as u32);
+        }
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let sheet = sample_sheet();
+        let options = GraphOptions {
+            width: 0,
+            ..GraphOptions::default()
+        };
+        assert!(create_graph(&sheet, "A1:A10", "B1:B10", options).is_err());
+    }
+
+    #[test]
+    fn test_svg_output() {
+        let sheet = sample_sheet();
+        let options = GraphOptions {
+            title: "Sales & Costs".to_string(),
+            graph_type: GraphType::Bar,
+            format: GraphFormat::Svg,
+            ..GraphOptions::default()
+        };
+        let svg =
+            String::from_utf8(create_graph(&sheet, "A1:A10", "B1:B10", options).unwrap()).unwrap();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains(r#"width="800" height="600""#));
+        assert!(svg.contains("Sales &amp; Costs"));
+        assert!(svg.contains("<rect"));
+        assert_eq!(GraphFormat::Svg.content_type(), "image/svg+xml");
+    }
+
+    #[test]
+    fn test_pdf_output() {
+        let sheet = sample_sheet();
+        let options = GraphOptions {
+            title: "Growth (%)".to_string(),
+            width: 400,
+            height: 300,
+            graph_type: GraphType::Area,
+            format: GraphFormat::Pdf,
+            ..GraphOptions::default()
+        };
+        let pdf = create_graph(&sheet, "A1:A10", "B1:B10", options).unwrap();
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(text.ends_with("%%EOF\n"));
+        assert!(text.contains("/MediaBox [0 0 400 300]"));
+        assert!(text.contains("/BaseFont /Helvetica"));
+
+        // Every object listed in the cross-reference table starts at its recorded offset
+        let startxref = text.rfind("startxref\n").unwrap();
+        let xref: usize = text[startxref + 10..]
+            .lines()
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let table = String::from_utf8_lossy(&pdf[xref..]);
+        let offsets: Vec<usize> = table
+            .lines()
+            .filter(|line| line.ends_with(" n "))
+            .map(|line| line[..10].parse().unwrap())
+            .collect();
+        assert!(offsets.len() >= 5);
+        for (i, offset) in offsets.iter().enumerate() {
+            assert!(pdf[*offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+        }
+
+        // The page content draws the title as text and the area with a transparent fill
+        let start = pdf.windows(7).position(|w| w == b"stream\n").unwrap() + 7;
+        let end = pdf.windows(10).position(|w| w == b"\nendstream").unwrap();
+        let mut content = String::new();
+        ZlibDecoder::new(&pdf[start..end])
+            .read_to_string(&mut content)
+            .unwrap();
+        assert!(content.contains("(Growth \\(%\\)) Tj"), "{}", content);
+        assert!(content.contains("/GS1 gs"));
+        assert!(content.contains("h f"));
+        assert_eq!(GraphFormat::Pdf.content_type(), "application/pdf");
+    }
+
+    #[test]
+    fn test_several_series() {
+        let sheet = sample_sheet();
+        let revenue = Series {
+            graph_type: Some(GraphType::Bar),
+            color: parse_color("#336699"),
+            ..Series::new("C1:C10")
+        };
+        let cost = Series {
+            name: Some("Cost".to_string()),
+            graph_type: Some(GraphType::Bar),
+            ..Series::new("B1:B10")
+        };
+        let profit = Series {
+            graph_type: Some(GraphType::Line),
+            secondary_axis: true,
+            ..Series::new("D1:D10")
+        };
+        let options = GraphOptions {
+            y2_label: "Profit".to_string(),
+            header: true,
+            format: GraphFormat::Svg,
+            ..GraphOptions::default()
+        };
+        let svg = create_series_graph(&sheet, "A1:A10", &[revenue, cost, profit], options).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        // Header cells name the series without a name of their own
+        assert!(svg.contains("\n3\n</text>"), "{}", svg);
+        assert!(svg.contains("\nCost\n</text>"));
+        assert!(svg.contains("\n2\n</text>"));
+        assert!(svg.contains("\nProfit\n</text>"));
+        assert!(svg.contains("#336699"));
+        // The second series takes the second colour of the palette
+        assert!(svg.contains("#DC143C"));
+    }
+
+    #[test]
+    fn test_series_errors() {
+        let sheet = sample_sheet();
+        let options = GraphOptions::default;
+        let error = |series: &[Series]| {
+            create_series_graph(&sheet, "A1:A10", series, options())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error(&[]), "At least one Y range is required");
+        assert_eq!(
+            error(&[Series::new("B1:B10"), Series::new("C1:C9")]),
+            "X range A1:A10 has 10 values but Y range C1:C9 has 9"
+        );
+        assert_eq!(
+            error(&[Series::new("B1:B10"), Series::new("C1:Q")]),
+            "Invalid end cell in Y range C1:Q"
+        );
+        assert_eq!(
+            create_graph(&sheet, "A1:A10", "B1:B9", options())
+                .unwrap_err()
+                .to_string(),
+            "X range A1:A10 has 10 values but Y range B1:B9 has 9"
+        );
+
+        assert_eq!(
+            parse_color("#FF8000").map(|c| (c.0, c.1, c.2)),
+            Some((255, 128, 0))
+        );
+        assert_eq!(parse_color("ff8000").map(|c| c.1), Some(128));
+        assert!(parse_color("#FF80").is_none());
+        assert!(parse_color("#GG8000").is_none());
+    }
+
+    #[test]
+    fn test_whole_graph_types() {
+        let sheet = sample_sheet();
+        let cases: [(GraphType, &str, &[&str]); 8] = [
+            (GraphType::Pie, "A1:A5", &["C1:C5"]),
+            (GraphType::Donut, "", &["C1:C5"]),
+            (GraphType::Histogram, "", &["B1:B10", "C1:C6"]),
+            (GraphType::BoxPlot, "", &["B1:B10", "C1:C6", "D1:D10"]),
+            (GraphType::Heatmap, "", &["A1:D10"]),
+            (GraphType::Heatmap, "", &["B2:B2"]),
+            (GraphType::StackedBar, "A1:A10", &["B1:B10", "D1:D10"]),
+            (GraphType::GroupedBar, "A1:A10", &["B1:B10", "D1:D10"]),
+        ];
+        for (graph_type, x_range, ranges) in cases {
+            let series: Vec<Series> = ranges.iter().map(|range| Series::new(range)).collect();
+            let options = GraphOptions {
+                graph_type,
+                bins: 4,
+                ..GraphOptions::default()
+            };
+            let png = create_series_graph(&sheet, x_range, &series, options)
+                .unwrap_or_else(|e| panic!("{:?}: {}", graph_type, e));
+            let image = image::load_from_memory(&png).unwrap();
+            assert_eq!(image.width(), 800, "{:?}", graph_type);
+        }
+    }
+
+    #[test]
+    fn test_whole_graph_type_errors() {
+        let mut sheet = sample_sheet();
+        let error = |sheet: &Spreadsheet, graph_type, ranges: &[&str]| {
+            let series: Vec<Series> = ranges.iter().map(|range| Series::new(range)).collect();
+            let options = GraphOptions {
+                graph_type,
+                ..GraphOptions::default()
+            };
+            create_series_graph(sheet, "", &series, options)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(&sheet, GraphType::Pie, &["B1:B10", "C1:C10"]),
+            "A Pie graph has a single series"
+        );
+        assert_eq!(
+            error(&sheet, GraphType::Line, &["B1:B10", "C1:C9"]),
+            "Y range B1:B10 has 10 values but Y range C1:C9 has 9"
+        );
+        assert_eq!(
+            error(&sheet, GraphType::Heatmap, &["D10:A1"]),
+            "Heatmap range D10:A1 must run from its first to its last cell"
+        );
+
+        let pie = Series {
+            graph_type: Some(GraphType::Pie),
+            ..Series::new("B1:B10")
+        };
+        let options = GraphOptions::default();
+        assert_eq!(
+            create_series_graph(&sheet, "", std::slice::from_ref(&pie), options.clone())
+                .unwrap_err()
+                .to_string(),
+            "Series 1 cannot be a Pie graph; set it as the type of the whole graph"
+        );
+        let line = Series {
+            graph_type: Some(GraphType::Line),
+            ..Series::new("B1:B10")
+        };
+        let histogram = GraphOptions {
+            graph_type: GraphType::Histogram,
+            ..options.clone()
+        };
+        assert_eq!(
+            create_series_graph(&sheet, "", &[line], histogram.clone())
+                .unwrap_err()
+                .to_string(),
+            "Series of a Histogram graph cannot have a type of their own"
+        );
+        let too_many_bins = GraphOptions {
+            bins: 1001,
+            ..histogram
+        };
+        assert!(create_series_graph(&sheet, "", &[Series::new("B1:B10")], too_many_bins).is_err());
+
+        let (_, row, col, formula) = sheet.is_valid_command("C2", "0-5");
+        let mut status = String::new();
+        sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+        assert_eq!(
+            error(&sheet, GraphType::Pie, &["C1:C5"]),
+            "Pie graph values must not be negative"
+        );
+    }
+
+    #[test]
+    fn test_styling_options() {
+        let sheet = sample_sheet();
+        let options = GraphOptions {
+            format: GraphFormat::Svg,
+            width: 640,
+            height: 480,
+            palette: vec![parse_color("#112233").unwrap()],
+            line_width: 3,
+            marker: Some(MarkerStyle::Square),
+            theme: Theme::Dark,
+            title_size: 18,
+            data_labels: true,
+            tick_format: TickFormat::Decimal(1),
+            ..GraphOptions::default()
+        };
+        let svg = create_graph(&sheet, "A1:A10", "C1:C10", options).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+
+        assert!(svg.contains(r#"width="640" height="480""#));
+        // The dark background and the first colour of the palette
+        assert!(svg.contains("#202124"));
+        assert!(svg.contains("#112233"));
+        assert!(svg.contains(r#"stroke-width="3""#));
+        // Plotters writes font sizes in points, 1.24 pixels each
+        assert!(svg.contains(r#"font-size="14.516"#));
+        // Data labels are written in the tick format
+        assert!(svg.contains("\n30.0\n</text>"), "{}", svg);
+    }
+
+    #[test]
+    fn test_styling_every_type() {
+        let sheet = sample_sheet();
+        let cases: [(GraphType, &str, &[&str]); 6] = [
+            (GraphType::Scatter, "A1:A10", &["C1:C10"]),
+            (GraphType::StackedBar, "A1:A10", &["C1:C10", "D1:D10"]),
+            (GraphType::Pie, "A1:A5", &["C1:C5"]),
+            (GraphType::Histogram, "", &["C1:C10"]),
+            (GraphType::BoxPlot, "", &["C1:C10"]),
+            (GraphType::Heatmap, "", &["A1:D10"]),
+        ];
+        for (graph_type, x_range, ranges) in cases {
+            let series: Vec<Series> = ranges.iter().map(|range| Series::new(range)).collect();
+            let options = GraphOptions {
+                graph_type,
+                palette: named_palette("colorblind").unwrap().to_vec(),
+                marker: Some(MarkerStyle::Triangle),
+                x_grid: false,
+                y_grid: false,
+                data_labels: true,
+                tick_format: TickFormat::Thousands,
+                theme: Theme::Dark,
+                ..GraphOptions::default()
+            };
+            create_series_graph(&sheet, x_range, &series, options)
+                .unwrap_or_else(|e| panic!("{:?}: {}", graph_type, e));
+        }
+    }
+
+    #[test]
+    fn test_log_scale_and_axis_limits() {
+        let sheet = sample_sheet();
+        let log = GraphOptions {
+            graph_type: GraphType::Bar,
+            log_scale: true,
+            ..GraphOptions::default()
+        };
+        assert!(create_graph(&sheet, "A1:A10", "C1:C10", log.clone()).is_ok());
+        // B7 holds 49 % 7 = 0
+        assert_eq!(
+            create_graph(&sheet, "A1:A10", "B1:B10", log)
+                .unwrap_err()
+                .to_string(),
+            "A log scale needs values greater than zero"
+        );
+
+        let limited = GraphOptions {
+            x_min: Some(3.0),
+            x_max: Some(8.0),
+            y_min: Some(-10.0),
+            y_max: Some(50.0),
+            ..GraphOptions::default()
+        };
+        assert!(create_graph(&sheet, "A1:A10", "C1:C10", limited).is_ok());
+        // A minimum above the highest value leaves no room on the axis
+        let above = GraphOptions {
+            y_min: Some(100.0),
+            ..GraphOptions::default()
+        };
+        assert_eq!(
+            create_graph(&sheet, "A1:A10", "C1:C10", above)
+                .unwrap_err()
+                .to_string(),
+            "The Y axis runs from 100 to 31; its minimum must be below its maximum"
+        );
+    }
+
+    #[test]
+    fn test_option_errors() {
+        let sheet = sample_sheet();
+        let error = |options: GraphOptions| {
+            create_graph(&sheet, "A1:A10", "C1:C10", options)
+                .unwrap_err()
+                .to_string()
+        };
+        let defaults = GraphOptions::default;
+        assert_eq!(
+            error(GraphOptions {
+                width: 5000,
+                ..defaults()
+            }),
+            "Graph width and height must be at most 4000 pixels"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                palette: Vec::new(),
+                ..defaults()
+            }),
+            "The palette needs at least one colour"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                line_width: 0,
+                ..defaults()
+            }),
+            "Line width must be between 1 and 20 pixels"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                marker_size: 51,
+                ..defaults()
+            }),
+            "Marker size must be between 1 and 50 pixels"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                title_size: 0,
+                ..defaults()
+            }),
+            "Title size must be between 1 and 200 pixels"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                tick_format: TickFormat::Decimal(11),
+                ..defaults()
+            }),
+            "Tick format can have at most 10 decimal places"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                x_min: Some(f64::NAN),
+                ..defaults()
+            }),
+            "Axis limits must be finite numbers"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                x_min: Some(5.0),
+                x_max: Some(5.0),
+                ..defaults()
+            }),
+            "The X axis minimum must be below its maximum"
+        );
+        assert_eq!(
+            error(GraphOptions {
+                log_scale: true,
+                y_min: Some(0.0),
+                ..defaults()
+            }),
+            "A log scale needs a Y axis minimum greater than zero"
+        );
+    }
+
+    #[test]
+    fn test_tick_formats_and_palettes() {
+        assert_eq!(TickFormat::Auto.format(12.0), "12");
+        assert_eq!(TickFormat::Decimal(2).format(1.5), "1.50");
+        assert_eq!(TickFormat::Thousands.format(1234567.0), "1,234,567");
+        assert_eq!(TickFormat::Thousands.format(-1234.4), "-1,234");
+        assert_eq!(TickFormat::Thousands.format(999.0), "999");
+        assert_eq!(TickFormat::Percent.format(25.0), "25%");
+        assert_eq!(TickFormat::Scientific.format(1500.0), "1.5e3");
+
+        assert_eq!(parse_tick_format("Thousands"), Some(TickFormat::Thousands));
+        assert_eq!(parse_tick_format("decimal:3"), Some(TickFormat::Decimal(3)));
+        assert_eq!(parse_tick_format("decimal"), None);
+        assert_eq!(parse_tick_format("money"), None);
+
+        assert_eq!(named_palette("default"), Some(&PALETTE[..]));
+        assert_eq!(named_palette("Pastel").map(<[_]>::len), Some(8));
+        assert_eq!(named_palette("neon"), None);
+    }
+
+    /// Months in column A and sales and costs in columns B and C, under a header row
+    fn monthly_sheet() -> Box<Spreadsheet> {
+        let mut sheet = Spreadsheet::spreadsheet_create(13, 4).unwrap();
+        let months = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        let mut cells = vec![
+            ("A1".to_string(), "\"Month\"".to_string()),
+            ("B1".to_string(), "\"Sales\"".to_string()),
+            ("C1".to_string(), "\"Costs\"".to_string()),
+        ];
+        for (i, month) in months.iter().enumerate() {
+            let row = i + 2;
+            cells.push((format!("A{}", row), format!("\"{}\"", month)));
+            cells.push((format!("B{}", row), (10 + i * 5).to_string()));
+            cells.push((format!("C{}", row), (8 + i * 2).to_string()));
+        }
+        for (cell, text) in cells {
+            let (valid, r, c, formula) = sheet.is_valid_command(&cell, &text);
+            assert!(valid, "{}={}", cell, text);
+            let mut status = String::new();
+            sheet.spreadsheet_set_cell_value(r, c, formula, &mut status);
+        }
+        sheet
+    }
+
+    #[test]
+    fn test_categories_and_blocks() {
+        let sheet = monthly_sheet();
+        for graph_type in [GraphType::Bar, GraphType::Line, GraphType::StackedBar] {
+            let options = GraphOptions {
+                graph_type,
+                format: GraphFormat::Svg,
+                ..GraphOptions::default()
+            };
+            let svg = create_graph(&sheet, "A1:A13", "B1:C13", options).unwrap();
+            let svg = String::from_utf8(svg).unwrap();
+
+            // The months label the X axis and the header row names one series per column
+            for label in ["Jan", "Jun", "Dec", "Sales", "Costs"] {
+                assert!(
+                    svg.contains(&format!("\n{}\n</text>", label)),
+                    "{:?} {}",
+                    graph_type,
+                    label
+                );
+            }
+            assert!(!svg.contains("\nMonth\n</text>"));
+            assert!(svg.contains("#0000FF") && svg.contains("#DC143C"));
+        }
+
+        // An explicit name applies to a single column, and pie slices take the categories
+        let options = GraphOptions {
+            graph_type: GraphType::Pie,
+            format: GraphFormat::Svg,
+            ..GraphOptions::default()
+        };
+        let series = Series {
+            name: Some("Revenue".to_string()),
+            ..Series::new("B1:B13")
+        };
+        let svg = create_series_graph(&sheet, "A1:A13", &[series], options).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("\nMar\n</text>"));
+
+        // Without an X range the values are numbered, and blocks work for box plots too
+        for graph_type in [GraphType::Area, GraphType::BoxPlot, GraphType::Histogram] {
+            let options = GraphOptions {
+                graph_type,
+                ..GraphOptions::default()
+            };
+            create_graph(&sheet, "", "B1:C13", options)
+                .unwrap_or_else(|e| panic!("{:?}: {}", graph_type, e));
+        }
+    }
+
+    #[test]
+    fn test_range_errors() {
+        let mut sheet = monthly_sheet();
+        let error = |sheet: &Spreadsheet, x_range, y_range, graph_type| {
+            let options = GraphOptions {
+                graph_type,
+                header: true,
+                ..GraphOptions::default()
+            };
+            create_graph(sheet, x_range, y_range, options)
+                .unwrap_err()
+                .to_string()
+        };
+        let line = GraphType::Line;
+        assert_eq!(
+            error(&sheet, "A1:B13", "C1:C13", line),
+            "X range A1:B13 must be a single row or column"
+        );
+        assert_eq!(
+            error(&sheet, "A1:A13", "B13:B1", line),
+            "Y range B13:B1 must run from its first to its last cell"
+        );
+        assert_eq!(
+            error(&sheet, "", "B1:B1", line),
+            "Y range B1:B1 has no values after its header"
+        );
+        assert_eq!(
+            error(&sheet, "A1:A13", "B1:C10", line),
+            "X range A1:A13 has 12 values but Y range B1:B10 has 9"
+        );
+        assert_eq!(
+            error(&sheet, "", "B1", line),
+            "Invalid Y range B1; expected a range such as A1:A10"
+        );
+        assert_eq!(
+            error(&sheet, "", "Q1:Q5", line),
+            "Invalid start cell in Y range Q1:Q5"
+        );
+        assert_eq!(
+            error(&sheet, "A1:A13", "B1:C13", GraphType::Pie),
+            "A Pie graph has a single series, but Y range B1:C13 has 2 columns"
+        );
+
+        let (_, r, c, formula) = sheet.is_valid_command("C5", "\"n/a\"");
+        let mut status = String::new();
+        sheet.spreadsheet_set_cell_value(r, c, formula, &mut status);
+        assert_eq!(
+            error(&sheet, "A1:A13", "B1:C13", line),
+            "Y range C1:C13 holds text in C5; only its first cell can be a header"
+        );
+    }
+
+    fn points(xs: &[f64], ys: &[f64]) -> Vec<(f64, f64)> {
+        xs.iter().copied().zip(ys.iter().copied()).collect()
+    }
+
+    fn assert_coefficients(curve: &TrendCurve, expected: &[f64]) {
+        let TrendCurve::Polynomial(coefficients) = curve else {
+            panic!("{:?} should be a polynomial", curve);
+        };
+        assert_eq!(coefficients.len(), expected.len());
+        for (coefficient, expected) in coefficients.iter().zip(expected) {
+            assert!(
+                (coefficient - expected).abs() < 1e-6,
+                "{:?} should be {:?}",
+                coefficients,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_trendline_fits() {
+        // Reference values computed exactly, and matching Excel's trendlines
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let fit =
+            fit_trendline(Trendline::Linear, &points(&xs, &[2.0, 4.0, 5.0, 4.0, 5.0])).unwrap();
+        assert_coefficients(&fit.curve, &[2.2, 0.6]);
+        assert!((fit.r_squared - 0.6).abs() < 1e-9);
+
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0];
+        let ys = [3.0, 5.0, 4.0, 8.0, 12.0, 18.0, 30.0];
+        let fit = fit_trendline(Trendline::Polynomial(2), &points(&xs, &ys)).unwrap();
+        assert_coefficients(&fit.curve, &[7.142857, -3.988095, 1.011905]);
+        assert!((fit.r_squared - 0.983476).abs() < 1e-6);
+        let fit = fit_trendline(Trendline::Polynomial(3), &points(&xs, &ys)).unwrap();
+        assert_coefficients(&fit.curve, &[1.142857, 2.845238, -0.988095, 0.166667]);
+        assert!((fit.r_squared - 0.994045).abs() < 1e-6);
+
+        // An exact quadratic far from zero, where the normal equations in x itself are
+        // badly conditioned
+        let xs: Vec<f64> = (1000..1008).map(f64::from).collect();
+        let ys: Vec<f64> = xs.iter().map(|x| 2.0 * x * x - 3.0 * x + 1.0).collect();
+        let fit = fit_trendline(Trendline::Polynomial(2), &points(&xs, &ys)).unwrap();
+        for &x in &xs {
+            let expected = 2.0 * x * x - 3.0 * x + 1.0;
+            assert!((fit.curve.value_at(x) - expected).abs() < 1e-3);
+        }
+        assert!((fit.r_squared - 1.0).abs() < 1e-9);
+
+        // Exponential fits are made to the logarithms of the values
+        let xs = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let fit = fit_trendline(
+            Trendline::Exponential,
+            &points(&xs, &[2.0, 4.0, 7.0, 15.0, 30.0]),
+        )
+        .unwrap();
+        let TrendCurve::Exponential { a, b } = fit.curve else {
+            panic!("{:?} should be exponential", fit.curve);
+        };
+        assert!((a - 1.005578).abs() < 1e-6);
+        assert!((b - 0.673786).abs() < 1e-6);
+        assert!((fit.r_squared - 0.998105).abs() < 1e-6);
+        let exact: Vec<f64> = xs.iter().map(|x| 3.0 * (0.5 * x).exp()).collect();
+        let fit = fit_trendline(Trendline::Exponential, &points(&xs, &exact)).unwrap();
+        assert!((fit.curve.value_at(6.0) - 3.0 * 3f64.exp()).abs() < 1e-9);
+
+        // Constant values are fitted perfectly
+        let fit = fit_trendline(Trendline::Linear, &points(&xs, &[4.0; 5])).unwrap();
+        assert_coefficients(&fit.curve, &[4.0, 0.0]);
+        assert_eq!(fit.r_squared, 1.0);
+
+        // Curves that cannot be determined
+        assert!(
+            fit_trendline(
+                Trendline::Polynomial(3),
+                &points(&xs[..3], &[1.0, 2.0, 3.0])
+            )
+            .is_none()
+        );
+        assert!(fit_trendline(Trendline::Linear, &points(&[2.0, 2.0], &[1.0, 3.0])).is_none());
+        assert!(
+            fit_trendline(
+                Trendline::Exponential,
+                &points(&xs, &[1.0, 0.0, 2.0, 3.0, 4.0])
+            )
+            .is_none()
+        );
+        assert!(fit_trendline(Trendline::MovingAverage(2), &points(&xs, &exact)).is_none());
+
+        // Moving averages follow the points in order of x
+        let average = moving_average(&points(&[3.0, 1.0, 2.0, 4.0], &[9.0, 3.0, 6.0, 0.0]), 3);
+        assert_eq!(average, vec![(3.0, 6.0), (4.0, 5.0)]);
+        assert!(moving_average(&points(&[1.0, 2.0], &[1.0, 2.0]), 3).is_empty());
+    }
+
+    #[test]
+    fn test_trendline_equations() {
+        let curve = TrendCurve::Polynomial(vec![1.0, -2.0, 0.5]);
+        assert_eq!(curve.equation(), "y = 0.5x^2 - 2x + 1");
+        assert_eq!(curve.value_at(4.0), 1.0);
+        let curve = TrendCurve::Polynomial(vec![0.0, -1.0, 0.0, 123456.0]);
+        assert_eq!(curve.equation(), "y = 123500x^3 - x");
+        assert_eq!(TrendCurve::Polynomial(vec![0.0, 0.0]).equation(), "y = 0");
+        let curve = TrendCurve::Exponential {
+            a: 1.005578,
+            b: 0.6737856,
+        };
+        assert_eq!(curve.equation(), "y = 1.006e^(0.6738x)");
+
+        assert_eq!(parse_trendline("Linear"), Some(Trendline::Linear));
+        assert_eq!(parse_trendline("exponential"), Some(Trendline::Exponential));
+        assert_eq!(
+            parse_trendline("polynomial:3"),
+            Some(Trendline::Polynomial(3))
+        );
+        assert_eq!(
+            parse_trendline(" moving_average:4 "),
+            Some(Trendline::MovingAverage(4))
+        );
+        assert_eq!(parse_trendline("polynomial"), None);
+        assert_eq!(parse_trendline("logarithmic"), None);
+    }
+
+    #[test]
+    fn test_trendlines_drawn() {
+        let sheet = sample_sheet();
+        let svg = |graph_type: GraphType, trendline: Trendline, equation: bool| {
+            let options = GraphOptions {
+                format: GraphFormat::Svg,
+                graph_type,
+                trendline: Some(trendline),
+                trendline_equation: equation,
+                ..GraphOptions::default()
+            };
+            String::from_utf8(create_graph(&sheet, "A1:A10", "C1:C10", options).unwrap()).unwrap()
+        };
+
+        // C is 3 times A
+        let linear = svg(GraphType::Scatter, Trendline::Linear, true);
+        assert!(
+            linear.contains("Series 1: y = 3x   R² = 1.0000"),
+            "{}",
+            linear
+        );
+        assert!(linear.contains("Linear (Series 1)"));
+        assert!(!svg(GraphType::Line, Trendline::Linear, false).contains("R²"));
+        let average = svg(GraphType::Line, Trendline::MovingAverage(3), true);
+        assert!(average.contains("Moving average (Series 1)"));
+        assert!(!average.contains("R²"));
+        assert!(
+            svg(GraphType::Line, Trendline::Exponential, true).contains("Exponential (Series 1)")
+        );
+
+        // Bars have no trendline
+        let bars = svg(GraphType::Bar, Trendline::Linear, true);
+        assert!(!bars.contains("Linear"));
+        assert_eq!(bars, svg(GraphType::Bar, Trendline::Exponential, true));
+
+        // Each line and scatter series of a combined graph has its own
+        let series = [
+            Series {
+                name: Some("Squares".to_string()),
+                ..Series::new("B1:B10")
+            },
+            Series {
+                graph_type: Some(GraphType::Bar),
+                ..Series::new("D1:D10")
+            },
+            Series {
+                name: Some("Triples".to_string()),
+                graph_type: Some(GraphType::Scatter),
+                secondary_axis: true,
+                ..Series::new("C1:C10")
+            },
+        ];
+        let options = GraphOptions {
+            format: GraphFormat::Svg,
+            trendline: Some(Trendline::Polynomial(2)),
+            ..GraphOptions::default()
+        };
+        let svg = create_series_graph(&sheet, "A1:A10", &series, options).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("Polynomial (Squares)"));
+        assert!(svg.contains("Polynomial (Triples)"));
+        assert!(svg.contains("Triples: y = 3x   R² = 1.0000"));
+        assert!(!svg.contains("Polynomial (Series 2)"));
+    }
+
+    #[test]
+    fn test_trendline_errors() {
+        let sheet = sample_sheet();
+        let error = |x_range: &str, y_range: &str, trendline: Trendline| {
+            let options = GraphOptions {
+                trendline: Some(trendline),
+                ..GraphOptions::default()
+            };
+            create_graph(&sheet, x_range, y_range, options)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error("A1:A10", "C1:C10", Trendline::Polynomial(7)),
+            "A polynomial trendline must have a degree between 2 and 6"
+        );
+        assert_eq!(
+            error("A1:A10", "C1:C10", Trendline::MovingAverage(1)),
+            "A moving average trendline needs a period of at least 2 points"
+        );
+        // B7 is 0
+        assert_eq!(
+            error("A1:A10", "B1:B10", Trendline::Exponential),
+            "An exponential trendline needs values greater than zero in Series 1"
+        );
+        assert_eq!(
+            error("A1:A3", "C1:C3", Trendline::Polynomial(3)),
+            "Series 1 has too few distinct X values for its trendline"
+        );
+        assert_eq!(
+            error("A1:A3", "C1:C3", Trendline::MovingAverage(4)),
+            "A moving average over 4 points needs at least 4 values in Series 1"
+        );
+    }
+}
@@ -0,0 +1,240 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Journal Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod journal_tests {
+    use cop::journal::{self, Edit, JournalEntry, RestorePoint};
+    use cop::spreadsheet::Spreadsheet;
+    use std::io::Write;
+
+    fn value(sheet: &Spreadsheet, cell_name: &str) -> i32 {
+        let (row, col) = sheet.spreadsheet_parse_cell_name(cell_name).unwrap();
+        let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+        sheet.cells[index].as_ref().map_or(0, |cell| cell.value)
+    }
+
+    fn edit(cell: &str, old: &str, new: &str) -> Edit {
+        Edit {
+            cell: cell.to_string(),
+            old: old.to_string(),
+            new: new.to_string(),
+        }
+    }
+
+    /// Makes the edits on the sheet and returns them as journal entries one second apart.
+    fn history(sheet: &mut Spreadsheet, changes: &[(&str, &str)]) -> Vec<JournalEntry> {
+        changes
+            .iter()
+            .enumerate()
+            .map(|(i, (cell, text))| JournalEntry {
+                seq: i as u64 + 1,
+                timestamp: 1_000 * (i as u64 + 1),
+                user: String::from("ann"),
+                edit: journal::set_formula(sheet, cell, text).unwrap(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_append_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = journal::journal_path(dir.path().join("sheet.bin.gz").to_str().unwrap());
+        assert!(path.to_str().unwrap().ends_with("sheet.bin.gz.journal"));
+        assert!(journal::read_journal(&path).unwrap().is_empty());
+
+        let written = journal::append(&path, "ann", &[edit("A1", "", "5")]).unwrap();
+        assert_eq!(written[0].seq, 1);
+        let written = journal::append(
+            &path,
+            "bob",
+            &[edit("A1", "5", "6"), edit("B1", "", "A1*2")],
+        )
+        .unwrap();
+        assert_eq!(written.iter().map(|e| e.seq).collect::<Vec<_>>(), [2, 3]);
+
+        let entries = journal::read_journal(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].user, "bob");
+        assert_eq!(entries[2].edit, edit("B1", "", "A1*2"));
+        assert!(entries[0].timestamp <= entries[2].timestamp);
+        assert!(entries[0].timestamp > 1_600_000_000_000);
+
+        // One line of JSON per entry, with the edit's fields inline
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text.lines().next().unwrap().contains(r#""cell":"A1""#));
+
+        // A torn last line is ignored, and the next append starts on a fresh position
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(br#"{"seq":4,"timest"#).unwrap();
+        assert_eq!(journal::read_journal(&path).unwrap().len(), 3);
+        let written = journal::append(&path, "ann", &[edit("C1", "", "1")]).unwrap();
+        assert_eq!(written[0].seq, 4);
+        assert_eq!(journal::read_journal(&path).unwrap().len(), 4);
+
+        std::fs::write(&path, "not json\n").unwrap();
+        let error = journal::read_journal(&path).unwrap_err();
+        assert!(error.to_string().starts_with("journal line 1"));
+    }
+
+    #[test]
+    fn test_set_formula() {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        assert_eq!(
+            journal::set_formula(&mut sheet, "A1", "5"),
+            Ok(edit("A1", "", "5"))
+        );
+        assert_eq!(
+            journal::set_formula(&mut sheet, "B1", "SUM(A1:A3)"),
+            Ok(edit("B1", "", "SUM(A1:A3)"))
+        );
+        assert_eq!(journal::formula_text(&sheet, 1, 2), "SUM(A1:A3)");
+        assert_eq!(
+            journal::set_formula(&mut sheet, "A1", ""),
+            Ok(edit("A1", "5", ""))
+        );
+        assert_eq!(value(&sheet, "A1"), 0);
+        assert!(journal::set_formula(&mut sheet, "A1", "B1").is_err());
+        assert!(journal::set_formula(&mut sheet, "A1", "5++").is_err());
+        assert!(journal::set_formula(&mut sheet, "Z9", "1").is_err());
+    }
+
+    #[test]
+    fn test_roll_back_and_replay() {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        let changes = [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("B1", "SUM(A1:A2)"),
+            ("A1", "10"),
+            ("A2", ""),
+            ("C1", "B1*2"),
+        ];
+        let entries = history(&mut sheet, &changes);
+        assert_eq!(value(&sheet, "C1"), 20);
+
+        let mut at_three = sheet.clone();
+        let undone =
+            journal::roll_back(&mut at_three, &entries, RestorePoint::Position(3)).unwrap();
+        assert_eq!(
+            undone,
+            vec![
+                edit("C1", "B1*2", ""),
+                edit("A2", "", "2"),
+                edit("A1", "10", "1")
+            ]
+        );
+        assert_eq!(value(&at_three, "B1"), 3);
+        assert_eq!(journal::formula_text(&at_three, 1, 3), "");
+        assert!(at_three.undo_stack.is_empty());
+
+        let mut by_time = sheet.clone();
+        journal::roll_back(&mut by_time, &entries, RestorePoint::Time(4_500)).unwrap();
+        assert_eq!(value(&by_time, "B1"), 12);
+        assert_eq!(journal::formula_text(&by_time, 1, 3), "");
+
+        let mut empty = sheet.clone();
+        journal::roll_back(&mut empty, &entries, RestorePoint::Position(0)).unwrap();
+        assert!(empty.formulas().is_empty());
+
+        // Replaying from an empty sheet reaches the same states
+        let mut replayed = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        journal::replay(&mut replayed, &entries, 0, RestorePoint::Position(3)).unwrap();
+        assert_eq!(replayed.formulas(), at_three.formulas());
+        let edits =
+            journal::replay(&mut replayed, &entries, 3, RestorePoint::Position(u64::MAX)).unwrap();
+        assert_eq!(edits.len(), 3);
+        assert_eq!(replayed.formulas(), sheet.formulas());
+        assert_eq!(value(&replayed, "C1"), 20);
+    }
+
+    #[test]
+    fn test_journal_keeps_position() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = journal::journal_path(dir.path().join("sheet.bin.gz").to_str().unwrap());
+        journal::append(&path, "ann", &[edit("A1", "", "5")]).unwrap();
+
+        let mut open = journal::Journal::open(&path).unwrap();
+        assert_eq!(open.path(), path.as_path());
+        let written = open.append("bob", &[edit("A1", "5", "6")]).unwrap();
+        assert_eq!(written[0].seq, 2);
+        let written = open.append("bob", &[edit("B1", "", "1")]).unwrap();
+        assert_eq!(written[0].seq, 3);
+        let seqs: Vec<u64> = journal::read_journal(&path)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.seq)
+            .collect();
+        assert_eq!(seqs, [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_edit_undo_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let sheet_path = dir.path().join("sheet.bin.gz");
+        let sheet_path = sheet_path.to_str().unwrap();
+        let path = journal::journal_path(sheet_path);
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        let mut open = journal::Journal::open(&path).unwrap();
+
+        let edits = vec![journal::set_formula(&mut sheet, "A1", "1").unwrap()];
+        open.append("ann", &edits).unwrap();
+        sheet.undo_stack.clear();
+        let edits = vec![journal::set_formula(&mut sheet, "A1", "2").unwrap()];
+        open.append("ann", &edits).unwrap();
+
+        // An undo is journaled from the formulas it replaced, and the sheet saved with it
+        let mut status = String::new();
+        sheet.spreadsheet_undo(&mut status);
+        let undone = journal::edits_since(&sheet, 0);
+        assert_eq!(undone, vec![edit("A1", "2", "1")]);
+        open.append("ann", &undone).unwrap();
+        cop::saving::save_spreadsheet(&sheet, sheet_path).unwrap();
+
+        let mut saved = cop::saving::load_spreadsheet(sheet_path).unwrap();
+        let entries = journal::read_journal(&path).unwrap();
+        let restored = journal::roll_back(&mut saved, &entries, RestorePoint::Position(0)).unwrap();
+        assert_eq!(restored.len(), 3);
+        assert_eq!(journal::formula_text(&saved, 1, 1), "");
+    }
+
+    #[test]
+    fn test_paste_edits_and_mismatched_journal() {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        let mut entries = history(&mut sheet, &[("A1", "1"), ("A2", "2"), ("C2", "7")]);
+
+        // A paste changes every destination cell, and each one is an edit
+        let undo_len = sheet.undo_stack.len();
+        let (_, row, col, copy) = sheet.is_valid_command("C1", "COPY(A1:A2)");
+        let mut status = String::new();
+        sheet.spreadsheet_set_cell_value(row, col, copy, &mut status);
+        let edits = journal::edits_since(&sheet, undo_len);
+        assert_eq!(edits, vec![edit("C1", "", "1"), edit("C2", "7", "2")]);
+        for edit in edits {
+            entries.push(JournalEntry {
+                seq: entries.len() as u64 + 1,
+                timestamp: 0,
+                user: String::from("ann"),
+                edit,
+            });
+        }
+        let mut rolled_back = sheet.clone();
+        journal::roll_back(&mut rolled_back, &entries, RestorePoint::Position(3)).unwrap();
+        assert_eq!(journal::formula_text(&rolled_back, 1, 3), "");
+        assert_eq!(value(&rolled_back, "C2"), 7);
+
+        // A change missing from the journal makes it refuse to roll back
+        journal::set_formula(&mut sheet, "C2", "9").unwrap();
+        let error =
+            journal::roll_back(&mut sheet, &entries, RestorePoint::Position(3)).unwrap_err();
+        assert!(error.contains("does not match"), "{}", error);
+    }
+}
@@ -0,0 +1,515 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Loader Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use cop::cli::{CommandResult, Session};
+    use cop::downloader::{CsvExportOptions, excel_formula, to_csv_with_options};
+    use cop::journal;
+    use cop::loader::{
+        CellIssue, CsvImport, CsvOptions, CsvReader, read_csv, translate_excel_formula,
+    };
+    use cop::snapshot;
+    use cop::spreadsheet::Spreadsheet;
+
+    fn records(data: &[u8], options: &CsvOptions) -> Vec<Vec<String>> {
+        CsvReader::new(data, options)
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn value(sheet: &Spreadsheet, cell_name: &str) -> Option<i32> {
+        let (row, col) = sheet.spreadsheet_parse_cell_name(cell_name).unwrap();
+        let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+        sheet.cells[index].as_ref().map(|cell| cell.value)
+    }
+
+    fn import(data: &[u8], options: &CsvOptions) -> CsvImport {
+        read_csv(data, options).unwrap()
+    }
+
+    #[test]
+    fn test_rfc_4180_records() {
+        let options = CsvOptions::default();
+        let data = b"1,\"two, with comma\",\"say \"\"hi\"\"\"\r\n\"multi\r\nline\",,x\n\nlast";
+        assert_eq!(
+            records(data, &options),
+            vec![
+                vec!["1", "two, with comma", "say \"hi\""],
+                vec!["multi\r\nline", "", "x"],
+                vec![""],
+                vec!["last"],
+            ]
+        );
+        assert_eq!(records(b"a,b\n", &options), vec![vec!["a", "b"]]);
+        assert_eq!(records(b"a\rb", &options), vec![vec!["a"], vec!["b"]]);
+        assert!(records(b"", &options).is_empty());
+
+        let unclosed: Result<Vec<_>, _> =
+            CsvReader::new(&b"1\n\"open,2\n3"[..], &options).collect();
+        assert!(unclosed.unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn test_dialects_and_encodings() {
+        let semicolon = CsvOptions {
+            delimiter: ';',
+            quote: Some('\''),
+            ..CsvOptions::default()
+        };
+        assert_eq!(
+            records(b"1;'a;b';\"c\"", &semicolon),
+            vec![vec!["1", "a;b", "\"c\""]]
+        );
+        let tab = CsvOptions {
+            delimiter: '\t',
+            quote: None,
+            ..CsvOptions::default()
+        };
+        assert_eq!(records(b"\"1\t2\"\t3", &tab), vec![vec!["\"1", "2\"", "3"]]);
+
+        let options = CsvOptions::default();
+        assert_eq!(
+            records("\u{feff}é,1".as_bytes(), &options),
+            vec![vec!["é", "1"]]
+        );
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend("a,\"😀\"\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(records(&utf16, &options), vec![vec!["a", "😀"]]);
+        let mut utf16 = vec![0xFE, 0xFF];
+        utf16.extend("5,6".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(records(&utf16, &options), vec![vec!["5", "6"]]);
+        assert_eq!(records(b"a\xFFb", &options), vec![vec!["a\u{FFFD}b"]]);
+    }
+
+    #[test]
+    fn test_import_report() {
+        let options = CsvOptions {
+            has_header: true,
+            ..CsvOptions::default()
+        };
+        let data = b"Item,Qty,Price\nApples, 4 ,=B1*3\nPears,2.5\nPlums,99999999999,=B9,extra\n";
+        let CsvImport {
+            sheet,
+            issues,
+            ragged_rows,
+        } = import(data, &options);
+        assert_eq!((sheet.rows, sheet.cols), (3, 4));
+        assert_eq!(value(&sheet, "B1"), Some(4));
+        assert_eq!(value(&sheet, "C1"), Some(12));
+        assert_eq!(journal::formula_text(&sheet, 2, 2), "");
+        assert_eq!(ragged_rows, vec![2, 3]);
+
+        let issue = |cell: &str, text: &str, reason: &str| CellIssue {
+            cell: cell.to_string(),
+            text: text.to_string(),
+            reason: reason.to_string(),
+        };
+        assert_eq!(
+            issues,
+            vec![
+                issue("A1", "Apples", "not a number"),
+                issue("A2", "Pears", "not a number"),
+                issue("B2", "2.5", "not a whole number"),
+                issue("A3", "Plums", "not a number"),
+                issue("B3", "99999999999", "number out of range"),
+                issue("C3", "=B9", "invalid formula"),
+                issue("D3", "extra", "not a number"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let options = CsvOptions {
+            max_rows: 2,
+            max_cols: 3,
+            ..CsvOptions::default()
+        };
+        assert!(read_csv(&b"1,2,3\n4,5,6\n"[..], &options).is_ok());
+        let error = read_csv(&b"1\n2\n3\n"[..], &options).err().unwrap();
+        assert!(error.to_string().contains("more than 2 rows"));
+        let error = read_csv(&b"1\n2,3,4,5\n"[..], &options).err().unwrap();
+        assert!(error.to_string().contains("row 2"));
+        assert!(read_csv(&b""[..], &options).is_err());
+
+        let huge = CsvOptions {
+            max_rows: usize::MAX,
+            ..CsvOptions::default()
+        };
+        let data = "1\n".repeat(1000);
+        assert!(read_csv(data.as_bytes(), &huge).is_err());
+
+        let short_fields = CsvOptions {
+            max_field_len: 8,
+            ..CsvOptions::default()
+        };
+        assert!(read_csv(&b"12345678,\"1234\n567\"\n"[..], &short_fields).is_ok());
+        let data = format!("1\n\"{}", "x".repeat(100));
+        let error = read_csv(data.as_bytes(), &short_fields).err().unwrap();
+        assert_eq!(error.to_string(), "field on line 2 is longer than 8 bytes");
+
+        let small = CsvOptions {
+            max_total_len: 12,
+            ..CsvOptions::default()
+        };
+        assert!(
+            read_csv(
+                &b"1,2,3
+4,5,6
+"[..],
+                &small
+            )
+            .is_ok()
+        );
+        let error = read_csv(
+            &b"1,2,3
+4,5,6
+7
+"[..],
+            &small,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.to_string(), "CSV data is larger than 12 bytes");
+    }
+
+    #[test]
+    fn test_cli_import_options() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("data.csv");
+        std::fs::write(&path, "a\tb\n1\tx\n").unwrap();
+        let mut session = Session::new(Spreadsheet::spreadsheet_create(5, 5).unwrap());
+        let mut status = String::new();
+
+        let command = format!("import {} --delimiter tab --header", path.display());
+        let result = session.execute(&command, &mut status);
+        assert_eq!(status, "ok");
+        assert_eq!(
+            result,
+            CommandResult::Output(String::from(
+                "1 fields not imported:\n  B1 \"x\": not a number"
+            ))
+        );
+        assert_eq!((session.sheet.rows, session.sheet.cols), (1, 2));
+        assert_eq!(value(&session.sheet, "A1"), Some(1));
+
+        let command = format!("import {} --delimiter", path.display());
+        session.execute(&command, &mut status);
+        assert_eq!(status, "invalid delimiter");
+        let command = format!("import {} --bogus", path.display());
+        session.execute(&command, &mut status);
+        assert_eq!(status, "unknown option --bogus");
+    }
+
+    #[test]
+    fn test_formula_round_trip() {
+        let mut sheet = Spreadsheet::spreadsheet_create(6, 4).unwrap();
+        for (cell, formula) in [
+            ("A1", "5"),
+            ("A2", "-3"),
+            ("A3", "A1*2"),
+            ("B1", "SUM(A1:A3)"),
+            ("B2", "PMT(0.05,10,1000)"),
+            ("C1", "SORT(A1:A3,1,-1)"),
+            ("D1", "FILTER(A1:A3,A1:A3>0)"),
+            ("B4", "MAX(C1:C3)"),
+        ] {
+            journal::set_formula(&mut sheet, cell, formula).unwrap();
+        }
+
+        for header in [false, true] {
+            let options = CsvExportOptions {
+                header,
+                formulas: true,
+            };
+            let csv = to_csv_with_options(&sheet, &options).unwrap();
+            assert_eq!(csv.lines().next().unwrap() == "A,B,C,D", header);
+            assert!(csv.contains("=SUM(A1:A3)"));
+
+            let import = read_csv(
+                csv.as_bytes(),
+                &CsvOptions {
+                    has_header: header,
+                    ..CsvOptions::default()
+                },
+            )
+            .unwrap();
+            assert!(import.issues.is_empty(), "{:?}", import.issues);
+            assert!(snapshot::diff(&sheet, &import.sheet).is_empty());
+        }
+
+        let values = to_csv_with_options(&sheet, &CsvExportOptions::default()).unwrap();
+        assert!(values.starts_with("A,B,C,D\n5,12,10,5\n"), "{}", values);
+        assert!(!values.contains('='));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut sheet = Spreadsheet::spreadsheet_create(4, 3).unwrap();
+        for (cell, formula) in [
+            ("A1", "\"Month\""),
+            ("A2", "\"Jan\""),
+            ("A3", "\"Feb, late\""),
+            ("A4", "\"say \"\"hi\"\"\""),
+            ("B1", "\"Sales\""),
+            ("B2", "10"),
+            ("B3", "20"),
+            ("C2", "A2"),
+            ("C3", "B3*2"),
+        ] {
+            journal::set_formula(&mut sheet, cell, formula).unwrap();
+        }
+
+        let options = CsvExportOptions {
+            header: false,
+            formulas: true,
+        };
+        let csv = to_csv_with_options(&sheet, &options).unwrap();
+        assert!(
+            csv.starts_with("\"=\"\"Month\"\"\",\"=\"\"Sales\"\"\",\n"),
+            "{}",
+            csv
+        );
+        assert!(csv.contains(",10,=A2\n"));
+        let import = read_csv(csv.as_bytes(), &CsvOptions::default()).unwrap();
+        assert!(import.issues.is_empty(), "{:?}", import.issues);
+        assert!(snapshot::diff(&sheet, &import.sheet).is_empty());
+
+        let values = to_csv_with_options(&sheet, &CsvExportOptions::default()).unwrap();
+        assert!(
+            values.contains("\nJan,10,0\n\"Feb, late\",20,40\n"),
+            "{}",
+            values
+        );
+    }
+
+    #[test]
+    fn test_formula_import_errors() {
+        let data = b"=B1+1,=A1*2,=C2\n=A2,4,=A1/0\n";
+        let CsvImport { sheet, issues, .. } = import(data, &CsvOptions::default());
+        let reasons: Vec<(&str, &str)> = issues
+            .iter()
+            .map(|issue| (issue.cell.as_str(), issue.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![("B1", "Cycle Detected"), ("A2", "Cycle Detected")]
+        );
+        assert_eq!(journal::formula_text(&sheet, 1, 1), "B1+1");
+        assert_eq!(journal::formula_text(&sheet, 1, 2), "");
+        assert!(sheet.cells[5].as_ref().unwrap().error);
+
+        let mut session = Session::new(sheet);
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("out.csv");
+        let mut status = String::new();
+        session.execute(
+            &format!("export {} --formulas --no-header", path.display()),
+            &mut status,
+        );
+        assert_eq!(status, "ok");
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .starts_with("=B1+1,,=C2\n,4,=A1/0\n")
+        );
+        session.execute(&format!("export {} --bogus", path.display()), &mut status);
+        assert_eq!(status, "export failed: unknown option --bogus");
+    }
+
+    #[test]
+    fn test_translate_excel_formula() {
+        for (excel, ours) in [
+            ("=A1+B1", "A1+B1"),
+            ("=sum( $A$1 : A$5 )", "SUM(A1:A5)"),
+            ("=AVERAGE(A1:A5)", "AVG(A1:A5)"),
+            ("=STDEV.P(A1:B2)", "STDEV(A1:B2)"),
+            ("=_xlfn._xlws.SORT(A1:A5,1,-1)", "SORT(A1:A5,1,-1)"),
+            (
+                "=_xlfn.FORECAST.LINEAR(5,B1:B5,A1:A5)",
+                "FORECAST.LINEAR(5,B1:B5,A1:A5)",
+            ),
+            ("PMT(0.05,10,1000)", "PMT(0.05,10,1000)"),
+        ] {
+            assert_eq!(translate_excel_formula(excel).unwrap(), ours);
+        }
+        assert_eq!(
+            translate_excel_formula("=Sheet2!A1").unwrap_err(),
+            "refers to another worksheet"
+        );
+        assert!(translate_excel_formula("=IF(A1>0,\"yes\",\"no\")").is_err());
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_workbook_import() {
+        use cop::loader::from_workbook;
+        use rust_xlsxwriter::Workbook;
+
+        let mut workbook = Workbook::new();
+        let first = workbook.add_worksheet();
+        first.set_name("Data").unwrap();
+        first.write_number(0, 0, 4.0).unwrap();
+        first.write_number(1, 0, 6.0).unwrap();
+        first.write_number(2, 0, 2.5).unwrap();
+        first.write_string(0, 1, "label").unwrap();
+        first.write_formula(3, 0, "=AVERAGE($A$1:A2)").unwrap();
+        first.write_formula(3, 1, "=COUNTIF(A1:A3,\">3\")").unwrap();
+        let second = workbook.add_worksheet();
+        second.set_name("Totals").unwrap();
+        second.write_formula(1, 2, "=SUM(A1:A2)").unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("book.xlsx");
+        workbook.save(&path).unwrap();
+
+        let sheets = from_workbook(&path).unwrap();
+        let names: Vec<&str> = sheets.iter().map(|sheet| sheet.name.as_str()).collect();
+        assert_eq!(names, vec!["Data", "Totals"]);
+
+        let data = &sheets[0];
+        assert_eq!((data.sheet.rows, data.sheet.cols), (4, 2));
+        assert_eq!(journal::formula_text(&data.sheet, 4, 1), "AVG(A1:A2)");
+        assert_eq!(value(&data.sheet, "A4"), Some(5));
+        let reasons: Vec<(&str, &str)> = data
+            .issues
+            .iter()
+            .map(|issue| (issue.cell.as_str(), issue.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("B1", "not a number"),
+                ("A3", "not a whole number"),
+                ("B4", "text in formulas is not supported, value kept"),
+            ]
+        );
+
+        let totals = &sheets[1];
+        assert_eq!((totals.sheet.rows, totals.sheet.cols), (2, 3));
+        assert_eq!(journal::formula_text(&totals.sheet, 2, 3), "SUM(A1:A2)");
+    }
+
+    #[test]
+    fn test_excel_formula_round_trip() {
+        let sheet = Spreadsheet::spreadsheet_create(10, 10).unwrap();
+        for (ours, excel) in [
+            ("A1*-3", Some("=A1*-3")),
+            ("B2", Some("=B2")),
+            ("A1/-3", Some("=QUOTIENT(A1,-3)")),
+            ("AVG(A1:A5)", Some("=TRUNC(AVERAGE(A1:A5))")),
+            ("STDEV(A1:B5)", Some("=ROUND(STDEVP(A1:B5),0)")),
+            (
+                "FORECAST.LINEAR(5,B1:B5,A1:A5)",
+                Some("=FORECAST(5,B1:B5,A1:A5)"),
+            ),
+            ("PMT(0.05,10,1000)", Some("=PMT(0.05,10,1000)")),
+            ("7", None),
+            ("SLEEP(1)", None),
+            ("COPY(A1:A3)", None),
+            ("SEQUENCE(3,1)", None),
+        ] {
+            let (valid, _, _, formula) = sheet.is_valid_command("E5", ours);
+            assert!(valid, "{}", ours);
+            let translated = excel_formula(&formula);
+            assert_eq!(translated.as_deref(), excel, "{}", ours);
+            if let Some(translated) = translated {
+                assert_eq!(translate_excel_formula(&translated).unwrap(), ours);
+            }
+        }
+    }
+
+    /// Returns the XML of the first worksheet in an XLSX workbook
+    #[cfg(feature = "web")]
+    fn worksheet_xml(xlsx: Vec<u8>) -> String {
+        use std::io::Read;
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(xlsx)).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name("xl/worksheets/sheet1.xml")
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        xml
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_whole_number_export() {
+        use cop::downloader::to_xlsx;
+
+        let mut sheet = Spreadsheet::spreadsheet_create(3, 2).unwrap();
+        for (cell, formula) in [
+            ("A1", "3"),
+            ("A2", "4"),
+            ("A3", "AVG(A1:A2)"),
+            ("B1", "A2/A1"),
+            ("B2", "STDEV(A1:A2)"),
+        ] {
+            journal::set_formula(&mut sheet, cell, formula).unwrap();
+        }
+
+        // Excel computes the same truncated and rounded results, which are cached too
+        let xml = worksheet_xml(to_xlsx(&sheet).unwrap());
+        assert!(xml.contains(r#"<c r="A3"><f>TRUNC(AVERAGE(A1:A2))</f><v>3</v></c>"#));
+        assert!(xml.contains(r#"<c r="B1"><f>QUOTIENT(A2,A1)</f><v>1</v></c>"#));
+        assert!(xml.contains(r#"<c r="B2"><f>ROUND(STDEVP(A1:A2),0)</f><v>1</v></c>"#));
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_workbook_export() {
+        use cop::downloader::to_xlsx_workbook;
+        use cop::loader::from_workbook;
+
+        let mut budget = Spreadsheet::spreadsheet_create(4, 3).unwrap();
+        for (cell, formula) in [
+            ("A1", "4"),
+            ("A2", "10"),
+            ("A3", "AVG(A1:A2)"),
+            ("B1", "A2/0"),
+            ("B2", "B1+1"),
+            ("C1", "SEQUENCE(3,1)"),
+            ("A4", "123456789"),
+        ] {
+            journal::set_formula(&mut budget, cell, formula).unwrap();
+        }
+        let mut totals = Spreadsheet::spreadsheet_create(2, 2).unwrap();
+        journal::set_formula(&mut totals, "B2", "SORT(A1:A2)").unwrap();
+        journal::set_formula(&mut totals, "B1", "SUM(A1:A2)").unwrap();
+        journal::set_formula(&mut totals, "A1", "3").unwrap();
+
+        let xlsx = to_xlsx_workbook(&[("Budget", &budget), ("Totals", &totals)]).unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("book.xlsx");
+        std::fs::write(&path, xlsx).unwrap();
+
+        let sheets = from_workbook(&path).unwrap();
+        let names: Vec<&str> = sheets.iter().map(|sheet| sheet.name.as_str()).collect();
+        assert_eq!(names, vec!["Budget", "Totals"]);
+
+        let imported = &sheets[0].sheet;
+        for (row, col, formula) in [(3, 1, "AVG(A1:A2)"), (1, 2, "A2/0"), (2, 2, "B1+1")] {
+            assert_eq!(journal::formula_text(imported, row, col), formula);
+        }
+        assert!(imported.cells[1].as_ref().unwrap().error);
+        assert_eq!(journal::formula_text(imported, 1, 3), "1");
+        assert_eq!(value(imported, "C3"), Some(3));
+        assert_eq!(value(imported, "A4"), Some(123456789));
+        assert!(sheets[0].issues.is_empty(), "{:?}", sheets[0].issues);
+
+        let reasons: Vec<(&str, &str)> = sheets[1]
+            .issues
+            .iter()
+            .map(|issue| (issue.cell.as_str(), issue.reason.as_str()))
+            .collect();
+        assert_eq!(reasons, vec![("B2", "error value")]);
+        assert_eq!(journal::formula_text(&sheets[1].sheet, 1, 2), "SUM(A1:A2)");
+    }
+}
@@ -0,0 +1,157 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Plot Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod plot_tests {
+    use cop::journal;
+    use cop::plot::{PlotKind, PlotOptions, cell_sparkline, plot, sparkline, sparkline_svg};
+    use cop::spreadsheet::Spreadsheet;
+
+    /// X values 1 to 4 in A1:A4 and Y values in B1:B4
+    fn sheet() -> Box<Spreadsheet> {
+        let mut sheet = Spreadsheet::spreadsheet_create(6, 3).unwrap();
+        for (cell, formula) in [
+            ("A1", "1"),
+            ("A2", "2"),
+            ("A3", "3"),
+            ("A4", "4"),
+            ("B1", "0"),
+            ("B2", "4"),
+            ("B3", "2"),
+            ("B4", "8"),
+        ] {
+            journal::set_formula(&mut sheet, cell, formula).unwrap();
+        }
+        sheet
+    }
+
+    fn options(width: usize, height: usize, ascii: bool) -> PlotOptions {
+        PlotOptions {
+            width,
+            height,
+            ascii,
+        }
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(
+            sparkline(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0], false),
+            "▁▂▃▄▅▆▇█"
+        );
+        assert_eq!(sparkline(&[3.0, -1.0, 7.0], true), "-_^");
+        // Equal values are drawn at mid height
+        assert_eq!(sparkline(&[2.0, 2.0], false), "▄▄");
+        assert_eq!(sparkline(&[], false), "");
+    }
+
+    #[test]
+    fn test_cell_sparkline() {
+        let values: Vec<f64> = (1..=12).map(f64::from).collect();
+        assert_eq!(cell_sparkline(&values, 12, false), "▁▂▂▃▄▄▅▅▆▇▇█");
+        // Averages of 1-3, 4-6, 7-9 and 10-12
+        assert_eq!(cell_sparkline(&values, 4, false), "▁▃▆█");
+        assert_eq!(cell_sparkline(&values[..2], 4, true), "_^");
+    }
+
+    #[test]
+    fn test_sparkline_svg() {
+        let svg = sparkline_svg(&[0.0, 10.0, 5.0], 24, 14);
+        assert!(
+            svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="14""#)
+        );
+        assert!(svg.contains(r#"points="2.0,12.0 12.0,2.0 22.0,7.0""#));
+        assert!(svg.contains(r#"<circle cx="22.0" cy="7.0""#));
+        // A single value sits in the middle
+        assert!(sparkline_svg(&[3.0], 24, 14).contains(r#"points="12.0,7.0""#));
+        assert_eq!(sparkline_svg(&[], 24, 14), "");
+    }
+
+    #[test]
+    fn test_line_chart() {
+        let chart = plot(
+            &sheet(),
+            PlotKind::Line,
+            &["A1:A4", "B1:B4"],
+            &options(7, 5, true),
+        )
+        .unwrap();
+        let expected = [
+            "8 +      *",
+            "  |      *",
+            "4 +  *  *",
+            "  | * **",
+            "0 +*",
+            "  +-------",
+            "   1     4",
+            "* B1:B4",
+        ];
+        assert_eq!(chart, expected.join("\n"));
+
+        // A single range is drawn against its positions
+        let chart = plot(&sheet(), PlotKind::Line, &["B1:B4"], &options(7, 5, false)).unwrap();
+        assert!(chart.starts_with("8 ┤      ●\n"));
+        assert!(chart.contains("  └───────\n   1     4\n● B1:B4"));
+    }
+
+    #[test]
+    fn test_bar_chart() {
+        let chart = plot(&sheet(), PlotKind::Bar, &["B1:B4"], &options(8, 5, false)).unwrap();
+        assert_eq!(chart, "1 │ 0\n2 │████ 4\n3 │██ 2\n4 │████████ 8");
+
+        // Negative values run left of the zero line
+        let mut sheet = sheet();
+        journal::set_formula(&mut sheet, "B3", "0-8").unwrap();
+        let chart = plot(&sheet, PlotKind::Bar, &["B2:B4"], &options(4, 2, true)).unwrap();
+        assert_eq!(chart, "1 |  # 4\n2 |## -8\n3 |  ## 8");
+
+        // Series are told apart by their fill
+        let chart = plot(&sheet, PlotKind::Bar, &["B2:C3"], &options(4, 2, true)).unwrap();
+        let expected = [
+            "1 |   # 4",
+            "  |    0",
+            "2 |### -8",
+            "  |    0",
+            "# B2:B3  = C2:C3",
+        ];
+        assert_eq!(chart, expected.join("\n"));
+    }
+
+    #[test]
+    fn test_plot_errors() {
+        let sheet = sheet();
+        let error = plot(
+            &sheet,
+            PlotKind::Line,
+            &["A1:A4", "B1:B3"],
+            &PlotOptions::default(),
+        );
+        assert_eq!(
+            error.unwrap_err(),
+            "X range A1:A4 has 4 values but Y range B1:B3 has 3"
+        );
+        let error = plot(&sheet, PlotKind::Bar, &[], &PlotOptions::default());
+        assert_eq!(error.unwrap_err(), "At least one Y range is required");
+        let error = plot(&sheet, PlotKind::Line, &["A1"], &PlotOptions::default());
+        assert_eq!(
+            error.unwrap_err(),
+            "Invalid Y range A1; expected a range such as A1:A10"
+        );
+        let error = plot(&sheet, PlotKind::Line, &["B1:B4"], &options(1, 5, false));
+        assert!(error.is_err());
+
+        // Sparklines of ranges of different lengths
+        let lines = plot(
+            &sheet,
+            PlotKind::Sparkline,
+            &["A1:A4", "B1:B2"],
+            &PlotOptions::default(),
+        );
+        assert_eq!(lines.unwrap(), "A1:A4  ▁▃▆█  1..4\nB1:B2  ▁█  0..4");
+    }
+}
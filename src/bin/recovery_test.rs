@@ -0,0 +1,89 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Recovery Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod recovery_tests {
+    use cop::recovery::{self, UNTITLED};
+    use cop::saving;
+    use cop::spreadsheet::Spreadsheet;
+    use std::fs::{self, File, FileTimes};
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, SystemTime};
+
+    fn sheet_with(value: &str) -> Box<Spreadsheet> {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        let mut status = String::new();
+        let (_, row, col, rhs) = sheet.is_valid_command("A1", value);
+        sheet.spreadsheet_set_cell_value(row, col, rhs, &mut status);
+        sheet
+    }
+
+    fn set_modified(path: &Path, seconds_ago: u64) {
+        let time = SystemTime::now() - Duration::from_secs(seconds_ago);
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_times(FileTimes::new().set_modified(time)).unwrap();
+    }
+
+    #[test]
+    fn test_recovery_path() {
+        let database = Path::new("database");
+        assert_eq!(
+            recovery::recovery_path(Some("database/ann/budget.bin.gz"), database),
+            PathBuf::from("database/ann/budget.bin.gz.recovery")
+        );
+        assert_eq!(
+            recovery::recovery_path(None, database),
+            database.join(format!("{}.recovery", UNTITLED))
+        );
+    }
+
+    #[test]
+    fn test_find_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = dir.path();
+        let user_dir = database.join("ann");
+        fs::create_dir_all(&user_dir).unwrap();
+        assert!(recovery::find_recoveries(database).is_empty());
+
+        // Edited after the last save
+        let budget = user_dir.join("budget.bin.gz");
+        let budget = budget.to_str().unwrap();
+        saving::save_spreadsheet(&sheet_with("1"), budget).unwrap();
+        set_modified(Path::new(budget), 60);
+        let budget_recovery = recovery::recovery_path(Some(budget), database);
+        recovery::write_recovery(&sheet_with("2"), &budget_recovery).unwrap();
+        set_modified(&budget_recovery, 30);
+
+        // Saved after the last autosave
+        let notes = user_dir.join("notes.bin.gz");
+        let notes = notes.to_str().unwrap();
+        let notes_recovery = recovery::recovery_path(Some(notes), database);
+        recovery::write_recovery(&sheet_with("3"), &notes_recovery).unwrap();
+        set_modified(&notes_recovery, 60);
+        saving::save_spreadsheet(&sheet_with("4"), notes).unwrap();
+
+        // Never saved
+        let untitled = recovery::recovery_path(None, database);
+        recovery::write_recovery(&sheet_with("5"), &untitled).unwrap();
+
+        let found = recovery::find_recoveries(database);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].path, untitled);
+        assert_eq!(found[0].sheet_path, None);
+        assert_eq!(found[1].path, budget_recovery);
+        assert_eq!(found[1].sheet_path, Some(PathBuf::from(budget)));
+
+        let restored = recovery::restore(&found[1]).unwrap();
+        assert_eq!(restored.cells[0].as_ref().unwrap().value, 2);
+        assert_eq!(saving::backups(&budget_recovery.to_string_lossy()).len(), 0);
+
+        recovery::discard(&untitled).unwrap();
+        recovery::discard(&untitled).unwrap();
+        assert_eq!(recovery::find_recoveries(database).len(), 1);
+    }
+}
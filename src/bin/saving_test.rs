@@ -0,0 +1,347 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Saving Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod saving_tests {
+    use cop::saving::{self, FORMAT_VERSION, FormatError, MAGIC};
+    use cop::spreadsheet::Spreadsheet;
+    use flate2::Compression;
+    use flate2::read::GzDecoder;
+    use flate2::write::GzEncoder;
+    use std::io::{self, Read, Write};
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn gunzip(bytes: &[u8]) -> Vec<u8> {
+        let mut contents = Vec::new();
+        GzDecoder::new(bytes).read_to_end(&mut contents).unwrap();
+        contents
+    }
+
+    fn format_error(error: io::Error) -> FormatError {
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        *error
+            .into_inner()
+            .unwrap()
+            .downcast::<FormatError>()
+            .unwrap()
+    }
+
+    fn set_cell(sheet: &mut Spreadsheet, cell_name: &str, formula: &str) -> String {
+        let mut status = String::new();
+        let (valid, row, col, rhs) = sheet.is_valid_command(cell_name, formula);
+        assert!(valid, "{}={} should be a valid command", cell_name, formula);
+        sheet.spreadsheet_set_cell_value(row, col, rhs, &mut status);
+        status
+    }
+
+    fn value(sheet: &Spreadsheet, row: i16, col: i16) -> i32 {
+        let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+        sheet.cells[index].as_ref().unwrap().value
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut sheet = Spreadsheet::spreadsheet_create(10, 10).unwrap();
+        set_cell(&mut sheet, "A1", "5");
+        set_cell(&mut sheet, "B1", "A1*2");
+        set_cell(&mut sheet, "A2", "SEQUENCE(3)");
+        set_cell(&mut sheet, "C1", "PERCENTILE(A2:A4,0.5)");
+
+        let bytes = saving::to_bytes(&sheet).unwrap();
+        let contents = gunzip(&bytes);
+        assert!(contents.starts_with(MAGIC));
+        assert_eq!(contents[8..12], FORMAT_VERSION.to_le_bytes());
+
+        // Only formulas are stored
+        let full = bincode::serialize(&sheet).unwrap();
+        assert!(contents.len() < full.len() / 4);
+
+        let mut loaded = saving::from_bytes(&bytes).unwrap();
+        assert_eq!(value(&loaded, 1, 2), 10);
+        assert_eq!(value(&loaded, 4, 1), 3);
+        assert_eq!(loaded.spill_anchors.len(), 1);
+        set_cell(&mut loaded, "A1", "7");
+        assert_eq!(value(&loaded, 1, 2), 14);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sheet.bin.gz");
+        let path = path.to_str().unwrap();
+        saving::save_spreadsheet(&loaded, path).unwrap();
+        assert_eq!(value(&saving::load_spreadsheet(path).unwrap(), 1, 2), 14);
+    }
+
+    #[test]
+    fn test_rebuild_without_sleeping() {
+        // Formulas are listed so that readers come before the cells they read
+        let sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        let formulas: Vec<_> = [
+            ("C1", "B1+1"),
+            ("B1", "SLEEP(A1)"),
+            ("A1", "3"),
+            ("B2", "SLEEP(2)"),
+            ("E1", "D2*10"),
+            ("D1", "SEQUENCE(2,1,4)"),
+        ]
+        .iter()
+        .map(|(cell, formula)| {
+            let (valid, row, col, rhs) = sheet.is_valid_command(cell, formula);
+            assert!(valid, "{}={}", cell, formula);
+            (row, col, rhs)
+        })
+        .collect();
+
+        let started = std::time::Instant::now();
+        let rebuilt = Spreadsheet::spreadsheet_from_formulas(5, 5, formulas).unwrap();
+        let bytes = saving::to_bytes(&rebuilt).unwrap();
+        let loaded = saving::from_bytes(&bytes).unwrap();
+        assert!(started.elapsed().as_secs() < 1, "{:?}", started.elapsed());
+
+        for sheet in [&*rebuilt, &loaded] {
+            assert_eq!(value(sheet, 1, 2), 3);
+            assert_eq!(value(sheet, 1, 3), 4);
+            assert_eq!(value(sheet, 2, 2), 2);
+            assert_eq!(value(sheet, 2, 4), 5);
+            assert_eq!(value(sheet, 1, 5), 50);
+            assert!(sheet.undo_stack.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_legacy_file() {
+        // A 1x2 sheet in format version 0: A1 = 5, B1 = A1+1
+        let mut legacy = Vec::new();
+        for n in [1i16, 2, 0, 0] {
+            legacy.extend(n.to_le_bytes());
+        }
+        legacy.extend(2u64.to_le_bytes());
+        // A1: SingleValue(Number(5)), dependents Vector([(1, 2)])
+        legacy.push(1);
+        legacy.extend([1, 0, 1, 0, 0]);
+        legacy.extend(5i32.to_le_bytes());
+        legacy.extend(3u32.to_le_bytes());
+        legacy.extend(0u32.to_le_bytes());
+        legacy.extend(5i32.to_le_bytes());
+        legacy.extend(0u32.to_le_bytes());
+        legacy.extend(1u64.to_le_bytes());
+        legacy.extend([1, 0, 2, 0]);
+        // B1: Arithmetic { Cell(1, 1) + Number(1) }, no dependents
+        legacy.push(1);
+        legacy.extend([1, 0, 2, 0, 0]);
+        legacy.extend(6i32.to_le_bytes());
+        legacy.extend(2u32.to_le_bytes());
+        legacy.extend(1u32.to_le_bytes());
+        legacy.extend([1, 0, 1, 0]);
+        legacy.push(b'+');
+        legacy.extend(0u32.to_le_bytes());
+        legacy.extend(1i32.to_le_bytes());
+        legacy.extend(2u32.to_le_bytes());
+        // Empty undo stack
+        legacy.extend(0u64.to_le_bytes());
+
+        let mut sheet = saving::from_bytes(&gzip(&legacy)).unwrap();
+        assert_eq!((sheet.rows, sheet.cols), (1, 2));
+        assert_eq!(value(&sheet, 1, 2), 6);
+        assert_eq!(set_cell(&mut sheet, "A1", "10"), "ok");
+        assert_eq!(value(&sheet, 1, 2), 11);
+
+        // Saving writes the current version
+        let contents = gunzip(&saving::to_bytes(&sheet).unwrap());
+        assert!(contents.starts_with(MAGIC));
+    }
+
+    fn with_header(version: u32, payload: &[u8]) -> Vec<u8> {
+        let mut contents = MAGIC.to_vec();
+        contents.extend(version.to_le_bytes());
+        contents.extend(crc32fast::hash(payload).to_le_bytes());
+        contents.extend(payload);
+        gzip(&contents)
+    }
+
+    #[test]
+    fn test_version_1_file() {
+        // Version 1 stored the whole sheet, including derived values
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        set_cell(&mut sheet, "A1", "2");
+        set_cell(&mut sheet, "B1", "A1+1");
+        set_cell(&mut sheet, "C1", "SEQUENCE(2,2)");
+        sheet.view_row = 3;
+        let mut payload = bincode::serialize(&sheet).unwrap();
+        // A stale value for B1 is recomputed on load
+        let b1 = bincode::serialize(&sheet.cells[1]).unwrap();
+        let at = payload.windows(b1.len()).position(|w| w == b1).unwrap();
+        payload[at + 1 + 5..at + 1 + 9].copy_from_slice(&99i32.to_le_bytes());
+
+        let loaded = saving::from_bytes(&with_header(1, &payload)).unwrap();
+        assert_eq!(value(&loaded, 1, 2), 3);
+        assert_eq!(value(&loaded, 2, 4), 4);
+        assert_eq!(loaded.view_row, 0);
+        assert!(loaded.undo_stack.is_empty());
+        assert_eq!(loaded.formulas(), sheet.formulas());
+    }
+
+    #[test]
+    fn test_version_2_file() {
+        // Version 2 stored the size and formulas, without charts
+        let mut sheet = Spreadsheet::spreadsheet_create(4, 4).unwrap();
+        set_cell(&mut sheet, "A1", "3");
+        set_cell(&mut sheet, "B2", "A1*4");
+        let payload = bincode::serialize(&(sheet.rows, sheet.cols, sheet.formulas())).unwrap();
+
+        let loaded = saving::from_bytes(&with_header(2, &payload)).unwrap();
+        assert_eq!(value(&loaded, 2, 2), 12);
+        assert_eq!(loaded.formulas(), sheet.formulas());
+        assert!(loaded.charts.is_empty());
+    }
+
+    #[test]
+    fn test_version_3_file() {
+        // Version 3 stored charts without their trendline options, the last two fields
+        let mut sheet = Spreadsheet::spreadsheet_create(4, 4).unwrap();
+        set_cell(&mut sheet, "A1", "3");
+        let chart = serde_json::from_str(
+            r#"{"x_range":"","y_range":"A1:A4","title":"Sales","x_label":"","y_label":"","graph_type":"Line","title_size":20}"#,
+        )
+        .unwrap();
+        sheet.add_chart(chart);
+        let mut payload =
+            bincode::serialize(&(sheet.rows, sheet.cols, sheet.formulas(), &sheet.charts)).unwrap();
+        assert!(payload.ends_with(&[0, 0]));
+        payload.truncate(payload.len() - 2);
+
+        let loaded = saving::from_bytes(&with_header(3, &payload)).unwrap();
+        assert_eq!(value(&loaded, 1, 1), 3);
+        assert_eq!(loaded.charts, sheet.charts);
+        assert_eq!(loaded.charts[0].definition.title_size, Some(20));
+        assert_eq!(loaded.charts[0].definition.trendline, None);
+    }
+
+    #[test]
+    fn test_version_4_file() {
+        // Version 4 had neither text constants nor SPARKLINE, whose variants came last
+        let mut sheet = Spreadsheet::spreadsheet_create(4, 4).unwrap();
+        set_cell(&mut sheet, "A1", "3");
+        set_cell(&mut sheet, "B1", "SUM(A1:A4)");
+        let payload =
+            bincode::serialize(&(sheet.rows, sheet.cols, sheet.formulas(), &sheet.charts)).unwrap();
+
+        let loaded = saving::from_bytes(&with_header(4, &payload)).unwrap();
+        assert_eq!(value(&loaded, 1, 2), 3);
+        assert_eq!(loaded.formulas(), sheet.formulas());
+    }
+
+    #[test]
+    fn test_saved_variant_indices() {
+        // Renumbering these breaks every saved file; adding one needs a new FORMAT_VERSION
+        let index = |formula: &str| {
+            let (_, _, _, parsed) = Spreadsheet::spreadsheet_create(4, 4)
+                .unwrap()
+                .is_valid_command("A1", formula);
+            bincode::serialize(&parsed).unwrap()
+        };
+        assert_eq!(index("\"Jan\"")[..4], 6u32.to_le_bytes());
+        let sparkline = index("SPARKLINE(B1:B4)");
+        assert_eq!(sparkline[..4], 4u32.to_le_bytes());
+        assert_eq!(sparkline[4..8], 23u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let sheet = Spreadsheet::spreadsheet_create(3, 3).unwrap();
+        let contents = gunzip(&saving::to_bytes(&sheet).unwrap());
+
+        let error = saving::from_bytes(b"A,B\n1,2\n").unwrap_err();
+        assert_eq!(format_error(error), FormatError::NotASpreadsheet);
+
+        let mut newer = contents.clone();
+        newer[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let error = saving::from_bytes(&gzip(&newer)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "file format version {} is newer than this program supports (up to {})",
+                FORMAT_VERSION + 1,
+                FORMAT_VERSION
+            )
+        );
+
+        let mut flipped = contents.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 0xff;
+        let error = saving::from_bytes(&gzip(&flipped)).unwrap_err();
+        assert_eq!(format_error(error), FormatError::ChecksumMismatch);
+
+        let bytes = saving::to_bytes(&sheet).unwrap();
+        let error = saving::from_bytes(&bytes[..bytes.len() / 2]).unwrap_err();
+        assert!(matches!(format_error(error), FormatError::Corrupt(_)));
+        let error = saving::from_bytes(&gzip(&MAGIC[..])).unwrap_err();
+        assert!(matches!(format_error(error), FormatError::Corrupt(_)));
+        let error = saving::from_bytes(&gzip(&[1, 2, 3])).unwrap_err();
+        assert!(error.to_string().starts_with("file is corrupt"));
+
+        // A formula outside the sheet is rejected rather than entered
+        let mut sheet = Spreadsheet::spreadsheet_create(10, 10).unwrap();
+        set_cell(&mut sheet, "A1", "SUM(B2:J10)");
+        let mut payload = gunzip(&saving::to_bytes(&sheet).unwrap())[16..].to_vec();
+        payload[0..2].copy_from_slice(&5i16.to_le_bytes());
+        let error = saving::from_bytes(&with_header(FORMAT_VERSION, &payload)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "file is corrupt: formula in A1 is out of range"
+        );
+    }
+
+    #[test]
+    fn test_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sheet.bin.gz");
+        let path = path.to_str().unwrap();
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+
+        for n in 1..=5 {
+            set_cell(&mut sheet, "A1", &n.to_string());
+            saving::save_spreadsheet_with_backups(&sheet, path, 3).unwrap();
+        }
+        assert_eq!(value(&saving::load_spreadsheet(path).unwrap(), 1, 1), 5);
+        let backups = saving::backups(path);
+        assert_eq!(backups.len(), 3);
+        for (backup, expected) in backups.iter().zip([4, 3, 2]) {
+            let sheet = saving::load_spreadsheet(backup.to_str().unwrap()).unwrap();
+            assert_eq!(value(&sheet, 1, 1), expected);
+        }
+
+        // Nothing but the sheet and its backups is left in the directory
+        let entries = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries, 4);
+
+        // A failed save reports the error
+        let missing = dir.path().join("missing").join("sheet.bin.gz");
+        assert!(saving::save_spreadsheet(&sheet, missing.to_str().unwrap()).is_err());
+        assert_eq!(value(&saving::load_spreadsheet(path).unwrap(), 1, 1), 5);
+
+        let other = dir.path().join("other.bin.gz");
+        let other = other.to_str().unwrap();
+        saving::save_spreadsheet_with_backups(&sheet, other, 0).unwrap();
+        saving::save_spreadsheet_with_backups(&sheet, other, 0).unwrap();
+        assert!(saving::backups(other).is_empty());
+
+        // A save that cannot replace the file keeps every backup
+        std::fs::remove_file(path).unwrap();
+        std::fs::create_dir(path).unwrap();
+        std::fs::write(std::path::Path::new(path).join("inside"), "x").unwrap();
+        assert!(saving::save_spreadsheet_with_backups(&sheet, path, 3).is_err());
+        let backups = saving::backups(path);
+        assert_eq!(backups.len(), 3);
+        let oldest = saving::load_spreadsheet(backups[2].to_str().unwrap()).unwrap();
+        assert_eq!(value(&oldest, 1, 1), 2);
+    }
+}
@@ -0,0 +1,163 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Script Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod script_tests {
+    use cop::cli::Session;
+    use cop::script::{self, DEFAULT_SIZE, ScriptFailure, ScriptOptions};
+    use cop::spreadsheet::Spreadsheet;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    fn session(rows: i16, cols: i16) -> Session {
+        Session::new(Spreadsheet::spreadsheet_create(rows, cols).unwrap())
+    }
+
+    #[test]
+    fn test_options() {
+        assert!(ScriptOptions::requested(&args(
+            "spreadsheet --script a.txt"
+        )));
+        assert!(!ScriptOptions::requested(&args("spreadsheet 10 10 --tui")));
+
+        let options = ScriptOptions::from_args(&args(
+            "--script a.txt --load in.csv --out out.xlsx --print A1,B2:B3 --print C1",
+        ))
+        .unwrap();
+        assert_eq!((options.rows, options.cols), DEFAULT_SIZE);
+        assert_eq!(options.script.as_deref(), Some("a.txt"));
+        assert_eq!(options.load.as_deref(), Some("in.csv"));
+        assert_eq!(options.out.as_deref(), Some("out.xlsx"));
+        assert_eq!(options.print, vec!["A1", "B2:B3", "C1"]);
+
+        let options = ScriptOptions::from_args(&args("20 5 --script -")).unwrap();
+        assert_eq!((options.rows, options.cols), (20, 5));
+
+        assert!(ScriptOptions::from_args(&args("--script")).is_err());
+        assert!(ScriptOptions::from_args(&args("--verbose x")).is_err());
+        assert!(ScriptOptions::from_args(&args("10 --script a")).is_err());
+        assert!(ScriptOptions::from_args(&args("0 10 --script a")).is_err());
+        assert!(ScriptOptions::from_args(&args("1 2 3 --script a")).is_err());
+    }
+
+    #[test]
+    fn test_run_commands() {
+        let mut session = session(10, 10);
+        let mut messages = Vec::new();
+        let script = "# totals\nA1=5\n\nB1=A1*2\nshow B1\nC1=SUM(A1:B1)\n";
+        assert_eq!(
+            script::run_commands(&mut session, script, &mut messages),
+            Ok(())
+        );
+        let messages = String::from_utf8(messages).unwrap();
+        assert!(messages.contains("formula:    A1*2"));
+
+        let json = script::values_json(&session.sheet, &["C1".into(), "A1:B1".into()]);
+        assert_eq!(json.unwrap(), r#"{"C1":15,"A1":5,"B1":10}"#);
+
+        // The first failing command stops the script
+        let script = "A2=1\nA3=A3+1\nA4=1\n";
+        let failure = script::run_commands(&mut session, script, &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            failure,
+            ScriptFailure {
+                line: 2,
+                command: "A3=A3+1".into(),
+                status: "Cycle Detected".into(),
+            }
+        );
+        assert_eq!(failure.to_string(), "line 2: A3=A3+1: Cycle Detected");
+        let json = script::values_json(&session.sheet, &["A2:A4".into()]).unwrap();
+        assert_eq!(json, r#"{"A2":1,"A3":0,"A4":0}"#);
+
+        // Resize reports how many formulas were dropped, which is not a failure
+        let script = "resize 5 5\nq\nnonsense\n";
+        assert_eq!(
+            script::run_commands(&mut session, script, &mut Vec::new()),
+            Ok(())
+        );
+        let failure = script::run_commands(&mut session, "A1=1\nA1=", &mut Vec::new());
+        assert_eq!(failure.unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn test_values_json() {
+        let mut session = session(5, 5);
+        let script = "A1=1/0\nA2=SEQUENCE(2)\nB1=PERCENTILE(A2:A3,0.5)\n";
+        script::run_commands(&mut session, script, &mut Vec::new()).unwrap();
+        let json = script::values_json(&session.sheet, &["A1:B3".into()]).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["A1"], "ERR");
+        assert_eq!(value["A3"], 2);
+        assert_eq!(value["B1"], 1.5);
+        assert_eq!(value["B3"], 0);
+
+        assert_eq!(
+            script::values_json(&session.sheet, &["F1".into()]),
+            Err("F1".to_string())
+        );
+        assert!(script::values_json(&session.sheet, &["B2:A1".into()]).is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let commands = dir.path().join("commands.txt");
+        let input = dir.path().join("in.csv");
+        let output = dir.path().join("out.csv");
+        std::fs::write(&commands, "C1=A1+B1\n").unwrap();
+        std::fs::write(&input, "1,2,0\n3,4,0\n").unwrap();
+
+        let options = ScriptOptions::from_args(&[
+            "--load".into(),
+            input.display().to_string(),
+            "--script".into(),
+            commands.display().to_string(),
+            "--out".into(),
+            output.display().to_string(),
+        ])
+        .unwrap();
+        assert_eq!(script::run(&options), 0);
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap().lines().nth(1),
+            Some("1,2,3")
+        );
+
+        std::fs::write(&commands, "C1=A1+\n").unwrap();
+        assert_eq!(script::run(&options), script::EXIT_COMMAND_FAILED);
+
+        let mut options = options;
+        options.load = Some(dir.path().join("missing.csv").display().to_string());
+        assert_eq!(script::run(&options), script::EXIT_USAGE);
+    }
+
+    #[cfg(feature = "web")]
+    #[test]
+    fn test_run_workbook() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.xlsm");
+        let output = dir.path().join("out.csv");
+        let mut sheet = Spreadsheet::spreadsheet_create(2, 2).unwrap();
+        let (_, row, col, rhs) = sheet.is_valid_command("B1", "7");
+        sheet.spreadsheet_set_cell_value(row, col, rhs, &mut String::new());
+        std::fs::write(&input, cop::downloader::to_xlsx(&sheet).unwrap()).unwrap();
+
+        // Any workbook the importer reads is imported, not loaded as a native file
+        let options = ScriptOptions::from_args(&[
+            "--load".into(),
+            input.display().to_string(),
+            "--out".into(),
+            output.display().to_string(),
+        ])
+        .unwrap();
+        assert_eq!(script::run(&options), 0);
+        assert!(std::fs::read_to_string(&output).unwrap().contains(",7"));
+    }
+}
@@ -0,0 +1,151 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== Snapshot Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod snapshot_tests {
+    use cop::cli::Session;
+    use cop::journal;
+    use cop::snapshot::{self, CellDiff, ChangeKind};
+    use cop::spreadsheet::Spreadsheet;
+    use tempfile::TempDir;
+
+    fn run(session: &mut Session, command: &str) -> String {
+        let mut status = String::new();
+        session.execute(command, &mut status);
+        status
+    }
+
+    fn output(session: &mut Session, command: &str) -> String {
+        let mut status = String::new();
+        match session.execute(command, &mut status) {
+            cop::cli::CommandResult::Output(text) => text,
+            result => panic!("{:?} ({})", result, status),
+        }
+    }
+
+    #[test]
+    fn test_create_list_and_open() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("budget.bin.gz");
+        let path = path.to_str().unwrap();
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+        journal::set_formula(&mut sheet, "A1", "5").unwrap();
+        let edit = journal::set_formula(&mut sheet, "B1", "A1*2").unwrap();
+        journal::append(&journal::journal_path(path), "ann", &[edit]).unwrap();
+
+        assert!(snapshot::list(path).unwrap().is_empty());
+        let first = snapshot::create(&sheet, path, "Q3 close").unwrap();
+        assert_eq!((first.id, first.journal_position), (1, 1));
+        journal::set_formula(&mut sheet, "A1", "7").unwrap();
+        let second = snapshot::create(&sheet, path, "before import").unwrap();
+        assert_eq!(second.id, 2);
+
+        assert!(snapshot::create(&sheet, path, "Q3 close").is_err());
+        assert!(snapshot::create(&sheet, path, "12").is_err());
+        assert!(snapshot::create(&sheet, path, " ").is_err());
+
+        let snapshots = snapshot::list(path).unwrap();
+        assert_eq!(snapshots, vec![first.clone(), second]);
+        assert_eq!(snapshot::find(&snapshots, "Q3 close"), Some(&first));
+        assert_eq!(snapshot::find(&snapshots, "1"), Some(&first));
+        assert_eq!(snapshot::find(&snapshots, "Q4"), None);
+
+        let opened = snapshot::open(path, &first).unwrap();
+        assert_eq!(journal::formula_text(&opened, 1, 2), "A1*2");
+        assert_eq!(opened.cells[1].as_ref().unwrap().value, 10);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = Spreadsheet::spreadsheet_create(3, 3).unwrap();
+        journal::set_formula(&mut old, "A1", "5").unwrap();
+        journal::set_formula(&mut old, "B1", "A1*2").unwrap();
+        journal::set_formula(&mut old, "C3", "1").unwrap();
+        journal::set_formula(&mut old, "A2", "SUM(A1:B1)").unwrap();
+
+        let (mut new, _) = old.spreadsheet_resize(3, 4).unwrap();
+        journal::set_formula(&mut new, "A1", "6").unwrap();
+        journal::set_formula(&mut new, "C3", "").unwrap();
+        journal::set_formula(&mut new, "D1", "SEQUENCE(2,1)").unwrap();
+
+        let diffs = snapshot::diff(&old, &new);
+        let changed =
+            |cell: &str, old_formula: &str, new_formula: &str, old_value: &str, new_value: &str| {
+                CellDiff {
+                    cell: cell.to_string(),
+                    kind: ChangeKind::Changed,
+                    old_formula: old_formula.to_string(),
+                    new_formula: new_formula.to_string(),
+                    old_value: old_value.to_string(),
+                    new_value: new_value.to_string(),
+                }
+            };
+        assert_eq!(
+            diffs,
+            vec![
+                changed("A1", "5", "6", "5", "6"),
+                changed("B1", "A1*2", "A1*2", "10", "12"),
+                CellDiff {
+                    kind: ChangeKind::Added,
+                    ..changed("D1", "", "SEQUENCE(2,1)", "", "1")
+                },
+                changed("A2", "SUM(A1:B1)", "SUM(A1:B1)", "15", "18"),
+                CellDiff {
+                    kind: ChangeKind::Added,
+                    ..changed("D2", "", "", "", "2")
+                },
+                CellDiff {
+                    kind: ChangeKind::Removed,
+                    ..changed("C3", "1", "", "1", "")
+                },
+            ]
+        );
+        assert_eq!(
+            snapshot::format_diff(&diffs[..3]),
+            "~ A1: 5 = 5 -> 6 = 6\n~ B1: A1*2 = 10 -> A1*2 = 12\n+ D1: SEQUENCE(2,1) = 1"
+        );
+        assert_eq!(snapshot::format_diff(&[]), "no differences");
+        assert!(snapshot::diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_cli_commands() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("budget.bin.gz");
+        let path = path.to_str().unwrap();
+        let mut session = Session::new(Spreadsheet::spreadsheet_create(5, 5).unwrap());
+
+        run(&mut session, "A1=5");
+        assert_eq!(
+            run(&mut session, "snapshot Q3 close"),
+            "save the sheet first"
+        );
+        assert_eq!(run(&mut session, &format!("save {}", path)), "ok");
+        assert_eq!(run(&mut session, "snapshot Q3 close"), "ok, snapshot 1");
+        assert!(output(&mut session, "snapshots").ends_with("Q3 close"));
+
+        run(&mut session, "A1=8");
+        assert_eq!(output(&mut session, "diff 1"), "~ A1: 5 = 5 -> 8 = 8");
+
+        assert_eq!(run(&mut session, "open_snapshot Q3 close"), "ok");
+        assert_eq!(session.sheet.cells[0].as_ref().unwrap().value, 5);
+        assert_eq!(run(&mut session, "A1=9"), "snapshot is read-only");
+        assert_eq!(run(&mut session, "clear A1"), "snapshot is read-only");
+        assert_eq!(output(&mut session, "diff 1"), "~ A1: 5 = 5 -> 8 = 8");
+        assert_eq!(run(&mut session, "close_snapshot"), "ok");
+        assert_eq!(session.sheet.cells[0].as_ref().unwrap().value, 8);
+        assert_eq!(run(&mut session, "close_snapshot"), "no snapshot open");
+
+        assert_eq!(run(&mut session, "snapshot later"), "ok, snapshot 2");
+        assert_eq!(output(&mut session, "diff 2 1"), "~ A1: 8 = 8 -> 5 = 5");
+        assert_eq!(run(&mut session, "restore_snapshot Q4"), "no snapshot Q4");
+        assert_eq!(run(&mut session, "restore_snapshot 1"), "ok");
+        assert_eq!(session.sheet.cells[0].as_ref().unwrap().value, 5);
+        assert_eq!(output(&mut session, "diff 1"), "no differences");
+    }
+}
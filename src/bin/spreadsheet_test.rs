@@ -4,8 +4,9 @@ fn main() {}
 
 #[cfg(test)]
 mod spreadsheet_tests {
+    use cop::cell::{CellError, Spill};
     use cop::cell::{cell_contains, cell_dep_insert};
-    use cop::spreadsheet::{FunctionName, Operand, ParsedRHS, Spreadsheet};
+    use cop::spreadsheet::{Argument, Comparison, FunctionName, Operand, ParsedRHS, Spreadsheet};
     // use std::collections::BTreeSet;
     use std::time::Instant;
 
@@ -2034,4 +2035,548 @@ mod spreadsheet_tests {
         assert!(!sheet.cells[a6_idx].as_ref().unwrap().error);
         assert_eq!(sheet.cells[a6_idx].as_ref().unwrap().value, 0);
     }
+
+    fn set_cell(sheet: &mut Spreadsheet, cell_name: &str, formula: &str) -> String {
+        let mut status = String::new();
+        let (valid, row, col, rhs) = sheet.is_valid_command(cell_name, formula);
+        assert!(valid, "{}={} should be a valid command", cell_name, formula);
+        sheet.spreadsheet_set_cell_value(row, col, rhs, &mut status);
+        status
+    }
+
+    fn cell_at<'a>(sheet: &'a Spreadsheet, cell_name: &str) -> &'a cop::cell::Cell {
+        let (row, col) = sheet.spreadsheet_parse_cell_name(cell_name).unwrap();
+        let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+        sheet.cells[index].as_ref().unwrap()
+    }
+
+    fn column_values(
+        sheet: &Spreadsheet,
+        col: &str,
+        rows: std::ops::RangeInclusive<i16>,
+    ) -> Vec<i32> {
+        rows.map(|r| cell_at(sheet, &format!("{}{}", col, r)).value)
+            .collect()
+    }
+
+    #[test]
+    fn test_array_formula_validation() {
+        let sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+
+        let (valid, row, col, expr) = sheet.is_valid_command("C1", "SORT(A1:A5)");
+        assert!(valid);
+        assert_eq!((row, col), (1, 3));
+        assert_eq!(
+            expr,
+            ParsedRHS::FunctionCall {
+                name: FunctionName::Sort,
+                args: vec![Argument::Range(Operand::Cell(1, 1), Operand::Cell(5, 1))],
+            }
+        );
+
+        let (valid, _, _, expr) = sheet.is_valid_command("E1", "FILTER(A1:B5, B1:B5>=10)");
+        assert!(valid);
+        assert_eq!(
+            expr,
+            ParsedRHS::FunctionCall {
+                name: FunctionName::Filter,
+                args: vec![
+                    Argument::Range(Operand::Cell(1, 1), Operand::Cell(5, 2)),
+                    Argument::Condition {
+                        range: (Operand::Cell(1, 2), Operand::Cell(5, 2)),
+                        operator: Comparison::GreaterEqual,
+                        value: Operand::Number(10),
+                    },
+                ],
+            }
+        );
+        assert!(expr.is_array());
+
+        assert!(sheet.is_valid_command("A1", "SEQUENCE(3,2,10,-5)").0);
+        assert!(sheet.is_valid_command("A1", "sort(A1:B5,2,-1)").0);
+        assert!(sheet.is_valid_command("A1", "UNIQUE(B1:B10)").0);
+
+        // Malformed or mismatched argument lists
+        assert!(!sheet.is_valid_command("A1", "SORT()").0);
+        assert!(!sheet.is_valid_command("A1", "SORT(5)").0);
+        assert!(!sheet.is_valid_command("A1", "SORT(A1:A5,1,1,1)").0);
+        assert!(!sheet.is_valid_command("A1", "UNIQUE(A1:A5,B1:B5)").0);
+        assert!(!sheet.is_valid_command("A1", "FILTER(A1:A5)").0);
+        assert!(!sheet.is_valid_command("A1", "FILTER(A1:A5,B1:B4>1)").0); // Height mismatch
+        assert!(!sheet.is_valid_command("A1", "FILTER(A1:A5,B1:C5>1)").0); // Two-column condition
+        assert!(!sheet.is_valid_command("A1", "FILTER(A1:A5,B1:B5=>1)").0);
+        assert!(!sheet.is_valid_command("A1", "SEQUENCE(A1:A5)").0);
+        assert!(!sheet.is_valid_command("A1", "SEQUENCE(1,2,3,4,5)").0);
+        assert!(!sheet.is_valid_command("A1", "SORT(A5:A1)").0);
+        assert!(!sheet.is_valid_command("A1", "SORT(A1:A50)").0); // Out of bounds
+    }
+
+    #[test]
+    fn test_dynamic_array_functions() {
+        let mut sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+        for (i, value) in [30, 10, 20, 10, 40].iter().enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &value.to_string());
+            set_cell(&mut sheet, &format!("B{}", i + 1), &(i + 1).to_string());
+        }
+
+        // SORT spills down from its anchor
+        assert_eq!(set_cell(&mut sheet, "D1", "SORT(A1:A5)"), "ok");
+        assert_eq!(column_values(&sheet, "D", 1..=5), vec![10, 10, 20, 30, 40]);
+        assert_eq!(
+            cell_at(&sheet, "D1").spill,
+            Spill::Anchor { rows: 5, cols: 1 }
+        );
+        assert_eq!(cell_at(&sheet, "D3").spill, Spill::Member(1, 4));
+        assert_eq!(cell_at(&sheet, "D3").formula, ParsedRHS::None);
+
+        // Descending sort of a two-column block by its first column
+        set_cell(&mut sheet, "E1", "SORT(A1:B5,1,-1)");
+        assert_eq!(column_values(&sheet, "E", 1..=5), vec![40, 30, 20, 10, 10]);
+        assert_eq!(column_values(&sheet, "F", 1..=5), vec![5, 1, 3, 2, 4]);
+
+        // UNIQUE keeps the first occurrence of each value
+        set_cell(&mut sheet, "G1", "UNIQUE(A1:A5)");
+        assert_eq!(column_values(&sheet, "G", 1..=4), vec![30, 10, 20, 40]);
+        assert_eq!(
+            cell_at(&sheet, "G1").spill,
+            Spill::Anchor { rows: 4, cols: 1 }
+        );
+
+        // FILTER keeps the rows whose condition holds
+        set_cell(&mut sheet, "H1", "FILTER(A1:A5,B1:B5>2)");
+        assert_eq!(column_values(&sheet, "H", 1..=3), vec![20, 10, 40]);
+
+        // SEQUENCE(rows, cols, start, step) fills row by row
+        set_cell(&mut sheet, "A10", "SEQUENCE(2,3,10,5)");
+        assert_eq!(column_values(&sheet, "A", 10..=11), vec![10, 25]);
+        assert_eq!(column_values(&sheet, "B", 10..=11), vec![15, 30]);
+        assert_eq!(column_values(&sheet, "C", 10..=11), vec![20, 35]);
+
+        // FILTER without any matching row is an error
+        set_cell(&mut sheet, "I1", "FILTER(A1:A5,B1:B5>100)");
+        assert!(cell_at(&sheet, "I1").error);
+        assert_eq!(cell_at(&sheet, "I1").error_text(), Some("ERR"));
+
+        // A SEQUENCE larger than the sheet cannot be produced
+        set_cell(&mut sheet, "J1", "SEQUENCE(25)");
+        assert!(cell_at(&sheet, "J1").error);
+    }
+
+    #[test]
+    fn test_spill_region_updates() {
+        let mut sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+        for (i, value) in [3, 1, 2].iter().enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &value.to_string());
+        }
+        set_cell(&mut sheet, "C1", "SORT(A1:A3)");
+        assert_eq!(column_values(&sheet, "C", 1..=3), vec![1, 2, 3]);
+
+        // Formulas can read spilled cells and follow changes to the source
+        set_cell(&mut sheet, "E1", "C3*10");
+        set_cell(&mut sheet, "E2", "SUM(C1:C3)");
+        assert_eq!(cell_at(&sheet, "E1").value, 30);
+        set_cell(&mut sheet, "A1", "9");
+        assert_eq!(column_values(&sheet, "C", 1..=3), vec![1, 2, 9]);
+        assert_eq!(cell_at(&sheet, "E1").value, 90);
+        assert_eq!(cell_at(&sheet, "E2").value, 12);
+
+        // A region that grows recalculates formulas reading the newly spilled cells
+        set_cell(&mut sheet, "G1", "UNIQUE(A1:A3)");
+        set_cell(&mut sheet, "H1", "G3+1");
+        set_cell(&mut sheet, "A2", "9");
+        assert_eq!(
+            cell_at(&sheet, "G1").spill,
+            Spill::Anchor { rows: 2, cols: 1 }
+        );
+        assert_eq!(cell_at(&sheet, "H1").value, 1);
+        set_cell(&mut sheet, "A2", "5");
+        assert_eq!(column_values(&sheet, "G", 1..=3), vec![9, 5, 2]);
+        assert_eq!(cell_at(&sheet, "H1").value, 3);
+
+        // Replacing the array formula releases the spilled cells
+        set_cell(&mut sheet, "G1", "7");
+        assert_eq!(cell_at(&sheet, "G2").value, 0);
+        assert_eq!(cell_at(&sheet, "G3").spill, Spill::None);
+        assert_eq!(cell_at(&sheet, "H1").value, 1);
+        assert!(!sheet.spill_anchors.contains(&(1, 7)));
+    }
+
+    #[test]
+    fn test_spill_blocked() {
+        let mut sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+        for (i, value) in [3, 1, 2].iter().enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &value.to_string());
+        }
+
+        // A non-empty cell in the way blocks the spill
+        set_cell(&mut sheet, "C2", "5");
+        set_cell(&mut sheet, "C1", "SORT(A1:A3)");
+        let anchor = cell_at(&sheet, "C1");
+        assert!(anchor.error);
+        assert_eq!(anchor.error_text(), Some("#SPILL!"));
+        assert_eq!(anchor.spill, Spill::Blocked { rows: 3, cols: 1 });
+        assert_eq!(cell_at(&sheet, "C3").spill, Spill::None);
+
+        // Emptying the obstructing cell lets the formula spill again
+        let mut status = String::new();
+        sheet.spreadsheet_set_cell_value(2, 3, ParsedRHS::None, &mut status);
+        assert_eq!(status, "ok");
+        assert!(!cell_at(&sheet, "C1").error);
+        assert_eq!(column_values(&sheet, "C", 1..=3), vec![1, 2, 3]);
+
+        // Typing into a spilled cell blocks the anchor and empties the rest of the region
+        sheet.undo_stack.clear();
+        set_cell(&mut sheet, "C3", "4");
+        assert_eq!(cell_at(&sheet, "C1").error_text(), Some("#SPILL!"));
+        assert_eq!(cell_at(&sheet, "C2").value, 0);
+        assert_eq!(cell_at(&sheet, "C3").value, 4);
+
+        // Undo restores the empty cell and the spill
+        sheet.spreadsheet_undo(&mut status);
+        assert!(!cell_at(&sheet, "C1").error);
+        assert_eq!(column_values(&sheet, "C", 1..=3), vec![1, 2, 3]);
+        assert_eq!(cell_at(&sheet, "C3").spill, Spill::Member(1, 3));
+
+        // Regions may not run off the sheet or overlap their own source
+        set_cell(&mut sheet, "A19", "SEQUENCE(3)");
+        assert_eq!(cell_at(&sheet, "A19").error_text(), Some("#SPILL!"));
+        set_cell(&mut sheet, "E5", "1");
+        set_cell(&mut sheet, "E4", "SORT(E5:E6)");
+        assert_eq!(cell_at(&sheet, "E4").error_text(), Some("#SPILL!"));
+
+        // A spilled cell cannot be claimed by a second array formula
+        set_cell(&mut sheet, "G1", "SEQUENCE(3)");
+        set_cell(&mut sheet, "H1", "SEQUENCE(1,2)");
+        set_cell(&mut sheet, "F3", "SEQUENCE(1,2)");
+        assert_eq!(cell_at(&sheet, "F3").error_text(), Some("#SPILL!"));
+        assert!(!cell_at(&sheet, "H1").error);
+
+        // Reading the spill region from inside the source is a cycle
+        assert_eq!(set_cell(&mut sheet, "A1", "G2"), "ok");
+        assert_eq!(set_cell(&mut sheet, "J1", "SORT(A1:A3)"), "ok");
+        set_cell(&mut sheet, "A5", "J2");
+        assert_eq!(set_cell(&mut sheet, "J1", "SORT(A1:A5)"), "Cycle Detected");
+    }
+
+    fn assert_close(sheet: &Spreadsheet, cell_name: &str, expected: f64) {
+        let cell = cell_at(sheet, cell_name);
+        assert!(!cell.error, "{} should not be in error", cell_name);
+        assert!(
+            (cell.number() - expected).abs() < 0.005,
+            "{} is {}, expected {}",
+            cell_name,
+            cell.number(),
+            expected
+        );
+        assert_eq!(cell.value, expected.round() as i32);
+    }
+
+    #[test]
+    fn test_analysis_formula_validation() {
+        let sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+
+        let (valid, _, _, expr) = sheet.is_valid_command("C1", "FORECAST.LINEAR(6,B1:B5,A1:A5)");
+        assert!(valid);
+        assert_eq!(
+            expr,
+            ParsedRHS::FunctionCall {
+                name: FunctionName::ForecastLinear,
+                args: vec![
+                    Argument::Value(Operand::Number(6)),
+                    Argument::Range(Operand::Cell(1, 2), Operand::Cell(5, 2)),
+                    Argument::Range(Operand::Cell(1, 1), Operand::Cell(5, 1)),
+                ],
+            }
+        );
+        assert!(!expr.is_array());
+
+        let (valid, _, _, expr) = sheet.is_valid_command("C1", "PMT(0.005,10,A1)");
+        assert!(valid);
+        assert_eq!(
+            expr,
+            ParsedRHS::FunctionCall {
+                name: FunctionName::Pmt,
+                args: vec![
+                    Argument::Decimal(0.005),
+                    Argument::Value(Operand::Number(10)),
+                    Argument::Value(Operand::Cell(1, 1)),
+                ],
+            }
+        );
+
+        assert!(sheet.is_valid_command("C1", "PERCENTILE(A1:B5,.25)").0);
+        assert!(sheet.is_valid_command("C1", "QUARTILE(A1:A5,3)").0);
+        assert!(sheet.is_valid_command("C1", "RANK(A1,A1:A5,1)").0);
+        assert!(sheet.is_valid_command("C1", "CORREL(A1:A5,B2:B6)").0);
+        assert!(sheet.is_valid_command("C1", "COVAR(A1:B2,C1:C4)").0); // Same number of cells
+        assert!(sheet.is_valid_command("C1", "NPV(0.1,A1:A5,100,B1:B3)").0);
+        assert!(sheet.is_valid_command("C1", "IRR(A1:A5,-0.5)").0);
+        assert!(sheet.is_valid_command("C1", "FV(0.005,10,-200,-500,1)").0);
+        assert!(sheet.is_valid_command("C1", "PV(A1,B1,C1)").0);
+        assert!(sheet.is_valid_command("C1", "SPARKLINE(A1:E1)").0);
+
+        assert!(!sheet.is_valid_command("C1", "PERCENTILE(A1:A5)").0);
+        assert!(!sheet.is_valid_command("C1", "PERCENTILE(0.5,A1:A5)").0);
+        assert!(!sheet.is_valid_command("C1", "RANK(A1:A5,A1)").0);
+        assert!(!sheet.is_valid_command("C1", "SLOPE(A1:A5,B1:B4)").0);
+        assert!(
+            !sheet
+                .is_valid_command("C1", "FORECAST.LINEAR(A1:A5,B1:B5)")
+                .0
+        );
+        assert!(!sheet.is_valid_command("C1", "NPV(0.1)").0);
+        assert!(!sheet.is_valid_command("C1", "IRR(100)").0);
+        assert!(!sheet.is_valid_command("C1", "PMT(0.1,10)").0);
+        assert!(!sheet.is_valid_command("C1", "PMT(0.1,10,100,0,0,1)").0);
+        assert!(!sheet.is_valid_command("C1", "PMT(0.1.2,10,100)").0);
+        assert!(!sheet.is_valid_command("C1", "SPARKLINE(A1)").0);
+        assert!(!sheet.is_valid_command("C1", "SPARKLINE(A1:A5,B1:B5)").0);
+        assert!(
+            !sheet
+                .is_valid_command("C1", "FORECAST.SOMETHING(1,A1:A2,B1:B2)")
+                .0
+        );
+    }
+
+    #[test]
+    fn test_statistical_functions() {
+        let mut sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+        let xs = [1, 2, 3, 4, 5];
+        let ys = [2, 4, 5, 4, 5];
+        for (i, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &x.to_string());
+            set_cell(&mut sheet, &format!("B{}", i + 1), &y.to_string());
+        }
+
+        // Reference values computed in Excel
+        set_cell(&mut sheet, "D1", "SLOPE(B1:B5,A1:A5)");
+        assert_close(&sheet, "D1", 0.6);
+        assert_eq!(cell_at(&sheet, "D1").decimal, Some(0.6));
+        assert_eq!(cell_at(&sheet, "D1").formatted_value(), "0.6");
+        set_cell(&mut sheet, "D2", "INTERCEPT(B1:B5,A1:A5)");
+        assert_close(&sheet, "D2", 2.2);
+        set_cell(&mut sheet, "D3", "CORREL(A1:A5,B1:B5)");
+        assert_close(&sheet, "D3", 0.774597);
+        set_cell(&mut sheet, "D4", "COVAR(A1:A5,B1:B5)");
+        assert_close(&sheet, "D4", 1.2);
+        set_cell(&mut sheet, "D5", "FORECAST.LINEAR(6,B1:B5,A1:A5)");
+        assert_close(&sheet, "D5", 5.8);
+        set_cell(&mut sheet, "D6", "FORECAST(A5,B1:B5,A1:A5)");
+        assert_close(&sheet, "D6", 5.2);
+
+        // PERCENTILE({1,3,2,4}, 0.3) = 1.9 and QUARTILE({1,2,4,7,8,9,10,12}, 1) = 3.5
+        for (i, value) in [1, 3, 2, 4].iter().enumerate() {
+            set_cell(&mut sheet, &format!("F{}", i + 1), &value.to_string());
+        }
+        for (i, value) in [1, 2, 4, 7, 8, 9, 10, 12].iter().enumerate() {
+            set_cell(&mut sheet, &format!("G{}", i + 1), &value.to_string());
+        }
+        set_cell(&mut sheet, "H1", "PERCENTILE(F1:F4,0.3)");
+        assert_close(&sheet, "H1", 1.9);
+        set_cell(&mut sheet, "H2", "PERCENTILE(F1:F4,1)");
+        assert_close(&sheet, "H2", 4.0);
+        assert_eq!(cell_at(&sheet, "H2").decimal, None);
+        set_cell(&mut sheet, "H3", "QUARTILE(G1:G8,1)");
+        assert_close(&sheet, "H3", 3.5);
+        set_cell(&mut sheet, "H4", "QUARTILE(G1:G8,3)");
+        assert_close(&sheet, "H4", 9.25);
+
+        // RANK({7,3,3,1,2}): descending by default, ascending for a non-zero order
+        for (i, value) in [7, 3, 3, 1, 2].iter().enumerate() {
+            set_cell(&mut sheet, &format!("I{}", i + 1), &value.to_string());
+        }
+        set_cell(&mut sheet, "J1", "RANK(7,I1:I5)");
+        assert_close(&sheet, "J1", 1.0);
+        set_cell(&mut sheet, "J2", "RANK(I2,I1:I5,1)");
+        assert_close(&sheet, "J2", 3.0);
+        set_cell(&mut sheet, "J3", "RANK(2,I1:I5,0)");
+        assert_close(&sheet, "J3", 4.0);
+
+        // Results follow changes to their inputs
+        set_cell(&mut sheet, "B5", "10");
+        assert_close(&sheet, "D1", 1.6);
+        assert_close(&sheet, "D5", 9.8);
+
+        // Decimal results keep their precision when read by another analysis function
+        set_cell(&mut sheet, "E1", "PERCENTILE(D1:D2,0)");
+        assert_close(&sheet, "E1", 0.2);
+
+        // Undefined results are errors
+        set_cell(&mut sheet, "E2", "SLOPE(B1:B5,C1:C5)"); // Constant x values
+        assert!(cell_at(&sheet, "E2").error);
+        set_cell(&mut sheet, "E3", "PERCENTILE(F1:F4,1.5)");
+        assert!(cell_at(&sheet, "E3").error);
+        set_cell(&mut sheet, "E4", "RANK(5,I1:I5)"); // Not in the list
+        assert!(cell_at(&sheet, "E4").error);
+        set_cell(&mut sheet, "E5", "QUARTILE(G1:G8,5)");
+        assert!(cell_at(&sheet, "E5").error);
+
+        // Errors propagate from the cells read
+        set_cell(&mut sheet, "A6", "1/0");
+        set_cell(&mut sheet, "E6", "CORREL(A2:A6,B1:B5)");
+        assert!(cell_at(&sheet, "E6").error);
+        set_cell(&mut sheet, "A6", "6");
+        assert!(!cell_at(&sheet, "E6").error);
+    }
+
+    #[test]
+    fn test_financial_functions() {
+        let mut sheet = Spreadsheet::spreadsheet_create(20, 10).unwrap();
+
+        // NPV(10%, -10000, 3000, 4200, 6800) = 1188.44
+        for (i, value) in [-10000, 3000, 4200, 6800].iter().enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &value.to_string());
+        }
+        set_cell(&mut sheet, "C1", "NPV(0.1,A1:A4)");
+        assert_close(&sheet, "C1", 1188.44);
+        set_cell(&mut sheet, "C2", "NPV(0.1,A1,A2:A3,6800)");
+        assert_close(&sheet, "C2", 1188.44);
+
+        // IRR of -70000 followed by five years of income is 8.66%, and -2.12% after four
+        for (i, value) in [-70000, 12000, 15000, 18000, 21000, 26000]
+            .iter()
+            .enumerate()
+        {
+            set_cell(&mut sheet, &format!("B{}", i + 1), &value.to_string());
+        }
+        set_cell(&mut sheet, "C3", "IRR(B1:B6)");
+        assert!((cell_at(&sheet, "C3").number() - 0.086631).abs() < 1e-6);
+        assert_eq!(cell_at(&sheet, "C3").value, 0);
+        set_cell(&mut sheet, "C4", "IRR(B1:B5,-0.1)");
+        assert!((cell_at(&sheet, "C4").number() + 0.021245).abs() < 1e-6);
+        set_cell(&mut sheet, "C5", "IRR(A2:A4)"); // No outflow
+        assert!(cell_at(&sheet, "C5").error);
+
+        // PMT(8%/12, 10, 10000) = -1037.03 and PMT(6%/12, 18*12, 0, 50000) = -129.08
+        set_cell(&mut sheet, "D1", "PMT(0.0066666666667,10,10000)");
+        assert_close(&sheet, "D1", -1037.03);
+        set_cell(&mut sheet, "D2", "PMT(0.005,216,0,50000)");
+        assert_close(&sheet, "D2", -129.08);
+        set_cell(&mut sheet, "D3", "PMT(0,10,1000)");
+        assert_close(&sheet, "D3", -100.0);
+        set_cell(&mut sheet, "D4", "PMT(0.1,0,1000)");
+        assert!(cell_at(&sheet, "D4").error);
+
+        // FV(6%/12, 10, -200, -500, 1) = 2581.40 and FV(11%/12, 35, -2000, , 1) = 82846.25
+        set_cell(&mut sheet, "E1", "FV(0.005,10,-200,-500,1)");
+        assert_close(&sheet, "E1", 2581.40);
+        set_cell(&mut sheet, "E2", "FV(0.0091666666667,35,-2000,0,1)");
+        assert_close(&sheet, "E2", 82846.25);
+        set_cell(&mut sheet, "E3", "FV(0,10,-200)");
+        assert_close(&sheet, "E3", 2000.0);
+
+        // PV(8%/12, 12*20, 500) = -59777.15
+        set_cell(&mut sheet, "F1", "PV(0.0066666666667,240,500)");
+        assert_close(&sheet, "F1", -59777.15);
+        set_cell(&mut sheet, "F2", "240");
+        set_cell(&mut sheet, "F3", "PV(0.0066666666667,F2,500,0,1)");
+        assert_close(&sheet, "F3", -60175.66);
+    }
+
+    #[test]
+    fn test_sparkline() {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 12).unwrap();
+        for (i, value) in [3, 5, 2, 8].iter().enumerate() {
+            set_cell(
+                &mut sheet,
+                &format!("{}2", (b'B' + i as u8) as char),
+                &value.to_string(),
+            );
+        }
+        set_cell(&mut sheet, "F2", "G2/2");
+        set_cell(&mut sheet, "G2", "10");
+        set_cell(&mut sheet, "A2", "SPARKLINE(B2:F2)");
+
+        // The value is the last point of the trend, so formulas can build on it
+        assert_close(&sheet, "A2", 5.0);
+        set_cell(&mut sheet, "A3", "A2*2");
+        assert_eq!(cell_at(&sheet, "A3").value, 10);
+        assert_eq!(
+            sheet.sparkline_values(2, 1),
+            Some(vec![3.0, 5.0, 2.0, 8.0, 5.0])
+        );
+        assert_eq!(sheet.sparkline_values(3, 1), None);
+
+        // The trend follows its range, through formulas as well
+        set_cell(&mut sheet, "G2", "20");
+        assert_eq!(sheet.sparkline_values(2, 1).unwrap()[4], 10.0);
+        assert_eq!(cell_at(&sheet, "A3").value, 20);
+
+        // An error in the range is the cell's error
+        set_cell(&mut sheet, "C2", "1/0");
+        assert!(cell_at(&sheet, "A2").error);
+        assert!(cell_at(&sheet, "A3").error);
+        assert_eq!(sheet.sparkline_values(2, 1), None);
+    }
+
+    #[test]
+    fn test_text_constants() {
+        let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+
+        set_cell(&mut sheet, "A1", "\"Jan\"");
+        set_cell(&mut sheet, "A2", "\"Say \"\"hi\"\"\"");
+        let a1 = cell_at(&sheet, "A1");
+        assert_eq!(a1.text(), Some("Jan"));
+        assert_eq!(a1.formatted_value(), "Jan");
+        assert_eq!(a1.formula, ParsedRHS::Text("Jan".to_string()));
+        assert_eq!(cell_at(&sheet, "A2").text(), Some("Say \"hi\""));
+        assert_eq!(
+            Spreadsheet::formula_to_string(&cell_at(&sheet, "A2").formula),
+            "\"Say \"\"hi\"\"\""
+        );
+
+        // Text counts as zero in formulas
+        set_cell(&mut sheet, "B1", "5");
+        set_cell(&mut sheet, "B2", "A1+B1");
+        assert_eq!(cell_at(&sheet, "B2").value, 5);
+        assert!(!cell_at(&sheet, "B2").error);
+        assert_eq!(cell_at(&sheet, "B1").text(), None);
+
+        for formula in ["\"", "\"Jan", "\"a\"b\"", "Jan\""] {
+            assert!(!sheet.is_valid_command("C1", formula).0, "{}", formula);
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let mut sheet = Spreadsheet::spreadsheet_create(30, 10).unwrap();
+        for (i, value) in [1, 2, 3, 4].iter().enumerate() {
+            set_cell(&mut sheet, &format!("A{}", i + 1), &value.to_string());
+            set_cell(&mut sheet, &format!("B{}", i + 1), "5");
+        }
+        let kind = |sheet: &Spreadsheet, cell_name: &str| {
+            let cell = cell_at(sheet, cell_name);
+            assert!(cell.error, "{} should be in error", cell_name);
+            cell.error_kind
+        };
+
+        for (cell_name, formula, expected) in [
+            ("C1", "A1/0", CellError::DivZero),
+            ("C2", "IRR(A1:A4)", CellError::Num), // Only inflows, so no rate of return
+            ("C3", "PERCENTILE(A1:A4,1.5)", CellError::Num),
+            ("C4", "QUARTILE(A1:A4,5)", CellError::Num),
+            ("C5", "SLOPE(A1:A4,B1:B4)", CellError::DivZero), // Constant x values
+            ("C6", "CORREL(A1:A4,B1:B4)", CellError::DivZero),
+            ("C7", "RANK(9,A1:A4)", CellError::NotAvailable),
+            ("C8", "FILTER(A1:A4,A1:A4>10)", CellError::Calc),
+            ("C9", "SEQUENCE(50)", CellError::Value),
+        ] {
+            set_cell(&mut sheet, cell_name, formula);
+            assert_eq!(kind(&sheet, cell_name), expected, "{}", formula);
+        }
+
+        // Cells reading an error pass its kind on
+        set_cell(&mut sheet, "D1", "C2+1");
+        assert_eq!(kind(&sheet, "D1"), CellError::Num);
+        set_cell(&mut sheet, "D2", "SUM(C7:C8)");
+        assert_eq!(kind(&sheet, "D2"), CellError::NotAvailable);
+        set_cell(&mut sheet, "D3", "MAX(C8:C9)");
+        assert_eq!(kind(&sheet, "D3"), CellError::Calc);
+
+        assert_eq!(CellError::DivZero.excel_value(), "#DIV/0!");
+        assert_eq!(CellError::Num.excel_value(), "#NUM!");
+        assert_eq!(CellError::NotAvailable.excel_value(), "#N/A");
+        assert_eq!(CellError::Calc.excel_value(), "#CALC!");
+        assert_eq!(CellError::Value.excel_value(), "#VALUE!");
+    }
 }
@@ -0,0 +1,221 @@
+#![cfg(not(tarpaulin_include))]
+
+fn main() {
+    // When run directly, this will run the tests
+    println!("=== TUI Test Suite ===");
+    println!("Run with 'cargo test' to execute tests");
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use cop::cli::Session;
+    use cop::spreadsheet::Spreadsheet;
+    use cop::tui::{COLUMN_WIDTH, Line, Mode, Style, Tui};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn tui(rows: i16, cols: i16, width: u16, height: u16) -> Tui {
+        let sheet = Spreadsheet::spreadsheet_create(rows, cols).unwrap();
+        Tui::new(Session::new(sheet), width, height)
+    }
+
+    fn press(tui: &mut Tui, code: KeyCode) -> bool {
+        tui.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn ctrl(tui: &mut Tui, c: char) -> bool {
+        tui.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL))
+    }
+
+    fn type_text(tui: &mut Tui, text: &str) {
+        for c in text.chars() {
+            press(tui, KeyCode::Char(c));
+        }
+    }
+
+    fn value(tui: &Tui, cell_name: &str) -> i32 {
+        let sheet = &tui.session.sheet;
+        let (row, col) = sheet.spreadsheet_parse_cell_name(cell_name).unwrap();
+        let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+        sheet.cells[index].as_ref().unwrap().value
+    }
+
+    fn text(line: &Line) -> String {
+        line.iter().map(|(text, _)| text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_navigation_and_viewport() {
+        // 4 grid rows and 3 columns fit on the screen
+        let width = 4 + 3 * COLUMN_WIDTH + 5;
+        let mut tui = tui(20, 10, width, 7);
+        assert_eq!((tui.visible_rows(), tui.visible_cols()), (4, 3));
+
+        press(&mut tui, KeyCode::Up);
+        press(&mut tui, KeyCode::Left);
+        assert_eq!(tui.cursor, (1, 1));
+
+        for _ in 0..5 {
+            press(&mut tui, KeyCode::Down);
+        }
+        assert_eq!(tui.cursor, (6, 1));
+        assert_eq!(tui.top_left, (3, 1));
+        assert_eq!(tui.session.sheet.view_row, 2);
+
+        for _ in 0..3 {
+            press(&mut tui, KeyCode::Right);
+        }
+        assert_eq!(tui.top_left, (3, 2));
+        press(&mut tui, KeyCode::Home);
+        assert_eq!((tui.cursor, tui.top_left), ((6, 1), (3, 1)));
+        press(&mut tui, KeyCode::End);
+        assert_eq!(tui.cursor, (6, 10));
+        press(&mut tui, KeyCode::PageDown);
+        press(&mut tui, KeyCode::PageDown);
+        press(&mut tui, KeyCode::PageDown);
+        press(&mut tui, KeyCode::PageDown);
+        assert_eq!(tui.cursor, (20, 10));
+        assert_eq!(tui.top_left, (17, 8));
+
+        // A smaller terminal keeps the cursor on screen
+        tui.resize(4 + COLUMN_WIDTH, 5);
+        assert_eq!(tui.top_left, (19, 10));
+
+        // Commands that scroll move the cursor with the view
+        press(&mut tui, KeyCode::Char(':'));
+        type_text(&mut tui, "goto C2");
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!((tui.cursor, tui.top_left), ((2, 3), (2, 3)));
+        assert_eq!(tui.status, "ok");
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut tui = tui(10, 10, 80, 24);
+        type_text(&mut tui, "5");
+        assert_eq!(tui.mode, Mode::Edit);
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.mode, Mode::Navigate);
+        assert_eq!(value(&tui, "A1"), 5);
+        assert_eq!(tui.cursor, (2, 1));
+
+        type_text(&mut tui, "A1*2");
+        press(&mut tui, KeyCode::Tab);
+        assert_eq!(value(&tui, "A2"), 10);
+        assert_eq!(tui.cursor, (2, 2));
+
+        // Editing starts from the current formula, with the text cursor at the end
+        press(&mut tui, KeyCode::Left);
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.input, "A1*2");
+        press(&mut tui, KeyCode::Backspace);
+        type_text(&mut tui, "3");
+        press(&mut tui, KeyCode::Home);
+        press(&mut tui, KeyCode::Delete);
+        type_text(&mut tui, "B");
+        assert_eq!(tui.input, "B1*3");
+        press(&mut tui, KeyCode::Esc);
+        assert_eq!(value(&tui, "A2"), 10);
+
+        press(&mut tui, KeyCode::F(2));
+        press(&mut tui, KeyCode::Backspace);
+        type_text(&mut tui, "3");
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(value(&tui, "A2"), 15);
+
+        // Errors are reported on the status line
+        press(&mut tui, KeyCode::Up);
+        type_text(&mut tui, "A2+");
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(tui.status, "invalid command");
+        assert_eq!(value(&tui, "A2"), 15);
+
+        press(&mut tui, KeyCode::Up);
+        press(&mut tui, KeyCode::Delete);
+        assert_eq!(value(&tui, "A2"), 0);
+        ctrl(&mut tui, 'z');
+        assert_eq!(value(&tui, "A2"), 15);
+
+        // Emptying the formula clears the cell
+        press(&mut tui, KeyCode::Enter);
+        press(&mut tui, KeyCode::Backspace);
+        press(&mut tui, KeyCode::Backspace);
+        press(&mut tui, KeyCode::Backspace);
+        press(&mut tui, KeyCode::Backspace);
+        press(&mut tui, KeyCode::Enter);
+        assert_eq!(value(&tui, "A2"), 0);
+        assert_eq!(tui.status, "ok");
+
+        assert!(ctrl(&mut tui, 'q'));
+        press(&mut tui, KeyCode::Char(':'));
+        type_text(&mut tui, "q");
+        assert!(press(&mut tui, KeyCode::Enter));
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut tui = tui(10, 10, 60, 8);
+        type_text(&mut tui, "7");
+        press(&mut tui, KeyCode::Enter);
+        type_text(&mut tui, "1/0");
+        press(&mut tui, KeyCode::Enter);
+        type_text(&mut tui, "SEQUENCE(2)");
+        press(&mut tui, KeyCode::Enter);
+        press(&mut tui, KeyCode::Up);
+        press(&mut tui, KeyCode::Up);
+
+        let frame = tui.frame();
+        assert_eq!(frame.len(), 8);
+        assert_eq!(text(&frame[0]), "A2  | 1/0");
+        assert!(text(&frame[1]).contains("A"));
+        assert!(text(&frame[1]).contains("D"));
+        assert!(!text(&frame[1]).contains("E"));
+
+        let row = &frame[2];
+        assert_eq!(row[0], ("  1 ".to_string(), Style::Header));
+        assert_eq!(row[1].0.trim(), "7");
+        assert_eq!(row[1].1, Style::Plain);
+        assert_eq!(row[2], (" ".repeat(COLUMN_WIDTH as usize), Style::Plain));
+
+        let row = &frame[3];
+        assert_eq!(row[1].0.trim(), "ERR");
+        assert_eq!(row[1].1, Style::Selected);
+        assert_eq!(frame[4][1], (format!("{:>11} ", 1), Style::Plain));
+        assert_eq!(frame[5][1], (format!("{:>11} ", 2), Style::Spilled));
+
+        let status = text(&frame[7]);
+        assert!(status.starts_with("[0.0] (ok)"));
+        assert!(status.len() <= 60);
+
+        press(&mut tui, KeyCode::Down);
+        assert_eq!(tui.frame()[3][1].1, Style::Error);
+
+        type_text(&mut tui, "12");
+        assert_eq!(text(&tui.frame()[0]), "A3  = 12");
+        press(&mut tui, KeyCode::Esc);
+        press(&mut tui, KeyCode::Char(':'));
+        type_text(&mut tui, "show A1");
+        assert_eq!(text(&tui.frame()[0]), "    : show A1");
+        press(&mut tui, KeyCode::Enter);
+        assert!(text(&tui.frame()[7]).contains("formula:    7"));
+    }
+
+    #[test]
+    fn test_input_column() {
+        let mut tui = tui(100, 30, 60, 8);
+        assert_eq!(tui.input_column(), None);
+        press(&mut tui, KeyCode::Enter);
+        type_text(&mut tui, "12");
+        assert_eq!(tui.input_column(), Some(8));
+
+        // A cell name wider than the row numbers pushes the input to the right
+        press(&mut tui, KeyCode::Esc);
+        tui.cursor = (100, 28);
+        press(&mut tui, KeyCode::Enter);
+        type_text(&mut tui, "345");
+        let bar = text(&tui.frame()[0]);
+        assert_eq!(bar, "AB100= 345");
+        assert_eq!(tui.input_column(), Some(bar.len() as u16));
+        press(&mut tui, KeyCode::Left);
+        assert_eq!(tui.input_column(), Some(9));
+    }
+}
@@ -15,12 +15,21 @@ pub struct Cell {
     pub col: i16,
     /// Whether the cell contains an error
     pub error: bool,
+    /// What kind of error the cell is in; only meaningful while `error` is set. Worked
+    /// out again whenever the cell is evaluated, so not serialized.
+    #[serde(skip)]
+    pub error_kind: CellError,
     /// Current numeric value of the cell
     pub value: i32,
+    /// Exact value when the cell's result is not a whole number; `value` then holds it
+    /// rounded to the nearest integer
+    pub decimal: Option<f64>,
     /// Formula defining how the cell's value is calculated
     pub formula: ParsedRHS,
     /// Collection of cells that depend on this cell
     pub dependents: Dependents,
+    /// Role of the cell in a dynamic array (spilled) formula
+    pub spill: Spill,
 }
 
 /// Represents the collection of cells that depend on a particular cell.
@@ -39,6 +48,54 @@ pub enum Dependents {
     None,
 }
 
+/// The kinds of error a formula can end in, each standing for an Excel error value.
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub enum CellError {
+    /// Division by zero, directly or inside a statistic such as CORREL (`#DIV/0!`)
+    DivZero,
+    /// A number that cannot be computed or stored, e.g. IRR not converging (`#NUM!`)
+    Num,
+    /// A value that is not there, e.g. RANK of a number missing from its range (`#N/A`)
+    NotAvailable,
+    /// A calculation with an empty result, e.g. FILTER keeping no rows (`#CALC!`)
+    Calc,
+    /// An argument out of range, e.g. SEQUENCE larger than the sheet (`#VALUE!`)
+    #[default]
+    Value,
+}
+
+impl CellError {
+    /// Returns the Excel error value of this kind of error, e.g. `#NUM!`.
+    pub fn excel_value(self) -> &'static str {
+        match self {
+            CellError::DivZero => "#DIV/0!",
+            CellError::Num => "#NUM!",
+            CellError::NotAvailable => "#N/A",
+            CellError::Calc => "#CALC!",
+            CellError::Value => "#VALUE!",
+        }
+    }
+}
+
+/// Describes the part a cell plays in a dynamic array formula.
+///
+/// Functions such as `SORT(A1:A20)` or `SEQUENCE(10)` produce a block of values.
+/// The cell holding the formula is the anchor, and the result spills into the
+/// cells below and to the right of it. The whole region is treated as one
+/// dependency unit: cells referring to any spilled cell are recalculated
+/// whenever the anchor is.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub enum Spill {
+    /// The cell is not part of a spill region
+    None,
+    /// The cell holds an array formula whose result fills `rows` x `cols` cells
+    Anchor { rows: i16, cols: i16 },
+    /// The cell holds an array formula whose `rows` x `cols` result is obstructed (`#SPILL!`)
+    Blocked { rows: i16, cols: i16 },
+    /// The cell displays part of the result of the array formula anchored at (row, col)
+    Member(i16, i16),
+}
+
 impl Cell {
     /// Creates a new cell at the specified row and column.
     ///
@@ -53,17 +110,97 @@ impl Cell {
     ///
     /// # Default Values
     /// - value: 0
-    /// - error: false
+    /// - decimal: None
+    /// - error: false, of kind `Value`
     /// - formula: None
     /// - dependents: None
+    /// - spill: None
     pub fn create(row: i16, col: i16) -> Self {
         Cell {
             row,
             col,
             value: 0,
+            decimal: None,
             error: false,
+            error_kind: CellError::default(),
             formula: ParsedRHS::None,
             dependents: Dependents::None,
+            spill: Spill::None,
+        }
+    }
+
+    /// Returns the numeric value of the cell, including any fractional part.
+    pub fn number(&self) -> f64 {
+        self.decimal.unwrap_or(self.value as f64)
+    }
+
+    /// Returns the cell's text if it holds a text constant rather than a number.
+    pub fn text(&self) -> Option<&str> {
+        match &self.formula {
+            ParsedRHS::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as shown in the grid.
+    ///
+    /// Text cells show their text, whole numbers are shown as integers, and other values
+    /// with up to four decimal places.
+    pub fn formatted_value(&self) -> String {
+        if let Some(text) = self.text() {
+            return text.to_string();
+        }
+        match self.decimal {
+            Some(decimal) => {
+                let text = format!("{:.4}", decimal);
+                text.trim_end_matches('0').trim_end_matches('.').to_string()
+            }
+            None => self.value.to_string(),
+        }
+    }
+
+    /// Sets the cell's value from a computed result that may have a fractional part.
+    ///
+    /// # Returns
+    /// `false` if the result is not finite or does not fit in an integer cell value
+    pub fn set_number(&mut self, number: f64) -> bool {
+        let Some(value) = round_to_value(number) else {
+            return false;
+        };
+        self.value = value;
+        self.decimal = if number.fract() == 0.0 {
+            None
+        } else {
+            Some(number)
+        };
+        true
+    }
+
+    /// Returns the extent of the region an array formula in this cell spills into.
+    ///
+    /// # Returns
+    /// * `Some((rows, cols))` - For anchors, including blocked ones
+    /// * `None` - If the cell is not the anchor of a dynamic array formula
+    pub fn spill_extent(&self) -> Option<(i16, i16)> {
+        match self.spill {
+            Spill::Anchor { rows, cols } | Spill::Blocked { rows, cols } => Some((rows, cols)),
+            _ => None,
+        }
+    }
+
+    /// Returns the text shown in place of the value when the cell is in error.
+    ///
+    /// # Returns
+    /// * `Some("#SPILL!")` - For an array formula whose spill region is obstructed
+    /// * `Some("ERR")` - For any other error
+    /// * `None` - If the cell is not in error
+    pub fn error_text(&self) -> Option<&'static str> {
+        if !self.error {
+            return None;
+        }
+        match self.spill {
+            Spill::Blocked { .. } => Some("#SPILL!"),
+            _ => Some("ERR"),
         }
     }
 
@@ -224,3 +361,16 @@ pub fn cell_dep_remove(cell: &mut Cell, row: i16, col: i16) {
 pub fn cell_contains(cell: &Cell, row: i16, col: i16) -> bool {
     cell.contains(row, col)
 }
+
+/// Rounds a computed result to the nearest integer cell value.
+///
+/// # Returns
+/// * `Some(value)` - The rounded result
+/// * `None` - If the result is not finite or does not fit in an `i32`
+pub fn round_to_value(number: f64) -> Option<i32> {
+    let rounded = number.round();
+    if !number.is_finite() || rounded < i32::MIN as f64 || rounded > i32::MAX as f64 {
+        return None;
+    }
+    Some(rounded as i32)
+}
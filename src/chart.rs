@@ -0,0 +1,547 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Charts saved in a sheet.
+//!
+//! A chart is defined by its type, ranges and options in the same shape as a
+//! `/api/graph` request, and is kept in the sheet under a numeric id, so it is saved
+//! with the sheet and can be drawn again whenever the cells it reads change.
+//!
+//! The ranges of a chart are read into values here rather than in the `graph` module,
+//! so that they can be checked and exported without the drawing dependencies of the
+//! `web` feature.
+
+use crate::cell::Cell;
+use crate::spreadsheet::Spreadsheet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// Type, ranges and options of a chart, as sent to `/api/graph` and `/api/charts`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChartDefinition {
+    /// Cell range for X-axis values (e.g., "A1:A10"); text cells make it categories
+    pub x_range: String,
+    /// Cell range for Y-axis values (e.g., "B1:B10"), the first series if given; a block
+    /// such as "B1:D10" gives one series per column
+    #[serde(default)]
+    pub y_range: String,
+    /// Further series drawn against the same X values
+    #[serde(default)]
+    pub series: Vec<ChartSeries>,
+    /// Title for the graph
+    pub title: String,
+    /// Label for the X-axis
+    pub x_label: String,
+    /// Label for the Y-axis
+    pub y_label: String,
+    /// Type of graph ("Line", "Bar", "Scatter", "Area", "GroupedBar", "StackedBar", "Pie",
+    /// "Donut", "Histogram", "BoxPlot" or "Heatmap")
+    pub graph_type: String,
+    /// Image format ("png", "svg" or "pdf"), PNG if omitted
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Label for the secondary Y-axis
+    #[serde(default)]
+    pub y2_label: String,
+    /// Whether the first cell of every range is a header naming its series; it is also
+    /// taken as one when the first cell of a Y range holds text
+    #[serde(default)]
+    pub header: bool,
+    /// Whether to draw a legend, true if omitted
+    #[serde(default)]
+    pub legend: Option<bool>,
+    /// Number of histogram bins, chosen from the number of values if omitted
+    #[serde(default)]
+    pub bins: usize,
+    /// Width of the image in pixels, 800 if omitted
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Height of the image in pixels, 600 if omitted
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Name of a built-in palette or a list of "#rrggbb" colours for the series
+    #[serde(default)]
+    pub palette: Option<Palette>,
+    /// Width of lines in pixels
+    #[serde(default)]
+    pub line_width: Option<u32>,
+    /// Marker at every point ("circle", "square", "triangle" or "cross")
+    #[serde(default)]
+    pub marker: Option<String>,
+    /// Radius of the markers in pixels
+    #[serde(default)]
+    pub marker_size: Option<u32>,
+    /// Whether to draw gridlines at the X-axis ticks, true if omitted
+    #[serde(default)]
+    pub x_grid: Option<bool>,
+    /// Whether to draw gridlines at the Y-axis ticks, true if omitted
+    #[serde(default)]
+    pub y_grid: Option<bool>,
+    /// Whether the Y-axes have a logarithmic scale
+    #[serde(default)]
+    pub log_scale: bool,
+    /// Lowest value on the X-axis
+    #[serde(default)]
+    pub x_min: Option<f64>,
+    /// Highest value on the X-axis
+    #[serde(default)]
+    pub x_max: Option<f64>,
+    /// Lowest value on the Y-axis
+    #[serde(default)]
+    pub y_min: Option<f64>,
+    /// Highest value on the Y-axis
+    #[serde(default)]
+    pub y_max: Option<f64>,
+    /// Number format of the value axes ("auto", "decimal:N", "thousands", "percent" or
+    /// "scientific")
+    #[serde(default)]
+    pub tick_format: Option<String>,
+    /// Whether to write the value of every point next to it
+    #[serde(default)]
+    pub data_labels: bool,
+    /// Colour scheme ("light" or "dark"), light if omitted
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// Font size of the title in pixels
+    #[serde(default)]
+    pub title_size: Option<u32>,
+    /// Trendline of the line and scatter series ("linear", "polynomial:N" for degree N,
+    /// "exponential" or "moving_average:N" for a period of N points)
+    #[serde(default)]
+    pub trendline: Option<String>,
+    /// Whether to write the equation and R² of fitted trendlines, true if omitted
+    #[serde(default)]
+    pub trendline_equation: Option<bool>,
+}
+
+/// One series of a chart definition
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChartSeries {
+    /// Cell range for the series' values (e.g., "C1:C10")
+    pub range: String,
+    /// Name shown in the legend
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Colour of the series as "#rrggbb"
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Type of graph for this series ("Line", "Bar", "Scatter", "Area", "GroupedBar" or
+    /// "StackedBar"), the graph's type if omitted
+    #[serde(default)]
+    pub graph_type: Option<String>,
+    /// Whether the series is plotted against the secondary Y-axis
+    #[serde(default)]
+    pub secondary_axis: bool,
+}
+
+/// Palette of a chart, by name or as a list of colours
+///
+/// In JSON it is written as a plain string or list. The binary sheet file cannot tell
+/// the two apart when reading, so there it is tagged with its variant.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Palette {
+    /// Name of a built-in palette ("default", "colorblind", "pastel" or "grayscale")
+    Named(String),
+    /// Colours as "#rrggbb"
+    Colors(Vec<String>),
+}
+
+/// Palette as written in JSON
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum UntaggedPalette {
+    Named(String),
+    Colors(Vec<String>),
+}
+
+/// Palette as written in the binary sheet file
+#[derive(Serialize, Deserialize)]
+enum TaggedPalette {
+    Named(String),
+    Colors(Vec<String>),
+}
+
+impl Serialize for Palette {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self.clone(), serializer.is_human_readable()) {
+            (Palette::Named(name), true) => UntaggedPalette::Named(name).serialize(serializer),
+            (Palette::Colors(colors), true) => {
+                UntaggedPalette::Colors(colors).serialize(serializer)
+            }
+            (Palette::Named(name), false) => TaggedPalette::Named(name).serialize(serializer),
+            (Palette::Colors(colors), false) => TaggedPalette::Colors(colors).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Palette {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Ok(match UntaggedPalette::deserialize(deserializer)? {
+                UntaggedPalette::Named(name) => Palette::Named(name),
+                UntaggedPalette::Colors(colors) => Palette::Colors(colors),
+            })
+        } else {
+            Ok(match TaggedPalette::deserialize(deserializer)? {
+                TaggedPalette::Named(name) => Palette::Named(name),
+                TaggedPalette::Colors(colors) => Palette::Colors(colors),
+            })
+        }
+    }
+}
+
+impl ChartDefinition {
+    /// Returns the Y ranges of the chart in order: `y_range` if given, then the ranges of
+    /// the further series
+    pub fn y_ranges(&self) -> Vec<&str> {
+        let first = Some(self.y_range.as_str()).filter(|range| !range.is_empty());
+        first
+            .into_iter()
+            .chain(self.series.iter().map(|series| series.range.as_str()))
+            .collect()
+    }
+}
+
+/// A chart kept in a sheet
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chart {
+    /// Identifier of the chart, unique within its sheet
+    pub id: u32,
+    /// What the chart draws and how
+    pub definition: ChartDefinition,
+}
+
+impl Spreadsheet {
+    /// Adds a chart to the sheet.
+    ///
+    /// # Returns
+    /// The id of the new chart, one more than the highest id in use
+    pub fn add_chart(&mut self, definition: ChartDefinition) -> u32 {
+        let id = self.charts.iter().map(|chart| chart.id).max().unwrap_or(0) + 1;
+        self.charts.push(Chart { id, definition });
+        id
+    }
+
+    /// Returns the chart with the given id, if the sheet has one.
+    pub fn chart(&self, id: u32) -> Option<&Chart> {
+        self.charts.iter().find(|chart| chart.id == id)
+    }
+
+    /// Replaces the definition of a chart, keeping its id.
+    ///
+    /// # Returns
+    /// `false` if the sheet has no chart with the given id
+    pub fn replace_chart(&mut self, id: u32, definition: ChartDefinition) -> bool {
+        match self.charts.iter_mut().find(|chart| chart.id == id) {
+            Some(chart) => {
+                chart.definition = definition;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a chart from the sheet.
+    ///
+    /// # Returns
+    /// `false` if the sheet has no chart with the given id
+    pub fn remove_chart(&mut self, id: u32) -> bool {
+        let count = self.charts.len();
+        self.charts.retain(|chart| chart.id != id);
+        self.charts.len() < count
+    }
+
+    /// Returns a digest of the cells a chart reads.
+    ///
+    /// The digest changes whenever the value, text or error state of a cell in one of the
+    /// chart's ranges changes, so comparing it with the digest of the last drawing tells
+    /// whether the chart is stale. Ranges that cannot be read are left out.
+    pub fn chart_signature(&self, definition: &ChartDefinition) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let ranges = std::iter::once(definition.x_range.as_str()).chain(definition.y_ranges());
+        for range in ranges.filter_map(|text| CellRange::parse(self, text, "range").ok()) {
+            for (row, col) in range.cells() {
+                let cell = cell_at(self, row, col);
+                (row, col).hash(&mut hasher);
+                cell.map(|cell| (cell.value, cell.decimal.map(f64::to_bits), cell.error))
+                    .hash(&mut hasher);
+                cell.and_then(Cell::text).hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+}
+
+/// A rectangular range of cells, from its top left to its bottom right cell
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellRange {
+    /// (row, col) of the first cell, 1-based
+    pub start: (i16, i16),
+    /// (row, col) of the last cell, 1-based
+    pub end: (i16, i16),
+}
+
+impl CellRange {
+    /// Parses a range such as "A1:C10".
+    ///
+    /// # Arguments
+    /// * `sheet` - The spreadsheet the range lies in
+    /// * `text` - Range specification (e.g., "A1:A10")
+    /// * `what` - Description of the range used in error messages (e.g., "X range")
+    ///
+    /// # Errors
+    /// Returns a message naming the range if it is not two valid cells, or if its last
+    /// cell lies above or to the left of its first
+    pub fn parse(sheet: &Spreadsheet, text: &str, what: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let Some((first, last)) = text.split_once(':') else {
+            return Err(format!(
+                "Invalid {} {}; expected a range such as A1:A10",
+                what, text
+            ));
+        };
+        let start = sheet
+            .spreadsheet_parse_cell_name(first)
+            .ok_or_else(|| format!("Invalid start cell in {} {}", what, text))?;
+        let end = sheet
+            .spreadsheet_parse_cell_name(last)
+            .ok_or_else(|| format!("Invalid end cell in {} {}", what, text))?;
+        if end.0 < start.0 || end.1 < start.1 {
+            return Err(format!(
+                "{} {} must run from its first to its last cell",
+                what, text
+            ));
+        }
+        Ok(CellRange { start, end })
+    }
+
+    /// Returns the number of cells in the range.
+    pub fn cell_count(&self) -> usize {
+        (self.end.0 - self.start.0 + 1) as usize * (self.end.1 - self.start.1 + 1) as usize
+    }
+
+    /// Returns true if the range lies within one row or one column.
+    pub fn is_line(&self) -> bool {
+        self.start.0 == self.end.0 || self.start.1 == self.end.1
+    }
+
+    /// Returns the (row, col) of every cell, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = (i16, i16)> {
+        let (start, end) = (self.start, self.end);
+        (start.0..=end.0).flat_map(move |row| (start.1..=end.1).map(move |col| (row, col)))
+    }
+
+    /// Returns every column of the range as a range of its own.
+    pub fn columns(&self) -> impl Iterator<Item = CellRange> {
+        let (start, end) = (self.start, self.end);
+        (start.1..=end.1).map(move |col| CellRange {
+            start: (start.0, col),
+            end: (end.0, col),
+        })
+    }
+}
+
+impl fmt::Display for CellRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}",
+            Spreadsheet::get_cell_name(self.start.0, self.start.1),
+            Spreadsheet::get_cell_name(self.end.0, self.end.1)
+        )
+    }
+}
+
+/// Values read from the ranges of a chart
+#[derive(Debug, PartialEq)]
+pub struct ChartData {
+    /// X value of every point
+    pub x: Vec<f64>,
+    /// Labels of the X values 1, 2, 3 and so on when the X range holds text, or empty
+    pub categories: Vec<String>,
+    /// Whether the first cell of every range is a header
+    pub header: bool,
+    /// The X range, if one was given
+    pub x_range: Option<CellRange>,
+    /// One column of values for each series to draw
+    pub columns: Vec<ChartColumn>,
+}
+
+/// Values of one series of a chart
+#[derive(Debug, PartialEq)]
+pub struct ChartColumn {
+    /// Index of the Y range the values come from
+    pub source: usize,
+    /// Whether the Y range is a block of several columns, of which this is one
+    pub block: bool,
+    /// Cells of the column, including its header
+    pub range: CellRange,
+    /// Displayed value of the header cell, if the ranges have headers and it is not empty
+    pub header: Option<String>,
+    /// Y value of every point
+    pub values: Vec<f64>,
+}
+
+/// Reads the X values and the series of a chart from their ranges
+///
+/// The X range must lie within one row or column. A Y range within one row or column is
+/// a single series; a block of several rows and columns gives one series per column.
+/// The first cell of every range is a header rather than data when `header` is set or
+/// when the first cell of any Y range holds text.
+///
+/// An X range holding text gives categories: the points are numbered from 1 and labelled
+/// with the displayed values of the X cells, so that for example months can be shown.
+///
+/// # Arguments
+/// * `sheet` - Reference to the spreadsheet to extract data from
+/// * `x_range` - Range specification for X values (e.g., "A1:A10"), or empty
+/// * `y_ranges` - The Y ranges, read in order
+/// * `header` - Whether the first cell of every range is a header
+/// * `same_length` - Whether every Y column must have as many values as the X range
+///
+/// # Errors
+/// * Returns a message naming the range if it is invalid, runs backwards, has no values
+///   after its header, or holds text after its first cell
+/// * Returns a message if the X range spans several rows and columns
+/// * Returns a message giving both lengths if two ranges have different lengths
+///
+/// # Notes
+/// * Empty cells in the range will be treated as having a value of 0
+/// * An empty X range numbers the values of the first series from 1
+pub fn read_ranges(
+    sheet: &Spreadsheet,
+    x_range: &str,
+    y_ranges: &[&str],
+    header: bool,
+    same_length: bool,
+) -> Result<ChartData, String> {
+    let x_range = if x_range.trim().is_empty() {
+        None
+    } else {
+        let range = CellRange::parse(sheet, x_range, "X range")?;
+        if !range.is_line() {
+            return Err(format!("X range {} must be a single row or column", range));
+        }
+        Some(range)
+    };
+
+    // Every Y range is split into the columns it covers, each becoming a series
+    let mut columns = Vec::with_capacity(y_ranges.len());
+    for (i, text) in y_ranges.iter().enumerate() {
+        let range = CellRange::parse(sheet, text, "Y range")?;
+        if range.is_line() {
+            columns.push((i, false, range));
+        } else {
+            columns.extend(range.columns().map(|column| (i, true, column)));
+        }
+    }
+    if columns.is_empty() {
+        return Err("At least one Y range is required".to_string());
+    }
+
+    let is_text =
+        |(row, col): (i16, i16)| cell_at(sheet, row, col).is_some_and(|c| c.text().is_some());
+    let header = header || columns.iter().any(|(_, _, range)| is_text(range.start));
+    let skip = usize::from(header);
+    for (_, _, range) in &columns {
+        if range.cell_count() <= skip {
+            return Err(format!("Y range {} has no values after its header", range));
+        }
+        if let Some((row, col)) = range.cells().skip(skip).find(|&cell| is_text(cell)) {
+            return Err(format!(
+                "Y range {} holds text in {}; only its first cell can be a header",
+                range,
+                Spreadsheet::get_cell_name(row, col)
+            ));
+        }
+    }
+    if let Some(range) = x_range
+        && range.cell_count() <= skip
+    {
+        return Err(format!("X range {} has no values after its header", range));
+    }
+
+    // Ensure the ranges have the same length
+    if same_length {
+        let (what, first) = match x_range {
+            Some(range) => ("X range", range),
+            None => ("Y range", columns[0].2),
+        };
+        if let Some((_, _, range)) = columns
+            .iter()
+            .find(|(_, _, range)| range.cell_count() != first.cell_count())
+        {
+            return Err(format!(
+                "{} {} has {} values but Y range {} has {}",
+                what,
+                first,
+                first.cell_count() - skip,
+                range,
+                range.cell_count() - skip
+            ));
+        }
+    }
+
+    let values = |range: CellRange| -> Vec<f64> {
+        range
+            .cells()
+            .skip(skip)
+            .map(|(row, col)| cell_at(sheet, row, col).map_or(0.0, |cell| cell.number()))
+            .collect()
+    };
+    let mut categories = Vec::new();
+    let x = match x_range {
+        Some(range) if range.cells().skip(skip).any(is_text) => {
+            categories = range
+                .cells()
+                .skip(skip)
+                .map(|(row, col)| shown(cell_at(sheet, row, col)))
+                .collect();
+            (1..=categories.len()).map(|i| i as f64).collect()
+        }
+        Some(range) => values(range),
+        None => (1..=columns[0].2.cell_count() - skip)
+            .map(|i| i as f64)
+            .collect(),
+    };
+
+    let columns = columns
+        .into_iter()
+        .map(|(source, block, range)| ChartColumn {
+            source,
+            block,
+            range,
+            header: cell_at(sheet, range.start.0, range.start.1)
+                .filter(|_| header)
+                .map(|cell| shown(Some(cell))),
+            values: values(range),
+        })
+        .collect();
+    Ok(ChartData {
+        x,
+        categories,
+        header,
+        x_range,
+        columns,
+    })
+}
+
+/// Returns the cell at a position, if it lies within the sheet
+fn cell_at(sheet: &Spreadsheet, row: i16, col: i16) -> Option<&Cell> {
+    if !(1..=sheet.rows).contains(&row) || !(1..=sheet.cols).contains(&col) {
+        return None;
+    }
+    let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+    sheet.cells[index].as_deref()
+}
+
+/// Returns a cell as displayed in the sheet, empty for an empty cell
+fn shown(cell: Option<&Cell>) -> String {
+    match cell {
+        Some(cell) => match cell.error_text() {
+            Some(text) => text.to_string(),
+            None => cell.formatted_value(),
+        },
+        None => String::new(),
+    }
+}
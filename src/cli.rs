@@ -0,0 +1,798 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Command interpreter for the terminal interface.
+//!
+//! The interactive prompt in `main.rs` reads lines and hands them to a [`Session`],
+//! which parses and runs the command against the spreadsheet it owns. Keeping the
+//! commands here, rather than in the binary, lets them be tested directly.
+
+use crate::cell::Dependents;
+use crate::cell::Spill;
+use crate::downloader;
+use crate::loader;
+use crate::plot::{self, PlotKind, PlotOptions};
+use crate::saving;
+use crate::snapshot;
+use crate::spreadsheet::{ParsedRHS, Spreadsheet};
+use std::fs;
+
+/// Names of all functions that can be used in a formula, as offered by tab completion.
+pub const FUNCTION_NAMES: &[&str] = &[
+    "AVG",
+    "CORREL",
+    "COPY",
+    "COVAR",
+    "FILTER",
+    "FORECAST.LINEAR",
+    "FV",
+    "INTERCEPT",
+    "IRR",
+    "MAX",
+    "MIN",
+    "NPV",
+    "PERCENTILE",
+    "PMT",
+    "PV",
+    "QUARTILE",
+    "RANK",
+    "SEQUENCE",
+    "SLEEP",
+    "SLOPE",
+    "SORT",
+    "SPARKLINE",
+    "STDEV",
+    "SUM",
+    "UNIQUE",
+];
+
+/// Names of the commands understood by [`Session::execute`], as offered by tab completion.
+pub const COMMANDS: &[&str] = &[
+    "clear",
+    "close_snapshot",
+    "diff",
+    "disable_output",
+    "enable_output",
+    "export",
+    "goto",
+    "help",
+    "import",
+    "load",
+    "open_snapshot",
+    "plot",
+    "resize",
+    "restore_snapshot",
+    "save",
+    "scroll_to",
+    "show",
+    "snapshot",
+    "snapshots",
+    "UNDO",
+];
+
+/// What the caller should do after a command has run.
+#[derive(Debug, PartialEq)]
+pub enum CommandResult {
+    /// Show the sheet (if output is enabled) and read the next command
+    Continue,
+    /// Print the given text, then carry on as for `Continue`
+    Output(String),
+    /// Leave the program
+    Quit,
+}
+
+/// A spreadsheet being edited from the terminal, together with the display settings.
+pub struct Session {
+    /// The spreadsheet commands are run against
+    pub sheet: Box<Spreadsheet>,
+    /// Whether the sheet is printed after every command
+    pub show_output: bool,
+    /// File the sheet was last saved to or loaded from, which its snapshots belong to
+    pub path: Option<String>,
+    /// The live sheet, set aside while `sheet` shows a snapshot read-only
+    pub live: Option<Box<Spreadsheet>>,
+}
+
+impl Session {
+    /// Creates a session editing the given spreadsheet, with output enabled.
+    pub fn new(sheet: Box<Spreadsheet>) -> Self {
+        Session {
+            sheet,
+            show_output: true,
+            path: None,
+            live: None,
+        }
+    }
+
+    /// Runs a single command.
+    ///
+    /// # Arguments
+    /// * `command` - The command line, without the trailing newline
+    /// * `status_out` - Output parameter for the status shown in the prompt
+    ///
+    /// # Commands
+    /// - `q`: Quit
+    /// - `w`, `a`, `s`, `d`: Scroll up, left, down or right by 10 cells
+    /// - `enable_output`, `disable_output`: Turn printing of the sheet on or off
+    /// - `scroll_to <cell>`, `goto <cell>`: Scroll so that the cell is at the top left
+    /// - `<cell>=<formula>`: Set the formula of a cell
+    /// - `UNDO`: Undo the last change
+    /// - `show <cell>`: Print the formula, value and dependents of a cell
+    /// - `clear <cell|range>`: Empty a cell or range of cells
+    /// - `resize <rows> <cols>`: Change the size of the sheet
+    /// - `save <file>`, `load <file>`: Save or load the sheet in the native format
+    /// - `import <file> [--delimiter <c>] [--quote <c>|none] [--header] [--sheet <name>]`:
+    ///   Replace the sheet with the contents of a CSV file or a worksheet of a workbook
+    /// - `export <file> [--formulas] [--no-header]`: Write the sheet to a CSV or XLSX file
+    /// - `snapshot <name>`: Take a named snapshot of the sheet, which must have been saved
+    /// - `snapshots`: List the snapshots of the sheet
+    /// - `open_snapshot <id|name>`, `close_snapshot`: Show a snapshot read-only, and go back
+    /// - `restore_snapshot <id|name>`: Replace the sheet with a snapshot
+    /// - `diff <id|name> [<id|name>]`: Compare two snapshots, or a snapshot with the sheet
+    /// - `plot <line|bar|sparkline> <range>... [--width <n>] [--height <n>] [--ascii]`:
+    ///   Draw a chart of the ranges in the terminal
+    /// - `help`: List the commands
+    pub fn execute(&mut self, command: &str, status_out: &mut String) -> CommandResult {
+        let command = command.trim();
+        *status_out = String::from("ok");
+
+        let (keyword, argument) = match command.split_once(char::is_whitespace) {
+            Some((keyword, argument)) => (keyword, argument.trim()),
+            None => (command, ""),
+        };
+
+        let edits = matches!(
+            keyword,
+            "UNDO" | "clear" | "resize" | "save" | "load" | "import"
+        ) || (!COMMANDS.contains(&keyword) && command.contains('='));
+        if edits && self.live.is_some() {
+            *status_out = String::from("snapshot is read-only");
+            return CommandResult::Continue;
+        }
+
+        match keyword {
+            "" => *status_out = String::from("invalid command"),
+            "q" if argument.is_empty() => return CommandResult::Quit,
+            "w" | "a" | "s" | "d" if argument.is_empty() => self.scroll(keyword),
+            "disable_output" if argument.is_empty() => self.show_output = false,
+            "enable_output" if argument.is_empty() => self.show_output = true,
+            "help" if argument.is_empty() => return CommandResult::Output(help_text()),
+            "scroll_to" | "goto" => match self.sheet.spreadsheet_parse_cell_name(argument) {
+                Some((row, col)) => {
+                    self.sheet.view_row = row - 1;
+                    self.sheet.view_col = col - 1;
+                }
+                None => *status_out = String::from("invalid cell"),
+            },
+            "UNDO" if argument.is_empty() => {
+                if self.sheet.undo_stack.is_empty() {
+                    *status_out = String::from("no undo");
+                } else {
+                    self.sheet.spreadsheet_undo(status_out);
+                }
+            }
+            "show" => match self.describe_cell(argument) {
+                Some(text) => return CommandResult::Output(text),
+                None => *status_out = String::from("invalid cell"),
+            },
+            "clear" => self.clear(argument, status_out),
+            "resize" => self.resize(argument, status_out),
+            "save" if !argument.is_empty() => {
+                match saving::save_spreadsheet(&self.sheet, argument) {
+                    Ok(()) => self.path = Some(argument.to_string()),
+                    Err(e) => *status_out = format!("save failed: {}", e),
+                }
+            }
+            "load" if !argument.is_empty() => match saving::load_spreadsheet(argument) {
+                Ok(sheet) => {
+                    *self.sheet = sheet;
+                    self.path = Some(argument.to_string());
+                }
+                Err(e) => *status_out = format!("load failed: {}", e),
+            },
+            "import" if !argument.is_empty() => {
+                if let Some(report) = self.import(argument, status_out) {
+                    return CommandResult::Output(report);
+                }
+            }
+            "export" if !argument.is_empty() => {
+                if let Err(e) = self.export(argument) {
+                    *status_out = format!("export failed: {}", e);
+                }
+            }
+            "snapshot" if !argument.is_empty() => {
+                if let Some(path) = self.snapshot_path(status_out) {
+                    let sheet = self.live.as_ref().unwrap_or(&self.sheet);
+                    match snapshot::create(sheet, &path, argument) {
+                        Ok(snapshot) => *status_out = format!("ok, snapshot {}", snapshot.id),
+                        Err(e) => *status_out = format!("snapshot failed: {}", e),
+                    }
+                }
+            }
+            "snapshots" if argument.is_empty() => {
+                if let Some(text) = self.list_snapshots(status_out) {
+                    return CommandResult::Output(text);
+                }
+            }
+            "open_snapshot" if !argument.is_empty() => {
+                if let Some(sheet) = self.open_snapshot(argument, status_out) {
+                    let live = std::mem::replace(&mut self.sheet, sheet);
+                    self.live.get_or_insert(live);
+                }
+            }
+            "close_snapshot" if argument.is_empty() => match self.live.take() {
+                Some(live) => self.sheet = live,
+                None => *status_out = String::from("no snapshot open"),
+            },
+            "restore_snapshot" if !argument.is_empty() => {
+                if let Some(sheet) = self.open_snapshot(argument, status_out) {
+                    self.live = None;
+                    self.sheet = sheet;
+                }
+            }
+            "diff" if !argument.is_empty() => {
+                if let Some(text) = self.diff(argument, status_out) {
+                    return CommandResult::Output(text);
+                }
+            }
+            "plot" if !argument.is_empty() => {
+                if let Some(text) = self.plot(argument, status_out) {
+                    return CommandResult::Output(text);
+                }
+            }
+            _ => match command.split_once('=') {
+                Some((cell_name, formula)) => {
+                    self.sheet.undo_stack.clear();
+                    let (valid, row, col, rhs) = self.sheet.is_valid_command(cell_name, formula);
+                    if valid {
+                        self.sheet
+                            .spreadsheet_set_cell_value(row, col, rhs, status_out);
+                    } else {
+                        *status_out = String::from("invalid command");
+                    }
+                }
+                None => *status_out = String::from("invalid command"),
+            },
+        }
+        CommandResult::Continue
+    }
+
+    /// Returns the file the snapshots of the sheet belong to, or sets the status if the
+    /// sheet has never been saved.
+    fn snapshot_path(&self, status_out: &mut String) -> Option<String> {
+        if self.path.is_none() {
+            *status_out = String::from("save the sheet first");
+        }
+        self.path.clone()
+    }
+
+    /// Lists the snapshots of the sheet for the `snapshots` command.
+    fn list_snapshots(&self, status_out: &mut String) -> Option<String> {
+        let path = self.snapshot_path(status_out)?;
+        let snapshots = match snapshot::list(&path) {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                *status_out = format!("snapshots failed: {}", e);
+                return None;
+            }
+        };
+        if snapshots.is_empty() {
+            return Some(String::from("no snapshots"));
+        }
+        let lines: Vec<String> = snapshots
+            .iter()
+            .map(|snapshot| {
+                let created = chrono::DateTime::from_timestamp_millis(snapshot.created as i64)
+                    .map(|time| {
+                        time.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                format!("{:>3}  {}  {}", snapshot.id, created, snapshot.name)
+            })
+            .collect();
+        Some(lines.join("\n"))
+    }
+
+    /// Loads the snapshot with the given id or name, setting the status if there is none.
+    fn open_snapshot(&self, key: &str, status_out: &mut String) -> Option<Box<Spreadsheet>> {
+        let path = self.snapshot_path(status_out)?;
+        let snapshots = snapshot::list(&path).unwrap_or_default();
+        let Some(found) = snapshot::find(&snapshots, key) else {
+            *status_out = format!("no snapshot {}", key);
+            return None;
+        };
+        match snapshot::open(&path, found) {
+            Ok(sheet) => Some(Box::new(sheet)),
+            Err(e) => {
+                *status_out = format!("snapshot failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Compares two snapshots, or a snapshot with the live sheet, for the `diff` command.
+    ///
+    /// Snapshots whose names contain spaces are named by their id.
+    fn diff(&self, argument: &str, status_out: &mut String) -> Option<String> {
+        let keys: Vec<&str> = argument.split_whitespace().collect();
+        let (old, new) = match keys[..] {
+            [old] => (
+                self.open_snapshot(old, status_out)?,
+                self.live.clone().unwrap_or_else(|| self.sheet.clone()),
+            ),
+            [old, new] => (
+                self.open_snapshot(old, status_out)?,
+                self.open_snapshot(new, status_out)?,
+            ),
+            _ => {
+                *status_out = String::from("invalid command");
+                return None;
+            }
+        };
+        Some(snapshot::format_diff(&snapshot::diff(&old, &new)))
+    }
+
+    /// Draws a chart of ranges of the sheet for the `plot` command.
+    ///
+    /// The first word is the kind of chart and the others are ranges, as taken by
+    /// [`plot::plot`], followed by the options.
+    fn plot(&self, argument: &str, status_out: &mut String) -> Option<String> {
+        let mut words = argument.split_whitespace();
+        let Some(kind) = words.next().and_then(PlotKind::parse) else {
+            *status_out = String::from("invalid command");
+            return None;
+        };
+        let mut ranges = Vec::new();
+        let mut options = PlotOptions::default();
+        while let Some(word) = words.next() {
+            let size = |value: Option<&str>| value.and_then(|value| value.parse().ok());
+            match word {
+                "--ascii" => options.ascii = true,
+                "--width" => match size(words.next()) {
+                    Some(width) => options.width = width,
+                    None => {
+                        *status_out = String::from("invalid width");
+                        return None;
+                    }
+                },
+                "--height" => match size(words.next()) {
+                    Some(height) => options.height = height,
+                    None => {
+                        *status_out = String::from("invalid height");
+                        return None;
+                    }
+                },
+                _ if word.starts_with("--") => {
+                    *status_out = format!("unknown option {}", word);
+                    return None;
+                }
+                range => ranges.push(range),
+            }
+        }
+
+        match plot::plot(&self.sheet, kind, &ranges, &options) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                *status_out = format!("plot failed: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Scrolls the view by 10 cells in the direction given by `w`, `a`, `s` or `d`.
+    fn scroll(&mut self, direction: &str) {
+        let sheet = &mut self.sheet;
+        match direction {
+            "w" if sheet.view_row > 0 => {
+                sheet.view_row = (sheet.view_row - 10).max(0);
+            }
+            "s" if sheet.view_row < sheet.rows - 10 => {
+                sheet.view_row = (sheet.view_row + 10).min(sheet.rows - 10);
+            }
+            "a" if sheet.view_col > 0 => {
+                sheet.view_col = (sheet.view_col - 10).max(0);
+            }
+            "d" if sheet.view_col < sheet.cols - 10 => {
+                sheet.view_col = (sheet.view_col + 10).min(sheet.cols - 10);
+            }
+            _ => {}
+        }
+    }
+
+    /// Describes a cell for the `show` command: its formula, value (followed by the trend
+    /// of a `SPARKLINE`) and dependents.
+    fn describe_cell(&self, cell_name: &str) -> Option<String> {
+        let (row, col) = self.sheet.spreadsheet_parse_cell_name(cell_name)?;
+        let index = (row - 1) as usize * self.sheet.cols as usize + (col - 1) as usize;
+        let cell = self.sheet.cells[index].as_ref()?;
+
+        let formula = match (&cell.formula, &cell.spill) {
+            (_, Spill::Member(anchor_row, anchor_col)) => format!(
+                "(spilled from {})",
+                Spreadsheet::get_cell_name(*anchor_row, *anchor_col)
+            ),
+            (ParsedRHS::None, _) => String::from("(empty)"),
+            (formula, _) => Spreadsheet::formula_to_string(formula),
+        };
+        let mut value = match cell.error_text() {
+            Some(text) => text.to_string(),
+            None => cell.formatted_value(),
+        };
+        if let Some(values) = self.sheet.sparkline_values(row, col) {
+            value = format!("{}  {}", value, plot::sparkline(&values, false));
+        }
+        let mut dependents: Vec<(i16, i16)> = match &cell.dependents {
+            Dependents::Vector(vec) => vec.clone(),
+            Dependents::Set(set) => set.iter().copied().collect(),
+            Dependents::None => Vec::new(),
+        };
+        dependents.sort();
+        let dependents = if dependents.is_empty() {
+            String::from("(none)")
+        } else {
+            dependents
+                .iter()
+                .map(|&(r, c)| Spreadsheet::get_cell_name(r, c))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        Some(format!(
+            "{}\n  formula:    {}\n  value:      {}\n  dependents: {}",
+            Spreadsheet::get_cell_name(row, col),
+            formula,
+            value,
+            dependents
+        ))
+    }
+
+    /// Empties every cell in a range such as `A1:B5` (or a single cell) as one undoable step.
+    fn clear(&mut self, range: &str, status_out: &mut String) {
+        let (start, end) = range.split_once(':').unwrap_or((range, range));
+        let (Some((start_row, start_col)), Some((end_row, end_col))) = (
+            self.sheet.spreadsheet_parse_cell_name(start),
+            self.sheet.spreadsheet_parse_cell_name(end),
+        ) else {
+            *status_out = String::from("invalid range");
+            return;
+        };
+        if start_row > end_row || start_col > end_col {
+            *status_out = String::from("invalid range");
+            return;
+        }
+
+        self.sheet.undo_stack.clear();
+        let mut undo = Vec::new();
+        for r in start_row..=end_row {
+            for c in start_col..=end_col {
+                let index = (r - 1) as usize * self.sheet.cols as usize + (c - 1) as usize;
+                let empty = self.sheet.cells[index]
+                    .as_ref()
+                    .is_none_or(|cell| cell.formula == ParsedRHS::None);
+                if !empty {
+                    self.sheet
+                        .spreadsheet_set_cell_value(r, c, ParsedRHS::None, status_out);
+                    undo.append(&mut self.sheet.undo_stack);
+                }
+            }
+        }
+        self.sheet.undo_stack = undo;
+        *status_out = String::from("ok");
+    }
+
+    /// Replaces the sheet with a resized copy for the `resize <rows> <cols>` command.
+    fn resize(&mut self, argument: &str, status_out: &mut String) {
+        let dimensions: Vec<i32> = argument
+            .split_whitespace()
+            .map(|n| n.parse().unwrap_or(0))
+            .collect();
+        let [rows, cols] = dimensions[..] else {
+            *status_out = String::from("invalid dimensions");
+            return;
+        };
+        if !(1..=999).contains(&rows) || !(1..=18278).contains(&cols) {
+            *status_out = String::from("invalid dimensions");
+            return;
+        }
+        match self.sheet.spreadsheet_resize(rows as i16, cols as i16) {
+            Some((sheet, 0)) => self.sheet = sheet,
+            Some((sheet, dropped)) => {
+                self.sheet = sheet;
+                *status_out = format!("ok, {} formulas dropped", dropped);
+            }
+            None => *status_out = String::from("invalid dimensions"),
+        }
+    }
+
+    /// Replaces the sheet with an imported CSV or XLSX file for the `import` command.
+    ///
+    /// The dialect options only apply to CSV files.
+    ///
+    /// # Returns
+    /// A report of the CSV fields that could not be imported, if there are any
+    fn import(&mut self, argument: &str, status_out: &mut String) -> Option<String> {
+        let (path, mut words) = split_options(argument);
+        let mut options = loader::CsvOptions::default();
+        let mut worksheet = None;
+        while let Some(option) = words.next() {
+            let character = |value: Option<&str>| match value {
+                Some("tab") => Some('\t'),
+                Some(value) if value.chars().count() == 1 => value.chars().next(),
+                _ => None,
+            };
+            match option {
+                "--header" => options.has_header = true,
+                "--sheet" => worksheet = words.next(),
+                "--delimiter" => match character(words.next()) {
+                    Some(delimiter) => options.delimiter = delimiter,
+                    None => {
+                        *status_out = String::from("invalid delimiter");
+                        return None;
+                    }
+                },
+                "--quote" => match words.next() {
+                    Some("none") => options.quote = None,
+                    value => match character(value) {
+                        Some(quote) => options.quote = Some(quote),
+                        None => {
+                            *status_out = String::from("invalid quote");
+                            return None;
+                        }
+                    },
+                },
+                _ => {
+                    *status_out = format!("unknown option {}", option);
+                    return None;
+                }
+            }
+        }
+
+        if !path.to_lowercase().ends_with(".csv") {
+            return self.import_workbook(&path, worksheet, status_out);
+        }
+        let import = match loader::from_csv_with_options(&path, &options) {
+            Ok(import) => import,
+            Err(e) => {
+                *status_out = format!("import failed: {}", e);
+                return None;
+            }
+        };
+        self.sheet = import.sheet;
+
+        let mut report = issue_report(&import.issues);
+        if !import.ragged_rows.is_empty() {
+            let rows: Vec<String> = import.ragged_rows.iter().map(i16::to_string).collect();
+            report.push(format!(
+                "rows with a different number of fields: {}",
+                rows.join(", ")
+            ));
+        }
+        (!report.is_empty()).then(|| report.join("\n"))
+    }
+
+    /// Replaces the sheet with a worksheet of an Excel or OpenDocument workbook, the first
+    /// one unless `worksheet` names another.
+    ///
+    /// # Returns
+    /// A report of the cells that could not be imported as they are, if there are any
+    #[cfg(feature = "web")]
+    fn import_workbook(
+        &mut self,
+        path: &str,
+        worksheet: Option<&str>,
+        status_out: &mut String,
+    ) -> Option<String> {
+        let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+        if !matches!(extension.as_deref(), Some("xlsx" | "xlsm" | "xls" | "ods")) {
+            match loader::load_spreadsheet(path) {
+                Ok(sheet) => self.sheet = sheet,
+                Err(e) => *status_out = format!("import failed: {}", e),
+            }
+            return None;
+        }
+        let mut sheets = match loader::from_workbook(path) {
+            Ok(sheets) => sheets,
+            Err(e) => {
+                *status_out = format!("import failed: {}", e);
+                return None;
+            }
+        };
+        let index = match worksheet {
+            Some(name) => match sheets.iter().position(|sheet| sheet.name == name) {
+                Some(index) => index,
+                None => {
+                    *status_out = format!("no worksheet {}", name);
+                    return None;
+                }
+            },
+            None => 0,
+        };
+        let others: Vec<String> = sheets
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| i != index)
+            .map(|(_, sheet)| sheet.name.clone())
+            .collect();
+        let imported = sheets.swap_remove(index);
+        self.sheet = imported.sheet;
+
+        let mut report = issue_report(&imported.issues);
+        if !others.is_empty() {
+            report.push(format!(
+                "other worksheets (import with --sheet): {}",
+                others.join(", ")
+            ));
+        }
+        (!report.is_empty()).then(|| report.join("\n"))
+    }
+
+    /// Replaces the sheet with an imported file other than CSV, which without the `web`
+    /// feature fails for workbooks.
+    #[cfg(not(feature = "web"))]
+    fn import_workbook(
+        &mut self,
+        path: &str,
+        _worksheet: Option<&str>,
+        status_out: &mut String,
+    ) -> Option<String> {
+        match loader::load_spreadsheet(path) {
+            Ok(sheet) => self.sheet = sheet,
+            Err(e) => *status_out = format!("import failed: {}", e),
+        }
+        None
+    }
+
+    /// Writes the sheet to a CSV or XLSX file, chosen by the file extension.
+    ///
+    /// `--formulas` writes formulas rather than values and `--no-header` leaves out the row
+    /// of column letters; both only apply to CSV files.
+    fn export(&self, argument: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (path, options) = split_options(argument);
+        let mut csv_options = downloader::CsvExportOptions::default();
+        for option in options {
+            match option {
+                "--formulas" => csv_options.formulas = true,
+                "--no-header" => csv_options.header = false,
+                _ => return Err(format!("unknown option {}", option).into()),
+            }
+        }
+
+        let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+        match extension.as_deref() {
+            Some("csv") => fs::write(
+                &path,
+                downloader::to_csv_with_options(&self.sheet, &csv_options)?,
+            )?,
+            #[cfg(feature = "web")]
+            Some("xlsx") => fs::write(&path, downloader::to_xlsx(&self.sheet)?)?,
+            #[cfg(not(feature = "web"))]
+            Some("xlsx") => return Err("XLSX support requires the 'web' feature".into()),
+            _ => return Err("file must end in .csv or .xlsx".into()),
+        }
+        Ok(())
+    }
+
+    /// Returns the completions for the word ending at byte offset `pos` of `line`.
+    ///
+    /// At the start of the line commands and cell names are offered, inside a formula
+    /// function names and cell names, and in command arguments only cell names. Only
+    /// non-empty cells are offered. Cell names are only looked up in the
+    /// column named by the word typed so far, so completion stays fast on large sheets.
+    ///
+    /// # Returns
+    /// The offset at which the completed word starts, and the candidates for it
+    pub fn completions(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before
+            .rfind(|c: char| " =(,:+-*/<>".contains(c))
+            .map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let at_line_start = before[..start].trim().is_empty();
+        let in_formula = before[..start].contains('=');
+        if word.is_empty() {
+            return (start, Vec::new());
+        }
+
+        let upper = word.to_uppercase();
+        let mut candidates: Vec<String> = Vec::new();
+        if at_line_start {
+            candidates.extend(
+                COMMANDS
+                    .iter()
+                    .filter(|command| command.starts_with(word))
+                    .map(|command| command.to_string()),
+            );
+        } else if in_formula {
+            candidates.extend(
+                FUNCTION_NAMES
+                    .iter()
+                    .filter(|name| name.starts_with(&upper))
+                    .map(|name| format!("{}(", name)),
+            );
+        }
+
+        let letters: String = upper
+            .chars()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        let digits = &upper[letters.len()..];
+        if (1..=3).contains(&letters.len()) && digits.chars().all(|c| c.is_ascii_digit()) {
+            let col = Spreadsheet::letter_to_col(&letters);
+            if col >= 1 && col <= self.sheet.cols {
+                for row in 1..=self.sheet.rows {
+                    let index = (row - 1) as usize * self.sheet.cols as usize + (col - 1) as usize;
+                    let filled = self.sheet.cells[index]
+                        .as_ref()
+                        .is_some_and(|cell| cell.formula != ParsedRHS::None);
+                    let name = Spreadsheet::get_cell_name(row, col);
+                    if filled && name.starts_with(&upper) {
+                        candidates.push(name);
+                    }
+                }
+            }
+        }
+        (start, candidates)
+    }
+}
+
+/// Lists the fields or cells that could not be imported, one per line after a count.
+fn issue_report(issues: &[loader::CellIssue]) -> Vec<String> {
+    if issues.is_empty() {
+        return Vec::new();
+    }
+    let mut report = vec![format!("{} fields not imported:", issues.len())];
+    report.extend(
+        issues
+            .iter()
+            .map(|issue| format!("  {} {:?}: {}", issue.cell, issue.text, issue.reason)),
+    );
+    report
+}
+
+/// Splits the argument of a file command into the file name and the `--` options after it.
+fn split_options(argument: &str) -> (String, std::iter::Peekable<std::str::SplitWhitespace<'_>>) {
+    let mut words = argument.split_whitespace().peekable();
+    let mut path = Vec::new();
+    while let Some(word) = words.next_if(|word| !word.starts_with("--")) {
+        path.push(word);
+    }
+    (path.join(" "), words)
+}
+
+/// Returns the text printed by the `help` command.
+pub fn help_text() -> String {
+    [
+        "Commands:",
+        "  q: Quit",
+        "  w: Move up",
+        "  s: Move down",
+        "  a: Move left",
+        "  d: Move right",
+        "  disable_output: Disable output display",
+        "  enable_output: Enable output display",
+        "  scroll_to <cell>, goto <cell>: Scroll to the specified cell",
+        "  <cell>=<formula>: Set the formula for the specified cell",
+        "  UNDO: Undo the last change",
+        "  show <cell>: Show the formula, value and dependents of a cell",
+        "  clear <cell|range>: Empty a cell or a range such as A1:B5",
+        "  resize <rows> <cols>: Change the size of the sheet",
+        "  save <file>: Save the sheet (.bin.gz)",
+        "  load <file>: Load a sheet saved with save",
+        "  import <file>: Replace the sheet with a .csv, .xlsx or .ods file",
+        "    --delimiter <c|tab>, --quote <c|none>, --header: Dialect of a .csv file",
+        "    --sheet <name>: Worksheet of an .xlsx or .ods file (default the first)",
+        "  export <file>: Write the sheet to a .csv or .xlsx file",
+        "    --formulas, --no-header: Write formulas instead of values, or no header row, to a .csv file",
+        "  snapshot <name>: Take a named snapshot of the saved sheet",
+        "  snapshots: List the snapshots of the sheet",
+        "  open_snapshot <id|name>: Show a snapshot read-only",
+        "  close_snapshot: Go back to the sheet from a snapshot",
+        "  restore_snapshot <id|name>: Replace the sheet with a snapshot",
+        "  diff <id|name> [<id|name>]: Compare two snapshots, or a snapshot with the sheet",
+        "  plot <line|bar|sparkline> <range>...: Draw a chart of the ranges",
+        "    With several ranges, the first gives the X values of a line or bar chart",
+        "    --width <n>, --height <n>, --ascii: Size of the chart, and ASCII characters only",
+    ]
+    .join("\n")
+}
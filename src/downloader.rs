@@ -1,10 +1,38 @@
 #![cfg(not(tarpaulin_include))]
 
-use crate::spreadsheet::Spreadsheet;
+#[cfg(feature = "web")]
+use crate::chart::{CellRange, ChartDefinition, read_ranges};
+use crate::spreadsheet::{FunctionName, Operand, ParsedRHS, Spreadsheet};
 use std::error::Error;
+/// Options for writing a spreadsheet as CSV.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CsvExportOptions {
+    /// Whether a first row of column letters (A, B, C, ...) is written
+    pub header: bool,
+    /// Whether cells holding a formula are written as the formula, e.g. `=SUM(A1:A5)`,
+    /// rather than as its value
+    pub formulas: bool,
+}
+
+impl Default for CsvExportOptions {
+    /// A header row followed by the values of the cells.
+    fn default() -> Self {
+        CsvExportOptions {
+            header: true,
+            formulas: false,
+        }
+    }
+}
+
+/// Quotes a CSV field if it contains a comma, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains(',') || text.contains('"') || text.contains('\n') || text.contains('\r') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
 
-#[cfg(feature = "web")]
-use crate::spreadsheet::{FunctionName, Operand, ParsedRHS};
 /// Convert spreadsheet to CSV format
 ///
 /// This function exports a spreadsheet to CSV (Comma-Separated Values) format.
@@ -30,16 +58,51 @@ use crate::spreadsheet::{FunctionName, Operand, ParsedRHS};
 /// }
 /// ```
 pub fn to_csv(sheet: &Spreadsheet) -> Result<String, Box<dyn Error>> {
+    to_csv_with_options(sheet, &CsvExportOptions::default())
+}
+
+/// Convert spreadsheet to CSV format with or without a header row and formulas
+///
+/// In formula mode, cells holding a formula are written as `=` followed by the formula
+/// as typed, which `loader::from_csv` parses back. Whole numbers entered directly are
+/// written as they are, text as a quoted constant such as `="Jan"`, and cells filled by a
+/// spilling formula are left empty, since the formula fills them again on import.
+///
+/// # Arguments
+/// * `sheet` - Reference to the spreadsheet to convert
+/// * `options` - Whether to write a header row and formulas
+///
+/// # Returns
+/// * `Result<String, Box<dyn Error>>` - CSV content as a string or an error
+///
+/// # Examples
+/// ```
+/// use cop::spreadsheet::Spreadsheet;
+/// use cop::downloader::{CsvExportOptions, to_csv_with_options};
+///
+/// let mut sheet = Spreadsheet::spreadsheet_create(2, 2).unwrap();
+/// let (_, row, col, formula) = sheet.is_valid_command("B1", "A1+1");
+/// sheet.spreadsheet_set_cell_value(row, col, formula, &mut String::new());
+///
+/// let options = CsvExportOptions { header: false, formulas: true };
+/// assert_eq!(to_csv_with_options(&sheet, &options).unwrap(), ",=A1+1\n,\n");
+/// ```
+pub fn to_csv_with_options(
+    sheet: &Spreadsheet,
+    options: &CsvExportOptions,
+) -> Result<String, Box<dyn Error>> {
     let mut csv_content = String::new();
 
     // Add header row with column letters
-    for c in 1..=sheet.cols {
-        if c > 1 {
-            csv_content.push(',');
+    if options.header {
+        for c in 1..=sheet.cols {
+            if c > 1 {
+                csv_content.push(',');
+            }
+            csv_content.push_str(&column_to_letter(c as u16));
         }
-        csv_content.push_str(&column_to_letter(c as u16));
+        csv_content.push('\n');
     }
-    csv_content.push('\n');
 
     // Add data rows
     for r in 1..=sheet.rows {
@@ -50,14 +113,18 @@ pub fn to_csv(sheet: &Spreadsheet) -> Result<String, Box<dyn Error>> {
 
             let index = ((r - 1) * sheet.cols + (c - 1)) as usize;
             if let Some(cell) = &sheet.cells[index] {
-                // Handle value - escape commas, quotes, newlines as needed
-                let value = cell.value.to_string();
-                if value.contains(',') || value.contains('"') || value.contains('\n') {
-                    let escaped = value.replace("\"", "\"\"");
-                    csv_content.push_str(&format!("\"{}\"", escaped));
+                let text = if options.formulas {
+                    match &cell.formula {
+                        ParsedRHS::None => String::new(),
+                        ParsedRHS::SingleValue(Operand::Number(num)) => num.to_string(),
+                        formula => format!("={}", Spreadsheet::formula_to_string(formula)),
+                    }
+                } else if let Some(text) = cell.text() {
+                    text.to_string()
                 } else {
-                    csv_content.push_str(&value);
-                }
+                    cell.number().to_string()
+                };
+                csv_content.push_str(&csv_field(&text));
             }
         }
         csv_content.push('\n');
@@ -70,6 +137,7 @@ pub fn to_csv(sheet: &Spreadsheet) -> Result<String, Box<dyn Error>> {
 ///
 /// This function exports a spreadsheet to XLSX (Excel) format using the rust_xlsxwriter library.
 /// It preserves all cell values in a format that Microsoft Excel and other spreadsheet applications can open.
+/// The sheet is written as a single worksheet named `Sheet1`; see [`to_xlsx_workbook`].
 ///
 /// # Arguments
 /// * `sheet` - Reference to the spreadsheet to convert
@@ -89,112 +157,189 @@ pub fn to_csv(sheet: &Spreadsheet) -> Result<String, Box<dyn Error>> {
 /// ```
 #[cfg(feature = "web")]
 pub fn to_xlsx(sheet: &Spreadsheet) -> Result<Vec<u8>, Box<dyn Error>> {
-    use rust_xlsxwriter::{Workbook, Worksheet};
+    to_xlsx_workbook(&[("Sheet1", sheet)])
+}
+
+/// Translates a formula into Excel syntax.
+///
+/// Functions known to Excel under another name are renamed (`FORECAST.LINEAR` to
+/// `FORECAST`). Whole-number results are written so Excel computes them the same way:
+/// `AVG` as a truncated `AVERAGE`, the population `STDEV` as a rounded `STDEVP` and
+/// division as `QUOTIENT`.
+///
+/// # Returns
+/// * `Option<String>` - The formula with its leading `=`, or `None` for plain numbers and
+///   for formulas with no Excel equivalent (`COPY`, `SLEEP`, `SPARKLINE` and the spilling
+///   array functions), whose computed value is written instead
+///
+/// # Examples
+/// ```
+/// use cop::downloader::excel_formula;
+/// use cop::spreadsheet::Spreadsheet;
+///
+/// let sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+/// let (_, _, _, formula) = sheet.is_valid_command("B1", "AVG(A1:A5)");
+/// assert_eq!(excel_formula(&formula).unwrap(), "=TRUNC(AVERAGE(A1:A5))");
+/// ```
+pub fn excel_formula(formula: &ParsedRHS) -> Option<String> {
+    let text = Spreadsheet::formula_to_string(formula);
+    let rename = |ours: &str, excel: &str| format!("={}{}", excel, &text[ours.len()..]);
+    match formula {
+        ParsedRHS::Function { name, .. } => {
+            let excel = match name {
+                FunctionName::Sum => "SUM",
+                FunctionName::Min => "MIN",
+                FunctionName::Max => "MAX",
+                FunctionName::Avg => "AVERAGE",
+                FunctionName::Stdev => "STDEVP",
+                _ => return None,
+            };
+            let call = rename(name.as_str(), excel);
+            // The sheet divides sums with truncation and rounds deviations
+            match name {
+                FunctionName::Avg => Some(format!("=TRUNC({})", &call[1..])),
+                FunctionName::Stdev => Some(format!("=ROUND({},0)", &call[1..])),
+                _ => Some(call),
+            }
+        }
+        ParsedRHS::FunctionCall { name, .. } if !name.is_array() => {
+            let excel = match name {
+                FunctionName::ForecastLinear => "FORECAST",
+                FunctionName::Sparkline => return None,
+                other => other.as_str(),
+            };
+            Some(rename(name.as_str(), excel))
+        }
+        ParsedRHS::Arithmetic {
+            lhs,
+            operator: '/',
+            rhs,
+        } => {
+            // The sheet's division truncates towards zero, like QUOTIENT
+            let operand =
+                |op: &Operand| Spreadsheet::formula_to_string(&ParsedRHS::SingleValue(op.clone()));
+            Some(format!("=QUOTIENT({},{})", operand(lhs), operand(rhs)))
+        }
+        ParsedRHS::Arithmetic { .. } | ParsedRHS::SingleValue(Operand::Cell(..)) => {
+            Some(format!("={}", text))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the Excel error value standing for a cell in error.
+///
+/// The value follows the kind of error recorded on the cell, such as `#NUM!` for an IRR
+/// that does not converge. An array formula whose result is obstructed is written as
+/// `#VALUE!`, since `#SPILL!` cannot be entered as a value.
+#[cfg(feature = "web")]
+fn excel_error(cell: &crate::cell::Cell) -> &'static str {
+    match cell.error_text() {
+        Some("#SPILL!") => "#VALUE!",
+        _ => cell.error_kind.excel_value(),
+    }
+}
+
+/// Convert several spreadsheets to one XLSX workbook
+///
+/// Each sheet becomes a worksheet with the given name, in order. Formulas with an Excel
+/// equivalent are written as formulas together with their computed result; other
+/// formulas are written as their computed value. Cells in error are written as Excel
+/// error values, and columns are widened to fit their widest value. Charts saved in a
+/// sheet become native Excel charts reading the worksheet's cells, placed to the right of
+/// its data one below another.
+///
+/// # Arguments
+/// * `sheets` - The worksheet names and the spreadsheets to write under them
+///
+/// # Returns
+/// * `Result<Vec<u8>, Box<dyn Error>>` - XLSX file content as bytes or an error
+///
+/// # Examples
+/// ```no_run
+/// use cop::spreadsheet::Spreadsheet;
+/// use cop::downloader::to_xlsx_workbook;
+///
+/// let budget = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+/// let totals = Spreadsheet::spreadsheet_create(2, 2).unwrap();
+/// let xlsx = to_xlsx_workbook(&[("Budget", &budget), ("Totals", &totals)]).unwrap();
+/// std::fs::write("book.xlsx", xlsx).unwrap();
+/// ```
+#[cfg(feature = "web")]
+pub fn to_xlsx_workbook(sheets: &[(&str, &Spreadsheet)]) -> Result<Vec<u8>, Box<dyn Error>> {
+    use crate::cell::Spill;
+    use rust_xlsxwriter::{Formula, Workbook, Worksheet};
+
+    /// Width of a column in Excel's default font, in characters
+    const DEFAULT_WIDTH: usize = 8;
 
-    // Create a new workbook and worksheet
     let mut workbook = Workbook::new();
-    let mut worksheet = Worksheet::new();
+    for (name, sheet) in sheets {
+        let mut worksheet = Worksheet::new();
+        worksheet.set_name(*name)?;
+        let mut widths = vec![DEFAULT_WIDTH; sheet.cols as usize];
 
-    // Write cell data
-    for r in 1..=sheet.rows {
-        for c in 1..=sheet.cols {
-            let index = ((r - 1) * sheet.cols + (c - 1)) as usize;
-            if let Some(cell) = &sheet.cells[index] {
-                // Check if the cell has a formula
-                match &cell.formula {
-                    ParsedRHS::Function {
-                        name,
-                        args: (arg1, arg2),
-                    } => {
-                        // Convert our internal formula to Excel formula syntax
-                        let excel_formula = match name {
-                            FunctionName::Sum => format!(
-                                "=SUM({cell_range})",
-                                cell_range = convert_range_to_excel(arg1, arg2)
-                            ),
-                            FunctionName::Min => format!(
-                                "=MIN({cell_range})",
-                                cell_range = convert_range_to_excel(arg1, arg2)
-                            ),
-                            FunctionName::Max => format!(
-                                "=MAX({cell_range})",
-                                cell_range = convert_range_to_excel(arg1, arg2)
-                            ),
-                            FunctionName::Avg => format!(
-                                "=AVERAGE({cell_range})",
-                                cell_range = convert_range_to_excel(arg1, arg2)
-                            ),
-                            FunctionName::Stdev => format!(
-                                "=STDEV({cell_range})",
-                                cell_range = convert_range_to_excel(arg1, arg2)
-                            ),
-                            // For COPY function, just write the value since Excel doesn't have a direct equivalent
-                            FunctionName::Copy => {
-                                // worksheet.write_number((r - 1) as u32, (c - 1) as u16, cell.value)?;
-                                continue;
-                            }
-                        };
-
-                        // Use as_str() to convert String to &str
-                        worksheet.write_formula(
-                            (r - 1) as u32,
-                            (c - 1) as u16,
-                            excel_formula.as_str(),
-                        )?;
-                        worksheet.write_number((r - 1) as u32, (c - 1) as u16, cell.value)?;
-                    }
-                    ParsedRHS::Arithmetic { lhs, operator, rhs } => {
-                        // Convert arithmetic to Excel formula
-                        let left = operand_to_excel_ref(lhs);
-                        let right = operand_to_excel_ref(rhs);
-                        let excel_formula = format!("={}{}{}", left, operator, right);
+        for r in 1..=sheet.rows {
+            for c in 1..=sheet.cols {
+                let index = ((r - 1) * sheet.cols + (c - 1)) as usize;
+                let Some(cell) = &sheet.cells[index] else {
+                    continue;
+                };
+                let spilled = matches!(cell.spill, Spill::Member(..));
+                if cell.formula == ParsedRHS::None && !spilled {
+                    continue;
+                }
+                let (row, col) = ((r - 1) as u32, (c - 1) as u16);
 
-                        // Use as_str() to convert String to &str
+                let shown = match cell.error_text() {
+                    Some(_) => excel_error(cell).to_string(),
+                    None => cell.formatted_value(),
+                };
+                let width = &mut widths[col as usize];
+                *width = (*width).max(shown.len());
+
+                match (excel_formula(&cell.formula), cell.error) {
+                    (Some(formula), _) => {
                         worksheet.write_formula(
-                            (r - 1) as u32,
-                            (c - 1) as u16,
-                            excel_formula.as_str(),
+                            row,
+                            col,
+                            Formula::new(formula).set_result(shown),
                         )?;
-                        worksheet.write_number((r - 1) as u32, (c - 1) as u16, cell.value)?;
-                    }
-                    ParsedRHS::SingleValue(operand) => {
-                        match operand {
-                            Operand::Cell(ref_row, ref_col) => {
-                                // Cell reference
-                                let cell_ref =
-                                    format!("={}", Spreadsheet::get_cell_name(*ref_row, *ref_col));
-                                // Use as_str() to convert String to &str
-                                worksheet.write_formula(
-                                    (r - 1) as u32,
-                                    (c - 1) as u16,
-                                    cell_ref.as_str(),
-                                )?;
-                                worksheet.write_number(
-                                    (r - 1) as u32,
-                                    (c - 1) as u16,
-                                    cell.value,
-                                )?;
-                            }
-                            Operand::Number(_) => {
-                                // Simple number
-                                worksheet.write_number(
-                                    (r - 1) as u32,
-                                    (c - 1) as u16,
-                                    cell.value,
-                                )?;
-                            }
-                        }
                     }
-                    // Handle Sleep or None by just writing the value
-                    _ => {
-                        worksheet.write_number((r - 1) as u32, (c - 1) as u16, cell.value)?;
+                    (None, true) => {
+                        let error = excel_error(cell);
+                        let formula = Formula::new(format!("={}", error)).set_result(error);
+                        worksheet.write_formula(row, col, formula)?;
                     }
+                    (None, false) => match cell.text() {
+                        Some(text) => {
+                            worksheet.write_string(row, col, text)?;
+                        }
+                        None => {
+                            worksheet.write_number(row, col, cell.number())?;
+                        }
+                    },
                 }
             }
         }
-    }
 
-    workbook.push_worksheet(worksheet);
+        for (col, width) in widths.into_iter().enumerate() {
+            if width > DEFAULT_WIDTH {
+                worksheet.set_column_width(col as u16, (width + 1) as f64)?;
+            }
+        }
+
+        let mut top = 0;
+        for saved in &sheet.charts {
+            let Some(chart) = excel_chart(name, sheet, &saved.definition) else {
+                continue;
+            };
+            worksheet.insert_chart(top, sheet.cols as u16 + 1, &chart)?;
+            // Rows are 20 pixels high by default
+            top += saved.definition.height.unwrap_or(600).div_ceil(20) + 1;
+        }
+        workbook.push_worksheet(worksheet);
+    }
 
     // Save to memory buffer
     let buffer = workbook.save_to_buffer()?;
@@ -202,29 +347,146 @@ pub fn to_xlsx(sheet: &Spreadsheet) -> Result<Vec<u8>, Box<dyn Error>> {
     Ok(buffer)
 }
 
+/// Builds a native Excel chart from a chart saved in a sheet.
+///
+/// The series refer to the cells of the worksheet, so the chart follows later edits made
+/// in Excel. Names given to series and headers become the series names, and the
+/// trendline of a line or scatter chart becomes an Excel trendline of every series.
+///
+/// # Returns
+/// * `Option<Chart>` - The chart, or `None` for histograms, box plots and heatmaps, which
+///   have no Excel equivalent, and for charts whose ranges cannot be read
 #[cfg(feature = "web")]
-// Helper function to convert our operand to Excel reference
-fn operand_to_excel_ref(operand: &Operand) -> String {
-    match operand {
-        Operand::Cell(row, col) => Spreadsheet::get_cell_name(*row, *col),
-        Operand::Number(n) => n.to_string(),
+fn excel_chart(
+    sheet_name: &str,
+    sheet: &Spreadsheet,
+    definition: &ChartDefinition,
+) -> Option<rust_xlsxwriter::Chart> {
+    use rust_xlsxwriter::{Chart, ChartType};
+
+    let chart_type = match definition.graph_type.as_str() {
+        "Histogram" | "BoxPlot" | "Heatmap" => return None,
+        "Bar" | "GroupedBar" => ChartType::Column,
+        "StackedBar" => ChartType::ColumnStacked,
+        "Area" => ChartType::Area,
+        "Scatter" => ChartType::Scatter,
+        "Pie" => ChartType::Pie,
+        "Donut" => ChartType::Doughnut,
+        _ => ChartType::Line,
+    };
+    let data = read_ranges(
+        sheet,
+        &definition.x_range,
+        &definition.y_ranges(),
+        definition.header,
+        true,
+    )
+    .ok()?;
+
+    let trendline = match chart_type {
+        ChartType::Line | ChartType::Scatter => excel_trendline(definition),
+        _ => None,
+    };
+
+    // Further series follow `y_range` when it is given
+    let offset = usize::from(!definition.y_range.is_empty());
+    let mut chart = Chart::new(chart_type);
+    for (k, column) in data.columns.iter().enumerate() {
+        let request = column
+            .source
+            .checked_sub(offset)
+            .and_then(|i| definition.series.get(i));
+        let (first_row, first_col, last_row, last_col) = excel_range(column.range, data.header);
+        let series = chart
+            .add_series()
+            .set_values((sheet_name, first_row, first_col, last_row, last_col));
+        if let Some(x_range) = data.x_range {
+            let (first_row, first_col, last_row, last_col) = excel_range(x_range, data.header);
+            series.set_categories((sheet_name, first_row, first_col, last_row, last_col));
+        }
+        match request.and_then(|request| request.name.as_ref()) {
+            Some(name) if !column.block => series.set_name(name),
+            _ if column.header.is_some() => {
+                let (row, col) = column.range.start;
+                series.set_name((sheet_name, (row - 1) as u32, (col - 1) as u16))
+            }
+            _ => series.set_name(&format!("Series {}", k + 1)),
+        };
+        if request.is_some_and(|request| request.secondary_axis) {
+            series.set_secondary_axis(true);
+        }
+        if let Some(trendline) = &trendline {
+            series.set_trendline(trendline);
+        }
+    }
+
+    if !definition.title.is_empty() {
+        chart.title().set_name(&definition.title);
+    }
+    if !definition.x_label.is_empty() {
+        chart.x_axis().set_name(&definition.x_label);
+    }
+    if !definition.y_label.is_empty() {
+        chart.y_axis().set_name(&definition.y_label);
+    }
+    if !definition.y2_label.is_empty() {
+        chart.y2_axis().set_name(&definition.y2_label);
+    }
+    if definition.legend == Some(false) {
+        chart.legend().set_hidden();
     }
+    chart
+        .set_width(definition.width.unwrap_or(800))
+        .set_height(definition.height.unwrap_or(600));
+    Some(chart)
 }
 
+/// Builds the Excel trendline of a chart definition, showing the equation and R² of
+/// fitted curves unless they are turned off
+///
+/// # Returns
+/// * `Option<ChartTrendline>` - The trendline, or `None` when the definition has none or
+///   one Excel cannot draw
 #[cfg(feature = "web")]
-// Helper function to convert our range representation to Excel range format (A1:B2)
-fn convert_range_to_excel(start: &Operand, end: &Operand) -> String {
-    match (start, end) {
-        (Operand::Cell(start_row, start_col), Operand::Cell(end_row, end_col)) => {
-            format!(
-                "{}:{}",
-                Spreadsheet::get_cell_name(*start_row, *start_col),
-                Spreadsheet::get_cell_name(*end_row, *end_col)
-            )
+fn excel_trendline(definition: &ChartDefinition) -> Option<rust_xlsxwriter::ChartTrendline> {
+    use crate::graph::{Trendline, parse_trendline};
+    use rust_xlsxwriter::{ChartTrendline, ChartTrendlineType};
+
+    let trend_type = match parse_trendline(definition.trendline.as_deref()?)? {
+        Trendline::Linear => ChartTrendlineType::Linear,
+        Trendline::Exponential => ChartTrendlineType::Exponential,
+        Trendline::Polynomial(degree @ 2..=6) => ChartTrendlineType::Polynomial(degree as u8),
+        Trendline::MovingAverage(period @ 2..=255) => {
+            ChartTrendlineType::MovingAverage(period as u8)
         }
-        // Fallback for cases that don't fit the expected pattern
-        _ => String::from("A1"),
+        _ => return None,
+    };
+    let fitted = !matches!(trend_type, ChartTrendlineType::MovingAverage(_));
+    let equation = fitted && definition.trendline_equation != Some(false);
+    let mut trendline = ChartTrendline::new();
+    trendline
+        .set_type(trend_type)
+        .display_equation(equation)
+        .display_r_squared(equation);
+    Some(trendline)
+}
+
+/// Returns the zero-based first row, first column, last row and last column of a range,
+/// leaving out its first cell when that is a header
+#[cfg(feature = "web")]
+fn excel_range(range: CellRange, header: bool) -> (u32, u16, u32, u16) {
+    let (mut first_row, mut first_col) = range.start;
+    if header && range.start.0 == range.end.0 && range.start.1 < range.end.1 {
+        first_col += 1;
+    } else if header {
+        first_row += 1;
     }
+    (
+        (first_row - 1) as u32,
+        (first_col - 1) as u16,
+        (range.end.0 - 1) as u32,
+        (range.end.1 - 1) as u16,
+    )
 }
 
 /// Convert column number to letter (A=1, B=2, etc.)
@@ -1,14 +1,26 @@
 #![cfg(not(tarpaulin_include))]
 #![cfg(feature = "web")]
+use crate::cell::Cell;
+use crate::chart::{CellRange, read_ranges};
+use crate::pdf::PdfBackend;
 use crate::spreadsheet::Spreadsheet;
+use image::ColorType;
+use image::ImageEncoder;
+use image::codecs::png::PngEncoder;
+use plotters::chart::{DualCoordChartContext, MeshStyle, SeriesLabelStyle};
+use plotters::coord::Shift;
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::coord::types::RangedCoordf64;
+use plotters::element::{DashedPathElement, Drawable, PointCollection};
 use plotters::prelude::*;
-use std::fs::remove_file;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use plotters_backend::{BackendCoord, DrawingErrorKind};
 
 /// Available graph types supported by the application
 ///
 /// This enum defines the different visualization formats that can be generated
 /// from spreadsheet data.
-#[derive(Clone, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum GraphType {
     /// Line graph - Shows trends over time or continuous data with lines
     /// connecting data points
@@ -22,6 +34,449 @@ pub enum GraphType {
 
     /// Area graph - Similar to line graphs but with the area below the line filled in
     Area,
+
+    /// Grouped bar graph - Draws the bars of several series side by side, as `Bar` does
+    GroupedBar,
+
+    /// Stacked bar graph - Stacks the bars of several series on top of each other,
+    /// negative values below zero
+    StackedBar,
+
+    /// Pie chart - Shows the share of each value of a single series in its total, with
+    /// the X values as slice labels
+    Pie,
+
+    /// Donut chart - A pie chart with a hole in the middle
+    Donut,
+
+    /// Histogram - Counts the values of each series in bins of equal width
+    Histogram,
+
+    /// Box plot - Shows the quartiles of each series as a box, with whiskers reaching
+    /// the furthest values within 1.5 times the interquartile range and outliers beyond
+    BoxPlot,
+
+    /// Heatmap - Colours every cell of a 2-D range by its value
+    Heatmap,
+}
+
+impl GraphType {
+    /// Returns true if series of this type can be drawn together with series of other
+    /// types on the same X and Y axes
+    pub fn is_combinable(&self) -> bool {
+        matches!(
+            self,
+            GraphType::Line
+                | GraphType::Bar
+                | GraphType::Scatter
+                | GraphType::Area
+                | GraphType::GroupedBar
+                | GraphType::StackedBar
+        )
+    }
+}
+
+/// Output formats a graph can be rendered to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphFormat {
+    /// PNG bitmap image
+    Png,
+
+    /// SVG vector image, suited to web pages and scaling
+    Svg,
+
+    /// Single-page PDF document with vector graphics, suited to reports and printing
+    Pdf,
+}
+
+impl GraphFormat {
+    /// Returns the MIME type of the format, for the `Content-Type` of a response
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            GraphFormat::Png => "image/png",
+            GraphFormat::Svg => "image/svg+xml",
+            GraphFormat::Pdf => "application/pdf",
+        }
+    }
+
+    /// Returns the usual file extension of the format, without the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Png => "png",
+            GraphFormat::Svg => "svg",
+            GraphFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// Colour schemes for the background, text, axes and gridlines of a graph
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Theme {
+    /// Dark text on a white background
+    Light,
+
+    /// Light text on a dark grey background
+    Dark,
+}
+
+impl Theme {
+    /// Returns the background colour of the theme
+    pub fn background(&self) -> RGBColor {
+        match self {
+            Theme::Light => WHITE,
+            Theme::Dark => RGBColor(32, 33, 36),
+        }
+    }
+
+    /// Returns the colour of text, axes and gridlines in the theme
+    pub fn foreground(&self) -> RGBColor {
+        match self {
+            Theme::Light => BLACK,
+            Theme::Dark => RGBColor(232, 234, 237),
+        }
+    }
+}
+
+/// Shapes drawn at the data points of line, area and scatter series
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MarkerStyle {
+    /// Filled circle
+    Circle,
+
+    /// Filled square
+    Square,
+
+    /// Filled triangle pointing up
+    Triangle,
+
+    /// Diagonal cross
+    Cross,
+}
+
+/// How numbers are written on the value axes and in data labels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TickFormat {
+    /// As chosen by the plotting library for ticks, and as plain numbers in labels
+    Auto,
+
+    /// With a fixed number of decimal places
+    Decimal(usize),
+
+    /// Rounded to a whole number, with commas between groups of thousands
+    Thousands,
+
+    /// Followed by a percent sign, for values that are already percentages
+    Percent,
+
+    /// In scientific notation, e.g. `1.5e3`
+    Scientific,
+}
+
+impl TickFormat {
+    /// Writes a number in this format
+    pub fn format(&self, value: f64) -> String {
+        match *self {
+            TickFormat::Auto => value.to_string(),
+            TickFormat::Decimal(places) => format!("{:.*}", places, value),
+            TickFormat::Thousands => {
+                let digits = (value.round() as i64).unsigned_abs().to_string();
+                let mut grouped = String::new();
+                for (i, digit) in digits.chars().enumerate() {
+                    if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                        grouped.push(',');
+                    }
+                    grouped.push(digit);
+                }
+                if value.round() < 0.0 {
+                    grouped.insert(0, '-');
+                }
+                grouped
+            }
+            TickFormat::Percent => format!("{}%", value),
+            TickFormat::Scientific => format!("{:e}", value),
+        }
+    }
+}
+
+/// Curves fitted to the points of line and scatter series
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trendline {
+    /// Straight line fitted by least squares
+    Linear,
+
+    /// Polynomial of the given degree, from 2 to 6, fitted by least squares
+    Polynomial(usize),
+
+    /// Exponential curve `y = a·e^(bx)`, fitted by least squares to the logarithms of
+    /// the values, which must all be greater than zero
+    Exponential,
+
+    /// Average of each value and the ones before it, over a period of at least 2 points
+    MovingAverage(usize),
+}
+
+/// A curve fitted to the points of a series, with its coefficient of determination
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrendFit {
+    /// The fitted curve
+    pub curve: TrendCurve,
+
+    /// Coefficient of determination (R²) of the fit: 1 for a perfect fit, and for an
+    /// exponential curve that of the straight line fitted to the logarithms, as in Excel
+    pub r_squared: f64,
+}
+
+/// Equation of a fitted trendline
+#[derive(Clone, Debug, PartialEq)]
+pub enum TrendCurve {
+    /// `y = c0 + c1·x + c2·x² + ...`, with the coefficients lowest power first; a
+    /// straight line has two
+    Polynomial(Vec<f64>),
+
+    /// `y = a·e^(bx)`
+    Exponential { a: f64, b: f64 },
+}
+
+impl TrendCurve {
+    /// Returns the value of the curve at x
+    pub fn value_at(&self, x: f64) -> f64 {
+        match self {
+            TrendCurve::Polynomial(coefficients) => coefficients
+                .iter()
+                .rev()
+                .fold(0.0, |value, coefficient| value * x + coefficient),
+            TrendCurve::Exponential { a, b } => a * (b * x).exp(),
+        }
+    }
+
+    /// Writes the equation of the curve with four significant digits, e.g.
+    /// `y = 0.5x^2 - 2x + 1` or `y = 1.5e^(0.3x)`
+    pub fn equation(&self) -> String {
+        match self {
+            TrendCurve::Polynomial(coefficients) => {
+                let mut equation = String::from("y =");
+                for (power, &coefficient) in coefficients.iter().enumerate().rev() {
+                    let rounded = significant(coefficient);
+                    if rounded == 0.0 && (power > 0 || equation.len() > 3) {
+                        continue;
+                    }
+                    let sign = match (equation.len() > 3, rounded < 0.0) {
+                        (false, false) => " ",
+                        (false, true) => " -",
+                        (true, false) => " + ",
+                        (true, true) => " - ",
+                    };
+                    let magnitude = rounded.abs();
+                    let number = if magnitude == 1.0 && power > 0 {
+                        String::new()
+                    } else {
+                        magnitude.to_string()
+                    };
+                    let variable = match power {
+                        0 => String::new(),
+                        1 => String::from("x"),
+                        _ => format!("x^{}", power),
+                    };
+                    equation.push_str(&format!("{}{}{}", sign, number, variable));
+                }
+                equation
+            }
+            TrendCurve::Exponential { a, b } => {
+                format!("y = {}e^({}x)", significant(*a), significant(*b))
+            }
+        }
+    }
+}
+
+/// Rounds a number to four significant digits
+fn significant(value: f64) -> f64 {
+    if value == 0.0 || !value.is_finite() {
+        return value;
+    }
+    let scale = 10f64.powi(3 - value.abs().log10().floor() as i32);
+    // Adding zero turns -0 into 0
+    (value * scale).round() / scale + 0.0
+}
+
+/// Fits a trendline to some points
+///
+/// # Arguments
+/// * `trendline` - Linear, polynomial or exponential trendline to fit
+/// * `points` - The (x, y) points of the series, in any order
+///
+/// # Returns
+/// * `Some(fit)` - The fitted curve and its R²
+/// * `None` - For a moving average, which is not a fitted curve, when there are not
+///   enough distinct x values to determine the curve, or for an exponential trendline
+///   when a value is zero or less
+///
+/// # Examples
+/// ```
+/// use cop::graph::{TrendCurve, Trendline, fit_trendline};
+///
+/// let fit = fit_trendline(Trendline::Linear, &[(1.0, 3.0), (2.0, 5.0), (3.0, 7.0)]).unwrap();
+/// assert_eq!(fit.curve.equation(), "y = 2x + 1");
+/// assert!((fit.r_squared - 1.0).abs() < 1e-9);
+/// ```
+pub fn fit_trendline(trendline: Trendline, points: &[(f64, f64)]) -> Option<TrendFit> {
+    match trendline {
+        Trendline::Linear => fit_polynomial(points, 1),
+        Trendline::Polynomial(degree) => fit_polynomial(points, degree),
+        Trendline::Exponential => {
+            if points.iter().any(|&(_, y)| y <= 0.0) {
+                return None;
+            }
+            let logarithms: Vec<(f64, f64)> = points.iter().map(|&(x, y)| (x, y.ln())).collect();
+            let TrendFit { curve, r_squared } = fit_polynomial(&logarithms, 1)?;
+            let TrendCurve::Polynomial(coefficients) = curve else {
+                return None;
+            };
+            Some(TrendFit {
+                curve: TrendCurve::Exponential {
+                    a: coefficients[0].exp(),
+                    b: coefficients[1],
+                },
+                r_squared,
+            })
+        }
+        Trendline::MovingAverage(_) => None,
+    }
+}
+
+/// Fits a polynomial of some degree to points by least squares
+///
+/// The normal equations are solved by Gaussian elimination with partial pivoting, after
+/// centring and scaling x to keep them well conditioned.
+fn fit_polynomial(points: &[(f64, f64)], degree: usize) -> Option<TrendFit> {
+    let n = points.len() as f64;
+    let mean = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let scale = points
+        .iter()
+        .map(|&(x, _)| (x - mean).abs())
+        .fold(0.0, f64::max);
+    if points.len() <= degree || scale == 0.0 {
+        return None;
+    }
+
+    // Normal equations in u = (x - mean) / scale, as an augmented matrix
+    let size = degree + 1;
+    let mut matrix = vec![vec![0.0; size + 1]; size];
+    for &(x, y) in points {
+        let u = (x - mean) / scale;
+        let powers: Vec<f64> = (0..=2 * degree).map(|k| u.powi(k as i32)).collect();
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().take(size).enumerate() {
+                *cell += powers[i + j];
+            }
+            row[size] += y * powers[i];
+        }
+    }
+    for column in 0..size {
+        let pivot = (column..size)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))?;
+        if matrix[pivot][column].abs() < 1e-12 * n {
+            // Fewer distinct x values than coefficients
+            return None;
+        }
+        matrix.swap(column, pivot);
+        for row in 0..size {
+            if row != column {
+                let factor = matrix[row][column] / matrix[column][column];
+                let pivot_row = matrix[column].clone();
+                for (cell, pivot_cell) in matrix[row].iter_mut().zip(pivot_row).skip(column) {
+                    *cell -= factor * pivot_cell;
+                }
+            }
+        }
+    }
+    let scaled: Vec<f64> = (0..size).map(|i| matrix[i][size] / matrix[i][i]).collect();
+
+    // Expand the polynomial in u back into one in x, where u = (x - mean) / scale
+    let mut coefficients = vec![0.0; size];
+    let mut power = vec![1.0];
+    for scaled_coefficient in scaled {
+        for (k, term) in power.iter().enumerate() {
+            coefficients[k] += scaled_coefficient * term;
+        }
+        // Multiply by (x - mean) / scale
+        let mut next = vec![0.0; power.len() + 1];
+        for (k, term) in power.iter().enumerate() {
+            next[k + 1] += term / scale;
+            next[k] -= term * mean / scale;
+        }
+        power = next;
+    }
+
+    // Drop the rounding error left in terms that contribute nothing over the points
+    let largest_x = points.iter().map(|&(x, _)| x.abs()).fold(0.0, f64::max);
+    let largest_y = points.iter().map(|&(_, y)| y.abs()).fold(0.0, f64::max);
+    for (power, coefficient) in coefficients.iter_mut().enumerate() {
+        if (*coefficient * largest_x.powi(power as i32)).abs() < 1e-9 * largest_y {
+            *coefficient = 0.0;
+        }
+    }
+
+    let curve = TrendCurve::Polynomial(coefficients);
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let total: f64 = points.iter().map(|&(_, y)| (y - mean_y).powi(2)).sum();
+    let residual: f64 = points
+        .iter()
+        .map(|&(x, y)| (y - curve.value_at(x)).powi(2))
+        .sum();
+    let r_squared = if total == 0.0 {
+        1.0
+    } else {
+        (1.0 - residual / total).max(0.0)
+    };
+    Some(TrendFit { curve, r_squared })
+}
+
+/// Returns the moving average of points over a period
+///
+/// The points are sorted by x, and each point from the `period`-th on is replaced by the
+/// average of its value and the `period - 1` values before it, as in Excel. There are
+/// no points when the series is shorter than the period.
+///
+/// # Examples
+/// ```
+/// use cop::graph::moving_average;
+///
+/// let points = [(1.0, 2.0), (2.0, 4.0), (3.0, 9.0), (4.0, 1.0)];
+/// assert_eq!(moving_average(&points, 2), vec![(2.0, 3.0), (3.0, 6.5), (4.0, 5.0)]);
+/// ```
+pub fn moving_average(points: &[(f64, f64)], period: usize) -> Vec<(f64, f64)> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+    if period == 0 {
+        return Vec::new();
+    }
+    sorted
+        .windows(period)
+        .map(|window| {
+            let x = window[period - 1].0;
+            (
+                x,
+                window.iter().map(|&(_, y)| y).sum::<f64>() / period as f64,
+            )
+        })
+        .collect()
+}
+
+/// Parses a trendline: `linear`, `exponential`, `polynomial:N` for degree N, or
+/// `moving_average:N` for a period of N points
+///
+/// # Returns
+/// * The trendline, or None if the text names none
+pub fn parse_trendline(text: &str) -> Option<Trendline> {
+    let text = text.trim().to_ascii_lowercase();
+    let number = |prefix: &str| text.strip_prefix(prefix).and_then(|n| n.parse().ok());
+    match text.as_str() {
+        "linear" => Some(Trendline::Linear),
+        "exponential" => Some(Trendline::Exponential),
+        _ => number("polynomial:")
+            .map(Trendline::Polynomial)
+            .or_else(|| number("moving_average:").map(Trendline::MovingAverage)),
+    }
 }
 
 /// Configuration options for graph generation
@@ -47,6 +502,230 @@ pub struct GraphOptions {
 
     /// Type of graph to generate
     pub graph_type: GraphType,
+
+    /// Format of the generated image
+    pub format: GraphFormat,
+
+    /// Label for the secondary Y-axis on the right, drawn when a series uses it
+    pub y2_label: String,
+
+    /// Whether the first cell of every range is a header rather than data; the header
+    /// of a Y range names its series. Ranges whose first Y cell holds text always have
+    /// headers
+    pub header: bool,
+
+    /// Whether to draw a legend when there are several series or a named one
+    pub legend: bool,
+
+    /// Number of histogram bins, or 0 to choose it from the number of values
+    pub bins: usize,
+
+    /// Colours given to series without a colour of their own, in order
+    pub palette: Vec<RGBColor>,
+
+    /// Width of lines and area outlines in pixels
+    pub line_width: u32,
+
+    /// Marker drawn at every point of line, area and scatter series; without one,
+    /// scatter series are drawn as circles and the other series have no markers
+    pub marker: Option<MarkerStyle>,
+
+    /// Radius of the markers in pixels
+    pub marker_size: u32,
+
+    /// Whether to draw gridlines at the X-axis ticks
+    pub x_grid: bool,
+
+    /// Whether to draw gridlines at the Y-axis ticks
+    pub y_grid: bool,
+
+    /// Whether the Y-axes of line, bar, scatter and area graphs have a logarithmic
+    /// scale; every value must then be greater than zero
+    pub log_scale: bool,
+
+    /// Lowest value on the X-axis, or None to fit the data; for a histogram, the
+    /// start of the first bin
+    pub x_min: Option<f64>,
+
+    /// Highest value on the X-axis, or None to fit the data; for a histogram, the
+    /// end of the last bin
+    pub x_max: Option<f64>,
+
+    /// Lowest value on the Y-axis, or None to fit the data
+    pub y_min: Option<f64>,
+
+    /// Highest value on the Y-axis, or None to fit the data
+    pub y_max: Option<f64>,
+
+    /// How numbers are written on the value axes and in data labels
+    pub tick_format: TickFormat,
+
+    /// Whether to write the value of every point, bar or heatmap cell next to it
+    pub data_labels: bool,
+
+    /// Colour scheme of the background, text and axes
+    pub theme: Theme,
+
+    /// Font size of the title in pixels
+    pub title_size: u32,
+
+    /// Trendline drawn for every line and scatter series, or None for no trendlines
+    pub trendline: Option<Trendline>,
+
+    /// Whether to write the equation and R² of fitted trendlines on the graph
+    pub trendline_equation: bool,
+}
+
+/// One series of Y values drawn on a graph
+#[derive(Clone, Debug)]
+pub struct Series {
+    /// Range holding the Y values (e.g., "B1:B10"); a block such as "B1:D10" is drawn as
+    /// one series per column
+    pub range: String,
+
+    /// Name shown in the legend; taken from the header cell, or numbered, if not given.
+    /// Ignored for a block, whose columns are named by their headers
+    pub name: Option<String>,
+
+    /// Colour of the series; taken from the palette in order if not given. Ignored for a
+    /// block, whose columns are coloured from the palette
+    pub color: Option<RGBColor>,
+
+    /// How the series is drawn; the graph's type if not given
+    pub graph_type: Option<GraphType>,
+
+    /// Whether the series is plotted against the secondary Y-axis on the right
+    pub secondary_axis: bool,
+}
+
+impl Series {
+    /// Creates a series for a range, drawn with the graph's defaults
+    pub fn new(range: &str) -> Self {
+        Self {
+            range: range.to_string(),
+            name: None,
+            color: None,
+            graph_type: None,
+            secondary_axis: false,
+        }
+    }
+}
+
+/// Colours given to series without a colour of their own, in order
+pub const PALETTE: [RGBColor; 8] = [
+    RGBColor(0, 0, 255),
+    RGBColor(220, 20, 60),
+    RGBColor(0, 160, 0),
+    RGBColor(255, 140, 0),
+    RGBColor(128, 0, 160),
+    RGBColor(0, 170, 170),
+    RGBColor(140, 90, 40),
+    RGBColor(110, 110, 110),
+];
+
+/// Largest width and height of a graph in pixels
+pub const MAX_GRAPH_SIZE: u32 = 4000;
+
+/// Palette suited to readers with colour vision deficiencies (Okabe and Ito)
+const COLORBLIND_PALETTE: [RGBColor; 8] = [
+    RGBColor(0, 114, 178),
+    RGBColor(230, 159, 0),
+    RGBColor(0, 158, 115),
+    RGBColor(213, 94, 0),
+    RGBColor(86, 180, 233),
+    RGBColor(204, 121, 167),
+    RGBColor(240, 228, 66),
+    RGBColor(0, 0, 0),
+];
+
+/// Palette of light colours
+const PASTEL_PALETTE: [RGBColor; 8] = [
+    RGBColor(174, 198, 232),
+    RGBColor(255, 187, 120),
+    RGBColor(152, 223, 138),
+    RGBColor(255, 152, 150),
+    RGBColor(197, 176, 213),
+    RGBColor(196, 156, 148),
+    RGBColor(247, 182, 210),
+    RGBColor(199, 199, 199),
+];
+
+/// Palette of greys, for printing in black and white
+const GRAYSCALE_PALETTE: [RGBColor; 5] = [
+    RGBColor(0, 0, 0),
+    RGBColor(90, 90, 90),
+    RGBColor(140, 140, 140),
+    RGBColor(60, 60, 60),
+    RGBColor(180, 180, 180),
+];
+
+/// Returns one of the built-in palettes by name
+///
+/// The palettes are `default`, `colorblind`, `pastel` and `grayscale`.
+pub fn named_palette(name: &str) -> Option<&'static [RGBColor]> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "default" => Some(&PALETTE),
+        "colorblind" => Some(&COLORBLIND_PALETTE),
+        "pastel" => Some(&PASTEL_PALETTE),
+        "grayscale" => Some(&GRAYSCALE_PALETTE),
+        _ => None,
+    }
+}
+
+/// Parses a tick format: `auto`, `thousands`, `percent`, `scientific`, or `decimal:N`
+/// for N decimal places
+///
+/// # Returns
+/// * The tick format, or None if the text names none
+pub fn parse_tick_format(text: &str) -> Option<TickFormat> {
+    let text = text.trim().to_ascii_lowercase();
+    match text.as_str() {
+        "auto" => Some(TickFormat::Auto),
+        "thousands" => Some(TickFormat::Thousands),
+        "percent" => Some(TickFormat::Percent),
+        "scientific" => Some(TickFormat::Scientific),
+        _ => text
+            .strip_prefix("decimal:")
+            .and_then(|places| places.parse().ok())
+            .map(TickFormat::Decimal),
+    }
+}
+
+/// Data points of a graph, ready to be drawn
+struct GraphData {
+    /// X value of every point
+    x: Vec<f64>,
+    /// Labels of the X values 1, 2, 3 and so on when the X range holds text, or empty
+    categories: Vec<String>,
+    /// The series drawn against the X values
+    series: Vec<SeriesData>,
+}
+
+/// Values and resolved style of one series
+struct SeriesData {
+    /// Name shown in the legend
+    name: String,
+    /// Y value of every point
+    values: Vec<f64>,
+    /// Colour of the series
+    color: RGBColor,
+    /// How the series is drawn
+    graph_type: GraphType,
+    /// Whether the series uses the secondary Y-axis
+    secondary_axis: bool,
+}
+
+/// Parses a colour given as `#rrggbb` or `rrggbb`
+///
+/// # Returns
+/// * The colour, or None if the text is not six hexadecimal digits
+pub fn parse_color(text: &str) -> Option<RGBColor> {
+    let hex = text.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some(RGBColor(component(0)?, component(2)?, component(4)?))
 }
 
 impl Default for GraphOptions {
@@ -57,6 +736,13 @@ impl Default for GraphOptions {
     ///   - Line graph type
     ///   - 800x600 pixel dimensions
     ///   - Generic labels
+    ///   - PNG output
+    ///   - No header cells, and a legend when needed
+    ///   - Histogram bins chosen automatically
+    ///   - The default palette, 1-pixel lines and no markers
+    ///   - Gridlines, linear axes fitted to the data and no data labels
+    ///   - The light theme with a 30-pixel title
+    ///   - No trendlines, with their equations written when there are
     fn default() -> Self {
         Self {
             title: "Graph".to_string(),
@@ -65,14 +751,37 @@ impl Default for GraphOptions {
             width: 800,
             height: 600,
             graph_type: GraphType::Line,
+            format: GraphFormat::Png,
+            y2_label: String::new(),
+            header: false,
+            legend: true,
+            bins: 0,
+            palette: PALETTE.to_vec(),
+            line_width: 1,
+            marker: None,
+            marker_size: 5,
+            x_grid: true,
+            y_grid: true,
+            log_scale: false,
+            x_min: None,
+            x_max: None,
+            y_min: None,
+            y_max: None,
+            tick_format: TickFormat::Auto,
+            data_labels: false,
+            theme: Theme::Light,
+            title_size: 30,
+            trendline: None,
+            trendline_equation: true,
         }
     }
 }
 
 /// Creates a graph from spreadsheet data
 ///
-/// This is the main entry point for generating graphs from spreadsheet data.
-/// It parses cell ranges, extracts data, and delegates to the appropriate graph type generator.
+/// This is the main entry point for generating graphs with a single series.
+/// It parses cell ranges, extracts data, and renders the requested graph type in memory
+/// as a PNG, SVG or PDF image.
 ///
 /// # Arguments
 /// * `spreadsheet` - Reference to the spreadsheet containing the data
@@ -81,7 +790,7 @@ impl Default for GraphOptions {
 /// * `options` - Graph styling and type options
 ///
 /// # Returns
-/// * A Result containing the PNG image data as bytes or an error
+/// * A Result containing the image data in the requested format as bytes or an error
 ///
 /// # Examples
 /// ```
@@ -94,9 +803,8 @@ impl Default for GraphOptions {
 ///     title: "Sample Graph".to_string(),
 ///     x_label: "X Values".to_string(),
 ///     y_label: "Y Values".to_string(),
-///     width: 800,
-///     height: 600,
 ///     graph_type: GraphType::Line,
+///     ..GraphOptions::default()
 /// };
 ///
 /// match create_graph(&spreadsheet, "A1:A5", "B1:B5", options) {
@@ -110,628 +818,1522 @@ pub fn create_graph(
     y_range: &str,
     options: GraphOptions,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Parse the ranges to get the actual cells
-    let (x_cells, y_cells) = parse_ranges(spreadsheet, x_range, y_range)?;
-
-    // Extract the data from the cells
-    let data: Vec<(i32, i32)> = x_cells
-        .iter()
-        .zip(y_cells.iter())
-        .map(|(x, y)| (*x, *y))
-        .collect();
-
-    match options.graph_type {
-        GraphType::Line => create_line_graph(data, &options),
-        GraphType::Bar => create_bar_graph(data, &options),
-        GraphType::Scatter => create_scatter_graph(data, &options),
-        // GraphType::Pie => create_pie_graph(data, &options),
-        GraphType::Area => create_area_graph(data, &options),
-    }
+    create_series_graph(spreadsheet, x_range, &[Series::new(y_range)], options)
 }
 
-/// Parses the range strings and returns the cell values
+/// Creates a graph with several series sharing one X range
 ///
-/// This function extracts numerical data from spreadsheet ranges for graphing purposes.
-/// It supports both column ranges (A1:A10) and row ranges (A1:J1).
+/// Each series can have its own name, colour and graph type, so that for example
+/// revenue can be drawn as bars and profit as a line on the same graph. Series marked
+/// for the secondary axis are scaled against a second Y-axis on the right.
+///
+/// Pie, donut, histogram, box plot and heatmap graphs cannot be combined with other
+/// types. Pie and donut graphs draw a single series; histograms and box plots ignore
+/// the X range and accept series of different lengths; a heatmap draws the single 2-D
+/// range of its only series.
 ///
 /// # Arguments
-/// * `spreadsheet` - Reference to the spreadsheet to extract data from
-/// * `x_range` - Range specification for X values (e.g., "A1:A10")
-/// * `y_range` - Range specification for Y values (e.g., "B1:B10")
+/// * `spreadsheet` - Reference to the spreadsheet containing the data
+/// * `x_range` - Range for X values (e.g., "A1:A10"), or empty to number the values
+///   from 1
+/// * `series` - The Y series, each with its own range of the same length as `x_range`;
+///   a range covering several rows and columns gives one series per column
+/// * `options` - Graph styling and type options
 ///
 /// # Returns
-/// * A Result containing vectors of extracted X and Y values or an error
+/// * A Result containing the image data in the requested format as bytes or an error
 ///
-/// # Errors
-/// * Returns an error if the range format is invalid
-/// * Returns an error if the ranges have different lengths
+/// # Examples
+/// ```
+/// use cop::spreadsheet::Spreadsheet;
+/// use cop::graph::{GraphOptions, GraphType, Series, create_series_graph};
 ///
-/// # Notes
-/// * Empty cells in the range will be treated as having a value of 0
-fn parse_ranges(
+/// let spreadsheet = Spreadsheet::spreadsheet_create(10, 10).unwrap();
+///
+/// let revenue = Series {
+///     name: Some("Revenue".to_string()),
+///     graph_type: Some(GraphType::Bar),
+///     ..Series::new("B1:B5")
+/// };
+/// let profit = Series {
+///     name: Some("Profit".to_string()),
+///     secondary_axis: true,
+///     ..Series::new("C1:C5")
+/// };
+///
+/// let png = create_series_graph(&spreadsheet, "A1:A5", &[revenue, profit], GraphOptions::default());
+/// assert!(png.is_ok());
+/// ```
+pub fn create_series_graph(
     spreadsheet: &Spreadsheet,
     x_range: &str,
-    y_range: &str,
-) -> Result<(Vec<i32>, Vec<i32>), Box<dyn std::error::Error>> {
-    // Split the range at the colon
-    let x_parts: Vec<&str> = x_range.split(':').collect();
-    let y_parts: Vec<&str> = y_range.split(':').collect();
-
-    if x_parts.len() != 2 || y_parts.len() != 2 {
-        return Err("Invalid range format. Expected format: A1:A10".into());
+    series: &[Series],
+    options: GraphOptions,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if series.is_empty() {
+        return Err("At least one Y range is required".into());
     }
+    check_types(series, &options)?;
+    check_options(&options)?;
 
-    // Parse the cell coordinates
-    let (x_start_row, x_start_col) = match spreadsheet.spreadsheet_parse_cell_name(x_parts[0]) {
-        Some(coords) => coords,
-        None => return Err("Invalid start cell in X range".into()),
-    };
-
-    let (x_end_row, x_end_col) = match spreadsheet.spreadsheet_parse_cell_name(x_parts[1]) {
-        Some(coords) => coords,
-        None => return Err("Invalid end cell in X range".into()),
-    };
-
-    let (y_start_row, y_start_col) = match spreadsheet.spreadsheet_parse_cell_name(y_parts[0]) {
-        Some(coords) => coords,
-        None => return Err("Invalid start cell in Y range".into()),
-    };
-
-    let (y_end_row, y_end_col) = match spreadsheet.spreadsheet_parse_cell_name(y_parts[1]) {
-        Some(coords) => coords,
-        None => return Err("Invalid end cell in Y range".into()),
-    };
-
-    // Ensure the ranges have the same length
-    let x_len = if x_start_col == x_end_col {
-        (x_end_row - x_start_row + 1) as usize
-    } else {
-        (x_end_col - x_start_col + 1) as usize
-    };
-
-    let y_len = if y_start_col == y_end_col {
-        (y_end_row - y_start_row + 1) as usize
-    } else {
-        (y_end_col - y_start_col + 1) as usize
-    };
+    if options.graph_type == GraphType::Heatmap {
+        let color = series[0].color.unwrap_or(options.palette[0]);
+        let data = parse_block(spreadsheet, &series[0].range, color)?;
+        return render(&data, &options);
+    }
 
-    if x_len != y_len {
-        return Err("X and Y ranges must have the same length".into());
+    // Parse the ranges to get the actual values and header names
+    let same_length = !matches!(
+        options.graph_type,
+        GraphType::Histogram | GraphType::BoxPlot
+    );
+    let data = parse_ranges(spreadsheet, x_range, series, &options, same_length)?;
+    if matches!(options.graph_type, GraphType::Pie | GraphType::Donut) && data.series.len() > 1 {
+        return Err(format!(
+            "A {:?} graph has a single series, but Y range {} has {} columns",
+            options.graph_type,
+            series[0].range.trim(),
+            data.series.len()
+        )
+        .into());
     }
 
-    // Extract values
-    let mut x_values = Vec::with_capacity(x_len);
-    let mut y_values = Vec::with_capacity(y_len);
+    render(&data, &options)
+}
 
-    // Handle vertical ranges (same column)
-    if x_start_col == x_end_col {
-        for row in x_start_row..=x_end_row {
-            let index = ((row - 1) * spreadsheet.cols + (x_start_col - 1)) as usize;
-            if let Some(cell) = &spreadsheet.cells[index] {
-                x_values.push(cell.value);
-            } else {
-                x_values.push(0);
+/// Checks that the graph types of the series can be drawn together
+///
+/// # Errors
+/// * Returns an error if a series has a type that cannot be combined with others, if a
+///   graph of such a type has series with types of their own, or if a pie, donut or
+///   heatmap graph has more than one series
+/// * Returns an error for a histogram with more than 1000 bins
+fn check_types(
+    series: &[Series],
+    options: &GraphOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let graph_type = options.graph_type;
+    for (i, series) in series.iter().enumerate() {
+        match series.graph_type {
+            Some(series_type) if !series_type.is_combinable() => {
+                return Err(format!(
+                    "Series {} cannot be a {:?} graph; set it as the type of the whole graph",
+                    i + 1,
+                    series_type
+                )
+                .into());
             }
-        }
-    } else {
-        // Handle horizontal ranges (same row)
-        for col in x_start_col..=x_end_col {
-            let index = ((x_start_row - 1) * spreadsheet.cols + (col - 1)) as usize;
-            if let Some(cell) = &spreadsheet.cells[index] {
-                x_values.push(cell.value);
-            } else {
-                x_values.push(0);
+            Some(_) if !graph_type.is_combinable() => {
+                return Err(format!(
+                    "Series of a {:?} graph cannot have a type of their own",
+                    graph_type
+                )
+                .into());
             }
+            _ => {}
         }
     }
+    if matches!(
+        graph_type,
+        GraphType::Pie | GraphType::Donut | GraphType::Heatmap
+    ) && series.len() > 1
+    {
+        return Err(format!("A {:?} graph has a single series", graph_type).into());
+    }
+    if options.bins > 1000 {
+        return Err("A histogram can have at most 1000 bins".into());
+    }
+    Ok(())
+}
 
-    // Do the same for Y values
-    if y_start_col == y_end_col {
-        for row in y_start_row..=y_end_row {
-            let index = ((row - 1) * spreadsheet.cols + (y_start_col - 1)) as usize;
-            if let Some(cell) = &spreadsheet.cells[index] {
-                y_values.push(cell.value);
-            } else {
-                y_values.push(0);
-            }
+/// Checks that the styling options can be drawn
+///
+/// # Errors
+/// * Returns an error for a size, line width, marker size or title size out of range,
+///   an empty palette, or axis limits that are not finite or leave no room between them
+/// * Returns an error for a log scale with a Y-axis minimum of zero or less
+/// * Returns an error for a polynomial trendline of a degree other than 2 to 6, or a
+///   moving average over fewer than 2 points
+fn check_options(options: &GraphOptions) -> Result<(), Box<dyn std::error::Error>> {
+    check_size(options)?;
+    if options.palette.is_empty() {
+        return Err("The palette needs at least one colour".into());
+    }
+    if !(1..=20).contains(&options.line_width) {
+        return Err("Line width must be between 1 and 20 pixels".into());
+    }
+    if !(1..=50).contains(&options.marker_size) {
+        return Err("Marker size must be between 1 and 50 pixels".into());
+    }
+    if !(1..=200).contains(&options.title_size) {
+        return Err("Title size must be between 1 and 200 pixels".into());
+    }
+    if let TickFormat::Decimal(places) = options.tick_format
+        && places > 10
+    {
+        return Err("Tick format can have at most 10 decimal places".into());
+    }
+    let limits = [options.x_min, options.x_max, options.y_min, options.y_max];
+    if limits.iter().flatten().any(|limit| !limit.is_finite()) {
+        return Err("Axis limits must be finite numbers".into());
+    }
+    for (axis, min, max) in [
+        ("X", options.x_min, options.x_max),
+        ("Y", options.y_min, options.y_max),
+    ] {
+        if let (Some(min), Some(max)) = (min, max)
+            && min >= max
+        {
+            return Err(format!("The {} axis minimum must be below its maximum", axis).into());
         }
-    } else {
-        for col in y_start_col..=y_end_col {
-            let index = ((y_start_row - 1) * spreadsheet.cols + (col - 1)) as usize;
-            if let Some(cell) = &spreadsheet.cells[index] {
-                y_values.push(cell.value);
-            } else {
-                y_values.push(0);
-            }
+    }
+    if options.log_scale && options.y_min.is_some_and(|min| min <= 0.0) {
+        return Err("A log scale needs a Y axis minimum greater than zero".into());
+    }
+    match options.trendline {
+        Some(Trendline::Polynomial(degree)) if !(2..=6).contains(&degree) => {
+            return Err("A polynomial trendline must have a degree between 2 and 6".into());
         }
+        Some(Trendline::MovingAverage(period)) if period < 2 => {
+            return Err("A moving average trendline needs a period of at least 2 points".into());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Reads the X values and the series of a graph from their ranges
+///
+/// The ranges are read by [`read_ranges`]: a block of several rows and columns gives one
+/// series per column, and an X range holding text gives categories.
+///
+/// # Arguments
+/// * `spreadsheet` - Reference to the spreadsheet to extract data from
+/// * `x_range` - Range specification for X values (e.g., "A1:A10"), or empty
+/// * `series` - The Y series, whose ranges are read in order
+/// * `options` - Graph options, giving the header setting and the palette
+/// * `same_length` - Whether every Y column must have as many values as the X range
+///
+/// # Returns
+/// * A Result containing the X values, their categories and the series to draw
+///
+/// # Notes
+/// * A header is the displayed value of the cell; an empty header cell leaves the series
+///   unnamed
+/// * The name and colour of a series apply to a single row or column; the columns of a
+///   block are named by their headers and coloured from the palette
+fn parse_ranges(
+    spreadsheet: &Spreadsheet,
+    x_range: &str,
+    series: &[Series],
+    options: &GraphOptions,
+    same_length: bool,
+) -> Result<GraphData, Box<dyn std::error::Error>> {
+    let ranges: Vec<&str> = series.iter().map(|s| s.range.as_str()).collect();
+    let data = read_ranges(spreadsheet, x_range, &ranges, options.header, same_length)?;
+
+    let series = data
+        .columns
+        .into_iter()
+        .enumerate()
+        .map(|(k, column)| {
+            let series = &series[column.source];
+            let whole = !column.block;
+            SeriesData {
+                name: series
+                    .name
+                    .clone()
+                    .filter(|_| whole)
+                    .or(column.header)
+                    .unwrap_or_else(|| format!("Series {}", k + 1)),
+                values: column.values,
+                color: series
+                    .color
+                    .filter(|_| whole)
+                    .unwrap_or(options.palette[k % options.palette.len()]),
+                graph_type: series.graph_type.unwrap_or(options.graph_type),
+                secondary_axis: series.secondary_axis,
+            }
+        })
+        .collect();
+    Ok(GraphData {
+        x: data.x,
+        categories: data.categories,
+        series,
+    })
+}
+
+/// Parses the 2-D range of a heatmap
+///
+/// # Arguments
+/// * `spreadsheet` - Reference to the spreadsheet to extract data from
+/// * `range` - Range specification covering any block of cells (e.g., "B2:F10")
+/// * `color` - Colour of the highest value
+///
+/// # Returns
+/// * A Result containing the column numbers as X values and one series per row, named
+///   by its row number
+fn parse_block(
+    spreadsheet: &Spreadsheet,
+    range: &str,
+    color: RGBColor,
+) -> Result<GraphData, Box<dyn std::error::Error>> {
+    let CellRange {
+        start: (start_row, start_col),
+        end: (end_row, end_col),
+    } = CellRange::parse(spreadsheet, range, "Heatmap range")?;
+
+    let series = (start_row..=end_row)
+        .map(|row| SeriesData {
+            name: row.to_string(),
+            values: (start_col..=end_col)
+                .map(|col| {
+                    let index = (row - 1) as usize * spreadsheet.cols as usize + (col - 1) as usize;
+                    value(spreadsheet.cells[index].as_deref())
+                })
+                .collect(),
+            color,
+            graph_type: GraphType::Heatmap,
+            secondary_axis: false,
+        })
+        .collect();
+    Ok(GraphData {
+        x: (start_col..=end_col).map(f64::from).collect(),
+        categories: Vec::new(),
+        series,
+    })
+}
+
+/// Returns the lowest of some values, or None if there are none
+fn lowest(values: impl Iterator<Item = f64>) -> Option<f64> {
+    values.reduce(f64::min)
+}
+
+/// Returns the highest of some values, or None if there are none
+fn highest(values: impl Iterator<Item = f64>) -> Option<f64> {
+    values.reduce(f64::max)
+}
+
+/// Returns the value of a cell for graphing, 0 for an empty cell
+fn value(cell: Option<&Cell>) -> f64 {
+    cell.map_or(0.0, |cell| cell.number())
+}
+
+/// Renders a graph in the format chosen in its options
+fn render(data: &GraphData, options: &GraphOptions) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    match options.format {
+        GraphFormat::Png => render_png(data, options),
+        GraphFormat::Svg => render_svg(data, options),
+        GraphFormat::Pdf => render_pdf(data, options),
+    }
+}
+
+/// Renders a graph into a PNG image held in memory
+///
+/// The graph is drawn into an RGB bitmap buffer which is then encoded with `image`,
+/// so rendering never touches the filesystem and concurrent renders cannot interfere.
+///
+/// # Arguments
+/// * `data` - X values and series to draw
+/// * `options` - Graph styling and type options
+///
+/// # Returns
+/// * A Result containing the PNG image data as bytes or an error
+fn render_png(
+    data: &GraphData,
+    options: &GraphOptions,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    check_size(options)?;
+    let mut pixels = vec![0u8; options.width as usize * options.height as usize * 3];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (options.width, options.height))
+            .into_drawing_area();
+        draw_graph(&root, data, options)?;
+        root.present()?;
     }
 
-    Ok((x_values, y_values))
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(
+        &pixels,
+        options.width,
+        options.height,
+        ColorType::Rgb8,
+    )?;
+    Ok(png)
 }
 
-/// Creates a line graph from data points
-///
-/// Generates a line graph showing the trend between X and Y values with connected lines.
-/// Line graphs are ideal for showing trends over continuous data.
+/// Renders a graph into an SVG document
 ///
 /// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
+/// * `data` - X values and series to draw
+/// * `options` - Graph styling and type options
 ///
 /// # Returns
-/// * A Result containing the PNG image data as bytes or an error
-///
-/// # Implementation Notes
-/// * Creates a temporary file to store the image before reading it back
-/// * Automatically scales axes based on data range
-/// * Uses blue color for the line series
-fn create_line_graph(
-    data: Vec<(i32, i32)>,
+/// * A Result containing the UTF-8 SVG document as bytes or an error
+fn render_svg(
+    data: &GraphData,
     options: &GraphOptions,
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Create a temporary file-based bitmap solution
-    let filename = "temp_graph.png";
+    check_size(options)?;
+    let mut svg = String::new();
     {
-        // Create a file-based bitmap backend
         let root =
-            BitMapBackend::new(filename, (options.width, options.height)).into_drawing_area();
-        root.fill(&WHITE)?;
-
-        let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-        let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-        let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0);
-        let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
-
-        let x_range = *min_x as f64..*max_x as f64 + 1.0;
-        let y_range = *min_y as f64..*max_y as f64 + 1.0;
-
-        let mut chart = ChartBuilder::on(&root)
-            .caption(&options.title, ("sans-serif", 30).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(40)
-            .build_cartesian_2d(x_range, y_range)?;
-
-        chart
-            .configure_mesh()
-            .x_desc(&options.x_label)
-            .y_desc(&options.y_label)
-            .draw()?;
-
-        chart.draw_series(LineSeries::new(
-            data.iter().map(|&(x, y)| (x as f64, y as f64)),
-            &BLUE,
-        ))?;
-
+            SVGBackend::with_string(&mut svg, (options.width, options.height)).into_drawing_area();
+        draw_graph(&root, data, options)?;
         root.present()?;
     }
-
-    // Read the file directly
-    let mut file = std::fs::File::open(filename)?;
-    let mut buffer = Vec::new();
-    use std::io::Read;
-    file.read_to_end(&mut buffer)?;
-    remove_file(filename)?;
-    Ok(buffer)
+    Ok(svg.into_bytes())
 }
 
-/// Saves a line graph to a file
+/// Renders a graph into a single-page PDF document
 ///
-/// Creates a line graph and saves it directly to the specified file path.
-/// Useful for generating examples or saving graphs without returning the image data.
+/// The page is as many points wide and high as the graph has pixels.
 ///
 /// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-/// * `path` - File path where the graph should be saved
+/// * `data` - X values and series to draw
+/// * `options` - Graph styling and type options
 ///
 /// # Returns
-/// * A Result indicating success or failure
-fn save_line_graph(
-    data: Vec<(i32, i32)>,
+/// * A Result containing the PDF document as bytes or an error
+fn render_pdf(
+    data: &GraphData,
     options: &GraphOptions,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(path, (options.width, options.height)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-    let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-    let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0);
-    let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
-
-    let x_range = *min_x as f64..*max_x as f64 + 1.0;
-    let y_range = *min_y as f64..*max_y as f64 + 1.0;
-
-    let mut chart = ChartBuilder::on(&root)
-        .caption(&options.title, ("sans-serif", 30).into_font())
-        .margin(10)
-        .x_label_area_size(30)
-        .y_label_area_size(40)
-        .build_cartesian_2d(x_range, y_range)?;
-
-    chart
-        .configure_mesh()
-        .x_desc(&options.x_label)
-        .y_desc(&options.y_label)
-        .draw()?;
-
-    chart.draw_series(LineSeries::new(
-        data.iter().map(|&(x, y)| (x as f64, y as f64)),
-        &RED,
-    ))?;
-
-    root.present()?;
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    check_size(options)?;
+    let mut pdf = Vec::new();
+    {
+        let root = PdfBackend::new(&mut pdf, (options.width, options.height)).into_drawing_area();
+        draw_graph(&root, data, options)?;
+        root.present()?;
+    }
+    Ok(pdf)
+}
 
+/// Checks that a graph has a drawable size
+fn check_size(options: &GraphOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if options.width == 0 || options.height == 0 {
+        return Err("Graph width and height must be greater than zero".into());
+    }
+    if options.width > MAX_GRAPH_SIZE || options.height > MAX_GRAPH_SIZE {
+        return Err(format!(
+            "Graph width and height must be at most {} pixels",
+            MAX_GRAPH_SIZE
+        )
+        .into());
+    }
     Ok(())
 }
 
-/// Creates a bar graph from data points
+/// Draws a graph onto a drawing area
 ///
-/// Generates a bar graph showing values as vertical bars.
-/// Bar graphs are ideal for comparing values across different categories.
+/// This is the rendering pipeline shared by every graph type and output format. It
+/// fills the background and hands over to the drawing function of the graph type.
 ///
 /// # Arguments
-/// * `data` - Vector of (x,y) data points, where x is the category position and y is the value
-/// * `options` - Graph styling options
-///
-/// # Returns
-/// * A Result containing the PNG image data as bytes or an error
-///
-/// # Implementation Notes
-/// * Creates a temporary file to store the image before reading it back
-/// * Uses blue color for bars with solid fill
-/// * Each x-value positions a bar with the height of the corresponding y-value
-fn create_bar_graph(
-    data: Vec<(i32, i32)>,
+/// * `root` - Drawing area covering the whole image
+/// * `data` - X values and series to draw
+/// * `options` - Graph styling and type options
+fn draw_graph<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
     options: &GraphOptions,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Create a temporary file-based bitmap solution
-    let filename = "temp_graph.png";
-    {
-        // Create a file-based bitmap backend
-        let root =
-            BitMapBackend::new(filename, (options.width, options.height)).into_drawing_area();
-        root.fill(&WHITE)?;
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    root.fill(&options.theme.background())?;
+    match options.graph_type {
+        GraphType::Pie | GraphType::Donut => draw_pie(root, data, options),
+        GraphType::Histogram => draw_histogram(root, data, options),
+        GraphType::BoxPlot => draw_box_plot(root, data, options),
+        GraphType::Heatmap => draw_heatmap(root, data, options),
+        GraphType::Line
+        | GraphType::Bar
+        | GraphType::Scatter
+        | GraphType::Area
+        | GraphType::GroupedBar
+        | GraphType::StackedBar => draw_cartesian(root, data, options),
+    }
+}
 
-        let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-        let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-        let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
+/// Returns the font of the title, in the size and colour of the options
+fn title_font(options: &GraphOptions) -> TextStyle<'static> {
+    ("sans-serif", options.title_size)
+        .into_font()
+        .color(&options.theme.foreground())
+}
 
-        let x_range = *min_x..*max_x + 1;
-        let y_range = 0..*max_y + 1;
+/// Returns the font of tick labels, legends and data labels
+fn label_font(options: &GraphOptions) -> TextStyle<'static> {
+    ("sans-serif", 12)
+        .into_font()
+        .color(&options.theme.foreground())
+}
 
-        let mut chart = ChartBuilder::on(&root)
-            .caption(&options.title, ("sans-serif", 30).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(40)
-            .build_cartesian_2d(x_range, y_range)?;
+/// Applies the theme and gridline options to the mesh of a chart
+fn style_mesh<X: Ranged, Y: Ranged, DB: DrawingBackend>(
+    mesh: &mut MeshStyle<'_, '_, X, Y, DB>,
+    options: &GraphOptions,
+) {
+    let foreground = options.theme.foreground();
+    mesh.label_style(label_font(options))
+        .axis_style(foreground)
+        .bold_line_style(foreground.mix(0.2))
+        .light_line_style(foreground.mix(0.1));
+    if !options.x_grid {
+        mesh.disable_x_mesh();
+    }
+    if !options.y_grid {
+        mesh.disable_y_mesh();
+    }
+}
 
-        chart
-            .configure_mesh()
-            .x_desc(&options.x_label)
-            .y_desc(&options.y_label)
-            .draw()?;
+/// Draws the legend of a chart in the colours of the theme
+fn draw_legend<'a, DB: DrawingBackend + 'a, CT: CoordTranslate>(
+    mut labels: SeriesLabelStyle<'a, '_, DB, CT>,
+    options: &GraphOptions,
+) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>> {
+    labels
+        .background_style(options.theme.background().mix(0.8))
+        .border_style(options.theme.foreground())
+        .label_font(label_font(options))
+        .draw()
+}
 
-        // Draw wider bars with solid fill and clear borders
-        chart.draw_series(
-            data.iter()
-                .map(|&(x, y)| Rectangle::new([(x - 2, 0), (x + 2, y)], BLUE.filled())),
-        )?;
+/// A marker of some style centred on a point, drawn in pixels whatever the scale
+struct PointMarker<C> {
+    point: C,
+    style: MarkerStyle,
+    size: u32,
+    color: RGBColor,
+}
 
-        root.present()?;
+impl<C> PointMarker<C> {
+    /// Creates a marker of the given style and radius
+    fn new(style: MarkerStyle, point: C, size: u32, color: RGBColor) -> Self {
+        Self {
+            point,
+            style,
+            size,
+            color,
+        }
+    }
+}
+
+impl<'a, C> PointCollection<'a, C> for &'a PointMarker<C> {
+    type Point = &'a C;
+    type IntoIter = std::iter::Once<&'a C>;
+
+    fn point_iter(self) -> Self::IntoIter {
+        std::iter::once(&self.point)
     }
+}
 
-    // Read the file directly
-    let png_data = std::fs::read(filename)?;
+impl<C, DB: DrawingBackend> Drawable<DB> for PointMarker<C> {
+    fn draw<I: Iterator<Item = BackendCoord>>(
+        &self,
+        mut points: I,
+        backend: &mut DB,
+        _: (u32, u32),
+    ) -> Result<(), DrawingErrorKind<DB::ErrorType>> {
+        let Some((x, y)) = points.next() else {
+            return Ok(());
+        };
+        let size = self.size as i32;
+        let fill = self.color.filled();
+        match self.style {
+            MarkerStyle::Circle => backend.draw_circle((x, y), self.size, &fill, true),
+            MarkerStyle::Square => {
+                backend.draw_rect((x - size, y - size), (x + size, y + size), &fill, true)
+            }
+            MarkerStyle::Triangle => backend.fill_polygon(
+                [(x, y - size), (x + size, y + size), (x - size, y + size)],
+                &fill,
+            ),
+            MarkerStyle::Cross => {
+                let stroke = self.color.stroke_width(2);
+                backend.draw_line((x - size, y - size), (x + size, y + size), &stroke)?;
+                backend.draw_line((x - size, y + size), (x + size, y - size), &stroke)
+            }
+        }
+    }
+}
 
-    // Clean up
-    remove_file(filename)?;
+/// The value of a point written next to it, centred horizontally
+struct DataLabel {
+    point: (f64, f64),
+    text: String,
+    style: TextStyle<'static>,
+    offset: i32,
+}
 
-    Ok(png_data)
+impl DataLabel {
+    /// Creates a data label
+    ///
+    /// # Arguments
+    /// * `point` - Point the label belongs to
+    /// * `text` - The value as written
+    /// * `vpos` - Which edge of the label is placed at the point
+    /// * `offset` - Vertical distance of the label from the point in pixels
+    /// * `options` - Graph styling options, for the font
+    fn new(
+        point: (f64, f64),
+        text: String,
+        vpos: VPos,
+        offset: i32,
+        options: &GraphOptions,
+    ) -> Self {
+        Self {
+            point,
+            text,
+            style: label_font(options).pos(Pos::new(HPos::Center, vpos)),
+            offset,
+        }
+    }
 }
 
-/// Saves a bar graph to a file
-///
-/// Creates a bar graph and saves it directly to the specified file path.
+impl<'a> PointCollection<'a, (f64, f64)> for &'a DataLabel {
+    type Point = &'a (f64, f64);
+    type IntoIter = std::iter::Once<&'a (f64, f64)>;
+
+    fn point_iter(self) -> Self::IntoIter {
+        std::iter::once(&self.point)
+    }
+}
+
+impl<DB: DrawingBackend> Drawable<DB> for DataLabel {
+    fn draw<I: Iterator<Item = BackendCoord>>(
+        &self,
+        mut points: I,
+        backend: &mut DB,
+        _: (u32, u32),
+    ) -> Result<(), DrawingErrorKind<DB::ErrorType>> {
+        match points.next() {
+            Some((x, y)) => backend.draw_text(&self.text, &self.style, (x, y + self.offset)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Applies the axis limits of the options to a range fitted to the data
 ///
-/// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-/// * `path` - File path where the graph should be saved
+/// # Errors
+/// * Returns an error if a limit leaves no room on the axis
+fn axis_limits(
+    fitted: std::ops::Range<f64>,
+    min: Option<f64>,
+    max: Option<f64>,
+    axis: &str,
+) -> Result<std::ops::Range<f64>, Box<dyn std::error::Error>> {
+    let range = min.unwrap_or(fitted.start)..max.unwrap_or(fitted.end);
+    if range.start >= range.end {
+        return Err(format!(
+            "The {} axis runs from {} to {}; its minimum must be below its maximum",
+            axis, range.start, range.end
+        )
+        .into());
+    }
+    Ok(range)
+}
+
+/// Draws series against X and Y axes
 ///
-/// # Returns
-/// * A Result indicating success or failure
+/// The caption, axes and mesh are the same for every combinable graph type, and only
+/// the series differ. The axes are set up here, linear or logarithmic, and the series
+/// are drawn by `draw_cartesian_series`.
 ///
 /// # Implementation Notes
-/// * Bars are sized based on the x value - adjacent x values will create adjacent bars
-/// * Bar width is set to 0.8 units (from x-0.4 to x+0.4) for visual clarity
-fn save_bar_graph(
-    data: Vec<(i32, i32)>,
+/// * Each Y-axis is scaled from the series drawn against it, and their trendlines;
+///   axes with bar or area series always include y=0 on a linear scale
+/// * On a log scale, bars and areas start from the bottom of their axis
+fn draw_cartesian<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
     options: &GraphOptions,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(path, (options.width, options.height)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-    let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-    let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
-
-    let x_range = *min_x..*max_x + 1;
-    let y_range = 0..*max_y + 1;
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let min_x = lowest(data.x.iter().copied()).unwrap_or(0.0);
+    let max_x = highest(data.x.iter().copied()).unwrap_or(100.0);
+    let mut x_range = min_x..max_x + 1.0;
+    if !data.categories.is_empty() {
+        // Categories are unit wide and centred on their numbers
+        x_range = 0.5..data.categories.len() as f64 + 0.5;
+    } else if data.series.iter().any(|s| is_bar(s.graph_type)) {
+        // Leave room for the first and last bar on either side of their x value
+        x_range = x_range.start - 0.5..x_range.end - 0.5;
+    }
+    let x_range = axis_limits(x_range, options.x_min, options.x_max, "X")?;
+    let trends = fit_trends(data, options)?;
+    let secondary = data.series.iter().any(|s| s.secondary_axis);
+    let y_range = axis_limits(
+        include_trends(
+            value_range(data, false, options.log_scale)?,
+            data,
+            &trends,
+            false,
+            options.log_scale,
+        ),
+        options.y_min,
+        options.y_max,
+        "Y",
+    )?;
+    let y2_range = if secondary {
+        include_trends(
+            value_range(data, true, options.log_scale)?,
+            data,
+            &trends,
+            true,
+            options.log_scale,
+        )
+    } else {
+        y_range.clone()
+    };
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(&options.title, ("sans-serif", 30).into_font())
+    let mut builder = ChartBuilder::on(root);
+    builder
+        .caption(&options.title, title_font(options))
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(40)
-        .build_cartesian_2d(x_range, y_range)?;
-
-    chart.draw_series(data.iter().map(|&(x, y)| {
-        Rectangle::new(
-            [((x as f64 - 0.4) as i32, 0), ((x as f64 + 0.4) as i32, y)],
-            BLUE.filled(),
-        )
-    }))?;
-
-    root.present()?;
+        .right_y_label_area_size(if secondary { 40 } else { 0 });
+    if options.log_scale {
+        let baselines = [y_range.start, y2_range.start];
+        let chart = builder
+            .build_cartesian_2d(x_range.clone(), y_range.log_scale())?
+            .set_secondary_coord(x_range, y2_range.log_scale());
+        draw_cartesian_series(chart, data, &trends, options, baselines)
+    } else {
+        let chart = builder
+            .build_cartesian_2d(x_range.clone(), y_range)?
+            .set_secondary_coord(x_range, y2_range);
+        draw_cartesian_series(chart, data, &trends, options, [0.0, 0.0])
+    }
+}
 
-    Ok(())
+/// Returns true if series of a graph type are drawn as bars
+fn is_bar(graph_type: GraphType) -> bool {
+    matches!(
+        graph_type,
+        GraphType::Bar | GraphType::GroupedBar | GraphType::StackedBar
+    )
 }
 
-/// Creates a scatter plot from data points
-///
-/// Generates a scatter plot showing individual data points without connecting lines.
-/// Scatter plots are ideal for visualizing the relationship between two variables.
+/// Draws the mesh, series and legend of a graph with X and Y axes
 ///
 /// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-///
-/// # Returns
-/// * A Result containing the PNG image data as bytes or an error
+/// * `chart` - Chart with the primary and secondary coordinates set up
+/// * `data` - X values and series to draw
+/// * `trends` - Trendline of each series, if it has one
+/// * `options` - Graph styling and type options
+/// * `baselines` - Value bars and areas start from on the primary and secondary axis
 ///
 /// # Implementation Notes
-/// * Creates a temporary file to store the image before reading it back
-/// * Uses green circles with 5-pixel radius for data points
-/// * Automatically scales axes based on data range
-fn create_scatter_graph(
-    data: Vec<(i32, i32)>,
+/// * Scatter series are drawn as markers, circles unless another style is chosen
+/// * Bars share a 0.8 unit wide slot centred on each x value: every bar series takes a
+///   place side by side, and the stacked series of each Y-axis share one place
+/// * Area series are sorted by x and filled between the line and the baseline
+/// * Data labels go above points and bars, below negative bars, and in the middle of
+///   the segments of stacked bars
+/// * Trendlines are dashed lines in the colour of their series, and the equations of
+///   fitted ones are written in the top left corner of the plot
+fn draw_cartesian_series<'a, DB, Y, Y2>(
+    mut chart: DualCoordChartContext<
+        'a,
+        DB,
+        Cartesian2d<RangedCoordf64, Y>,
+        Cartesian2d<RangedCoordf64, Y2>,
+    >,
+    data: &GraphData,
+    trends: &[Option<Trend>],
     options: &GraphOptions,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Create a temporary file-based bitmap solution
-    let filename = "temp_graph.png";
-    {
-        // Create a file-based bitmap backend
-        let root =
-            BitMapBackend::new(filename, (options.width, options.height)).into_drawing_area();
-        root.fill(&WHITE)?;
+    baselines: [f64; 2],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend + 'a,
+    DB::ErrorType: 'static,
+    Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+    Y2: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    let foreground = options.theme.foreground();
+    let tick_format = |value: &f64| options.tick_format.format(*value);
+    let custom_ticks = options.tick_format != TickFormat::Auto;
+    let count = data.categories.len();
+    let category_of =
+        |x: &f64| category(*x - 1.0, count).map_or(String::new(), |i| data.categories[i].clone());
+    let mut mesh = chart.configure_mesh();
+    style_mesh(&mut mesh, options);
+    mesh.x_desc(&options.x_label).y_desc(&options.y_label);
+    if custom_ticks {
+        mesh.y_label_formatter(&tick_format);
+    }
+    if count > 0 {
+        mesh.x_labels(count + 1).x_label_formatter(&category_of);
+    }
+    mesh.draw()?;
+    if data.series.iter().any(|s| s.secondary_axis) {
+        let mut axes = chart.configure_secondary_axes();
+        axes.label_style(label_font(options))
+            .axis_style(foreground)
+            .y_desc(&options.y2_label);
+        if custom_ticks {
+            axes.y_label_formatter(&tick_format);
+        }
+        axes.draw()?;
+    }
+
+    // Places of the bar series within the slot of each x value; the stacked series of
+    // each axis share the place of the first of them
+    let mut stack_places = [None, None];
+    let mut places = Vec::with_capacity(data.series.len());
+    let mut place_count = 0;
+    for series in &data.series {
+        let place = match series.graph_type {
+            GraphType::StackedBar => *stack_places[usize::from(series.secondary_axis)]
+                .get_or_insert_with(|| {
+                    place_count += 1;
+                    place_count - 1
+                }),
+            graph_type if is_bar(graph_type) => {
+                place_count += 1;
+                place_count - 1
+            }
+            _ => 0,
+        };
+        places.push(place);
+    }
+    let bar_width = 0.8 / place_count.max(1) as f64;
+
+    // Tops and bottoms of the stacks drawn so far, for each axis and x value
+    let mut stack_tops = [vec![0.0; data.x.len()], vec![0.0; data.x.len()]];
+    let mut stack_bottoms = stack_tops.clone();
+
+    let line_style = |color: RGBColor| color.stroke_width(options.line_width);
+    let label_text = |y: f64| options.tick_format.format(y);
+    for (series, place) in data.series.iter().zip(places) {
+        let color = series.color;
+        let axis = usize::from(series.secondary_axis);
+        // On a log scale the bottom of the axis stands in for zero
+        let baseline = baselines[axis];
+        let floor = |y: f64| {
+            if options.log_scale {
+                y.max(baseline)
+            } else {
+                y
+            }
+        };
+        let points: Vec<(f64, f64)> = data
+            .x
+            .iter()
+            .zip(&series.values)
+            .map(|(&x, &y)| (x, y))
+            .collect();
+        let left = -0.4 + bar_width * place as f64;
+
+        let mut elements: Vec<DynElement<DB, (f64, f64)>> = Vec::new();
+        let mut labels = Vec::new();
+        match series.graph_type {
+            GraphType::Line | GraphType::Scatter | GraphType::Area => {
+                let mut sorted = points.clone();
+                if series.graph_type == GraphType::Area {
+                    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    let mut outline = sorted.clone();
+                    if let (Some(&(first, _)), Some(&(last, _))) = (sorted.first(), sorted.last()) {
+                        outline.push((last, baseline));
+                        outline.push((first, baseline));
+                    }
+                    elements.push(Polygon::new(outline, color.mix(0.2).filled()).into_dyn());
+                }
+                if series.graph_type != GraphType::Scatter {
+                    elements.push(PathElement::new(sorted, line_style(color)).into_dyn());
+                }
+                let style = match series.graph_type {
+                    GraphType::Scatter => Some(options.marker.unwrap_or(MarkerStyle::Circle)),
+                    _ => options.marker,
+                };
+                if let Some(style) = style {
+                    elements.extend(points.iter().map(|&point| {
+                        PointMarker::new(style, point, options.marker_size, color).into_dyn()
+                    }));
+                }
+                let offset = -(style.map_or(0, |_| options.marker_size as i32) + 3);
+                labels.extend(points.iter().map(|&(x, y)| {
+                    DataLabel::new((x, y), label_text(y), VPos::Bottom, offset, options)
+                }));
+            }
+            GraphType::StackedBar => {
+                for (i, (x, y)) in points.into_iter().enumerate() {
+                    let stack = if y >= 0.0 {
+                        &mut stack_tops[axis][i]
+                    } else {
+                        &mut stack_bottoms[axis][i]
+                    };
+                    let base = *stack;
+                    *stack += y;
+                    let middle = x + left + bar_width / 2.0;
+                    elements.push(
+                        Rectangle::new(
+                            [(x + left, floor(base)), (x + left + bar_width, base + y)],
+                            color.filled(),
+                        )
+                        .into_dyn(),
+                    );
+                    labels.push(DataLabel::new(
+                        (middle, (floor(base) + base + y) / 2.0),
+                        label_text(y),
+                        VPos::Center,
+                        0,
+                        options,
+                    ));
+                }
+            }
+            _ => {
+                for (x, y) in points {
+                    let middle = x + left + bar_width / 2.0;
+                    elements.push(
+                        Rectangle::new(
+                            [(x + left, floor(0.0)), (x + left + bar_width, y)],
+                            color.filled(),
+                        )
+                        .into_dyn(),
+                    );
+                    let (vpos, offset) = if y >= 0.0 {
+                        (VPos::Bottom, -3)
+                    } else {
+                        (VPos::Top, 3)
+                    };
+                    labels.push(DataLabel::new(
+                        (middle, y),
+                        label_text(y),
+                        vpos,
+                        offset,
+                        options,
+                    ));
+                }
+            }
+        }
+        if options.data_labels {
+            elements.extend(labels.into_iter().map(IntoDynElement::into_dyn));
+        }
+
+        let annotation = if series.secondary_axis {
+            chart.draw_secondary_series(elements)?
+        } else {
+            chart.draw_series(elements)?
+        };
+        let graph_type = series.graph_type;
+        let marker_style = options.marker.unwrap_or(MarkerStyle::Circle);
+        let line_width = options.line_width;
+        annotation
+            .label(series.name.as_str())
+            .legend(move |(x, y)| match graph_type {
+                GraphType::Line => {
+                    PathElement::new([(x, y), (x + 20, y)], color.stroke_width(line_width))
+                        .into_dyn()
+                }
+                GraphType::Scatter => {
+                    PointMarker::new(marker_style, (x + 10, y), 4, color).into_dyn()
+                }
+                _ => Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()).into_dyn(),
+            });
+    }
 
-        let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-        let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-        let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0);
-        let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
+    for (series, trend) in data.series.iter().zip(trends) {
+        let Some(trend) = trend else {
+            continue;
+        };
+        let style = series.color.stroke_width(options.line_width);
+        let line = DashedPathElement::new(trend.points.clone(), 8, 5, style);
+        let annotation = if series.secondary_axis {
+            chart.draw_secondary_series(std::iter::once(line))?
+        } else {
+            chart.draw_series(std::iter::once(line))?
+        };
+        annotation.label(trend.name.as_str()).legend(move |(x, y)| {
+            DashedPathElement::new(vec![(x, y), (x + 20, y)], 5, 3, style).into_dyn()
+        });
+    }
+    let equations: Vec<(&String, RGBColor)> = data
+        .series
+        .iter()
+        .zip(trends)
+        .filter_map(|(series, trend)| Some((trend.as_ref()?.equation.as_ref()?, series.color)))
+        .collect();
+    let area = chart.plotting_area().strip_coord_spec();
+    for (i, (equation, color)) in equations.into_iter().enumerate() {
+        area.draw(&Text::new(
+            equation.as_str(),
+            (8, 6 + 16 * i as i32),
+            label_font(options).color(&color),
+        ))?;
+    }
 
-        let x_range = *min_x as f64..*max_x as f64 + 1.0;
-        let y_range = *min_y as f64..*max_y as f64 + 1.0;
+    let named = data.series.len() > 1
+        || !data.series[0].name.starts_with("Series ")
+        || trends.iter().any(Option::is_some);
+    if options.legend && named {
+        draw_legend(chart.configure_series_labels(), options)?;
+    }
 
-        let mut chart = ChartBuilder::on(&root)
-            .caption(&options.title, ("sans-serif", 30).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(40)
-            .build_cartesian_2d(x_range, y_range)?;
+    Ok(())
+}
 
-        chart
-            .configure_mesh()
-            .x_desc(&options.x_label)
-            .y_desc(&options.y_label)
-            .draw()?;
+/// A trendline of one series, ready to be drawn
+struct Trend {
+    /// Points of the line
+    points: Vec<(f64, f64)>,
+    /// Name shown in the legend, e.g. "Linear (Sales)"
+    name: String,
+    /// Equation and R² written on the graph, for fitted curves when they are shown
+    equation: Option<String>,
+}
 
-        chart.draw_series(
-            data.iter()
-                .map(|&(x, y)| Circle::new((x as f64, y as f64), 5, GREEN.filled())),
-        )?;
+/// Number of points a fitted trendline is drawn with
+const TREND_SAMPLES: usize = 100;
 
-        root.present()?;
+/// Fits the trendline of the options to every line and scatter series
+///
+/// # Returns
+/// * The trendline of every series, None for series of other types or when the options
+///   have no trendline
+///
+/// # Errors
+/// * Returns an error naming the series when its trendline cannot be fitted: an
+///   exponential trendline of values that are not all above zero, too few distinct X
+///   values for the curve, or fewer values than the period of a moving average
+fn fit_trends(
+    data: &GraphData,
+    options: &GraphOptions,
+) -> Result<Vec<Option<Trend>>, Box<dyn std::error::Error>> {
+    let Some(trendline) = options.trendline else {
+        return Ok(data.series.iter().map(|_| None).collect());
+    };
+    let mut trends = Vec::with_capacity(data.series.len());
+    for series in &data.series {
+        if !matches!(series.graph_type, GraphType::Line | GraphType::Scatter) {
+            trends.push(None);
+            continue;
+        }
+        let points: Vec<(f64, f64)> = data
+            .x
+            .iter()
+            .zip(&series.values)
+            .map(|(&x, &y)| (x, y))
+            .collect();
+        let (kind, points, equation) = match trendline {
+            Trendline::MovingAverage(period) => {
+                let average = moving_average(&points, period);
+                if average.is_empty() {
+                    return Err(format!(
+                        "A moving average over {} points needs at least {} values in {}",
+                        period, period, series.name
+                    )
+                    .into());
+                }
+                ("Moving average", average, None)
+            }
+            _ => {
+                let Some(fit) = fit_trendline(trendline, &points) else {
+                    return Err(match trendline {
+                        Trendline::Exponential if points.iter().any(|&(_, y)| y <= 0.0) => {
+                            format!(
+                                "An exponential trendline needs values greater than zero in {}",
+                                series.name
+                            )
+                        }
+                        _ => format!(
+                            "{} has too few distinct X values for its trendline",
+                            series.name
+                        ),
+                    }
+                    .into());
+                };
+                let (low, high) = points.iter().fold(
+                    (f64::INFINITY, f64::NEG_INFINITY),
+                    |(low, high), &(x, _)| (low.min(x), high.max(x)),
+                );
+                let samples = (0..=TREND_SAMPLES)
+                    .map(|i| {
+                        let x = low + (high - low) * i as f64 / TREND_SAMPLES as f64;
+                        (x, fit.curve.value_at(x))
+                    })
+                    .filter(|&(_, y)| y.is_finite() && (!options.log_scale || y > 0.0))
+                    .collect();
+                let kind = match trendline {
+                    Trendline::Linear => "Linear",
+                    Trendline::Exponential => "Exponential",
+                    _ => "Polynomial",
+                };
+                let equation = options.trendline_equation.then(|| {
+                    format!(
+                        "{}: {}   R² = {:.4}",
+                        series.name,
+                        fit.curve.equation(),
+                        fit.r_squared
+                    )
+                });
+                (kind, samples, equation)
+            }
+        };
+        trends.push(Some(Trend {
+            points,
+            name: format!("{} ({})", kind, series.name),
+            equation,
+        }));
     }
+    Ok(trends)
+}
 
-    // Read the file directly
-    let png_data = std::fs::read(filename)?;
+/// Widens the Y-axis range of the series drawn against one axis to their trendlines
+///
+/// On a log scale the range still runs between powers of ten.
+fn include_trends(
+    mut range: std::ops::Range<f64>,
+    data: &GraphData,
+    trends: &[Option<Trend>],
+    secondary_axis: bool,
+    log_scale: bool,
+) -> std::ops::Range<f64> {
+    let values = data
+        .series
+        .iter()
+        .zip(trends)
+        .filter(|(series, _)| series.secondary_axis == secondary_axis)
+        .filter_map(|(_, trend)| trend.as_ref())
+        .flat_map(|trend| trend.points.iter().map(|&(_, y)| y));
+    for y in values {
+        range.start = range.start.min(y);
+        range.end = range.end.max(y);
+    }
+    if log_scale {
+        range.start = 10f64.powf(range.start.log10().floor());
+        range.end = 10f64.powf(range.end.log10().ceil());
+    }
+    range
+}
 
-    // Clean up
-    remove_file(filename)?;
+/// Returns the Y-axis range covering the series drawn against one axis
+///
+/// On a linear scale the range includes y=0 if any of the series is drawn as bars or
+/// an area, and the whole height of the stacks of stacked bar series. On a log scale
+/// the range runs between the powers of ten around the values.
+///
+/// # Errors
+/// * Returns an error for a value of zero or less on a log scale
+fn value_range(
+    data: &GraphData,
+    secondary_axis: bool,
+    log_scale: bool,
+) -> Result<std::ops::Range<f64>, Box<dyn std::error::Error>> {
+    let series: Vec<&SeriesData> = data
+        .series
+        .iter()
+        .filter(|s| s.secondary_axis == secondary_axis)
+        .collect();
+    let mut values: Vec<f64> = series
+        .iter()
+        .filter(|s| s.graph_type != GraphType::StackedBar)
+        .flat_map(|s| s.values.iter().copied())
+        .collect();
+    if log_scale && series.iter().any(|s| s.values.iter().any(|&v| v <= 0.0)) {
+        return Err("A log scale needs values greater than zero".into());
+    }
+    for i in 0..data.x.len() {
+        let stacked = series
+            .iter()
+            .filter(|s| s.graph_type == GraphType::StackedBar)
+            .filter_map(|s| s.values.get(i).copied());
+        if stacked.clone().next().is_none() {
+            continue;
+        }
+        values.push(stacked.clone().filter(|&v| v > 0.0).sum());
+        if !log_scale {
+            values.push(stacked.filter(|&v| v < 0.0).sum());
+        }
+    }
 
-    Ok(png_data)
+    let mut min_y = lowest(values.iter().copied()).unwrap_or(0.0);
+    let max_y = highest(values.iter().copied()).unwrap_or(100.0);
+    if log_scale {
+        // Without values the axis runs from 1
+        let positive = |y: f64| if y > 0.0 { y } else { 1.0 };
+        let min_y = 10f64.powf(positive(min_y).log10().floor());
+        let max_y = 10f64.powf(positive(max_y).log10().ceil());
+        return Ok(min_y..if max_y > min_y { max_y } else { min_y * 10.0 });
+    }
+    if series
+        .iter()
+        .any(|s| s.graph_type != GraphType::Line && s.graph_type != GraphType::Scatter)
+    {
+        min_y = min_y.min(0.0);
+    }
+    Ok(min_y..max_y + 1.0)
 }
 
-/// Saves a scatter plot to a file
-///
-/// Creates a scatter plot and saves it directly to the specified file path.
+/// Draws a pie or donut chart of the first series
 ///
-/// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-/// * `path` - File path where the graph should be saved
+/// Slices are labelled with the X values, or their categories, and show their
+/// percentage of the total.
+/// Slices are coloured from the palette of the options in order, starting with the
+/// series colour.
 ///
-/// # Returns
-/// * A Result indicating success or failure
-fn save_scatter_graph(
-    data: Vec<(i32, i32)>,
+/// # Errors
+/// * Returns an error for negative values or values adding up to zero
+fn draw_pie<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
     options: &GraphOptions,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(path, (options.width, options.height)).into_drawing_area();
-    root.fill(&WHITE)?;
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let series = &data.series[0];
+    if series.values.iter().any(|&v| v < 0.0) {
+        return Err("Pie graph values must not be negative".into());
+    }
+    let sizes = series.values.clone();
+    if sizes.iter().sum::<f64>() == 0.0 {
+        return Err("Pie graph values add up to zero".into());
+    }
+    let palette = &options.palette;
+    let first = palette.iter().position(|&c| c == series.color);
+    let colors: Vec<RGBColor> = (0..sizes.len())
+        .map(|i| match first {
+            Some(first) => palette[(first + i) % palette.len()],
+            None if i == 0 => series.color,
+            None => palette[(i - 1) % palette.len()],
+        })
+        .collect();
+    let labels: Vec<String> = if data.categories.is_empty() {
+        data.x.iter().map(|x| x.to_string()).collect()
+    } else {
+        data.categories.clone()
+    };
+
+    let area = root.titled(&options.title, title_font(options))?;
+    let (x_pixels, y_pixels) = area.get_pixel_range();
+    let center = (
+        (x_pixels.start + x_pixels.end) / 2,
+        (y_pixels.start + y_pixels.end) / 2,
+    );
+    let radius = (x_pixels.len().min(y_pixels.len()) as f64 * 0.38).max(1.0);
+    let mut pie = Pie::new(&center, &radius, &sizes, &colors, &labels);
+    pie.start_angle(-90.0);
+    pie.label_style(
+        ("sans-serif", 15)
+            .into_font()
+            .color(&options.theme.foreground()),
+    );
+    pie.percentages(("sans-serif", 13).into_font().color(&BLACK));
+    if options.graph_type == GraphType::Donut {
+        pie.donut_hole(radius * 0.5);
+    }
+    area.draw(&pie)?;
+    Ok(())
+}
 
-    let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-    let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-    let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0);
-    let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
+/// Draws a histogram of every series, counted in the same bins
+///
+/// The values from the lowest to the highest, or between the X-axis limits of the
+/// options, are split into bins of equal width; values outside the limits are left
+/// out. The number of bins is taken from the options or else follows Sturges' rule.
+/// The bars of several series are drawn over each other, half transparent.
+fn draw_histogram<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
+    options: &GraphOptions,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let all = data.series.iter().flat_map(|s| s.values.iter().copied());
+    let min = lowest(all.clone()).ok_or("A histogram needs at least one value")?;
+    let max = highest(all).unwrap_or(0.0) + 1.0;
+    let x_range = axis_limits(min..max, options.x_min, options.x_max, "X")?;
+    let (min, max) = (x_range.start, x_range.end);
+    let count = data
+        .series
+        .iter()
+        .map(|s| s.values.len())
+        .max()
+        .unwrap_or(1);
+    let bins = if options.bins > 0 {
+        options.bins
+    } else {
+        (count as f64).log2().ceil() as usize + 1
+    };
+    let width = (max - min) / bins as f64;
 
-    let x_range = *min_x as f64..*max_x as f64 + 1.0;
-    let y_range = *min_y as f64..*max_y as f64 + 1.0;
+    let counts: Vec<Vec<u32>> = data
+        .series
+        .iter()
+        .map(|s| {
+            let mut counts = vec![0; bins];
+            for &v in &s.values {
+                if v >= min && v <= max {
+                    let bin = (((v - min) / width) as usize).min(bins - 1);
+                    counts[bin] += 1;
+                }
+            }
+            counts
+        })
+        .collect();
+    let tallest = counts.iter().flatten().copied().max().unwrap_or(0);
+    let y_range = axis_limits(0.0..tallest as f64 + 1.0, options.y_min, options.y_max, "Y")?;
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(&options.title, ("sans-serif", 30).into_font())
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, title_font(options))
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(40)
-        .build_cartesian_2d(x_range, y_range)?;
-
-    chart
-        .configure_mesh()
-        .x_desc(&options.x_label)
-        .y_desc(&options.y_label)
-        .draw()?;
-
-    chart.draw_series(
-        data.iter()
-            .map(|&(x, y)| Circle::new((x as f64, y as f64), 5, GREEN.filled())),
-    )?;
+        .build_cartesian_2d(min..max, y_range)?;
+    let tick_format = |value: &f64| options.tick_format.format(*value);
+    let mut mesh = chart.configure_mesh();
+    style_mesh(&mut mesh, options);
+    mesh.x_desc(&options.x_label).y_desc(&options.y_label);
+    if options.tick_format != TickFormat::Auto {
+        mesh.x_label_formatter(&tick_format);
+    }
+    mesh.draw()?;
 
-    root.present()?;
+    let opacity = if data.series.len() > 1 { 0.5 } else { 1.0 };
+    for (series, counts) in data.series.iter().zip(&counts) {
+        let color = series.color;
+        chart
+            .draw_series(counts.iter().enumerate().map(|(bin, &count)| {
+                let left = min + width * bin as f64;
+                Rectangle::new(
+                    [(left, 0.0), (left + width, count as f64)],
+                    color.mix(opacity).filled(),
+                )
+            }))?
+            .label(series.name.as_str())
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()));
+        if options.data_labels {
+            chart.draw_series(
+                counts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, count)| **count > 0)
+                    .map(|(bin, &count)| {
+                        let middle = min + width * (bin as f64 + 0.5);
+                        DataLabel::new(
+                            (middle, count as f64),
+                            count.to_string(),
+                            VPos::Bottom,
+                            -3,
+                            options,
+                        )
+                    }),
+            )?;
+        }
+    }
 
+    if options.legend && data.series.len() > 1 {
+        draw_legend(chart.configure_series_labels(), options)?;
+    }
     Ok(())
 }
 
-/// Creates an area graph from data points
-///
-/// Generates an area graph showing values with the area under the line filled in.
-/// Area graphs are good for emphasizing the magnitude of changes over time.
-///
-/// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-///
-/// # Returns
-/// * A Result containing the PNG image data as bytes or an error
+/// Draws a box plot of every series, labelled with the series names
 ///
 /// # Implementation Notes
-/// * Creates a temporary file to store the image before reading it back
-/// * Uses semi-transparent blue for the area fill
-/// * Sorts data points by x-value to ensure proper area filling
-/// * Area is filled between the line and y=0
-fn create_area_graph(
-    data: Vec<(i32, i32)>,
+/// * Quartiles are interpolated linearly between the sorted values
+/// * Whiskers reach the furthest values within 1.5 times the interquartile range of
+///   the box; values beyond are drawn as outliers
+fn draw_box_plot<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
     options: &GraphOptions,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    // Create a temporary file-based bitmap solution
-    let filename = "temp_graph.png";
-    {
-        // Create a file-based bitmap backend
-        let root =
-            BitMapBackend::new(filename, (options.width, options.height)).into_drawing_area();
-        root.fill(&WHITE)?;
-
-        let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-        let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-        let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0).min(&0); // Ensure we include 0
-        let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
-
-        let x_range = *min_x as f64..*max_x as f64 + 1.0;
-        let y_range = *min_y as f64..*max_y as f64 + 1.0;
-
-        let mut chart = ChartBuilder::on(&root)
-            .caption(&options.title, ("sans-serif", 30).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(40)
-            .build_cartesian_2d(x_range.clone(), y_range.clone())?;
-
-        chart
-            .configure_mesh()
-            .x_desc(&options.x_label)
-            .y_desc(&options.y_label)
-            .draw()?;
-
-        // Sort data by x to ensure proper area graph
-        let mut sorted_data = data.clone();
-        sorted_data.sort_by_key(|&(x, _)| x);
-
-        use plotters::series::AreaSeries;
-        use plotters::style::RGBAColor;
-
-        // Draw the area graph
-        chart.draw_series(AreaSeries::new(
-            sorted_data.iter().map(|&(x, y)| (x as f64, y as f64)),
-            0.0,
-            RGBAColor(30, 144, 255, 0.5), // semi-transparent blue
-        ))?;
-
-        root.present()?;
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    if let Some(empty) = data.series.iter().find(|s| s.values.is_empty()) {
+        return Err(format!("{} has no values", empty.name).into());
     }
+    let all = data.series.iter().flat_map(|s| s.values.iter().copied());
+    let min_y = lowest(all.clone()).unwrap_or(0.0);
+    let max_y = highest(all).unwrap_or(100.0);
+    let margin = ((max_y - min_y) * 0.05).max(1.0);
+    let y_range = axis_limits(
+        min_y - margin..max_y + margin,
+        options.y_min,
+        options.y_max,
+        "Y",
+    )?;
+    let count = data.series.len();
+    let names: Vec<&str> = data.series.iter().map(|s| s.name.as_str()).collect();
 
-    // Read the file directly
-    let mut file = std::fs::File::open(filename)?;
-    let mut buffer = Vec::new();
-    use std::io::Read;
-    file.read_to_end(&mut buffer)?;
-    remove_file(filename)?;
-    Ok(buffer)
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, title_font(options))
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(-0.5..count as f64 - 0.5, y_range)?;
+    let name_of = |x: &f64| category(*x, count).map_or(String::new(), |i| names[i].to_string());
+    let tick_format = |value: &f64| options.tick_format.format(*value);
+    let mut mesh = chart.configure_mesh();
+    style_mesh(&mut mesh, options);
+    mesh.x_labels(count + 1)
+        .x_label_formatter(&name_of)
+        .disable_x_mesh()
+        .x_desc(&options.x_label)
+        .y_desc(&options.y_label);
+    if options.tick_format != TickFormat::Auto {
+        mesh.y_label_formatter(&tick_format);
+    }
+    mesh.draw()?;
+
+    for (i, series) in data.series.iter().enumerate() {
+        let x = i as f64;
+        let color = series.color;
+        let [lower_fence, q1, median, q3, upper_fence] =
+            Quartiles::new(&series.values).values().map(f64::from);
+        let values = series.values.iter().copied();
+        let inside = values
+            .clone()
+            .filter(|&v| v >= lower_fence && v <= upper_fence);
+        let low = inside.clone().fold(q1, f64::min);
+        let high = inside.fold(q3, f64::max);
+
+        let mut elements: Vec<DynElement<DB, (f64, f64)>> = vec![
+            Rectangle::new([(x - 0.25, q1), (x + 0.25, q3)], color.mix(0.3).filled()).into_dyn(),
+            Rectangle::new([(x - 0.25, q1), (x + 0.25, q3)], color).into_dyn(),
+            PathElement::new(
+                [(x - 0.25, median), (x + 0.25, median)],
+                color.stroke_width(2),
+            )
+            .into_dyn(),
+            PathElement::new([(x, q3), (x, high)], color).into_dyn(),
+            PathElement::new([(x, q1), (x, low)], color).into_dyn(),
+            PathElement::new([(x - 0.1, high), (x + 0.1, high)], color).into_dyn(),
+            PathElement::new([(x - 0.1, low), (x + 0.1, low)], color).into_dyn(),
+        ];
+        elements.extend(
+            values
+                .filter(|&v| v < lower_fence || v > upper_fence)
+                .map(|v| Circle::new((x, v), 3, color).into_dyn()),
+        );
+        chart.draw_series(elements)?;
+    }
+    Ok(())
 }
 
-/// Saves an area graph to a file
-///
-/// Creates an area graph and saves it directly to the specified file path.
+/// Returns the category whose centre an axis value is at
 ///
-/// # Arguments
-/// * `data` - Vector of (x,y) data points
-/// * `options` - Graph styling options
-/// * `path` - File path where the graph should be saved
-///
-/// # Returns
-/// * A Result indicating success or failure
+/// Categories are unit wide and centred on 0, 1, 2 and so on, so the values in between
+/// are left unlabelled.
+fn category(value: f64, count: usize) -> Option<usize> {
+    let i = value.round();
+    ((value - i).abs() < 1e-9 && i >= 0.0 && (i as usize) < count).then_some(i as usize)
+}
+
+/// Draws a heatmap of a 2-D range, one series per row
 ///
-/// # Implementation Notes
-/// * Sorts data by x-value to ensure proper area filling
-/// * Uses 20% opacity blue fill with blue border
-fn save_area_graph(
-    data: Vec<(i32, i32)>,
+/// Cells are coloured from the background colour for the lowest value to the series
+/// colour for the highest, and the axes are labelled with column letters and row
+/// numbers.
+fn draw_heatmap<DB>(
+    root: &DrawingArea<DB, Shift>,
+    data: &GraphData,
     options: &GraphOptions,
-    path: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(path, (options.width, options.height)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let min_x = data.iter().map(|(x, _)| x).min().unwrap_or(&0);
-    let max_x = data.iter().map(|(x, _)| x).max().unwrap_or(&100);
-    let min_y = data.iter().map(|(_, y)| y).min().unwrap_or(&0).min(&0); // Ensure we include 0
-    let max_y = data.iter().map(|(_, y)| y).max().unwrap_or(&100);
-
-    let x_range = *min_x as f64..*max_x as f64 + 1.0;
-    let y_range = *min_y as f64..*max_y as f64 + 1.0;
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+{
+    let columns = data.x.len();
+    let rows = data.series.len();
+    let all = data.series.iter().flat_map(|s| s.values.iter().copied());
+    let min = lowest(all.clone()).unwrap_or(0.0);
+    let max = highest(all).unwrap_or(0.0);
+    let color = data.series[0].color;
+    let background = options.theme.background();
+    let shade = |value: f64| {
+        let t = if max > min {
+            (value - min) / (max - min)
+        } else {
+            1.0
+        };
+        let blend = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t).round() as u8;
+        RGBColor(
+            blend(background.0, color.0),
+            blend(background.1, color.1),
+            blend(background.2, color.2),
+        )
+    };
 
-    let mut chart = ChartBuilder::on(&root)
-        .caption(&options.title, ("sans-serif", 30).into_font())
+    let mut chart = ChartBuilder::on(root)
+        .caption(&options.title, title_font(options))
         .margin(10)
         .x_label_area_size(30)
         .y_label_area_size(40)
-        .build_cartesian_2d(x_range, y_range)?;
+        .build_cartesian_2d(-0.5..columns as f64 - 0.5, -0.5..rows as f64 - 0.5)?;
 
-    chart
-        .configure_mesh()
+    // Rows are drawn from the top down, as in the sheet
+    let column_name = |x: &f64| {
+        category(*x, columns).map_or(String::new(), |i| {
+            Spreadsheet::col_to_letter(data.x[i] as i16)
+        })
+    };
+    let row_name = |y: &f64| {
+        category(*y, rows).map_or(String::new(), |i| data.series[rows - 1 - i].name.clone())
+    };
+    let mut mesh = chart.configure_mesh();
+    style_mesh(&mut mesh, options);
+    mesh.disable_mesh()
+        .x_labels(columns + 1)
+        .y_labels(rows + 1)
+        .x_label_formatter(&column_name)
+        .y_label_formatter(&row_name)
         .x_desc(&options.x_label)
         .y_desc(&options.y_label)
         .draw()?;
 
-    // Sort data by x to ensure proper area graph
-    let mut sorted_data = data.clone();
-    sorted_data.sort_by_key(|&(x, _)| x);
-
-    chart.draw_series(
-        AreaSeries::new(
-            sorted_data.iter().map(|&(x, y)| (x as f64, y as f64)),
-            0.0,
-            BLUE.mix(0.2),
-        )
-        .border_style(BLUE),
-    )?;
-
-    root.present()?;
+    chart.draw_series(data.series.iter().enumerate().flat_map(|(row, series)| {
+        let y = (rows - 1 - row) as f64;
+        series.values.iter().enumerate().map(move |(col, &value)| {
+            let x = col as f64;
+            Rectangle::new(
+                [(x - 0.5, y - 0.5), (x + 0.5, y + 0.5)],
+                shade(value).filled(),
+            )
+        })
+    }))?;
+    if options.data_labels {
+        chart.draw_series(data.series.iter().enumerate().flat_map(|(row, series)| {
+            let y = (rows - 1 - row) as f64;
+            series.values.iter().enumerate().map(move |(col, &value)| {
+                let text = options.tick_format.format(value);
+                DataLabel::new((col as f64, y), text, VPos::Center, 0, options)
+            })
+        }))?;
+    }
 
+    // The legend shows the colours of the lowest and highest values
+    if options.legend {
+        let extremes = if min < max { vec![min, max] } else { vec![max] };
+        for value in extremes {
+            let swatch = shade(value);
+            let border = options.theme.foreground();
+            chart
+                .draw_series(std::iter::empty::<Rectangle<(f64, f64)>>())?
+                .label(options.tick_format.format(value))
+                .legend(move |(x, y)| {
+                    EmptyElement::at((x, y))
+                        + Rectangle::new([(0, -5), (20, 5)], swatch.filled())
+                        + Rectangle::new([(0, -5), (20, 5)], border)
+                });
+        }
+        draw_legend(chart.configure_series_labels(), options)?;
+    }
     Ok(())
 }
 
@@ -767,15 +2369,8 @@ pub fn create_example_graphs() -> Vec<(String, String)> {
     });
 
     // Create sample data
-    let data: Vec<(i32, i32)> = vec![
-        (1, 10),
-        (2, 25),
-        (3, 15),
-        (4, 30),
-        (5, 22),
-        (6, 40),
-        (7, 35),
-    ];
+    let x: Vec<f64> = (1..=7).map(f64::from).collect();
+    let y: Vec<f64> = vec![10.0, 25.0, 15.0, 30.0, 22.0, 40.0, 35.0];
 
     // Standard options for all examples
     let base_options = GraphOptions {
@@ -784,43 +2379,37 @@ pub fn create_example_graphs() -> Vec<(String, String)> {
         y_label: "Y Values".to_string(),
         width: 600,
         height: 400,
-        graph_type: GraphType::Line,
+        ..GraphOptions::default()
     };
 
-    // Line graph
-    let mut line_options = base_options.clone();
-    line_options.title = "Example Line Graph".to_string();
-    line_options.graph_type = GraphType::Line;
-    let line_path = format!("{}/line_graph.png", output_dir);
-    if save_line_graph(data.clone(), &line_options, &line_path).is_ok() {
-        result.push(("Line".to_string(), line_path));
-    }
-
-    // Bar graph
-    let mut bar_options = base_options.clone();
-    bar_options.title = "Example Bar Graph".to_string();
-    bar_options.graph_type = GraphType::Bar;
-    let bar_path = format!("{}/bar_graph.png", output_dir);
-    if save_bar_graph(data.clone(), &bar_options, &bar_path).is_ok() {
-        result.push(("Bar".to_string(), bar_path));
-    }
-
-    // Scatter graph
-    let mut scatter_options = base_options.clone();
-    scatter_options.title = "Example Scatter Graph".to_string();
-    scatter_options.graph_type = GraphType::Scatter;
-    let scatter_path = format!("{}/scatter_graph.png", output_dir);
-    if save_scatter_graph(data.clone(), &scatter_options, &scatter_path).is_ok() {
-        result.push(("Scatter".to_string(), scatter_path));
-    }
-
-    // Area graph
-    let mut area_options = base_options.clone();
-    area_options.title = "Example Area Graph".to_string();
-    area_options.graph_type = GraphType::Area;
-    let area_path = format!("{}/area_graph.png", output_dir);
-    if save_area_graph(data.clone(), &area_options, &area_path).is_ok() {
-        result.push(("Area".to_string(), area_path));
+    let examples = [
+        ("Line", GraphType::Line),
+        ("Bar", GraphType::Bar),
+        ("Scatter", GraphType::Scatter),
+        ("Area", GraphType::Area),
+    ];
+    for (name, graph_type) in examples {
+        let options = GraphOptions {
+            title: format!("Example {} Graph", name),
+            graph_type,
+            ..base_options.clone()
+        };
+        let data = GraphData {
+            x: x.clone(),
+            categories: Vec::new(),
+            series: vec![SeriesData {
+                name: "Series 1".to_string(),
+                values: y.clone(),
+                color: PALETTE[0],
+                graph_type,
+                secondary_axis: false,
+            }],
+        };
+        let path = format!("{}/{}_graph.png", output_dir, name.to_lowercase());
+        let written = render_png(&data, &options).and_then(|png| Ok(std::fs::write(&path, png)?));
+        if written.is_ok() {
+            result.push((name.to_string(), path));
+        }
     }
 
     result
@@ -0,0 +1,325 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Append-only journal of cell edits.
+//!
+//! Every committed edit of a saved sheet is appended to a journal file next to it
+//! (`database/<user>/<sheet>.bin.gz.journal`), one JSON object per line, recording the
+//! cell, its formula before and after, who made the edit and when. Entries are numbered
+//! from 1 and never rewritten; restoring an earlier state appends the edits that undo the
+//! later ones, so the journal stays a complete history of the sheet.
+//!
+//! A sheet can be taken back to any journal position or time, either by rolling back the
+//! edits made after it from the current sheet ([`roll_back`]) or by replaying the edits
+//! made up to it onto an earlier copy ([`replay`]).
+
+use crate::spreadsheet::{ParsedRHS, Spreadsheet};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Extension added to the path of a sheet to form the path of its journal.
+pub const JOURNAL_EXTENSION: &str = "journal";
+
+/// A change to the formula of one cell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Edit {
+    /// Name of the cell, e.g. `B4`
+    pub cell: String,
+    /// Formula before the edit, empty for an empty cell
+    pub old: String,
+    /// Formula after the edit, empty for an empty cell
+    pub new: String,
+}
+
+/// An edit as recorded in the journal.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// Position in the journal, starting at 1
+    pub seq: u64,
+    /// When the edit was made, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// Who made the edit
+    pub user: String,
+    /// The edit itself
+    #[serde(flatten)]
+    pub edit: Edit,
+}
+
+/// A point in the history of a sheet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestorePoint {
+    /// Just after the entry with this position; 0 is before the first edit
+    Position(u64),
+    /// The last edit made at or before this time, in milliseconds since the Unix epoch
+    Time(u64),
+}
+
+impl RestorePoint {
+    /// Returns true if the entry was made at or before this point.
+    pub fn includes(&self, entry: &JournalEntry) -> bool {
+        match *self {
+            RestorePoint::Position(seq) => entry.seq <= seq,
+            RestorePoint::Time(timestamp) => entry.timestamp <= timestamp,
+        }
+    }
+}
+
+/// Returns the path of the journal of a sheet.
+pub fn journal_path(sheet_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", sheet_path, JOURNAL_EXTENSION))
+}
+
+/// Returns the current time in milliseconds since the Unix epoch.
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis() as u64)
+}
+
+/// Reads all entries of a journal.
+///
+/// A missing journal has no entries. A last line cut short by a crash during an append
+/// is ignored.
+///
+/// # Returns
+/// The entries in order, or an `InvalidData` error naming the first malformed line
+pub fn read_journal(path: &Path) -> io::Result<Vec<JournalEntry>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let complete = text.ends_with('\n');
+    let lines: Vec<&str> = text.lines().collect();
+    let mut entries = Vec::with_capacity(lines.len());
+    for (number, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(_) if !complete && number + 1 == lines.len() => break,
+            Err(e) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("journal line {}: {}", number + 1, e),
+                ));
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Appends edits to a journal and syncs it to disk.
+///
+/// The journal is read to find its last position; to append often, open it once as a
+/// [`Journal`] instead.
+///
+/// # Arguments
+/// * `path` - The journal file, created if missing
+/// * `user` - Who made the edits
+/// * `edits` - The edits, in the order they were made
+///
+/// # Returns
+/// The entries written
+pub fn append(path: &Path, user: &str, edits: &[Edit]) -> io::Result<Vec<JournalEntry>> {
+    Journal::open(path)?.append(user, edits)
+}
+
+/// A journal opened for appending, which remembers its last position.
+#[derive(Debug)]
+pub struct Journal {
+    /// The journal file
+    path: PathBuf,
+    /// Position of the last entry, 0 for an empty journal
+    last: u64,
+}
+
+impl Journal {
+    /// Opens a journal, reading it once to find its last position.
+    ///
+    /// A last line cut short by a crash is dropped, so new entries start on a line of
+    /// their own.
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let last = read_journal(path)?.last().map_or(0, |entry| entry.seq);
+        if let Ok(text) = fs::read_to_string(path)
+            && !text.is_empty()
+            && !text.ends_with('\n')
+        {
+            let complete = text.rfind('\n').map_or(0, |i| i + 1);
+            OpenOptions::new()
+                .write(true)
+                .open(path)?
+                .set_len(complete as u64)?;
+        }
+        Ok(Journal {
+            path: path.to_path_buf(),
+            last,
+        })
+    }
+
+    /// Returns the path of the journal file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends edits to the journal and syncs it to disk.
+    ///
+    /// # Arguments
+    /// * `user` - Who made the edits
+    /// * `edits` - The edits, in the order they were made
+    ///
+    /// # Returns
+    /// The entries written
+    pub fn append(&mut self, user: &str, edits: &[Edit]) -> io::Result<Vec<JournalEntry>> {
+        let timestamp = now_millis();
+        let entries: Vec<JournalEntry> = edits
+            .iter()
+            .enumerate()
+            .map(|(i, edit)| JournalEntry {
+                seq: self.last + 1 + i as u64,
+                timestamp,
+                user: user.to_string(),
+                edit: edit.clone(),
+            })
+            .collect();
+
+        let mut lines = String::new();
+        for entry in &entries {
+            lines.push_str(&serde_json::to_string(entry).map_err(io::Error::other)?);
+            lines.push('\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()?;
+        self.last += entries.len() as u64;
+        Ok(entries)
+    }
+}
+
+/// Returns the formula of a cell as typed, empty for an empty cell.
+pub fn formula_text(sheet: &Spreadsheet, row: i16, col: i16) -> String {
+    let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+    sheet.cells[index]
+        .as_ref()
+        .map(|cell| Spreadsheet::formula_to_string(&cell.formula))
+        .unwrap_or_default()
+}
+
+/// Returns the edits made to a sheet since its undo stack had `undo_len` entries.
+///
+/// A single command can change several cells, e.g. a paste with `COPY`; each changed
+/// cell gives one edit, in the order the cells were first changed. Cells set to the
+/// formula they already had are left out.
+pub fn edits_since(sheet: &Spreadsheet, undo_len: usize) -> Vec<Edit> {
+    let mut cells: Vec<(i16, i16, String)> = Vec::new();
+    for (formula, row, col) in sheet.undo_stack.iter().skip(undo_len) {
+        if !cells.iter().any(|&(r, c, _)| (r, c) == (*row, *col)) {
+            cells.push((*row, *col, Spreadsheet::formula_to_string(formula)));
+        }
+    }
+    cells
+        .into_iter()
+        .filter_map(|(row, col, old)| {
+            let new = formula_text(sheet, row, col);
+            (new != old).then(|| Edit {
+                cell: Spreadsheet::get_cell_name(row, col),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+/// Sets the formula of a cell from its text, emptying the cell for an empty text.
+///
+/// # Returns
+/// The edit made, or a message if the cell or formula is invalid or creates a cycle
+pub fn set_formula(sheet: &mut Spreadsheet, cell: &str, text: &str) -> Result<Edit, String> {
+    let (row, col) = sheet
+        .spreadsheet_parse_cell_name(cell)
+        .ok_or_else(|| format!("invalid cell {}", cell))?;
+    let old = formula_text(sheet, row, col);
+    let formula = if text.is_empty() {
+        ParsedRHS::None
+    } else {
+        let (valid, _, _, formula) = sheet.is_valid_command(cell, text);
+        if !valid {
+            return Err(format!("invalid formula {}={}", cell, text));
+        }
+        formula
+    };
+    let mut status = String::new();
+    sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+    if status != "ok" {
+        return Err(format!("{}={}: {}", cell, text, status));
+    }
+    Ok(Edit {
+        cell: cell.to_string(),
+        old,
+        new: formula_text(sheet, row, col),
+    })
+}
+
+/// Takes a sheet back to an earlier point by undoing the edits made after it.
+///
+/// `sheet` must be in the state reached after the last of `entries`. The edits after
+/// `point` are undone from the most recent backwards, each only if the cell still holds
+/// the formula the edit left in it; otherwise the sheet was changed without being
+/// journaled, and undoing the edits would not bring back the earlier state. On an error
+/// the sheet is left partly rolled back, so callers should roll back a copy.
+///
+/// # Returns
+/// The edits made to the sheet, to be appended to the journal, or a message if the
+/// journal does not match the sheet or an old formula could not be entered again
+pub fn roll_back(
+    sheet: &mut Spreadsheet,
+    entries: &[JournalEntry],
+    point: RestorePoint,
+) -> Result<Vec<Edit>, String> {
+    let mut edits = Vec::new();
+    for entry in entries.iter().rev().filter(|entry| !point.includes(entry)) {
+        let (row, col) = sheet
+            .spreadsheet_parse_cell_name(&entry.edit.cell)
+            .ok_or_else(|| format!("invalid cell {}", entry.edit.cell))?;
+        let current = formula_text(sheet, row, col);
+        if current != entry.edit.new {
+            return Err(format!(
+                "the journal does not match the sheet: entry {} left {} as '{}', but it is '{}'",
+                entry.seq, entry.edit.cell, entry.edit.new, current
+            ));
+        }
+        edits.push(set_formula(sheet, &entry.edit.cell, &entry.edit.old)?);
+    }
+    sheet.undo_stack.clear();
+    Ok(edits)
+}
+
+/// Brings a copy of a sheet forward to a later point by making the edits again.
+///
+/// # Arguments
+/// * `sheet` - The sheet as it was just after the entry at position `from`
+/// * `entries` - The journal
+/// * `from` - Position the copy was taken at, 0 for an empty sheet
+/// * `point` - The point to bring the sheet to
+///
+/// # Returns
+/// The edits made to the sheet, or a message if a formula could not be entered
+pub fn replay(
+    sheet: &mut Spreadsheet,
+    entries: &[JournalEntry],
+    from: u64,
+    point: RestorePoint,
+) -> Result<Vec<Edit>, String> {
+    let mut edits = Vec::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.seq > from && point.includes(entry))
+    {
+        edits.push(set_formula(sheet, &entry.edit.cell, &entry.edit.new)?);
+    }
+    sheet.undo_stack.clear();
+    Ok(edits)
+}
@@ -37,7 +37,7 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 ### Data Persistence Layer
 - Custom binary format with `.bin.gz` extension
 - Serialization via `bincode` + `serde`, compression with `gzip`
-- File versioning supports undo/redo state tracking
+- File versioning through named snapshots with cell-level diffs
 - CSV and XLSX export via `downloader` module
 
 ---
@@ -52,11 +52,29 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 ### `spreadsheet` Module
 - Main logic; manages grid, evaluation, updates
 - Formula evaluation engine with function support
+- Quoted text constants such as `"Jan"`, shown as written and worth 0 in formulas
+- In-cell `SPARKLINE(B2:M2)` trends, worth the last value of their range in formulas
 - Dependency tracking with cycle detection
 - Topological sorting for correct update order
 - Command processing (set cell, copy/paste of **values only**, undo/redo)
 - Error handling and propagation
 
+### `cli` Module
+- Command interpreter behind the terminal prompt
+- File, navigation and editing commands (`save`, `load`, `import`, `export`, `show`, `clear`, `resize`, `goto`)
+- Tab completion of commands, function names and cell references
+- Snapshot commands (`snapshot`, `snapshots`, `open_snapshot`, `restore_snapshot`, `diff`)
+- `plot line|bar|sparkline <ranges>` to chart ranges in the terminal
+
+### `script` Module
+- Non-interactive script mode (`--script`, `--load`, `--out`, `--print`) for pipelines
+- Stops at the first failing command with a non-zero exit code naming the line
+- Prints chosen cell values as JSON
+
+### `tui` Module
+- Full-screen terminal mode (`--tui`) with a cursor-driven grid sized to the terminal
+- Formula bar with in-place editing, error highlighting and a status line
+
 ### `login` Module
 - User registration, session validation, password reset
 - Cookie-based authentication system
@@ -70,20 +88,74 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 
 ### `saving` Module
 - Save/load logic with gzip+bincode
-- File version management
-- State tracking for undo/redo operations
 - Compressed binary format for efficient storage
+- File version management: magic number, format version and checksum header
+- Migration of files written in older format versions
+- Stores only the formulas of non-empty cells; values and dependencies are rebuilt on load
+- Atomic saves through a synced temporary file, keeping the last versions as backups
+
+### `journal` Module
+- Append-only journal of cell edits (cell, old and new formula, user, time) per saved sheet
+- Restore to any earlier journal position or time, by rolling back or replaying edits
+
+### `snapshot` Module
+- Named snapshots of a saved sheet ("Q3 close", "before import"), kept next to it
+- Listing, read-only opening and restoring of snapshots
+- Cell-level diff of added, removed and changed formulas and values between two sheets
+
+### `recovery` Module
+- Periodic autosave of the web session sheet to a recovery file
+- Detection of recovery files newer than their saved sheet at startup, with restore
 
 ### `downloader` Module
 - Data export in CSV/XLSX formats
+- CSV of values or of formulas (`=SUM(A1:A5)`), with an optional header row
+- XLSX workbooks of one or more sheets: formulas with an Excel equivalent and their results,
+  computed values for the rest, Excel error values and fitted column widths
+- Saved charts exported as native Excel charts reading the worksheet's cells
 - Format conversion utilities
 - Download request handling
 
+### `loader` Module
+- Streaming RFC 4180 CSV reader: quoted fields with embedded line breaks, CRLF/LF/CR records
+- Dialect options: delimiter, quote character, optional header row; UTF-8/UTF-16 byte order marks
+- Ragged rows, a maximum sheet size and data size, with a per-cell report of fields that could not be imported
+- Formulas in fields starting with `=`, with invalid formulas and reference cycles reported
+- XLSX, XLS and ODS import of every worksheet, with Excel formulas translated where supported
+  and unsupported ones reported
+
+### `chart` Module
+- Chart definitions (type, ranges and options) saved in the sheet file under a numeric id
+- Digest of the cells a chart reads, so charts are drawn again only when their data changes
+- Reading of X and Y ranges into values without the drawing dependencies
+
 ### `graph` Module
 - Graph plotting via `plotters`
-- Support for line, bar, area, scatter charts
-- Customizable titles, labels, and dimensions
-- Image data generation for browser display and saving via `image`
+- Support for line, bar, area, scatter, grouped and stacked bar charts
+- Pie and donut charts of shares, histograms with configurable bins, box plots of a
+  range's quartiles and heatmaps of a 2-D cell range
+- Several series per chart, each with its own name, colour and chart type, a legend and
+  an optional secondary Y axis; series can be named by a header cell
+- 2-D Y ranges drawn as one series per column, named by their header row, and text X
+  ranges drawn as categories such as month names; ranges are checked with errors that
+  name the range and cell at fault
+- Customizable titles, labels, and dimensions up to 4000 pixels
+- Styling through `GraphOptions`: palettes, line width, markers, gridlines, a log
+  scale, fixed axis limits, tick formats, data labels and light or dark themes
+- Linear, polynomial, exponential and moving-average trendlines on line and scatter
+  series, with the fitted equation and R² written on the chart
+- Rendering to an in-memory bitmap encoded as PNG via `image`, without temporary files
+- Vector output as SVG or PDF for reports and printing
+
+### `pdf` Module
+- `plotters` drawing backend writing single-page vector PDF documents
+- Standard Helvetica fonts, so no font is embedded
+
+### `plot` Module
+- Line charts, bar charts and sparklines drawn as text for the terminal
+- Unicode block and box-drawing characters, or plain ASCII
+- Ranges read like those of the web graphs, without the `web` feature
+- `SPARKLINE` cells drawn in block characters in the grids, and as inline SVG on the web
 
 ---
 
@@ -93,14 +165,20 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 - **Authentication**: `/login`, `/signup`, `/logout`, `/reset-password`, `/forgot-password`, `/change-password`
 - **Public Access**: `/:username/:sheet_name` for read-only sheet access
 - **Data Operations**: `/api/update_cell`, `/api/save`, `/api/load`, `/api/graph`, `/api/export`
+- **Saved Charts**: `/api/charts` (list, create), `/api/charts/:id` (get, replace, delete),
+  `/api/charts/:id/image`; cell updates report the ids of charts drawn again
 - **Downloads**: `/api/download/csv`, `/api/download/xlsx`
 - **API Access**: `/api/sheet`, `/api/cell/:cell_name`, `/api/sheet_info` for read-only data
+- **Versions**: `/api/journal`, `/api/snapshots`, `/api/snapshots/:snapshot` (read-only view),
+  `/api/diff?from=<snapshot>&to=<snapshot>` (against the live sheet without `to`)
 - **Static Content**: Static assets from `/static`
 
 ### Protected Endpoints
 - **Sheet Management**: `/sheet` for editing UI
 - **File Operations**: `/:username` (file listing), `/:username/create` (create new sheet),
   `/:username/:sheet_name/status` (update access), `/:username/:sheet_name/delete` (deletion)
+- **Versions**: `/api/journal/restore`, `/api/snapshots/create`, `/api/snapshots/:snapshot/restore`
+  (owner only)
 
 ### Middleware Logic
 - Auth checks via `require_auth` middleware for protected endpoints
@@ -141,6 +219,7 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 - Support for multiple chart types
 - Title and axis labeling
 - Dimension configuration
+- Text charts in the terminal
 
 ### Sharing & Access Control
 - Public/private spreadsheet access
@@ -179,15 +258,16 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 - Native compressed binary format (.bin.gz) for efficient storage
 - CSV export functionality for compatibility
 - Fast loading of existing spreadsheets from disk
-- File versioning support
+- File versioning through named snapshots, with diffs between versions
 - Auto-save capabilities
 
 ### Graph Plotting
 - Multiple chart types: line, bar, scatter, area
-- Customizable X and Y data ranges
+- Customizable X and Y data ranges, with several Y series per chart
 - Configurable titles, labels, and dimensions
 - Interactive graph generation from spreadsheet data
-- Image export options for generated graphs
+- Image export as PNG, SVG or PDF
+- Charts saved with the sheet and redrawn when their cells change
 
 ### Login System
 - Secure user registration and authentication
@@ -323,12 +403,22 @@ This application is a complete rewrite of a legacy spreadsheet tool from C to Ru
 #[cfg(feature = "web")]
 pub mod app;
 pub mod cell;
+pub mod chart;
+pub mod cli;
 pub mod downloader;
 pub mod graph;
+pub mod journal;
+pub mod loader;
 pub mod login;
 pub mod mailer;
+pub mod pdf;
+pub mod plot;
+pub mod recovery;
 pub mod saving;
+pub mod script;
+pub mod snapshot;
 pub mod spreadsheet;
+pub mod tui;
 
 /// Re-export everything from these modules to make it easier to use
 pub use cell::*;
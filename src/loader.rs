@@ -1,15 +1,459 @@
 #![cfg(not(tarpaulin_include))]
 
-use crate::spreadsheet::{FunctionName, Operand, ParsedRHS, Spreadsheet};
+use crate::spreadsheet::{Operand, ParsedRHS, Spreadsheet};
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::Path;
 
+/// Largest number of rows a sheet can have.
+pub const MAX_ROWS: usize = 999;
+
+/// Largest number of columns a sheet can have.
+pub const MAX_COLS: usize = 18278;
+
+/// Longest CSV field accepted by default, in bytes.
+pub const MAX_FIELD_LEN: usize = 32 * 1024;
+
+/// Largest total size of CSV fields accepted by default, in bytes.
+pub const MAX_TOTAL_LEN: usize = 64 * 1024 * 1024;
+
+/// Dialect and limits of a CSV file to import.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// Character separating fields, e.g. `,`, `;` or a tab
+    pub delimiter: char,
+    /// Character enclosing fields that contain delimiters, quotes or line breaks, or
+    /// `None` if fields are never quoted
+    pub quote: Option<char>,
+    /// Whether the first record holds column names rather than data
+    pub has_header: bool,
+    /// Largest number of data rows accepted, at most [`MAX_ROWS`]
+    pub max_rows: usize,
+    /// Largest number of fields in a record accepted, at most [`MAX_COLS`]
+    pub max_cols: usize,
+    /// Longest field accepted, in bytes, so that one unterminated or huge quoted field
+    /// cannot exhaust memory
+    pub max_field_len: usize,
+    /// Largest total size of the fields accepted, in bytes, counting one byte for the
+    /// delimiter or line break after each, so that the records held until the sheet is
+    /// built cannot exhaust memory
+    pub max_total_len: usize,
+}
+
+impl Default for CsvOptions {
+    /// Comma-separated fields quoted with `"`, no header row, the largest sheet size,
+    /// fields of up to [`MAX_FIELD_LEN`] bytes and up to [`MAX_TOTAL_LEN`] bytes of them in
+    /// all.
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            quote: Some('"'),
+            has_header: false,
+            max_rows: MAX_ROWS,
+            max_cols: MAX_COLS,
+            max_field_len: MAX_FIELD_LEN,
+            max_total_len: MAX_TOTAL_LEN,
+        }
+    }
+}
+
+/// A field that could not be imported into its cell.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellIssue {
+    /// Name of the cell, e.g. `B4`
+    pub cell: String,
+    /// The field as it appears in the file
+    pub text: String,
+    /// Why it was not imported
+    pub reason: String,
+}
+
+/// The result of importing a CSV file.
+pub struct CsvImport {
+    /// The imported sheet, as wide as the longest record
+    pub sheet: Box<Spreadsheet>,
+    /// Fields left empty because they could not be interpreted, row by row
+    pub issues: Vec<CellIssue>,
+    /// Rows of the sheet whose record had fewer or more fields than the first one
+    pub ragged_rows: Vec<i16>,
+}
+
+/// Text encodings told apart by a byte order mark.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// Streaming reader of CSV records as described by RFC 4180.
+///
+/// Quoted fields may contain delimiters, doubled quotes and line breaks. Records may end
+/// in CRLF, LF or CR. A UTF-8 or UTF-16 byte order mark selects the encoding; without one
+/// the input is read as UTF-8, with invalid bytes replaced by U+FFFD.
+pub struct CsvReader<R: Read> {
+    input: BufReader<R>,
+    encoding: Option<Encoding>,
+    delimiter: char,
+    quote: Option<char>,
+    max_field_len: usize,
+    pending: Option<char>,
+    line: usize,
+}
+
+impl<R: Read> CsvReader<R> {
+    /// Creates a reader of the records in `input`, using the delimiter, quote and field
+    /// length limit of `options`.
+    pub fn new(input: R, options: &CsvOptions) -> Self {
+        CsvReader {
+            input: BufReader::new(input),
+            encoding: None,
+            delimiter: options.delimiter,
+            quote: options.quote,
+            max_field_len: options.max_field_len,
+            pending: None,
+            line: 1,
+        }
+    }
+
+    /// Reads one byte, or `None` at the end of the input.
+    fn byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = self.input.fill_buf()?.first().copied();
+        if byte.is_some() {
+            self.input.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// Detects the encoding from a byte order mark and skips the mark.
+    fn detect_encoding(&mut self) -> io::Result<Encoding> {
+        let start = self.input.fill_buf()?;
+        let (encoding, mark) = if start.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            (Encoding::Utf8, 3)
+        } else if start.starts_with(&[0xFF, 0xFE]) {
+            (Encoding::Utf16Le, 2)
+        } else if start.starts_with(&[0xFE, 0xFF]) {
+            (Encoding::Utf16Be, 2)
+        } else {
+            (Encoding::Utf8, 0)
+        };
+        self.input.consume(mark);
+        Ok(encoding)
+    }
+
+    /// Reads one UTF-16 code unit in the given byte order.
+    fn unit(&mut self, encoding: Encoding) -> io::Result<Option<u16>> {
+        let (Some(first), Some(second)) = (self.byte()?, self.byte()?) else {
+            return Ok(None);
+        };
+        Ok(Some(match encoding {
+            Encoding::Utf16Le => u16::from_le_bytes([first, second]),
+            _ => u16::from_be_bytes([first, second]),
+        }))
+    }
+
+    /// Reads one character, or `None` at the end of the input.
+    fn next_char(&mut self) -> io::Result<Option<char>> {
+        if let Some(c) = self.pending.take() {
+            return Ok(Some(c));
+        }
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => {
+                let encoding = self.detect_encoding()?;
+                self.encoding = Some(encoding);
+                encoding
+            }
+        };
+        match encoding {
+            Encoding::Utf8 => {
+                let Some(first) = self.byte()? else {
+                    return Ok(None);
+                };
+                let width = match first {
+                    0x00..=0x7F => return Ok(Some(first as char)),
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+                };
+                let mut bytes = vec![first];
+                for _ in 1..width {
+                    match self.input.fill_buf()?.first() {
+                        Some(&next) if next & 0xC0 == 0x80 => {
+                            bytes.push(next);
+                            self.input.consume(1);
+                        }
+                        _ => return Ok(Some(char::REPLACEMENT_CHARACTER)),
+                    }
+                }
+                Ok(Some(
+                    std::str::from_utf8(&bytes)
+                        .ok()
+                        .and_then(|text| text.chars().next())
+                        .unwrap_or(char::REPLACEMENT_CHARACTER),
+                ))
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let Some(first) = self.unit(encoding)? else {
+                    return Ok(None);
+                };
+                let mut units = vec![first];
+                if (0xD800..0xDC00).contains(&first)
+                    && let Some(second) = self.unit(encoding)?
+                {
+                    units.push(second);
+                }
+                Ok(char::decode_utf16(units)
+                    .next()
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)))
+            }
+        }
+    }
+
+    /// Reads the next record.
+    ///
+    /// # Returns
+    /// The fields of the record, `None` at the end of the input, or an `InvalidData`
+    /// error for a quoted field that is never closed or a field longer than the limit
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<String>>> {
+        let Some(mut c) = self.next_char()? else {
+            return Ok(None);
+        };
+        let first_line = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut at_start = true;
+        let mut quoted = false;
+        loop {
+            if quoted {
+                if Some(c) == self.quote {
+                    match self.next_char()? {
+                        Some(next) if Some(next) == self.quote => field.push(next),
+                        Some(next) => {
+                            // The closing quote; carry on with the character after it
+                            quoted = false;
+                            c = next;
+                            continue;
+                        }
+                        None => {
+                            fields.push(field);
+                            return Ok(Some(fields));
+                        }
+                    }
+                } else {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    field.push(c);
+                }
+            } else if c == self.delimiter {
+                fields.push(std::mem::take(&mut field));
+                at_start = true;
+            } else if at_start && Some(c) == self.quote {
+                quoted = true;
+                at_start = false;
+            } else if c == '\n' || c == '\r' {
+                if c == '\r' {
+                    match self.next_char()? {
+                        Some('\n') | None => {}
+                        Some(next) => self.pending = Some(next),
+                    }
+                }
+                self.line += 1;
+                fields.push(field);
+                return Ok(Some(fields));
+            } else {
+                field.push(c);
+                at_start = false;
+            }
+            if field.len() > self.max_field_len {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "field on line {} is longer than {} bytes",
+                        self.line, self.max_field_len
+                    ),
+                ));
+            }
+
+            c = match self.next_char()? {
+                Some(c) => c,
+                None if quoted => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("quoted field starting on line {} is not closed", first_line),
+                    ));
+                }
+                None => {
+                    fields.push(field);
+                    return Ok(Some(fields));
+                }
+            };
+        }
+    }
+}
+
+impl<R: Read> Iterator for CsvReader<R> {
+    type Item = io::Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Enters one CSV field into its cell.
+///
+/// Whole numbers become values and fields starting with `=` formulas; empty fields leave
+/// the cell empty.
+///
+/// # Returns
+/// Why the field could not be entered, if it could not
+fn import_field(sheet: &mut Spreadsheet, row: i16, col: i16, text: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return None;
+    }
+    let mut status = String::new();
+    if let Ok(num) = text.parse::<i32>() {
+        let formula = ParsedRHS::SingleValue(Operand::Number(num));
+        sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+        None
+    } else if let Some(formula_str) = text.strip_prefix('=') {
+        let (is_valid, _, _, formula) =
+            sheet.is_valid_command(&Spreadsheet::get_cell_name(row, col), formula_str);
+        if !is_valid {
+            return Some(String::from("invalid formula"));
+        }
+        sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+        (status != "ok").then_some(status)
+    } else if text.parse::<i64>().is_ok() {
+        Some(String::from("number out of range"))
+    } else if text.parse::<f64>().is_ok() {
+        Some(String::from("not a whole number"))
+    } else {
+        Some(String::from("not a number"))
+    }
+}
+
+/// Imports CSV data into a new spreadsheet.
+///
+/// The sheet is sized to the number of records and the longest record; shorter records
+/// leave the rest of their row empty. Fields that are not whole numbers or valid formulas
+/// are left empty and reported.
+///
+/// Records are parsed one at a time, but they are all held in memory until the last one
+/// fixes the size of the sheet; the total size limit of `options` bounds how much that
+/// is, and data above it is rejected.
+///
+/// # Arguments
+/// * `input` - The CSV data
+/// * `options` - The dialect of the data and the largest size accepted
+///
+/// # Returns
+/// * `Result<CsvImport, Box<dyn Error>>` - The sheet with its report, or an error for
+///   malformed, empty or oversized data
+pub fn read_csv(input: impl Read, options: &CsvOptions) -> Result<CsvImport, Box<dyn Error>> {
+    let max_rows = options.max_rows.min(MAX_ROWS);
+    let max_cols = options.max_cols.min(MAX_COLS);
+    let mut records = CsvReader::new(input, options);
+    if options.has_header {
+        records.next().transpose()?;
+    }
+
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut total_len = 0usize;
+    for record in records {
+        let record = record?;
+        total_len = record.iter().fold(total_len, |total, field| {
+            total.saturating_add(field.len() + 1)
+        });
+        if total_len > options.max_total_len {
+            return Err(format!("CSV data is larger than {} bytes", options.max_total_len).into());
+        }
+        if rows.len() == max_rows {
+            return Err(format!("CSV data has more than {} rows", max_rows).into());
+        }
+        if record.len() > max_cols {
+            return Err(format!(
+                "row {} has {} fields, more than {}",
+                rows.len() + 1,
+                record.len(),
+                max_cols
+            )
+            .into());
+        }
+        rows.push(record);
+    }
+    if rows.is_empty() {
+        return Err("CSV file is empty".into());
+    }
+
+    let cols = rows.iter().map(Vec::len).max().unwrap_or(1);
+    let mut sheet = Spreadsheet::spreadsheet_create(rows.len() as i16, cols as i16)
+        .ok_or("Failed to create spreadsheet")?;
+    let mut issues = Vec::new();
+    let mut ragged_rows = Vec::new();
+    for (r, record) in rows.iter().enumerate() {
+        let row = (r + 1) as i16;
+        if record.len() != rows[0].len() {
+            ragged_rows.push(row);
+        }
+        for (c, text) in record.iter().enumerate() {
+            let col = (c + 1) as i16;
+            if let Some(reason) = import_field(&mut sheet, row, col, text) {
+                issues.push(CellIssue {
+                    cell: Spreadsheet::get_cell_name(row, col),
+                    text: text.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+    sheet.undo_stack.clear();
+
+    Ok(CsvImport {
+        sheet,
+        issues,
+        ragged_rows,
+    })
+}
+
+/// Load a spreadsheet from a CSV file with the given dialect
+///
+/// # Arguments
+/// * `filepath` - Path to the CSV file to load
+/// * `options` - The dialect of the file and the largest size accepted
+///
+/// # Returns
+/// * `Result<CsvImport, Box<dyn Error>>` - The sheet with a report of the fields that
+///   could not be imported, or an error
+///
+/// # Examples
+/// ```no_run
+/// use cop::loader::{CsvOptions, from_csv_with_options};
+///
+/// let options = CsvOptions { delimiter: ';', has_header: true, ..CsvOptions::default() };
+/// match from_csv_with_options("data.csv", &options) {
+///     Ok(import) => println!("{} fields could not be imported", import.issues.len()),
+///     Err(e) => eprintln!("Error loading CSV: {}", e),
+/// }
+/// ```
+pub fn from_csv_with_options(
+    filepath: impl AsRef<Path>,
+    options: &CsvOptions,
+) -> Result<CsvImport, Box<dyn Error>> {
+    read_csv(File::open(filepath)?, options)
+}
+
 /// Load a spreadsheet from a CSV file
 ///
-/// This function imports a CSV file and converts it to a Spreadsheet structure.
-/// It handles headers, data rows, and properly unescapes special characters.
+/// This function imports a comma-separated file without a header row and converts it to
+/// a Spreadsheet structure. Fields that cannot be interpreted are left empty; use
+/// [`from_csv_with_options`] to choose the dialect and get a report of them.
 ///
 /// # Arguments
 /// * `filepath` - Path to the CSV file to load
@@ -27,71 +471,268 @@ use std::path::Path;
 /// }
 /// ```
 pub fn from_csv(filepath: impl AsRef<Path>) -> Result<Box<Spreadsheet>, Box<dyn Error>> {
-    let file = File::open(filepath)?;
-    let reader = BufReader::new(file);
-    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+    Ok(from_csv_with_options(filepath, &CsvOptions::default())?.sheet)
+}
 
-    if lines.is_empty() {
-        return Err("CSV file is empty".into());
-    }
+/// Excel functions that have a different name here, and the name they translate to.
+const EXCEL_FUNCTIONS: &[(&str, &str)] = &[
+    ("AVERAGE", "AVG"),
+    ("FORECAST", "FORECAST.LINEAR"),
+    ("STDEV.P", "STDEV"),
+    ("STDEVP", "STDEV"),
+];
 
-    // Count rows and columns
-    let rows = lines.len();
-    let cols = csv_count_columns(&lines[0])?;
+lazy_static! {
+    /// Whole-number forms the exporter writes, and the formula each stands for: a
+    /// truncated average, a rounded deviation and a division
+    static ref EXPORTED_FORMS: [(Regex, &'static str); 3] = [
+        (
+            Regex::new(r"(?i)^TRUNC\((AVERAGE\([^()]*\))\)$").unwrap(),
+            "$1",
+        ),
+        (
+            Regex::new(r"(?i)^ROUND\((STDEVP\([^()]*\)),\s*0\)$").unwrap(),
+            "$1",
+        ),
+        (
+            Regex::new(r"(?i)^QUOTIENT\(([^(),]+),([^(),]+)\)$").unwrap(),
+            "$1/$2",
+        ),
+    ];
+}
 
-    // Create spreadsheet
-    let mut sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16)
-        .ok_or("Failed to create spreadsheet")?;
+/// Translates an Excel formula into the formula syntax of this spreadsheet.
+///
+/// Absolute references such as `$A$1` become plain references, the `_xlfn.` and `_xlws.`
+/// prefixes Excel stores before newer functions are dropped, and functions known here
+/// under another name (`AVERAGE`, `FORECAST`, `STDEV.P`) are renamed. Whether the result is a
+/// supported formula is left to `is_valid_command`. The whole-number forms written by
+/// [`excel_formula`](crate::downloader::excel_formula), such as `QUOTIENT(A1,B1)`, are
+/// read back as the formulas they were written from.
+///
+/// # Arguments
+/// * `formula` - The formula, with or without its leading `=`
+///
+/// # Returns
+/// * `Result<String, String>` - The formula without a leading `=`, or why it cannot be
+///   translated
+///
+/// # Examples
+/// ```
+/// use cop::loader::translate_excel_formula;
+///
+/// assert_eq!(translate_excel_formula("=AVERAGE($A$1:A5)").unwrap(), "AVG(A1:A5)");
+/// assert!(translate_excel_formula("=Sheet2!A1").is_err());
+/// ```
+pub fn translate_excel_formula(formula: &str) -> Result<String, String> {
+    let formula = formula.trim();
+    let formula = formula.strip_prefix('=').unwrap_or(formula);
+    if formula.contains('#') {
+        return Err(String::from("error value"));
+    }
+    if formula.contains('!') {
+        return Err(String::from("refers to another worksheet"));
+    }
+    if formula.contains('"') {
+        return Err(String::from("text in formulas is not supported"));
+    }
+    let formula = EXPORTED_FORMS
+        .iter()
+        .find(|(form, _)| form.is_match(formula))
+        .map_or(formula.into(), |(form, replacement)| {
+            form.replace(formula, *replacement)
+        });
 
-    // Parse and fill data
-    for (r, line) in lines.iter().enumerate() {
-        let row_cells = parse_csv_row(line)?;
-        for (c, value_str) in row_cells.iter().enumerate() {
-            if c >= cols || r >= rows {
-                continue; // Skip extra data
+    let mut translated = String::new();
+    let mut word = String::new();
+    for c in formula.chars().chain(std::iter::once(' ')) {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            word.push(c.to_ascii_uppercase());
+            continue;
+        }
+        if c == '(' {
+            let mut name = word.as_str();
+            for prefix in ["_XLFN.", "_XLWS."] {
+                name = name.strip_prefix(prefix).unwrap_or(name);
             }
+            let name = EXCEL_FUNCTIONS
+                .iter()
+                .find(|(excel, _)| *excel == name)
+                .map_or(name, |(_, ours)| ours);
+            translated.push_str(name);
+        } else {
+            translated.push_str(&word);
+        }
+        word.clear();
+        if c != '$' && !c.is_whitespace() {
+            translated.push(c);
+        }
+    }
+    Ok(translated)
+}
 
-            let row = (r + 1) as i16;
-            let col = (c + 1) as i16;
+/// A worksheet imported from a workbook.
+pub struct ImportedSheet {
+    /// Name of the worksheet in the workbook
+    pub name: String,
+    /// The imported sheet, sized to the last used cell of the worksheet
+    pub sheet: Box<Spreadsheet>,
+    /// Cells that could not be imported as they are, row by row
+    pub issues: Vec<CellIssue>,
+}
 
-            // Try to parse as number or formula
-            if let Ok(num) = value_str.parse::<i32>() {
-                let formula = ParsedRHS::SingleValue(Operand::Number(num));
-                let mut status = String::new();
-                sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-            } else if value_str.starts_with('=') {
-                // Handle formula - strip the = sign
-                let formula_str = &value_str[1..];
-                let mut status = String::new();
+/// Imports one worksheet of a workbook.
+///
+/// Formulas are translated and entered first where they are supported. Other cells keep
+/// their value if it is a whole number; anything else is left empty and reported.
+#[cfg(feature = "web")]
+fn import_worksheet(
+    name: &str,
+    values: &calamine::Range<calamine::Data>,
+    formulas: &calamine::Range<String>,
+) -> Result<ImportedSheet, Box<dyn Error>> {
+    use calamine::Data;
 
-                // Try to parse the formula
-                let (is_valid, _, _, formula) =
-                    sheet.is_valid_command(&Spreadsheet::get_cell_name(row, col), formula_str);
+    let extent =
+        |end: Option<(u32, u32)>| end.map_or((0, 0), |(r, c)| (r as usize + 1, c as usize + 1));
+    let (value_rows, value_cols) = extent(values.end());
+    let (formula_rows, formula_cols) = extent(formulas.end());
+    let rows = value_rows.max(formula_rows).max(1);
+    let cols = value_cols.max(formula_cols).max(1);
+    if rows > MAX_ROWS || cols > MAX_COLS {
+        return Err(format!(
+            "worksheet {} has {} rows and {} columns, more than {} by {}",
+            name, rows, cols, MAX_ROWS, MAX_COLS
+        )
+        .into());
+    }
+    let mut sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16)
+        .ok_or("Failed to create spreadsheet")?;
+    let mut issues = Vec::new();
+    let mut status = String::new();
 
+    // Cells whose formula was entered, and the issues of those whose formula was not
+    let mut has_formula = std::collections::HashSet::new();
+    let mut formula_issues = std::collections::HashMap::new();
+    let (start_row, start_col) = formulas.start().unwrap_or((0, 0));
+    for (r, c, text) in formulas.used_cells() {
+        let row = (start_row as usize + r + 1) as i16;
+        let col = (start_col as usize + c + 1) as i16;
+        let cell = Spreadsheet::get_cell_name(row, col);
+        let reason = match translate_excel_formula(text) {
+            Ok(formula) => {
+                let (is_valid, _, _, parsed) = sheet.is_valid_command(&cell, &formula);
                 if is_valid {
-                    sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+                    sheet.spreadsheet_set_cell_value(row, col, parsed, &mut status);
+                    if status == "ok" {
+                        has_formula.insert((row, col));
+                        continue;
+                    }
+                    status.clone()
                 } else {
-                    // If formula can't be parsed, store as text
-                    let formula = ParsedRHS::SingleValue(Operand::Number(0));
-                    sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+                    String::from("unsupported formula")
                 }
-            } else {
-                // Treat as text - store 0 as value for now
-                // In a real implementation, you might want to have a text type
-                let formula = ParsedRHS::SingleValue(Operand::Number(0));
-                let mut status = String::new();
+            }
+            Err(reason) => reason,
+        };
+        formula_issues.insert((row, col), issues.len());
+        issues.push(CellIssue {
+            cell,
+            text: format!("={}", text.trim_start_matches('=')),
+            reason,
+        });
+    }
+
+    let (start_row, start_col) = values.start().unwrap_or((0, 0));
+    for (r, c, value) in values.used_cells() {
+        let row = (start_row as usize + r + 1) as i16;
+        let col = (start_col as usize + c + 1) as i16;
+        if has_formula.contains(&(row, col)) {
+            continue;
+        }
+        let number = match value {
+            Data::Int(i) => i32::try_from(*i).map_err(|_| "number out of range"),
+            Data::Float(f) if f.fract() != 0.0 => Err("not a whole number"),
+            Data::Float(f) if f.abs() <= i32::MAX as f64 => Ok(*f as i32),
+            Data::Float(_) => Err("number out of range"),
+            Data::Error(_) => Err("error value"),
+            _ => Err("not a number"),
+        };
+        let formula_issue = formula_issues.get(&(row, col)).map(|&i| &mut issues[i]);
+        match (number, formula_issue) {
+            (Ok(number), Some(issue)) => {
+                let formula = ParsedRHS::SingleValue(Operand::Number(number));
                 sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+                issue.reason.push_str(", value kept");
             }
+            (Ok(number), None) => {
+                let formula = ParsedRHS::SingleValue(Operand::Number(number));
+                sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
+            }
+            (Err(_), Some(_)) => {}
+            (Err(reason), None) => issues.push(CellIssue {
+                cell: Spreadsheet::get_cell_name(row, col),
+                text: value.to_string(),
+                reason: reason.to_string(),
+            }),
         }
     }
+    sheet.undo_stack.clear();
+
+    let position = |issue: &CellIssue| sheet.spreadsheet_parse_cell_name(&issue.cell);
+    issues.sort_by_key(|issue| position(issue));
+    Ok(ImportedSheet {
+        name: name.to_string(),
+        sheet,
+        issues,
+    })
+}
+
+/// Load every worksheet of an Excel or OpenDocument workbook
+///
+/// This function imports XLSX, XLSM, XLS and ODS workbooks. Supported formulas are
+/// translated with [`translate_excel_formula`]; cells whose formula is not supported
+/// keep their last computed value if it is a whole number, and are reported.
+///
+/// # Arguments
+/// * `filepath` - Path to the workbook to load
+///
+/// # Returns
+/// * `Result<Vec<ImportedSheet>, Box<dyn Error>>` - The worksheets in workbook order, or
+///   an error
+///
+/// # Examples
+/// ```no_run
+/// use cop::loader::from_workbook;
+///
+/// for imported in from_workbook("data.xlsx").unwrap() {
+///     println!("{}: {} cells not imported", imported.name, imported.issues.len());
+/// }
+/// ```
+#[cfg(feature = "web")]
+pub fn from_workbook(filepath: impl AsRef<Path>) -> Result<Vec<ImportedSheet>, Box<dyn Error>> {
+    use calamine::{Reader, open_workbook_auto};
+
+    let mut workbook = open_workbook_auto(filepath)?;
+    let names = workbook.sheet_names().to_vec();
+    if names.is_empty() {
+        return Err("No sheets found in workbook".into());
+    }
 
-    Ok(sheet)
+    let mut sheets = Vec::with_capacity(names.len());
+    for name in names {
+        let values = workbook.worksheet_range(&name)?;
+        let formulas = workbook.worksheet_formula(&name)?;
+        sheets.push(import_worksheet(&name, &values, &formulas)?);
+    }
+    Ok(sheets)
 }
 
 /// Load a spreadsheet from an Excel file
 ///
-/// This function imports an Excel file (XLSX) and converts it to a Spreadsheet structure.
-/// It preserves cell values and attempts to convert Excel formulas to the internal format.
+/// This function imports the first worksheet of an Excel or OpenDocument workbook; use
+/// [`from_workbook`] to import every worksheet and get a report of the cells that could
+/// not be imported.
 ///
 /// # Arguments
 /// * `filepath` - Path to the Excel file to load
@@ -110,152 +751,7 @@ pub fn from_csv(filepath: impl AsRef<Path>) -> Result<Box<Spreadsheet>, Box<dyn
 /// ```
 #[cfg(feature = "web")]
 pub fn from_excel(filepath: impl AsRef<Path>) -> Result<Box<Spreadsheet>, Box<dyn Error>> {
-    use calamine::{open_workbook, Reader, Xlsx};
-
-    let mut workbook: Xlsx<_> = open_workbook(filepath)?;
-
-    // Get the first worksheet
-    let sheet_name = workbook
-        .sheet_names()
-        .get(0)
-        .ok_or("No sheets found in Excel file")?
-        .clone();
-
-    let range = workbook
-        .worksheet_range(&sheet_name)
-        .ok_or("Failed to get worksheet")??;
-
-    let rows = range.height();
-    let cols = range.width();
-
-    if rows == 0 || cols == 0 {
-        return Err("Excel sheet is empty".into());
-    }
-
-    // Create spreadsheet
-    let mut sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16)
-        .ok_or("Failed to create spreadsheet")?;
-
-    // Parse cells
-    for (r, row) in range.rows().enumerate() {
-        for (c, cell) in row.iter().enumerate() {
-            let row = (r + 1) as i16;
-            let col = (c + 1) as i16;
-            let mut status = String::new();
-
-            match cell {
-                calamine::Data::Int(i) => {
-                    // Integer value
-                    if *i <= i32::MAX as i64 && *i >= i32::MIN as i64 {
-                        let formula = ParsedRHS::SingleValue(Operand::Number(*i as i32));
-                        sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                    }
-                }
-                calamine::Data::Float(f) => {
-                    // Float value - convert to integer as the system works with i32
-                    let formula = ParsedRHS::SingleValue(Operand::Number(*f as i32));
-                    sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                }
-                calamine::Data::Formula(_, value) => {
-                    // For formulas, we store the calculated value
-                    // In a real implementation, we'd try to convert the Excel formula to our format
-                    match value {
-                        calamine::Data::Int(i)
-                            if *i <= i32::MAX as i64 && *i >= i32::MIN as i64 =>
-                        {
-                            let formula = ParsedRHS::SingleValue(Operand::Number(*i as i32));
-                            sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                        }
-                        calamine::Data::Float(f) => {
-                            let formula = ParsedRHS::SingleValue(Operand::Number(*f as i32));
-                            sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                        }
-                        _ => {
-                            // Default to 0 for other types
-                            let formula = ParsedRHS::SingleValue(Operand::Number(0));
-                            sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                        }
-                    }
-                }
-                // Handle other data types - default to 0
-                _ => {
-                    let formula = ParsedRHS::SingleValue(Operand::Number(0));
-                    sheet.spreadsheet_set_cell_value(row, col, formula, &mut status);
-                }
-            }
-        }
-    }
-
-    Ok(sheet)
-}
-
-// Helper function to count columns in a CSV row
-fn csv_count_columns(line: &str) -> Result<usize, Box<dyn Error>> {
-    let mut columns = 0;
-    let mut in_quotes = false;
-    let mut chars = line.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        if c == '"' {
-            // Toggle quote state
-            if let Some(&next) = chars.peek() {
-                if next == '"' {
-                    // Double quote inside quoted field - skip the second quote
-                    chars.next();
-                } else {
-                    in_quotes = !in_quotes;
-                }
-            } else {
-                in_quotes = !in_quotes;
-            }
-        } else if c == ',' && !in_quotes {
-            columns += 1;
-        }
-    }
-
-    // Add 1 for the last column
-    columns += 1;
-    Ok(columns)
-}
-
-// Parse a CSV row into a vector of strings
-fn parse_csv_row(line: &str) -> Result<Vec<String>, Box<dyn Error>> {
-    let mut result = Vec::new();
-    let mut current_field = String::new();
-    let mut in_quotes = false;
-    let mut chars = line.chars().peekable();
-
-    while let Some(c) = chars.next() {
-        match c {
-            '"' => {
-                if let Some(&next) = chars.peek() {
-                    if next == '"' && in_quotes {
-                        // Double quote inside quoted field - add a single quote
-                        current_field.push('"');
-                        chars.next();
-                    } else {
-                        // Toggle quote state
-                        in_quotes = !in_quotes;
-                    }
-                } else {
-                    in_quotes = !in_quotes;
-                }
-            }
-            ',' if !in_quotes => {
-                // End of field
-                result.push(current_field);
-                current_field = String::new();
-            }
-            _ => {
-                current_field.push(c);
-            }
-        }
-    }
-
-    // Add the last field
-    result.push(current_field);
-
-    Ok(result)
+    Ok(from_workbook(filepath)?.remove(0).sheet)
 }
 
 /// Detect file type and load appropriate format
@@ -288,9 +784,11 @@ pub fn load_spreadsheet(filepath: impl AsRef<Path>) -> Result<Box<Spreadsheet>,
     match extension.as_deref() {
         Some("csv") => from_csv(path),
         #[cfg(feature = "web")]
-        Some("xlsx") | Some("xls") => from_excel(path),
+        Some("xlsx" | "xlsm" | "xls" | "ods") => from_excel(path),
         #[cfg(not(feature = "web"))]
-        Some("xlsx") | Some("xls") => Err("Excel support requires the 'web' feature".into()),
+        Some("xlsx" | "xlsm" | "xls" | "ods") => {
+            Err("Excel support requires the 'web' feature".into())
+        }
         Some(ext) => Err(format!("Unsupported file extension: {}", ext).into()),
         None => Err("File has no extension".into()),
     }
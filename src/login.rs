@@ -173,7 +173,8 @@ lazy_static! {
 
 // Constants
 const USERS_FILE: &str = "database/users.json";
-const DATABASE_DIR: &str = "database";
+/// Directory holding `users.json` and one directory of sheets per user
+pub const DATABASE_DIR: &str = "database";
 #[cfg(feature = "web")]
 const SESSION_DURATION: u64 = 24 * 60 * 60; // 24 hours in seconds
 
@@ -432,10 +433,10 @@ pub fn create_session(username: &str) -> String {
 pub fn validate_session(session_id: &str) -> Option<String> {
     let sessions = SESSIONS.read().unwrap();
 
-    if let Some(session) = sessions.get(session_id) {
-        if session.expires_at > SystemTime::now() {
-            return Some(session.user_id.clone());
-        }
+    if let Some(session) = sessions.get(session_id)
+        && session.expires_at > SystemTime::now()
+    {
+        return Some(session.user_id.clone());
     }
 
     None
@@ -457,22 +458,23 @@ pub fn get_user_files(username: &str) -> Vec<UserFile> {
     if let Ok(entries) = fs::read_dir(user_dir) {
         for entry in entries.flatten() {
             let path = entry.path();
-            if path.is_file() && path.extension().and_then(|ext| ext.to_str()) == Some("gz") {
-                if let Some(filename) = path.file_name().and_then(|name| name.to_str()) {
-                    let metadata = match fs::metadata(&path) {
-                        Ok(meta) => meta,
-                        Err(_) => continue, // Skip this file if we can't get metadata
-                    };
-                    let created = metadata.created().unwrap_or(SystemTime::now());
-                    let modified = metadata.modified().unwrap_or(SystemTime::now());
-
-                    files.push(UserFile {
-                        name: filename.to_string(),
-                        path: path.to_string_lossy().to_string(),
-                        created,
-                        modified,
-                    });
-                }
+            if path.is_file()
+                && path.extension().and_then(|ext| ext.to_str()) == Some("gz")
+                && let Some(filename) = path.file_name().and_then(|name| name.to_str())
+            {
+                let metadata = match fs::metadata(&path) {
+                    Ok(meta) => meta,
+                    Err(_) => continue, // Skip this file if we can't get metadata
+                };
+                let created = metadata.created().unwrap_or(SystemTime::now());
+                let modified = metadata.modified().unwrap_or(SystemTime::now());
+
+                files.push(UserFile {
+                    name: filename.to_string(),
+                    path: path.to_string_lossy().to_string(),
+                    created,
+                    modified,
+                });
             }
         }
     }
@@ -588,11 +590,11 @@ pub async fn require_auth(
     next: axum::middleware::Next,
 ) -> Response {
     // First, if a valid session exists, allow the request.
-    if let Some(session_cookie) = jar.get("session") {
-        if let Some(username) = validate_session(session_cookie.value()) {
-            request.extensions_mut().insert(username);
-            return next.run(request).await;
-        }
+    if let Some(session_cookie) = jar.get("session")
+        && let Some(username) = validate_session(session_cookie.value())
+    {
+        request.extensions_mut().insert(username);
+        return next.run(request).await;
     }
 
     // No valid session; if the call is for an API endpoint, check if the sheet is public.
@@ -609,22 +611,22 @@ pub async fn require_auth(
         };
 
         // If there's an authenticated user matching the owner, allow access.
-        if let Some(auth_user) = request.extensions().get::<String>() {
-            if *auth_user == owner {
-                return next.run(request).await;
-            }
+        if let Some(auth_user) = request.extensions().get::<String>()
+            && *auth_user == owner
+        {
+            return next.run(request).await;
         }
 
         if !owner.is_empty() && !sheet_name.is_empty() {
             let list_path = format!("database/{}/list.json", owner);
-            if let Ok(data) = std::fs::read_to_string(&list_path) {
-                if let Ok(entries) = serde_json::from_str::<Vec<crate::login::SheetEntry>>(&data) {
-                    let is_public = entries
-                        .iter()
-                        .any(|entry| entry.name == sheet_name && entry.status == "public");
-                    if is_public {
-                        return next.run(request).await;
-                    }
+            if let Ok(data) = std::fs::read_to_string(&list_path)
+                && let Ok(entries) = serde_json::from_str::<Vec<crate::login::SheetEntry>>(&data)
+            {
+                let is_public = entries
+                    .iter()
+                    .any(|entry| entry.name == sheet_name && entry.status == "public");
+                if is_public {
+                    return next.run(request).await;
                 }
             }
         }
@@ -650,37 +652,35 @@ pub async fn list_files(
     AxumPath(username): AxumPath<String>,
 ) -> Result<Html<String>, (StatusCode, &'static str)> {
     // 1) auth check
-    if let Some(cookie) = jar.get("session") {
-        if let Some(current) = validate_session(cookie.value()) {
-            if current == username {
-                // 2) load list.json
-                let user_dir = PathBuf::from(DATABASE_DIR).join(&username);
-                let list_path = user_dir.join("list.json");
-                let entries: Vec<SheetEntry> = if list_path.exists() {
-                    let data = fs::read_to_string(&list_path).unwrap_or_default();
-                    serde_json::from_str(&data).unwrap_or_default()
-                } else {
-                    Vec::new()
-                };
+    if let Some(cookie) = jar.get("session")
+        && let Some(current) = validate_session(cookie.value())
+        && current == username
+    {
+        // 2) load list.json
+        let user_dir = PathBuf::from(DATABASE_DIR).join(&username);
+        let list_path = user_dir.join("list.json");
+        let entries: Vec<SheetEntry> = if list_path.exists() {
+            let data = fs::read_to_string(&list_path).unwrap_or_default();
+            serde_json::from_str(&data).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
 
-                // 3) Get the template and inject the data
-                let mut template = include_str!("./static/list.html").to_string();
+        // 3) Get the template and inject the data
+        let mut template = include_str!("./static/list.html").to_string();
 
-                // Insert the sheets data as JavaScript
-                let sheets_json =
-                    serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string());
+        // Insert the sheets data as JavaScript
+        let sheets_json = serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string());
 
-                template = template.replace(
-                    "</head>",
-                    &format!(
-                        "    <script>const SHEETS_DATA = {};</script>\n</head>",
-                        sheets_json
-                    ),
-                );
+        template = template.replace(
+            "</head>",
+            &format!(
+                "    <script>const SHEETS_DATA = {};</script>\n</head>",
+                sheets_json
+            ),
+        );
 
-                return Ok(Html(template));
-            }
-        }
+        return Ok(Html(template));
     }
     Err((StatusCode::UNAUTHORIZED, "Unauthorized"))
 }
@@ -783,17 +783,21 @@ pub async fn handle_delete_sheet(
     // 1) Delete the spreadsheet file
     let user_dir = PathBuf::from(DATABASE_DIR).join(&username);
     let file_path = user_dir.join(format!("{}.bin.gz", sheet_name));
+    for backup in saving::backups(&file_path.to_string_lossy()) {
+        let _ = fs::remove_file(backup);
+    }
+    let _ = fs::remove_dir_all(crate::snapshot::snapshot_dir(&file_path.to_string_lossy()));
+    let _ = fs::remove_file(crate::journal::journal_path(&file_path.to_string_lossy()));
     let _ = fs::remove_file(&file_path);
 
     // 2) Update list.json
     let list_path = user_dir.join("list.json");
-    if list_path.exists() {
-        if let Ok(data) = fs::read_to_string(&list_path) {
-            if let Ok(mut entries) = serde_json::from_str::<Vec<SheetEntry>>(&data) {
-                entries.retain(|entry| entry.name != sheet_name);
-                let _ = fs::write(&list_path, serde_json::to_string_pretty(&entries).unwrap());
-            }
-        }
+    if list_path.exists()
+        && let Ok(data) = fs::read_to_string(&list_path)
+        && let Ok(mut entries) = serde_json::from_str::<Vec<SheetEntry>>(&data)
+    {
+        entries.retain(|entry| entry.name != sheet_name);
+        let _ = fs::write(&list_path, serde_json::to_string_pretty(&entries).unwrap());
     }
 
     Redirect::to(&format!("/{}", username))
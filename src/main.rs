@@ -1,10 +1,23 @@
 #![cfg(not(tarpaulin_include))]
 
+use cop::cli::{CommandResult, Session};
+use cop::script::{self, ScriptOptions};
 use cop::spreadsheet::Spreadsheet;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
 
 // use crate::spreadsheet::{Spreadsheet, Spreadsheet as SpreadsheetTrait};
+use std::cell::RefCell;
 use std::env;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
 // use std::os::macos::raw::stat;
 use std::time::Instant;
 
@@ -26,8 +39,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //     app::run(rows, cols).await?;
     // } else {
     // Run the spreadsheet functionality
-    if args.len() != 3 {
-        eprintln!("Usage: {} <rows> <cols>", args[0]);
+    if ScriptOptions::requested(&args) {
+        match ScriptOptions::from_args(&args[1..]) {
+            Ok(options) => process::exit(script::run(&options)),
+            Err(message) => {
+                eprintln!("Error: {}", message);
+                eprintln!(
+                    "Usage: {} [rows cols] [--script <file>] [--load <file>] [--out <file>] [--print <cells>]",
+                    args[0]
+                );
+                process::exit(script::EXIT_USAGE);
+            }
+        }
+    }
+
+    let tui = args.len() == 4 && args[3] == "--tui";
+    if args.len() != 3 && !tui {
+        eprintln!("Usage: {} <rows> <cols> [--tui]", args[0]);
         return Ok(());
     }
 
@@ -40,109 +68,58 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     let mut start_time = Instant::now(); // Start time for the first command
-    let mut sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16).unwrap();
+    let sheet = Spreadsheet::spreadsheet_create(rows as i16, cols as i16).unwrap();
+    if tui {
+        cop::tui::run(Session::new(sheet))?;
+        return Ok(());
+    }
+    let session = Rc::new(RefCell::new(Session::new(sheet)));
     let mut elapsed_time;
     let mut status = String::from("ok");
-    let mut show = true;
+
+    // Line editing and tab completion when attached to a terminal; piped input is read as is
+    let interactive = io::stdin().is_terminal();
+    let mut editor: Editor<CommandHelper, FileHistory> = Editor::new()?;
+    editor.set_helper(Some(CommandHelper {
+        session: Rc::clone(&session),
+    }));
+    let history = history_path();
+    if interactive {
+        let _ = editor.load_history(&history);
+    }
+
     loop {
-        if show {
-            sheet.spreadsheet_display();
+        if session.borrow().show_output {
+            session.borrow().sheet.spreadsheet_display();
         }
 
         elapsed_time = start_time.elapsed().as_secs_f64(); // Calculate time since the last command
-        print!("[{:.1}] ({}) > ", elapsed_time, status);
-        io::stdout().flush().unwrap();
-
-        let mut command = String::new();
-        if io::stdin().read_line(&mut command).is_err() {
-            break;
+        let prompt = format!("[{:.1}] ({}) > ", elapsed_time, status);
+        if !interactive {
+            print!("{}", prompt);
+            io::stdout().flush().unwrap();
         }
-        let command = command.trim();
-
-        start_time = Instant::now(); // Reset the start time for the next command
 
-        if command.is_empty() {
-            status = String::from("invalid command");
-            continue;
+        let command = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(_) => break,
+        };
+        if interactive && !command.trim().is_empty() {
+            let _ = editor.add_history_entry(command.as_str());
         }
 
-        if command == "help" {
-            println!("Commands:");
-            println!("  q: Quit");
-            println!("  w: Move up");
-            println!("  s: Move down");
-            println!("  a: Move left");
-            println!("  d: Move right");
-            println!("  disable_output: Disable output display");
-            println!("  enable_output: Enable output display");
-            println!("  scroll_to <cell>: Scroll to the specified cell");
-            println!("  <cell>=<formula>: Set the formula for the specified cell");
-            continue;
-        }
+        start_time = Instant::now(); // Reset the start time for the next command
 
-        if command == "q" {
-            break;
-        } else if command.len() == 1 && "wasd".contains(command) {
-            match command {
-                "w" if sheet.view_row > 0 => {
-                    sheet.view_row = (sheet.view_row - 10).max(0);
-                }
-                "s" if sheet.view_row < sheet.rows - 10 => {
-                    sheet.view_row = (sheet.view_row + 10).min(sheet.rows - 10);
-                }
-                "a" if sheet.view_col > 0 => {
-                    sheet.view_col = (sheet.view_col - 10).max(0);
-                }
-                "d" if sheet.view_col < sheet.cols - 10 => {
-                    sheet.view_col = (sheet.view_col + 10).min(sheet.cols - 10);
-                }
-                _ => {}
-            }
-            status = String::from("ok");
-        } else if command == "disable_output" {
-            show = false;
-            status = String::from("ok");
-        } else if command == "enable_output" {
-            show = true;
-            status = String::from("ok");
-        } else if command.starts_with("scroll_to") {
-            let cell_name = command[10..].trim();
-            if let Some((row, col)) = sheet.spreadsheet_parse_cell_name(cell_name) {
-                sheet.view_row = row - 1;
-                sheet.view_col = col - 1;
-                status = String::from("ok");
-            } else {
-                status = String::from("invalid cell");
-            }
-        } else if let Some(equal_pos) = command.find('=') {
-            sheet.undo_stack.clear();
-            let cell_name = &command[..equal_pos];
-            let formula = &command[equal_pos + 1..];
-            let (valid, row, col, rhs) = sheet.is_valid_command(cell_name, formula);
-            if !valid {
-                status = String::from("invalid command");
-            } else {
-                sheet.spreadsheet_set_cell_value(row, col, rhs, &mut status);
-            }
-        } else if command == "UNDO" {
-            if sheet.undo_stack.is_empty() {
-                status = String::from("no undo");
-            } else {
-                sheet.spreadsheet_undo(&mut status);
-            }
-        // } else if command == "REDO" {
-        //     if sheet.redo_stack.is_empty() {
-        //         status = String::from("no redo");
-        //     } else {
-        //         sheet.spreadsheet_redo();
-        //         status = String::from("ok");
-        //     }
-        } else {
-            status = String::from("invalid command");
+        let result = session.borrow_mut().execute(&command, &mut status);
+        match result {
+            CommandResult::Quit => break,
+            CommandResult::Output(text) => println!("{}", text),
+            CommandResult::Continue => {}
         }
-
-        // Update the start_time after processing the command
-        // start_time = Instant::now();
+    }
+    if interactive {
+        let _ = editor.save_history(&history);
     }
     // }
     // let e = s.elapsed().as_secs_f64(); // Calculate total elapsed time
@@ -150,3 +127,48 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Tab completion of commands, function names and cell references for the prompt.
+struct CommandHelper {
+    session: Rc<RefCell<Session>>,
+}
+
+impl Completer for CommandHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let (start, candidates) = self.session.borrow().completions(line, pos);
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+/// Returns the file the command history is kept in: `.spreadsheet_history` in the home
+/// directory, or in the current directory if there is no home directory.
+fn history_path() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".spreadsheet_history")
+}
@@ -0,0 +1,426 @@
+#![cfg(not(tarpaulin_include))]
+#![cfg(feature = "web")]
+
+//! A plotters drawing backend writing single-page vector PDF documents.
+//!
+//! Shapes are written as PDF path operators and text with the standard Helvetica fonts,
+//! which every PDF reader provides, so no font is embedded. One pixel of the drawing is
+//! one point on the page. Transparent colours use graphics states with a constant alpha.
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use plotters_backend::text_anchor::{HPos, VPos};
+use plotters_backend::{
+    BackendColor, BackendCoord, BackendStyle, BackendTextStyle, DrawingBackend, DrawingErrorKind,
+    FontStyle, FontTransform,
+};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// Bezier control point distance approximating a quarter circle of radius 1
+const CIRCLE_KAPPA: f64 = 0.552_284_8;
+
+/// Names of the fonts in the page resources, for normal, bold and italic text
+const FONTS: [(&str, &str); 3] = [
+    ("F1", "Helvetica"),
+    ("F2", "Helvetica-Bold"),
+    ("F3", "Helvetica-Oblique"),
+];
+
+/// Drawing backend producing a PDF document.
+///
+/// The document is written to the target when the backend is presented or dropped.
+///
+/// # Examples
+/// ```
+/// use cop::pdf::PdfBackend;
+/// use plotters::prelude::*;
+///
+/// let mut pdf = Vec::new();
+/// {
+///     let root = PdfBackend::new(&mut pdf, (200, 100)).into_drawing_area();
+///     root.fill(&WHITE).unwrap();
+///     root.present().unwrap();
+/// }
+/// assert!(pdf.starts_with(b"%PDF-"));
+/// ```
+pub struct PdfBackend<'a> {
+    /// Where the finished document is written
+    target: &'a mut Vec<u8>,
+    /// Size of the page in points
+    size: (u32, u32),
+    /// Content stream of the page
+    content: String,
+    /// Alpha values of the graphics states used so far
+    alphas: Vec<f64>,
+    /// Alpha value currently in effect
+    alpha: f64,
+    /// Whether the document has been written to the target
+    saved: bool,
+}
+
+impl<'a> PdfBackend<'a> {
+    /// Creates a backend writing a page of the given size in points to `target`.
+    pub fn new(target: &'a mut Vec<u8>, size: (u32, u32)) -> Self {
+        // Flip the page so that drawing coordinates grow downwards, as in the other backends
+        let content = format!("1 0 0 -1 0 {} cm\n1 J 1 j\n", size.1);
+        Self {
+            target,
+            size,
+            content,
+            alphas: vec![1.0],
+            alpha: 1.0,
+            saved: false,
+        }
+    }
+
+    /// Selects the graphics state for a colour's alpha value.
+    fn set_alpha(&mut self, alpha: f64) {
+        if alpha == self.alpha {
+            return;
+        }
+        let index = match self.alphas.iter().position(|&a| a == alpha) {
+            Some(index) => index,
+            None => {
+                self.alphas.push(alpha);
+                self.alphas.len() - 1
+            }
+        };
+        let _ = writeln!(self.content, "/GS{} gs", index);
+        self.alpha = alpha;
+    }
+
+    /// Sets the fill colour, returning false if the colour is fully transparent.
+    fn set_fill(&mut self, color: BackendColor) -> bool {
+        if color.alpha == 0.0 {
+            return false;
+        }
+        self.set_alpha(color.alpha);
+        let (r, g, b) = color.rgb;
+        let _ = writeln!(self.content, "{} rg", rgb(r, g, b));
+        true
+    }
+
+    /// Sets the stroke colour and width, returning false if nothing would be drawn.
+    fn set_stroke<S: BackendStyle>(&mut self, style: &S) -> bool {
+        let color = style.color();
+        if color.alpha == 0.0 || style.stroke_width() == 0 {
+            return false;
+        }
+        self.set_alpha(color.alpha);
+        let (r, g, b) = color.rgb;
+        let _ = writeln!(
+            self.content,
+            "{} RG {} w",
+            rgb(r, g, b),
+            style.stroke_width()
+        );
+        true
+    }
+
+    /// Adds a path through the points to the content stream.
+    fn add_path(&mut self, points: impl IntoIterator<Item = BackendCoord>) {
+        for (i, (x, y)) in points.into_iter().enumerate() {
+            let operator = if i == 0 { "m" } else { "l" };
+            let _ = writeln!(self.content, "{} {} {}", x, y, operator);
+        }
+    }
+
+    /// Assembles the document from the content stream.
+    fn write_document(&self) -> io::Result<Vec<u8>> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(self.content.as_bytes())?;
+        let stream = encoder.finish()?;
+
+        let fonts: String = FONTS
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| format!("/{} {} 0 R ", name, 5 + i))
+            .collect();
+        let states_start = 5 + FONTS.len();
+        let states: String = (0..self.alphas.len())
+            .map(|i| format!("/GS{} {} 0 R ", i, states_start + i))
+            .collect();
+
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Contents 4 0 R \
+                 /Resources << /Font << {}>> /ExtGState << {}>> >> >>",
+                self.size.0, self.size.1, fonts, states
+            )
+            .into_bytes(),
+        ];
+        let mut contents = format!(
+            "<< /Length {} /Filter /FlateDecode >>\nstream\n",
+            stream.len()
+        )
+        .into_bytes();
+        contents.extend_from_slice(&stream);
+        contents.extend_from_slice(b"\nendstream");
+        objects.push(contents);
+        for (_, font) in FONTS {
+            objects.push(
+                format!(
+                    "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>",
+                    font
+                )
+                .into_bytes(),
+            );
+        }
+        for alpha in &self.alphas {
+            objects.push(format!("<< /Type /ExtGState /ca {0} /CA {0} >>", alpha).into_bytes());
+        }
+
+        let mut pdf = b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            let _ = writeln!(trailer, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            trailer,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        );
+        pdf.extend_from_slice(trailer.as_bytes());
+        Ok(pdf)
+    }
+}
+
+/// Formats a colour as PDF colour components.
+fn rgb(r: u8, g: u8, b: u8) -> String {
+    let component = |c: u8| format!("{:.3}", c as f64 / 255.0);
+    format!("{} {} {}", component(r), component(g), component(b))
+}
+
+/// Escapes text as a PDF literal string in the WinAnsi encoding.
+///
+/// Characters outside Latin-1 are replaced by `?`.
+fn pdf_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(escaped, "\\{:03o}", c as u32);
+            }
+            _ => escaped.push('?'),
+        }
+    }
+    escaped.push(')');
+    escaped
+}
+
+impl DrawingBackend for PdfBackend<'_> {
+    type ErrorType = io::Error;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        Ok(())
+    }
+
+    fn present(&mut self) -> Result<(), DrawingErrorKind<io::Error>> {
+        if !self.saved {
+            *self.target = self
+                .write_document()
+                .map_err(DrawingErrorKind::DrawingError)?;
+            self.saved = true;
+        }
+        Ok(())
+    }
+
+    fn draw_pixel(
+        &mut self,
+        point: BackendCoord,
+        color: BackendColor,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if self.set_fill(color) {
+            let _ = writeln!(self.content, "{} {} 1 1 re f", point.0, point.1);
+        }
+        Ok(())
+    }
+
+    fn draw_line<S: BackendStyle>(
+        &mut self,
+        from: BackendCoord,
+        to: BackendCoord,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if self.set_stroke(style) {
+            self.add_path([from, to]);
+            self.content.push_str("S\n");
+        }
+        Ok(())
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let drawn = if fill {
+            self.set_fill(style.color())
+        } else {
+            self.set_stroke(style)
+        };
+        if drawn {
+            let (x0, y0) = upper_left;
+            let (x1, y1) = bottom_right;
+            let _ = writeln!(
+                self.content,
+                "{} {} {} {} re {}",
+                x0,
+                y0,
+                x1 - x0,
+                y1 - y0,
+                if fill { "f" } else { "S" }
+            );
+        }
+        Ok(())
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        path: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if self.set_stroke(style) {
+            self.add_path(path);
+            self.content.push_str("S\n");
+        }
+        Ok(())
+    }
+
+    fn draw_circle<S: BackendStyle>(
+        &mut self,
+        center: BackendCoord,
+        radius: u32,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        let drawn = if fill {
+            self.set_fill(style.color())
+        } else {
+            self.set_stroke(style)
+        };
+        if !drawn {
+            return Ok(());
+        }
+        let (x, y) = (center.0 as f64, center.1 as f64);
+        let r = radius as f64;
+        let k = r * CIRCLE_KAPPA;
+        let _ = writeln!(self.content, "{} {} m", x + r, y);
+        for (c1, c2, end) in [
+            ((x + r, y + k), (x + k, y + r), (x, y + r)),
+            ((x - k, y + r), (x - r, y + k), (x - r, y)),
+            ((x - r, y - k), (x - k, y - r), (x, y - r)),
+            ((x + k, y - r), (x + r, y - k), (x + r, y)),
+        ] {
+            let _ = writeln!(
+                self.content,
+                "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c",
+                c1.0, c1.1, c2.0, c2.1, end.0, end.1
+            );
+        }
+        self.content.push_str(if fill { "f\n" } else { "S\n" });
+        Ok(())
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(
+        &mut self,
+        vert: I,
+        style: &S,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if self.set_fill(style.color()) {
+            self.add_path(vert);
+            self.content.push_str("h f\n");
+        }
+        Ok(())
+    }
+
+    fn draw_text<TStyle: BackendTextStyle>(
+        &mut self,
+        text: &str,
+        style: &TStyle,
+        pos: BackendCoord,
+    ) -> Result<(), DrawingErrorKind<io::Error>> {
+        if !self.set_fill(style.color()) {
+            return Ok(());
+        }
+
+        // Anchor the text using the layout of the font plotters measures it with
+        let ((min_x, _), (max_x, _)) = style
+            .layout_box(text)
+            .map_err(|e| DrawingErrorKind::FontError(Box::new(e)))?;
+        let width = (max_x - min_x) as f64;
+        let size = style.size() / 1.24;
+        let dx = match style.anchor().h_pos {
+            HPos::Left => 0.0,
+            HPos::Center => -width / 2.0,
+            HPos::Right => -width,
+        };
+        let dy = match style.anchor().v_pos {
+            VPos::Top => 0.76 * size,
+            VPos::Center => 0.25 * size,
+            VPos::Bottom => -0.25 * size,
+        };
+
+        // Text matrix columns are the directions of the glyphs' x and y axes on the page
+        let (a, b, c, d) = match style.transform() {
+            FontTransform::None => (1.0, 0.0, 0.0, -1.0),
+            FontTransform::Rotate90 => (0.0, 1.0, 1.0, 0.0),
+            FontTransform::Rotate180 => (-1.0, 0.0, 0.0, 1.0),
+            FontTransform::Rotate270 => (0.0, -1.0, -1.0, 0.0),
+        };
+        let x = pos.0 as f64 + a * dx - c * dy;
+        let y = pos.1 as f64 + b * dx - d * dy;
+
+        let font = match style.style() {
+            FontStyle::Bold => FONTS[1].0,
+            FontStyle::Italic | FontStyle::Oblique => FONTS[2].0,
+            FontStyle::Normal => FONTS[0].0,
+        };
+        let _ = writeln!(
+            self.content,
+            "BT /{} {:.2} Tf {} {} {} {} {:.2} {:.2} Tm {} Tj ET",
+            font,
+            size,
+            a,
+            b,
+            c,
+            d,
+            x,
+            y,
+            pdf_string(text)
+        );
+        Ok(())
+    }
+}
+
+impl Drop for PdfBackend<'_> {
+    fn drop(&mut self) {
+        if !self.saved {
+            let _ = self.present();
+        }
+    }
+}
@@ -0,0 +1,520 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Charts drawn as text for the terminal.
+//!
+//! The `plot` command of the CLI draws line charts, bar charts and sparklines of cell
+//! ranges with Unicode block and box-drawing characters, or with plain ASCII for
+//! terminals without them. The ranges are read with [`read_ranges`], like those of the
+//! web graphs, so this needs none of the drawing dependencies of the `web` feature.
+//!
+//! Cells holding a `SPARKLINE` formula are drawn in the grids with [`cell_sparkline`],
+//! and in the web grid with [`sparkline_svg`].
+
+use crate::chart::read_ranges;
+use crate::spreadsheet::Spreadsheet;
+
+/// Kind of chart drawn by [`plot`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlotKind {
+    /// Values joined by a line, in a grid with axes
+    Line,
+    /// One horizontal bar per value
+    Bar,
+    /// One line of block characters per series, showing its trend
+    Sparkline,
+}
+
+impl PlotKind {
+    /// Parses the name of a kind as used by the `plot` command ("line", "bar" or
+    /// "sparkline").
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "line" => Some(PlotKind::Line),
+            "bar" => Some(PlotKind::Bar),
+            "sparkline" => Some(PlotKind::Sparkline),
+            _ => None,
+        }
+    }
+}
+
+/// Size and character set of a text chart
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlotOptions {
+    /// Width of the plotting area in characters
+    pub width: usize,
+    /// Height of the plotting area of a line chart in lines
+    pub height: usize,
+    /// Whether to draw with ASCII characters only
+    pub ascii: bool,
+}
+
+impl Default for PlotOptions {
+    fn default() -> Self {
+        PlotOptions {
+            width: 60,
+            height: 15,
+            ascii: false,
+        }
+    }
+}
+
+/// Blocks of a sparkline, from the lowest value to the highest
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// ASCII characters of a sparkline, from the lowest value to the highest
+const SPARK_ASCII: [char; 5] = ['_', '.', '-', '~', '^'];
+
+/// Marks of the series of a line chart, in order
+const LINE_MARKS: [char; 4] = ['●', '○', '■', '□'];
+
+/// ASCII marks of the series of a line chart, in order
+const LINE_MARKS_ASCII: [char; 4] = ['*', 'o', '+', 'x'];
+
+/// Fills of the bars of each series of a bar chart, in order
+const BAR_FILLS: [char; 4] = ['█', '▓', '▒', '░'];
+
+/// ASCII fills of the bars of each series of a bar chart, in order
+const BAR_FILLS_ASCII: [char; 4] = ['#', '=', '*', '+'];
+
+/// Blocks ending a full-block bar, by the eighths of a character they fill
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// Values of one series of a text chart
+struct PlotSeries {
+    /// Header of the series, or its range when there is none
+    name: String,
+    /// Y value of every point
+    values: Vec<f64>,
+}
+
+/// Draws a chart of cell ranges as text
+///
+/// A line or bar chart given one range draws its values against their positions 1, 2,
+/// 3 and so on; given several, the first is the X range and the others are Y ranges of
+/// the same length. A Y range of several columns gives one series per column, and an X
+/// range holding text labels the points with it. Every range given for sparklines is a
+/// Y range, drawn as one line per series.
+///
+/// # Arguments
+/// * `sheet` - The spreadsheet holding the ranges
+/// * `kind` - Kind of chart to draw
+/// * `ranges` - The ranges, such as "A1:A10"
+/// * `options` - Size of the chart and whether to use ASCII characters only
+///
+/// # Returns
+/// * `Result<String, String>` - The chart, one line per row, or a message saying which
+///   range could not be read
+///
+/// # Examples
+/// ```
+/// use cop::plot::{PlotKind, PlotOptions, plot};
+/// use cop::spreadsheet::Spreadsheet;
+///
+/// let mut sheet = Spreadsheet::spreadsheet_create(5, 5).unwrap();
+/// let mut status = String::new();
+/// for (row, value) in [(1, "3"), (2, "1"), (3, "4")] {
+///     let (_, _, _, formula) = sheet.is_valid_command("A1", value);
+///     sheet.spreadsheet_set_cell_value(row, 1, formula, &mut status);
+/// }
+/// let chart = plot(&sheet, PlotKind::Sparkline, &["A1:A3"], &PlotOptions::default());
+/// assert_eq!(chart.unwrap(), "A1:A3  ▆▁█  1..4");
+/// ```
+pub fn plot(
+    sheet: &Spreadsheet,
+    kind: PlotKind,
+    ranges: &[&str],
+    options: &PlotOptions,
+) -> Result<String, String> {
+    if options.width < 2 || options.height < 2 {
+        return Err("The chart must be at least 2 characters wide and high".to_string());
+    }
+    let (x_range, y_ranges) = match (kind, ranges) {
+        (PlotKind::Sparkline, _) | (_, [_]) => ("", ranges),
+        (_, [x_range, y_ranges @ ..]) => (*x_range, y_ranges),
+        (_, []) => ("", ranges),
+    };
+    let data = read_ranges(sheet, x_range, y_ranges, false, kind != PlotKind::Sparkline)?;
+
+    let series: Vec<PlotSeries> = data
+        .columns
+        .into_iter()
+        .map(|column| PlotSeries {
+            name: column.header.unwrap_or_else(|| column.range.to_string()),
+            values: column.values,
+        })
+        .collect();
+    let x = data.x;
+    let labels: Vec<String> = if data.categories.is_empty() {
+        x.iter().map(|&x| number(x)).collect()
+    } else {
+        data.categories
+    };
+
+    Ok(match kind {
+        PlotKind::Line => line_chart(&x, &labels, &series, options),
+        PlotKind::Bar => bar_chart(&labels, &series, options),
+        PlotKind::Sparkline => sparklines(&series, options.ascii),
+    })
+}
+
+/// Draws values as a sparkline, one character per value
+///
+/// The lowest value gets the lowest block and the highest value the full block. When
+/// all values are equal they are drawn at mid height.
+///
+/// # Arguments
+/// * `values` - The values, in order
+/// * `ascii` - Whether to draw with ASCII characters only
+///
+/// # Examples
+/// ```
+/// use cop::plot::sparkline;
+///
+/// assert_eq!(sparkline(&[1.0, 5.0, 3.0, 8.0], false), "▁▅▃█");
+/// assert_eq!(sparkline(&[1.0, 5.0, 3.0, 8.0], true), "_-.^");
+/// ```
+pub fn sparkline(values: &[f64], ascii: bool) -> String {
+    let levels: &[char] = if ascii { &SPARK_ASCII } else { &SPARK_BLOCKS };
+    let Some((low, high)) = bounds(values.iter().copied()) else {
+        return String::new();
+    };
+    values
+        .iter()
+        .map(|&value| {
+            let level = if high > low {
+                ((value - low) / (high - low) * (levels.len() - 1) as f64).round() as usize
+            } else {
+                (levels.len() - 1) / 2
+            };
+            levels[level]
+        })
+        .collect()
+}
+
+/// Draws values as a sparkline fitting in a cell `width` characters wide
+///
+/// When there are more values than characters, neighbouring values are averaged so the
+/// whole trend stays visible.
+///
+/// # Examples
+/// ```
+/// use cop::plot::cell_sparkline;
+///
+/// assert_eq!(cell_sparkline(&[1.0, 3.0, 5.0, 7.0], 2, false), "▁█");
+/// assert_eq!(cell_sparkline(&[1.0, 3.0, 5.0, 7.0], 8, false), "▁▃▆█");
+/// ```
+pub fn cell_sparkline(values: &[f64], width: usize, ascii: bool) -> String {
+    if values.len() <= width {
+        return sparkline(values, ascii);
+    }
+    let averages: Vec<f64> = (0..width)
+        .map(|i| {
+            let bucket = &values[i * values.len() / width..(i + 1) * values.len() / width];
+            bucket.iter().sum::<f64>() / bucket.len() as f64
+        })
+        .collect();
+    sparkline(&averages, ascii)
+}
+
+/// Draws values as a small SVG line for the web grid, with a dot on the last value
+///
+/// Like [`sparkline`], equal values are drawn at mid height. An empty slice gives an
+/// empty string.
+///
+/// # Arguments
+/// * `values` - The values, in order
+/// * `width` - Width of the image in pixels
+/// * `height` - Height of the image in pixels
+pub fn sparkline_svg(values: &[f64], width: u32, height: u32) -> String {
+    let Some((low, high)) = bounds(values.iter().copied()) else {
+        return String::new();
+    };
+    // Keep the line and the dot clear of the edges
+    let margin = 2.0;
+    let (inner_width, inner_height) = (width as f64 - 2.0 * margin, height as f64 - 2.0 * margin);
+    let points: Vec<(f64, f64)> = values
+        .iter()
+        .enumerate()
+        .map(|(i, &value)| {
+            let x = if values.len() > 1 {
+                margin + inner_width * i as f64 / (values.len() - 1) as f64
+            } else {
+                margin + inner_width / 2.0
+            };
+            let y = if high > low {
+                margin + inner_height * (high - value) / (high - low)
+            } else {
+                margin + inner_height / 2.0
+            };
+            (x, y)
+        })
+        .collect();
+    let line = points
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let (last_x, last_y) = points[points.len() - 1];
+    format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+            r#"<polyline points="{line}" fill="none" stroke="currentColor" stroke-width="1.5"/>"#,
+            r#"<circle cx="{x:.1}" cy="{y:.1}" r="1.5" fill="currentColor"/></svg>"#
+        ),
+        w = width,
+        h = height,
+        line = line,
+        x = last_x,
+        y = last_y
+    )
+}
+
+/// Draws every series as a sparkline, after its name and followed by its range of values
+fn sparklines(series: &[PlotSeries], ascii: bool) -> String {
+    let name_width = series
+        .iter()
+        .map(|s| s.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    series
+        .iter()
+        .map(|s| {
+            let (low, high) = bounds(s.values.iter().copied()).unwrap_or((0.0, 0.0));
+            format!(
+                "{:<width$}  {}  {}..{}",
+                s.name,
+                sparkline(&s.values, ascii),
+                number(low),
+                number(high),
+                width = name_width
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Draws the series as lines through their points, with a Y axis on the left labelled
+/// with the highest, middle and lowest values, and an X axis labelled with the first and
+/// last X values
+fn line_chart(
+    x: &[f64],
+    labels: &[String],
+    series: &[PlotSeries],
+    options: &PlotOptions,
+) -> String {
+    let (width, height) = (options.width, options.height);
+    let marks: &[char] = if options.ascii {
+        &LINE_MARKS_ASCII
+    } else {
+        &LINE_MARKS
+    };
+    let (x_low, x_high) = bounds(x.iter().copied()).unwrap_or((0.0, 0.0));
+    let (y_low, y_high) =
+        bounds(series.iter().flat_map(|s| s.values.iter().copied())).unwrap_or((0.0, 0.0));
+    let row_of = |y: f64| {
+        if y_high > y_low {
+            ((y_high - y) / (y_high - y_low) * (height - 1) as f64).round() as usize
+        } else {
+            height / 2
+        }
+    };
+
+    // Every column of the plotting area shows the lines at its X value, joined to the
+    // point in the previous column so that steep lines stay unbroken
+    let mut grid = vec![vec![' '; width]; height];
+    for (k, s) in series.iter().enumerate() {
+        let mut points: Vec<(f64, f64)> = x.iter().copied().zip(s.values.iter().copied()).collect();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut previous: Option<usize> = None;
+        for col in 0..width {
+            if x_high <= x_low && col > 0 {
+                break;
+            }
+            let at = if col + 1 == width {
+                x_high
+            } else {
+                x_low + (x_high - x_low) * col as f64 / (width - 1) as f64
+            };
+            let Some(y) = interpolate(&points, at) else {
+                continue;
+            };
+            let row = row_of(y);
+            // Rows between the previous point and this one, not the previous point itself
+            let (top, bottom) = match previous {
+                Some(previous) if previous < row => (previous + 1, row),
+                Some(previous) if previous > row => (row, previous - 1),
+                _ => (row, row),
+            };
+            for line in &mut grid[top..=bottom] {
+                line[col] = marks[k % marks.len()];
+            }
+            previous = Some(row);
+        }
+    }
+
+    let mut ticks = vec![(0, y_high), (height - 1, y_low)];
+    if height >= 5 && y_high > y_low {
+        ticks.push((
+            height / 2,
+            y_high - (y_high - y_low) * (height / 2) as f64 / (height - 1) as f64,
+        ));
+    }
+    let tick_labels: Vec<(usize, String)> =
+        ticks.into_iter().map(|(row, y)| (row, number(y))).collect();
+    let label_width = tick_labels
+        .iter()
+        .map(|(_, label)| label.len())
+        .max()
+        .unwrap_or(0);
+    let (tick, axis, corner, rule) = if options.ascii {
+        ('+', '|', '+', '-')
+    } else {
+        ('┤', '│', '└', '─')
+    };
+
+    let mut lines = Vec::with_capacity(height + 3);
+    for (row, cells) in grid.iter().enumerate() {
+        let label = tick_labels
+            .iter()
+            .find(|(at, _)| *at == row)
+            .map(|(_, label)| label);
+        let line = format!(
+            "{:>label_width$} {}{}",
+            label.map_or("", String::as_str),
+            if label.is_some() { tick } else { axis },
+            cells.iter().collect::<String>()
+        );
+        lines.push(line.trim_end().to_string());
+    }
+    lines.push(format!(
+        "{:>label_width$} {}{}",
+        "",
+        corner,
+        rule.to_string().repeat(width)
+    ));
+
+    let first = labels.first().map_or("", String::as_str);
+    let last = labels.last().map_or("", String::as_str);
+    let gap = width
+        .saturating_sub(first.chars().count() + last.chars().count())
+        .max(1);
+    let x_labels = if labels.len() > 1 {
+        format!("{}{}{}", first, " ".repeat(gap), last)
+    } else {
+        first.to_string()
+    };
+    lines.push(
+        format!("{:>label_width$}  {}", "", x_labels)
+            .trim_end()
+            .to_string(),
+    );
+    lines.push(legend(series, marks));
+    lines.join("\n")
+}
+
+/// Draws one horizontal bar per value, grouped by point and labelled with the X values;
+/// bars of negative values run left from the zero line
+fn bar_chart(labels: &[String], series: &[PlotSeries], options: &PlotOptions) -> String {
+    let width = options.width;
+    let fills: &[char] = if options.ascii {
+        &BAR_FILLS_ASCII
+    } else {
+        &BAR_FILLS
+    };
+    let (low, high) =
+        bounds(series.iter().flat_map(|s| s.values.iter().copied())).unwrap_or((0.0, 0.0));
+    let (low, high) = (low.min(0.0), high.max(0.0));
+    let span = if high > low { high - low } else { 1.0 };
+    let zero = (-low / span * width as f64).round() as usize;
+    let label_width = labels
+        .iter()
+        .map(|label| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let axis = if options.ascii { '|' } else { '│' };
+
+    let mut lines = Vec::new();
+    for (i, label) in labels.iter().enumerate() {
+        for (k, s) in series.iter().enumerate() {
+            let Some(&value) = s.values.get(i) else {
+                continue;
+            };
+            let fill = fills[k % fills.len()];
+            let length = value.abs() / span * width as f64;
+            let bar = if value < 0.0 {
+                let cells = (length.round() as usize).min(zero);
+                format!(
+                    "{}{}",
+                    " ".repeat(zero - cells),
+                    fill.to_string().repeat(cells)
+                )
+            } else if fill == '█' {
+                let eighths = (length * 8.0).round() as usize;
+                let mut bar = format!("{}{}", " ".repeat(zero), "█".repeat(eighths / 8));
+                if let Some(&end) = (eighths % 8).checked_sub(1).and_then(|i| EIGHTHS.get(i)) {
+                    bar.push(end);
+                }
+                bar
+            } else {
+                format!(
+                    "{}{}",
+                    " ".repeat(zero),
+                    fill.to_string().repeat(length.round() as usize)
+                )
+            };
+            lines.push(format!(
+                "{:>label_width$} {}{} {}",
+                if k == 0 { label.as_str() } else { "" },
+                axis,
+                bar,
+                number(value)
+            ));
+        }
+    }
+    if series.len() > 1 {
+        lines.push(legend(series, fills));
+    }
+    lines.join("\n")
+}
+
+/// Names every series after the mark it is drawn with
+fn legend(series: &[PlotSeries], marks: &[char]) -> String {
+    series
+        .iter()
+        .enumerate()
+        .map(|(k, s)| format!("{} {}", marks[k % marks.len()], s.name))
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Returns the Y value of a line through points sorted by X at the given X value, or
+/// `None` outside the points
+fn interpolate(points: &[(f64, f64)], at: f64) -> Option<f64> {
+    if let [(x, y)] = points {
+        return (*x == at).then_some(*y);
+    }
+    points.windows(2).find_map(|pair| {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if at < x0 || at > x1 {
+            None
+        } else if x1 > x0 {
+            Some(y0 + (y1 - y0) * (at - x0) / (x1 - x0))
+        } else {
+            Some(y0)
+        }
+    })
+}
+
+/// Returns the lowest and highest of some values, or `None` if there are none
+fn bounds(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    values.fold(None, |bounds, value| match bounds {
+        None => Some((value, value)),
+        Some((low, high)) => Some((low.min(value), high.max(value))),
+    })
+}
+
+/// Formats a value for an axis or bar label, with at most two decimals
+fn number(value: f64) -> String {
+    // Adding zero turns -0 into 0
+    format!("{}", (value * 100.0).round() / 100.0 + 0.0)
+}
@@ -0,0 +1,119 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Recovery files for sheets with unsaved changes.
+//!
+//! The web server periodically writes the live sheet to a recovery file next to the file
+//! it was loaded from (`database/<user>/<sheet>.bin.gz.recovery`), or to
+//! `database/untitled.bin.gz.recovery` for a sheet that has never been saved. Saving the
+//! sheet, explicitly or after an edit, removes the recovery file. On startup, recovery
+//! files newer than their saved sheet are reported and can be restored.
+
+use crate::saving;
+use crate::spreadsheet::Spreadsheet;
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Extension added to the path of a sheet to form the path of its recovery file.
+pub const RECOVERY_EXTENSION: &str = "recovery";
+
+/// File name, in the database directory, standing in for a sheet that was never saved.
+pub const UNTITLED: &str = "untitled.bin.gz";
+
+/// A recovery file holding changes that were not saved.
+#[derive(Debug, PartialEq)]
+pub struct Recovery {
+    /// Path of the recovery file
+    pub path: PathBuf,
+    /// Path of the sheet it belongs to, or `None` for a sheet that was never saved
+    pub sheet_path: Option<PathBuf>,
+    /// When the recovery file was written
+    pub modified: SystemTime,
+}
+
+/// Returns the path of the recovery file for a sheet.
+///
+/// # Arguments
+/// * `sheet_path` - File the sheet was loaded from or last saved to, if any
+/// * `database_dir` - Directory holding the users' sheets
+pub fn recovery_path(sheet_path: Option<&str>, database_dir: &Path) -> PathBuf {
+    let sheet_path = match sheet_path {
+        Some(path) => PathBuf::from(path),
+        None => database_dir.join(UNTITLED),
+    };
+    let mut path = sheet_path.into_os_string();
+    path.push(".");
+    path.push(RECOVERY_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// Writes a sheet to its recovery file, atomically and without backups.
+pub fn write_recovery(sheet: &Spreadsheet, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    saving::save_spreadsheet_with_backups(sheet, &path.to_string_lossy(), 0)
+}
+
+/// Removes a recovery file, if there is one.
+pub fn discard(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Finds the recovery files holding changes newer than their saved sheet.
+///
+/// The database directory and the users' directories directly below it are searched.
+/// Recovery files older than their sheet are left out, since the sheet was saved after
+/// them.
+///
+/// # Returns
+/// The recovery files, most recent first
+pub fn find_recoveries(database_dir: &Path) -> Vec<Recovery> {
+    let mut dirs = vec![database_dir.to_path_buf()];
+    if let Ok(entries) = fs::read_dir(database_dir) {
+        dirs.extend(
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir()),
+        );
+    }
+
+    let mut recoveries = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().and_then(|ext| ext.to_str()) != Some(RECOVERY_EXTENSION) {
+                continue;
+            }
+            let Ok(modified) = fs::metadata(&path).and_then(|meta| meta.modified()) else {
+                continue;
+            };
+            let sheet = path.with_extension("");
+            let saved = fs::metadata(&sheet).and_then(|meta| meta.modified()).ok();
+            if saved.is_some_and(|saved| saved >= modified) {
+                continue;
+            }
+            let untitled = dir == database_dir && sheet.file_name() == Some(UNTITLED.as_ref());
+            recoveries.push(Recovery {
+                path,
+                sheet_path: (!untitled).then_some(sheet),
+                modified,
+            });
+        }
+    }
+    recoveries.sort_by_key(|recovery| Reverse(recovery.modified));
+    recoveries
+}
+
+/// Loads the sheet held in a recovery file.
+pub fn restore(recovery: &Recovery) -> io::Result<Spreadsheet> {
+    saving::load_spreadsheet(&recovery.path.to_string_lossy())
+}
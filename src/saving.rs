@@ -1,17 +1,114 @@
 #![cfg(not(tarpaulin_include))]
 
-use bincode::{deserialize_from, serialize_into};
+//! Native file format.
+//!
+//! A saved spreadsheet is a gzip stream holding a header followed by a bincode payload:
+//!
+//! | Bytes | Contents                                       |
+//! |-------|------------------------------------------------|
+//! | 8     | Magic number, `COPSHEET`                       |
+//! | 4     | Format version, little endian                  |
+//! | 4     | CRC-32 of the payload, little endian           |
+//! | rest  | Payload: the sheet size, the formula of every non-empty cell and the charts |
+//!
+//! Only formulas and charts are stored. Values, errors, dependents and spills are derived from them,
+//! so they are rebuilt on load by entering the formulas again; the view position and the
+//! undo history are not kept.
+//!
+//! Files written before the header was introduced are plain gzip-compressed bincode and
+//! are read as format version 0. Older versions are decoded with the layout they were
+//! written with and reduced to their formulas on load, so changes to the in-memory
+//! structures only need a new version and a migration here.
+
+use bincode::{deserialize, serialize};
 use flate2::Compression;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
-use std::fs::File;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::chart::Chart;
+use crate::spreadsheet::{ParsedRHS, Spreadsheet};
+use serde::{Deserialize, Serialize};
+
+/// Magic number at the start of every file in the native format.
+pub const MAGIC: &[u8; 8] = b"COPSHEET";
+
+/// Format version written by this build.
+///
+/// - 0: No header, as written by the first releases
+/// - 1: Header added; the whole sheet, with decimal values and spill information
+/// - 2: Only the sheet size and the formulas of non-empty cells
+/// - 3: Charts saved with the sheet
+/// - 4: Trendline options in charts
+/// - 5: Text constants and the SPARKLINE function in formulas
+///
+/// bincode writes enum variants by index, so a new [`ParsedRHS`] or function variant also
+/// needs a new version, or older builds misreport files using it as corrupt.
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Length of the header before the payload.
+const HEADER_LEN: usize = 16;
+
+/// Reasons a file cannot be read as a native spreadsheet.
+///
+/// These are returned wrapped in an [`io::Error`] of kind [`io::ErrorKind::InvalidData`].
+#[derive(Debug, PartialEq)]
+pub enum FormatError {
+    /// The file is not a gzip stream, so not a saved spreadsheet
+    NotASpreadsheet,
+    /// The file was written by a newer version of the program
+    UnsupportedVersion(u32),
+    /// The payload does not match the checksum in the header
+    ChecksumMismatch,
+    /// The contents are truncated or cannot be decoded
+    Corrupt(String),
+}
 
-use crate::spreadsheet::Spreadsheet;
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::NotASpreadsheet => write!(f, "not a spreadsheet file"),
+            FormatError::UnsupportedVersion(version) => write!(
+                f,
+                "file format version {} is newer than this program supports (up to {})",
+                version, FORMAT_VERSION
+            ),
+            FormatError::ChecksumMismatch => write!(f, "file is corrupt: checksum mismatch"),
+            FormatError::Corrupt(reason) => write!(f, "file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+impl From<FormatError> for io::Error {
+    fn from(error: FormatError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Payload of the current format version: everything needed to rebuild a sheet.
+#[derive(Serialize, Deserialize)]
+struct SavedSheet {
+    rows: i16,
+    cols: i16,
+    /// (row, col, formula) of every non-empty cell, row by row
+    formulas: Vec<(i16, i16, ParsedRHS)>,
+    /// Charts drawn from the cells, in the order they were added
+    charts: Vec<Chart>,
+}
+
+/// Number of earlier versions of a sheet kept by [`save_spreadsheet`].
+pub const DEFAULT_BACKUPS: usize = 3;
 
 /// Saves a spreadsheet to a file
 ///
 /// This function serializes a spreadsheet and saves it to a file with compression.
-/// The spreadsheet is first serialized using bincode, then compressed using gzip.
+/// The spreadsheet is encoded with [`to_bytes`], and the last [`DEFAULT_BACKUPS`]
+/// versions of the file are kept as backups (see [`save_spreadsheet_with_backups`]).
 ///
 /// # Arguments
 /// * `spreadsheet` - Reference to the spreadsheet to save
@@ -25,30 +122,112 @@ use crate::spreadsheet::Spreadsheet;
 /// use cop::spreadsheet::Spreadsheet;
 /// use cop::saving::save_spreadsheet;
 ///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("my_spreadsheet.bin.gz");
 /// let sheet = Spreadsheet::spreadsheet_create(10, 10).unwrap();
-/// let result = save_spreadsheet(&sheet, "my_spreadsheet.bin.gz");
+/// save_spreadsheet(&sheet, path.to_str().unwrap()).unwrap();
 /// ```
 pub fn save_spreadsheet(spreadsheet: &Spreadsheet, filename: &str) -> std::io::Result<()> {
-    let file = File::create(filename)?;
-    let encoder = GzEncoder::new(file, Compression::default());
-    let mut writer = std::io::BufWriter::new(encoder);
+    save_spreadsheet_with_backups(spreadsheet, filename, DEFAULT_BACKUPS)
+}
 
-    serialize_into(&mut writer, spreadsheet)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+/// Saves a spreadsheet to a file without ever leaving it partly written.
+///
+/// The sheet is written to a temporary file in the same directory, synced to disk and
+/// then renamed over `filename`, so a crash or a full disk leaves either the old or the
+/// new version in place. Once the new version is in place, the previous one becomes
+/// backup `1` (`<filename>.1`), backup `1` becomes `2`, and so on up to `backups`; older
+/// ones are deleted. A save that fails leaves the backups as they were.
+///
+/// # Arguments
+/// * `spreadsheet` - Reference to the spreadsheet to save
+/// * `filename` - Path to the file where the spreadsheet should be saved
+/// * `backups` - Number of earlier versions to keep, 0 for none
+///
+/// # Returns
+/// * `std::io::Result<()>` - Success, or the first error from encoding, writing, syncing
+///   or renaming. On error the existing file is left unchanged
+pub fn save_spreadsheet_with_backups(
+    spreadsheet: &Spreadsheet,
+    filename: &str,
+    backups: usize,
+) -> std::io::Result<()> {
+    let bytes = to_bytes(spreadsheet)?;
+    let path = Path::new(filename);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
 
+    let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+    temp.write_all(&bytes)?;
+    temp.as_file().sync_all()?;
+
+    // Keep hold of the previous version under backup 0 until the new one is in place
+    let previous = backup_path(filename, 0);
+    let keep_previous = backups > 0 && path.exists();
+    if keep_previous {
+        let _ = fs::remove_file(&previous);
+        if fs::hard_link(path, &previous).is_err() {
+            fs::copy(path, &previous)?;
+        }
+    }
+    if let Err(e) = temp.persist(path) {
+        if keep_previous {
+            let _ = fs::remove_file(&previous);
+        }
+        return Err(e.error);
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+
+    if keep_previous {
+        rotate_backups(path, backups)?;
+    }
+    Ok(())
+}
+
+/// Returns the path of backup `n` of a sheet, where 1 is the most recent and 0 the
+/// version being replaced by a save in progress.
+pub fn backup_path(filename: &str, n: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{}", filename, n))
+}
+
+/// Returns the backups of a sheet that exist, most recent first.
+pub fn backups(filename: &str) -> Vec<PathBuf> {
+    (1..)
+        .map(|n| backup_path(filename, n))
+        .take_while(|path| path.exists())
+        .collect()
+}
+
+/// Shifts the backups of `path` up by one and makes backup 0, the version just replaced,
+/// backup 1.
+fn rotate_backups(path: &Path, keep: usize) -> io::Result<()> {
+    let filename = path.to_string_lossy();
+    let _ = fs::remove_file(backup_path(&filename, keep));
+    for n in (0..keep).rev() {
+        let from = backup_path(&filename, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(&filename, n + 1))?;
+        }
+    }
     Ok(())
 }
 
 /// Loads a spreadsheet from a file
 ///
-/// This function deserializes a spreadsheet from a compressed file.
-/// The file is first decompressed using gzip, then deserialized using bincode.
+/// This function reads a file written by [`save_spreadsheet`], in the current or any
+/// older format version, and decodes it with [`from_bytes`].
 ///
 /// # Arguments
 /// * `filename` - Path to the file containing the saved spreadsheet
 ///
 /// # Returns
-/// * `std::io::Result<Spreadsheet>` - The loaded spreadsheet or an IO error
+/// * `std::io::Result<Spreadsheet>` - The loaded spreadsheet or an IO error. Files that
+///   cannot be decoded give an error of kind `InvalidData` wrapping a [`FormatError`]
 ///
 /// # Examples
 /// ```
@@ -60,12 +239,424 @@ pub fn save_spreadsheet(spreadsheet: &Spreadsheet, filename: &str) -> std::io::R
 /// }
 /// ```
 pub fn load_spreadsheet(filename: &str) -> std::io::Result<Spreadsheet> {
-    let file = File::open(filename)?;
-    let decoder = GzDecoder::new(file);
-    let mut reader = std::io::BufReader::new(decoder);
+    from_bytes(&fs::read(filename)?)
+}
+
+/// Encodes a spreadsheet in the current native format.
+///
+/// # Returns
+/// The gzip-compressed header and payload
+pub fn to_bytes(spreadsheet: &Spreadsheet) -> io::Result<Vec<u8>> {
+    let saved = SavedSheet {
+        rows: spreadsheet.rows,
+        cols: spreadsheet.cols,
+        formulas: spreadsheet.formulas(),
+        charts: spreadsheet.charts.clone(),
+    };
+    let payload = serialize(&saved).map_err(io::Error::other)?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(MAGIC)?;
+    encoder.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    encoder.write_all(&crc32fast::hash(&payload).to_le_bytes())?;
+    encoder.write_all(&payload)?;
+    encoder.finish()
+}
+
+/// Decodes a spreadsheet saved in any supported format version.
+///
+/// # Returns
+/// The spreadsheet, with values and dependencies recomputed from its formulas, or an
+/// `InvalidData` error wrapping a [`FormatError`]
+pub fn from_bytes(bytes: &[u8]) -> io::Result<Spreadsheet> {
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Err(FormatError::NotASpreadsheet.into());
+    }
+    let mut contents = Vec::new();
+    GzDecoder::new(bytes)
+        .read_to_end(&mut contents)
+        .map_err(|e| FormatError::Corrupt(e.to_string()))?;
+
+    // Legacy files start with the row count, which is far below the value of the magic bytes
+    if !contents.starts_with(MAGIC) {
+        return Ok(rebuild(migrate(0, &contents)?)?);
+    }
+    if contents.len() < HEADER_LEN {
+        return Err(FormatError::Corrupt(String::from("truncated header")).into());
+    }
+    let version = u32::from_le_bytes(contents[8..12].try_into().unwrap());
+    let checksum = u32::from_le_bytes(contents[12..16].try_into().unwrap());
+    let payload = &contents[HEADER_LEN..];
+    if version > FORMAT_VERSION {
+        return Err(FormatError::UnsupportedVersion(version).into());
+    }
+    if crc32fast::hash(payload) != checksum {
+        return Err(FormatError::ChecksumMismatch.into());
+    }
+    Ok(rebuild(migrate(version, payload)?)?)
+}
+
+/// Decodes a payload written with the given format version and upgrades it.
+fn migrate(version: u32, payload: &[u8]) -> Result<SavedSheet, FormatError> {
+    let corrupt = |e: bincode::Error| FormatError::Corrupt(e.to_string());
+    match version {
+        0 => deserialize::<v0::Spreadsheet>(payload)
+            .map(v0::Spreadsheet::upgrade)
+            .map_err(corrupt),
+        1 => deserialize::<v1::Spreadsheet>(payload)
+            .map(v1::Spreadsheet::upgrade)
+            .map_err(corrupt),
+        2 => deserialize::<v2::SavedSheet>(payload)
+            .map(v2::SavedSheet::upgrade)
+            .map_err(corrupt),
+        3 => deserialize::<v3::SavedSheet>(payload)
+            .map(v3::SavedSheet::upgrade)
+            .map_err(corrupt),
+        // Version 5 only appended variants, so version 4 payloads decode as they are
+        4 | FORMAT_VERSION => deserialize(payload).map_err(corrupt),
+        _ => Err(FormatError::UnsupportedVersion(version)),
+    }
+}
+
+/// Builds the spreadsheet described by a payload.
+fn rebuild(saved: SavedSheet) -> Result<Spreadsheet, FormatError> {
+    let (rows, cols) = (saved.rows, saved.cols);
+    if !(1..=999).contains(&rows) || !(1..=18278).contains(&cols) {
+        return Err(FormatError::Corrupt(format!(
+            "invalid size {}x{}",
+            rows, cols
+        )));
+    }
+    if let Some((row, col, _)) = saved
+        .formulas
+        .iter()
+        .find(|(row, col, formula)| !Spreadsheet::formula_fits(*row, *col, formula, rows, cols))
+    {
+        let cell = Spreadsheet::get_cell_name(*row, *col);
+        return Err(FormatError::Corrupt(format!(
+            "formula in {} is out of range",
+            cell
+        )));
+    }
+    let mut sheet = Spreadsheet::spreadsheet_from_formulas(rows, cols, saved.formulas)
+        .ok_or_else(|| FormatError::Corrupt(String::from("sheet could not be created")))?;
+    sheet.charts = saved.charts;
+    Ok(*sheet)
+}
+
+/// Layout of format version 0, frozen as it was written.
+mod v0 {
+    use crate::spreadsheet::{self, ParsedRHS as CurrentRHS};
+    use serde::Deserialize;
+    use std::collections::BTreeSet;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    pub struct Spreadsheet {
+        rows: i16,
+        cols: i16,
+        view_row: i16,
+        view_col: i16,
+        cells: Vec<Option<Box<Cell>>>,
+        undo_stack: Vec<(ParsedRHS, i16, i16)>,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Cell {
+        row: i16,
+        col: i16,
+        error: bool,
+        value: i32,
+        formula: ParsedRHS,
+        dependents: Dependents,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Dependents {
+        Vector(Vec<(i16, i16)>),
+        Set(BTreeSet<(i16, i16)>),
+        None,
+    }
+
+    #[derive(Deserialize)]
+    enum ParsedRHS {
+        Function {
+            name: FunctionName,
+            args: (Operand, Operand),
+        },
+        Sleep(Operand),
+        Arithmetic {
+            lhs: Operand,
+            operator: char,
+            rhs: Operand,
+        },
+        SingleValue(Operand),
+        None,
+    }
+
+    #[derive(Deserialize)]
+    enum Operand {
+        Number(i32),
+        Cell(i16, i16),
+    }
+
+    #[derive(Deserialize)]
+    enum FunctionName {
+        Min,
+        Max,
+        Avg,
+        Sum,
+        Stdev,
+        Copy,
+    }
+
+    impl Spreadsheet {
+        /// Keeps the size and formulas; everything else is rebuilt on load.
+        pub fn upgrade(self) -> super::SavedSheet {
+            super::SavedSheet {
+                rows: self.rows,
+                cols: self.cols,
+                formulas: self
+                    .cells
+                    .into_iter()
+                    .flatten()
+                    .filter(|cell| !matches!(cell.formula, ParsedRHS::None))
+                    .map(|cell| (cell.row, cell.col, cell.formula.upgrade()))
+                    .collect(),
+                charts: Vec::new(),
+            }
+        }
+    }
+
+    impl ParsedRHS {
+        fn upgrade(self) -> CurrentRHS {
+            match self {
+                ParsedRHS::Function { name, args } => CurrentRHS::Function {
+                    name: name.upgrade(),
+                    args: (args.0.upgrade(), args.1.upgrade()),
+                },
+                ParsedRHS::Sleep(operand) => CurrentRHS::Sleep(operand.upgrade()),
+                ParsedRHS::Arithmetic { lhs, operator, rhs } => CurrentRHS::Arithmetic {
+                    lhs: lhs.upgrade(),
+                    operator,
+                    rhs: rhs.upgrade(),
+                },
+                ParsedRHS::SingleValue(operand) => CurrentRHS::SingleValue(operand.upgrade()),
+                ParsedRHS::None => CurrentRHS::None,
+            }
+        }
+    }
+
+    impl Operand {
+        fn upgrade(self) -> spreadsheet::Operand {
+            match self {
+                Operand::Number(n) => spreadsheet::Operand::Number(n),
+                Operand::Cell(row, col) => spreadsheet::Operand::Cell(row, col),
+            }
+        }
+    }
+
+    impl FunctionName {
+        fn upgrade(self) -> spreadsheet::FunctionName {
+            match self {
+                FunctionName::Min => spreadsheet::FunctionName::Min,
+                FunctionName::Max => spreadsheet::FunctionName::Max,
+                FunctionName::Avg => spreadsheet::FunctionName::Avg,
+                FunctionName::Sum => spreadsheet::FunctionName::Sum,
+                FunctionName::Stdev => spreadsheet::FunctionName::Stdev,
+                FunctionName::Copy => spreadsheet::FunctionName::Copy,
+            }
+        }
+    }
+}
+
+/// Layout of format version 1: the whole sheet as it was held in memory.
+///
+/// `Dependents` and `Spill` are decoded with the current types, which are unchanged since.
+mod v1 {
+    use crate::cell::{Dependents, Spill};
+    use crate::spreadsheet::ParsedRHS;
+    use serde::Deserialize;
+    use std::collections::BTreeSet;
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    pub struct Spreadsheet {
+        rows: i16,
+        cols: i16,
+        view_row: i16,
+        view_col: i16,
+        cells: Vec<Option<Box<Cell>>>,
+        undo_stack: Vec<(ParsedRHS, i16, i16)>,
+        spill_anchors: BTreeSet<(i16, i16)>,
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Cell {
+        row: i16,
+        col: i16,
+        error: bool,
+        value: i32,
+        decimal: Option<f64>,
+        formula: ParsedRHS,
+        dependents: Dependents,
+        spill: Spill,
+    }
+
+    impl Spreadsheet {
+        /// Keeps the size and formulas; everything else is rebuilt on load.
+        pub fn upgrade(self) -> super::SavedSheet {
+            super::SavedSheet {
+                rows: self.rows,
+                cols: self.cols,
+                formulas: self
+                    .cells
+                    .into_iter()
+                    .flatten()
+                    .filter(|cell| cell.formula != ParsedRHS::None)
+                    .map(|cell| (cell.row, cell.col, cell.formula))
+                    .collect(),
+                charts: Vec::new(),
+            }
+        }
+    }
+}
+
+/// Layout of format version 2: the sheet size and formulas, without charts.
+///
+/// Formulas are decoded with the current `ParsedRHS`, which has only gained variants since.
+mod v2 {
+    use crate::spreadsheet::ParsedRHS;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct SavedSheet {
+        rows: i16,
+        cols: i16,
+        formulas: Vec<(i16, i16, ParsedRHS)>,
+    }
+
+    impl SavedSheet {
+        /// Keeps everything; version 2 sheets have no charts.
+        pub fn upgrade(self) -> super::SavedSheet {
+            super::SavedSheet {
+                rows: self.rows,
+                cols: self.cols,
+                formulas: self.formulas,
+                charts: Vec::new(),
+            }
+        }
+    }
+}
+
+/// Layout of format version 3: the sheet size, formulas and charts without trendlines.
+///
+/// Chart series and palettes are decoded with the current types, which are unchanged since.
+mod v3 {
+    use crate::chart::{self, ChartSeries, Palette};
+    use crate::spreadsheet::ParsedRHS;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct SavedSheet {
+        rows: i16,
+        cols: i16,
+        formulas: Vec<(i16, i16, ParsedRHS)>,
+        charts: Vec<Chart>,
+    }
+
+    #[derive(Deserialize)]
+    struct Chart {
+        id: u32,
+        definition: ChartDefinition,
+    }
+
+    #[derive(Deserialize)]
+    struct ChartDefinition {
+        x_range: String,
+        y_range: String,
+        series: Vec<ChartSeries>,
+        title: String,
+        x_label: String,
+        y_label: String,
+        graph_type: String,
+        format: Option<String>,
+        y2_label: String,
+        header: bool,
+        legend: Option<bool>,
+        bins: usize,
+        width: Option<u32>,
+        height: Option<u32>,
+        palette: Option<Palette>,
+        line_width: Option<u32>,
+        marker: Option<String>,
+        marker_size: Option<u32>,
+        x_grid: Option<bool>,
+        y_grid: Option<bool>,
+        log_scale: bool,
+        x_min: Option<f64>,
+        x_max: Option<f64>,
+        y_min: Option<f64>,
+        y_max: Option<f64>,
+        tick_format: Option<String>,
+        data_labels: bool,
+        theme: Option<String>,
+        title_size: Option<u32>,
+    }
 
-    let spreadsheet: Spreadsheet = deserialize_from(&mut reader)
-        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    impl SavedSheet {
+        /// Keeps everything; version 3 charts have no trendlines.
+        pub fn upgrade(self) -> super::SavedSheet {
+            super::SavedSheet {
+                rows: self.rows,
+                cols: self.cols,
+                formulas: self.formulas,
+                charts: self.charts.into_iter().map(Chart::upgrade).collect(),
+            }
+        }
+    }
 
-    Ok(spreadsheet)
+    impl Chart {
+        fn upgrade(self) -> chart::Chart {
+            let d = self.definition;
+            chart::Chart {
+                id: self.id,
+                definition: chart::ChartDefinition {
+                    x_range: d.x_range,
+                    y_range: d.y_range,
+                    series: d.series,
+                    title: d.title,
+                    x_label: d.x_label,
+                    y_label: d.y_label,
+                    graph_type: d.graph_type,
+                    format: d.format,
+                    y2_label: d.y2_label,
+                    header: d.header,
+                    legend: d.legend,
+                    bins: d.bins,
+                    width: d.width,
+                    height: d.height,
+                    palette: d.palette,
+                    line_width: d.line_width,
+                    marker: d.marker,
+                    marker_size: d.marker_size,
+                    x_grid: d.x_grid,
+                    y_grid: d.y_grid,
+                    log_scale: d.log_scale,
+                    x_min: d.x_min,
+                    x_max: d.x_max,
+                    y_min: d.y_min,
+                    y_max: d.y_max,
+                    tick_format: d.tick_format,
+                    data_labels: d.data_labels,
+                    theme: d.theme,
+                    title_size: d.title_size,
+                    trendline: None,
+                    trendline_equation: None,
+                },
+            }
+        }
+    }
 }
@@ -0,0 +1,264 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Non-interactive script mode.
+//!
+//! `spreadsheet [rows cols] --script commands.txt --load in.csv --out out.xlsx --print A1,B2:B5`
+//! runs the commands of a file against a sheet without printing it, for use from
+//! pipelines. Each line of the script is a command as typed at the prompt; blank lines
+//! and lines starting with `#` are skipped. The first command whose status is not `ok`
+//! stops the script, and the program exits with a non-zero code after naming the line
+//! and the reason on stderr.
+
+use crate::cli::{CommandResult, Session};
+use crate::spreadsheet::Spreadsheet;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+
+/// Size of the sheet when none is given on the command line.
+pub const DEFAULT_SIZE: (i16, i16) = (100, 100);
+
+/// Exit code when a script command fails.
+pub const EXIT_COMMAND_FAILED: i32 = 1;
+
+/// Exit code for bad arguments, or a file that cannot be read or written.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Settings for a script run, taken from the command line.
+#[derive(Debug, Default, PartialEq)]
+pub struct ScriptOptions {
+    /// Number of rows of the initial sheet
+    pub rows: i16,
+    /// Number of columns of the initial sheet
+    pub cols: i16,
+    /// File with the commands to run, `-` for stdin
+    pub script: Option<String>,
+    /// File loaded before the script runs (`.csv`, `.xlsx` or a saved sheet)
+    pub load: Option<String>,
+    /// File written after the script has run (`.csv`, `.xlsx` or a saved sheet)
+    pub out: Option<String>,
+    /// Cells and ranges whose values are printed as JSON at the end
+    pub print: Vec<String>,
+}
+
+impl ScriptOptions {
+    /// Returns true if the arguments ask for script mode rather than the interactive prompt.
+    pub fn requested(args: &[String]) -> bool {
+        args.iter()
+            .any(|arg| matches!(arg.as_str(), "--script" | "--load" | "--out" | "--print"))
+    }
+
+    /// Parses the arguments following the program name.
+    ///
+    /// # Arguments
+    /// * `args` - Optional `rows cols`, then any of `--script <file>`, `--load <file>`,
+    ///   `--out <file>` and `--print <cells>`, where `--print` may be repeated and takes a
+    ///   comma separated list of cells and ranges
+    ///
+    /// # Returns
+    /// The options, or a message describing the problem
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut options = ScriptOptions::default();
+        let mut size = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let flag = arg.as_str();
+            if !flag.starts_with("--") {
+                match arg.parse::<i16>() {
+                    Ok(n) if size.len() < 2 => size.push(n),
+                    _ => return Err(format!("unexpected argument '{}'", arg)),
+                }
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} needs a value", flag))?
+                .clone();
+            match flag {
+                "--script" => options.script = Some(value),
+                "--load" => options.load = Some(value),
+                "--out" => options.out = Some(value),
+                "--print" => options
+                    .print
+                    .extend(value.split(',').map(|r| r.trim().to_string())),
+                _ => return Err(format!("unknown option '{}'", flag)),
+            }
+        }
+
+        (options.rows, options.cols) = match size[..] {
+            [] => DEFAULT_SIZE,
+            [rows, cols] if (1..=999).contains(&rows) && (1..=18278).contains(&cols) => {
+                (rows, cols)
+            }
+            _ => return Err(String::from("invalid dimensions")),
+        };
+        Ok(options)
+    }
+}
+
+/// A script command that did not succeed.
+#[derive(Debug, PartialEq)]
+pub struct ScriptFailure {
+    /// Line of the script, starting at 1
+    pub line: usize,
+    /// The command on that line
+    pub command: String,
+    /// The status the command reported
+    pub status: String,
+}
+
+impl fmt::Display for ScriptFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.command, self.status)
+    }
+}
+
+/// Runs the commands of a script, one per line.
+///
+/// Text printed by commands such as `show` is written to `messages`. A `q` command
+/// ends the script early.
+///
+/// # Returns
+/// The first command whose status is not `ok`, if any
+pub fn run_commands(
+    session: &mut Session,
+    script: &str,
+    messages: &mut impl Write,
+) -> Result<(), ScriptFailure> {
+    let mut status = String::new();
+    for (number, line) in script.lines().enumerate() {
+        let command = line.trim();
+        if command.is_empty() || command.starts_with('#') {
+            continue;
+        }
+        let result = session.execute(command, &mut status);
+        if !status.starts_with("ok") {
+            return Err(ScriptFailure {
+                line: number + 1,
+                command: command.to_string(),
+                status,
+            });
+        }
+        match result {
+            CommandResult::Quit => break,
+            CommandResult::Output(text) => {
+                let _ = writeln!(messages, "{}", text);
+            }
+            CommandResult::Continue => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns the values of the given cells and ranges as a JSON object.
+///
+/// Keys are cell names, in the order given and row by row within a range. Values are
+/// numbers, or the error text (such as `"ERR"`) for cells in error.
+///
+/// # Returns
+/// The JSON text, or the first reference that is not a valid cell or range
+pub fn values_json(sheet: &Spreadsheet, references: &[String]) -> Result<String, String> {
+    let mut entries = Vec::new();
+    for reference in references {
+        let (from, to) = reference.split_once(':').unwrap_or((reference, reference));
+        let (Some(start), Some(end)) = (
+            sheet.spreadsheet_parse_cell_name(from),
+            sheet.spreadsheet_parse_cell_name(to),
+        ) else {
+            return Err(reference.clone());
+        };
+        if start.0 > end.0 || start.1 > end.1 {
+            return Err(reference.clone());
+        }
+        for row in start.0..=end.0 {
+            for col in start.1..=end.1 {
+                let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+                let value = match sheet.cells[index].as_ref() {
+                    Some(cell) => match (cell.error_text(), cell.decimal) {
+                        (Some(text), _) => Value::from(text),
+                        (None, Some(number)) => Value::from(number),
+                        (None, None) => Value::from(cell.value),
+                    },
+                    None => Value::from(0),
+                };
+                let name = Value::from(Spreadsheet::get_cell_name(row, col));
+                entries.push(format!("{}:{}", name, value));
+            }
+        }
+    }
+    Ok(format!("{{{}}}", entries.join(",")))
+}
+
+/// Runs script mode and returns the process exit code.
+///
+/// Problems are reported on stderr; stdout only receives the JSON asked for with `--print`.
+pub fn run(options: &ScriptOptions) -> i32 {
+    let sheet = Spreadsheet::spreadsheet_create(options.rows, options.cols).unwrap();
+    let mut session = Session::new(sheet);
+    session.show_output = false;
+    let mut status = String::new();
+
+    if let Some(path) = &options.load {
+        session.execute(
+            &format!("{} {}", file_command(path, "import", "load"), path),
+            &mut status,
+        );
+        if status != "ok" {
+            eprintln!("--load {}: {}", path, status);
+            return EXIT_USAGE;
+        }
+    }
+
+    if let Some(path) = &options.script {
+        let script = if path == "-" {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).map(|_| text)
+        } else {
+            fs::read_to_string(path)
+        };
+        let script = match script {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("--script {}: {}", path, e);
+                return EXIT_USAGE;
+            }
+        };
+        if let Err(failure) = run_commands(&mut session, &script, &mut io::stderr()) {
+            eprintln!("{}", failure);
+            return EXIT_COMMAND_FAILED;
+        }
+    }
+
+    if let Some(path) = &options.out {
+        session.execute(
+            &format!("{} {}", file_command(path, "export", "save"), path),
+            &mut status,
+        );
+        if status != "ok" {
+            eprintln!("--out {}: {}", path, status);
+            return EXIT_USAGE;
+        }
+    }
+
+    if !options.print.is_empty() {
+        match values_json(&session.sheet, &options.print) {
+            Ok(json) => println!("{}", json),
+            Err(reference) => {
+                eprintln!("--print: invalid cell or range '{}'", reference);
+                return EXIT_USAGE;
+            }
+        }
+    }
+    0
+}
+
+/// Picks the command for a file: `spreadsheet` for the CSV and workbook files that
+/// [`crate::loader::load_spreadsheet`] reads, `native` otherwise.
+fn file_command<'a>(path: &str, spreadsheet: &'a str, native: &'a str) -> &'a str {
+    let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("csv" | "xlsx" | "xlsm" | "xls" | "ods") => spreadsheet,
+        _ => native,
+    }
+}
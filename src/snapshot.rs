@@ -0,0 +1,253 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Named snapshots of a saved sheet, and cell-level differences between sheets.
+//!
+//! A snapshot is a copy of a sheet tagged with a name such as "Q3 close" or
+//! "before import". Snapshots are kept in a directory next to the sheet
+//! (`database/<user>/<sheet>.bin.gz.snapshots/`), one native file per snapshot plus an
+//! `index.json` listing their ids, names, creation times and the journal position they
+//! were taken at. Snapshots are never modified; restoring one replaces the live sheet
+//! with a copy of it.
+
+use crate::cell::Spill;
+use crate::journal;
+use crate::saving;
+use crate::spreadsheet::{ParsedRHS, Spreadsheet};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+/// Extension added to the path of a sheet to form the directory holding its snapshots.
+pub const SNAPSHOT_EXTENSION: &str = "snapshots";
+
+/// Name of the file listing the snapshots of a sheet, inside the snapshot directory.
+const INDEX_FILE: &str = "index.json";
+
+/// A named snapshot of a sheet.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Number of the snapshot, starting at 1
+    pub id: u32,
+    /// Name given by the user
+    pub name: String,
+    /// When the snapshot was taken, in milliseconds since the Unix epoch
+    pub created: u64,
+    /// Position of the last journal entry at the time, 0 for an empty journal
+    pub journal_position: u64,
+}
+
+/// How a cell differs between two sheets.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The cell is empty in the old sheet only
+    Added,
+    /// The cell is empty in the new sheet only
+    Removed,
+    /// The cell has a different formula or value in each sheet
+    Changed,
+}
+
+/// A cell that differs between two sheets.
+///
+/// Formulas are given as typed and values as displayed; both are empty for an empty
+/// cell. Cells showing part of a spilled result have a value but no formula.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CellDiff {
+    /// Name of the cell, e.g. `B4`
+    pub cell: String,
+    /// How the cell differs
+    pub kind: ChangeKind,
+    /// Formula in the old sheet
+    pub old_formula: String,
+    /// Formula in the new sheet
+    pub new_formula: String,
+    /// Value in the old sheet
+    pub old_value: String,
+    /// Value in the new sheet
+    pub new_value: String,
+}
+
+/// Returns the directory holding the snapshots of a sheet.
+pub fn snapshot_dir(sheet_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", sheet_path, SNAPSHOT_EXTENSION))
+}
+
+/// Returns the path of the file holding a snapshot.
+pub fn snapshot_path(sheet_path: &str, id: u32) -> PathBuf {
+    snapshot_dir(sheet_path).join(format!("{}.bin.gz", id))
+}
+
+/// Lists the snapshots of a sheet, oldest first.
+///
+/// A sheet without a snapshot directory has no snapshots.
+pub fn list(sheet_path: &str) -> io::Result<Vec<Snapshot>> {
+    let path = snapshot_dir(sheet_path).join(INDEX_FILE);
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Finds a snapshot by its id or its name.
+pub fn find<'a>(snapshots: &'a [Snapshot], key: &str) -> Option<&'a Snapshot> {
+    let key = key.trim();
+    match key.parse::<u32>() {
+        Ok(id) => snapshots.iter().find(|snapshot| snapshot.id == id),
+        Err(_) => snapshots.iter().find(|snapshot| snapshot.name == key),
+    }
+}
+
+/// Takes a named snapshot of a sheet.
+///
+/// # Arguments
+/// * `sheet` - The sheet as it is now
+/// * `sheet_path` - File the sheet is saved to, next to which the snapshot is kept
+/// * `name` - Name of the snapshot, which must not be empty, a number or already taken
+///
+/// # Returns
+/// The new snapshot, or an `InvalidInput` error for an unusable name
+pub fn create(sheet: &Spreadsheet, sheet_path: &str, name: &str) -> io::Result<Snapshot> {
+    let name = name.trim();
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    if name.is_empty() || name.parse::<u32>().is_ok() {
+        return Err(invalid(format!("invalid snapshot name '{}'", name)));
+    }
+    let mut snapshots = list(sheet_path)?;
+    if snapshots.iter().any(|snapshot| snapshot.name == name) {
+        return Err(invalid(format!("snapshot '{}' already exists", name)));
+    }
+
+    let journal_position = journal::read_journal(&journal::journal_path(sheet_path))?
+        .last()
+        .map_or(0, |entry| entry.seq);
+    let snapshot = Snapshot {
+        id: snapshots.last().map_or(1, |last| last.id + 1),
+        name: name.to_string(),
+        created: journal::now_millis(),
+        journal_position,
+    };
+
+    let dir = snapshot_dir(sheet_path);
+    fs::create_dir_all(&dir)?;
+    let path = snapshot_path(sheet_path, snapshot.id);
+    saving::save_spreadsheet_with_backups(sheet, &path.to_string_lossy(), 0)?;
+
+    // The index is replaced atomically, so a crash leaves either the old or the new list
+    snapshots.push(snapshot.clone());
+    let mut file = tempfile::NamedTempFile::new_in(&dir)?;
+    file.write_all(
+        serde_json::to_string_pretty(&snapshots)
+            .map_err(io::Error::other)?
+            .as_bytes(),
+    )?;
+    file.as_file().sync_all()?;
+    file.persist(dir.join(INDEX_FILE)).map_err(|e| e.error)?;
+    Ok(snapshot)
+}
+
+/// Loads the sheet held in a snapshot.
+pub fn open(sheet_path: &str, snapshot: &Snapshot) -> io::Result<Spreadsheet> {
+    saving::load_spreadsheet(&snapshot_path(sheet_path, snapshot.id).to_string_lossy())
+}
+
+/// Returns the formula and displayed value of a cell, both empty for an empty cell or a
+/// cell outside the sheet.
+pub fn cell_contents(sheet: &Spreadsheet, row: i16, col: i16) -> (String, String) {
+    if row > sheet.rows || col > sheet.cols {
+        return (String::new(), String::new());
+    }
+    let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+    match sheet.cells[index].as_ref() {
+        Some(cell)
+            if cell.formula != ParsedRHS::None || matches!(cell.spill, Spill::Member(..)) =>
+        {
+            let value = match cell.error_text() {
+                Some(text) => text.to_string(),
+                None => cell.formatted_value(),
+            };
+            (Spreadsheet::formula_to_string(&cell.formula), value)
+        }
+        _ => (String::new(), String::new()),
+    }
+}
+
+/// Compares two sheets cell by cell.
+///
+/// The sheets may differ in size; cells outside a sheet count as empty in it.
+///
+/// # Returns
+/// The cells that differ, row by row
+pub fn diff(old: &Spreadsheet, new: &Spreadsheet) -> Vec<CellDiff> {
+    let mut diffs = Vec::new();
+    for row in 1..=old.rows.max(new.rows) {
+        for col in 1..=old.cols.max(new.cols) {
+            let (old_formula, old_value) = cell_contents(old, row, col);
+            let (new_formula, new_value) = cell_contents(new, row, col);
+            if old_formula == new_formula && old_value == new_value {
+                continue;
+            }
+            let kind = if old_formula.is_empty() && old_value.is_empty() {
+                ChangeKind::Added
+            } else if new_formula.is_empty() && new_value.is_empty() {
+                ChangeKind::Removed
+            } else {
+                ChangeKind::Changed
+            };
+            diffs.push(CellDiff {
+                cell: Spreadsheet::get_cell_name(row, col),
+                kind,
+                old_formula,
+                new_formula,
+                old_value,
+                new_value,
+            });
+        }
+    }
+    diffs
+}
+
+/// Formats a difference for the terminal, one cell per line.
+///
+/// Added cells are marked `+`, removed ones `-` and changed ones `~`, e.g.
+/// `~ B2: A1*2 = 10 -> A1*3 = 15`.
+pub fn format_diff(diffs: &[CellDiff]) -> String {
+    fn side(formula: &str, value: &str) -> String {
+        if formula.is_empty() {
+            value.to_string()
+        } else {
+            format!("{} = {}", formula, value)
+        }
+    }
+    if diffs.is_empty() {
+        return String::from("no differences");
+    }
+    diffs
+        .iter()
+        .map(|diff| match diff.kind {
+            ChangeKind::Added => format!(
+                "+ {}: {}",
+                diff.cell,
+                side(&diff.new_formula, &diff.new_value)
+            ),
+            ChangeKind::Removed => {
+                format!(
+                    "- {}: {}",
+                    diff.cell,
+                    side(&diff.old_formula, &diff.old_value)
+                )
+            }
+            ChangeKind::Changed => format!(
+                "~ {}: {} -> {}",
+                diff.cell,
+                side(&diff.old_formula, &diff.old_value),
+                side(&diff.new_formula, &diff.new_value)
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -1,5 +1,7 @@
 /// Module for spreadsheet functionality including cell management, formula evaluation and dependency tracking.
-use crate::cell::{Cell, cell_create};
+use crate::cell::{Cell, CellError, Spill, cell_create, round_to_value};
+use crate::chart::Chart;
+use crate::plot::cell_sparkline;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -7,12 +9,19 @@ use std::collections::BTreeSet;
 
 lazy_static! {
     /// Regular expression for matching function syntax, e.g., SUM(A1:B2)
-    static ref FUNC_REGEX: Regex = Regex::new(r"^([A-Za-z]+)\((.*)\)$").unwrap();
+    static ref FUNC_REGEX: Regex = Regex::new(r"^([A-Za-z][A-Za-z.]*)\((.*)\)$").unwrap();
     /// Regular expression for matching arithmetic expressions, e.g., A1+B2 or 10-5
     static ref ARITH_EXPR_REGEX: Regex = Regex::new(
         r"^(([+-]?[0-9]+)|([A-Za-z]+[0-9]+))([+\-*/])(([+-]?[0-9]+)|([A-Za-z]+[0-9]+))$"
     )
     .unwrap();
+    /// Regular expression for matching a range compared against a value, e.g., C1:C50>10
+    static ref CONDITION_REGEX: Regex = Regex::new(
+        r"^([A-Za-z]+[0-9]+:[A-Za-z]+[0-9]+)(>=|<=|<>|=|>|<)([+-]?[0-9]+|[A-Za-z]+[0-9]+)$"
+    )
+    .unwrap();
+    /// Regular expression for matching a number with a fractional part, e.g., 0.05 or -.5
+    static ref DECIMAL_REGEX: Regex = Regex::new(r"^[+-]?([0-9]+\.[0-9]*|\.[0-9]+)$").unwrap();
 }
 
 /// Represents a spreadsheet with cells, dimensions, and view settings.
@@ -33,6 +42,10 @@ pub struct Spreadsheet {
     pub cells: Vec<Option<Box<Cell>>>,
     /// Stack of previous cell states for undo functionality
     pub undo_stack: Vec<(ParsedRHS, i16, i16)>,
+    /// Anchors of all dynamic array formulas, used to find the spill regions a cell lies in
+    pub spill_anchors: BTreeSet<(i16, i16)>,
+    /// Charts saved with the sheet, drawn from its cells
+    pub charts: Vec<Chart>,
 }
 
 /// Represents the parsed right-hand side of a cell formula.
@@ -56,8 +69,147 @@ pub enum ParsedRHS {
     },
     /// A single value (number or cell reference)
     SingleValue(Operand),
+    /// A function with a list of arguments, e.g. SORT(A1:A10, 1, -1) or FILTER(A1:B5, B1:B5>10)
+    FunctionCall {
+        name: FunctionName,
+        args: Vec<Argument>,
+    },
     /// No operation
     None,
+    /// A text constant, e.g. "Jan", shown as is and worth 0 in formulas
+    Text(String),
+}
+
+impl ParsedRHS {
+    /// Returns the cell ranges the expression reads from.
+    ///
+    /// Single cell references are returned as one-cell ranges.
+    pub fn references(&self) -> Vec<CellRange> {
+        let cell = |operand: &Operand| match operand {
+            Operand::Cell(r, c) => Some(((*r, *c), (*r, *c))),
+            Operand::Number(_) => None,
+        };
+        match self {
+            ParsedRHS::Function {
+                args: (Operand::Cell(r1, c1), Operand::Cell(r2, c2)),
+                ..
+            } => vec![((*r1, *c1), (*r2, *c2))],
+            ParsedRHS::Sleep(operand) | ParsedRHS::SingleValue(operand) => {
+                cell(operand).into_iter().collect()
+            }
+            ParsedRHS::Arithmetic { lhs, rhs, .. } => {
+                cell(lhs).into_iter().chain(cell(rhs)).collect()
+            }
+            ParsedRHS::FunctionCall { args, .. } => {
+                args.iter().flat_map(Argument::references).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Checks if the expression is a dynamic array formula whose result spills into
+    /// neighbouring cells.
+    pub fn is_array(&self) -> bool {
+        matches!(self, ParsedRHS::FunctionCall { name, .. } if name.is_array())
+    }
+}
+
+/// A rectangular block of cells given by its (row, col) top-left and bottom-right corners.
+pub type CellRange = ((i16, i16), (i16, i16));
+
+/// Represents one argument of a function taking an argument list.
+#[derive(Clone, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub enum Argument {
+    /// A single value (number or cell reference)
+    Value(Operand),
+    /// A number with a fractional part, e.g. the rate in PMT(0.05, 10, 1000)
+    Decimal(f64),
+    /// A rectangular range given by its top-left and bottom-right cells
+    Range(Operand, Operand),
+    /// A single-column range compared element-wise against a value, e.g. C1:C50>10
+    Condition {
+        range: (Operand, Operand),
+        operator: Comparison,
+        value: Operand,
+    },
+}
+
+impl Argument {
+    /// Returns the cell ranges this argument reads from.
+    ///
+    /// Single cell references are returned as one-cell ranges, so the result can be
+    /// used directly for cycle detection and dependency tracking.
+    pub fn references(&self) -> Vec<CellRange> {
+        let mut refs = Vec::new();
+        match self {
+            Argument::Value(Operand::Cell(r, c)) => refs.push(((*r, *c), (*r, *c))),
+            Argument::Range(Operand::Cell(r1, c1), Operand::Cell(r2, c2)) => {
+                refs.push(((*r1, *c1), (*r2, *c2)))
+            }
+            Argument::Condition {
+                range: (Operand::Cell(r1, c1), Operand::Cell(r2, c2)),
+                value,
+                ..
+            } => {
+                refs.push(((*r1, *c1), (*r2, *c2)));
+                if let Operand::Cell(r, c) = value {
+                    refs.push(((*r, *c), (*r, *c)));
+                }
+            }
+            _ => {}
+        }
+        refs
+    }
+}
+
+/// Represents a comparison operator used in conditions such as C1:C50>10.
+#[derive(Clone, Copy, serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    /// Converts an operator symbol (`<`, `<=`, `>`, `>=`, `=`, `<>`) to a Comparison.
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        match symbol {
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterEqual),
+            "=" => Some(Comparison::Equal),
+            "<>" => Some(Comparison::NotEqual),
+            _ => None,
+        }
+    }
+
+    /// Returns the operator symbol as written in a formula.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "<>",
+        }
+    }
+
+    /// Checks whether `lhs <op> rhs` holds.
+    pub fn holds(&self, lhs: i32, rhs: i32) -> bool {
+        match self {
+            Comparison::Less => lhs < rhs,
+            Comparison::LessEqual => lhs <= rhs,
+            Comparison::Greater => lhs > rhs,
+            Comparison::GreaterEqual => lhs >= rhs,
+            Comparison::Equal => lhs == rhs,
+            Comparison::NotEqual => lhs != rhs,
+        }
+    }
 }
 
 /// Represents an operand in a formula, which can be a number or a cell reference.
@@ -78,6 +230,24 @@ pub enum FunctionName {
     Sum,
     Stdev,
     Copy,
+    Sort,
+    Unique,
+    Filter,
+    Sequence,
+    Percentile,
+    Quartile,
+    Rank,
+    Correl,
+    Covar,
+    Slope,
+    Intercept,
+    ForecastLinear,
+    Npv,
+    Irr,
+    Pmt,
+    Fv,
+    Pv,
+    Sparkline,
 }
 
 impl FunctionName {
@@ -90,13 +260,176 @@ impl FunctionName {
             "SUM" => Some(FunctionName::Sum),
             "STDEV" => Some(FunctionName::Stdev),
             "COPY" => Some(FunctionName::Copy),
+            "SORT" => Some(FunctionName::Sort),
+            "UNIQUE" => Some(FunctionName::Unique),
+            "FILTER" => Some(FunctionName::Filter),
+            "SEQUENCE" => Some(FunctionName::Sequence),
+            "PERCENTILE" => Some(FunctionName::Percentile),
+            "QUARTILE" => Some(FunctionName::Quartile),
+            "RANK" => Some(FunctionName::Rank),
+            "CORREL" => Some(FunctionName::Correl),
+            "COVAR" => Some(FunctionName::Covar),
+            "SLOPE" => Some(FunctionName::Slope),
+            "INTERCEPT" => Some(FunctionName::Intercept),
+            "FORECAST.LINEAR" | "FORECAST" => Some(FunctionName::ForecastLinear),
+            "NPV" => Some(FunctionName::Npv),
+            "IRR" => Some(FunctionName::Irr),
+            "PMT" => Some(FunctionName::Pmt),
+            "FV" => Some(FunctionName::Fv),
+            "PV" => Some(FunctionName::Pv),
+            "SPARKLINE" => Some(FunctionName::Sparkline),
             _ => None,
         }
     }
+    /// Returns the upper-case name of the function as written in formulas.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FunctionName::Min => "MIN",
+            FunctionName::Max => "MAX",
+            FunctionName::Avg => "AVG",
+            FunctionName::Sum => "SUM",
+            FunctionName::Stdev => "STDEV",
+            FunctionName::Copy => "COPY",
+            FunctionName::Sort => "SORT",
+            FunctionName::Unique => "UNIQUE",
+            FunctionName::Filter => "FILTER",
+            FunctionName::Sequence => "SEQUENCE",
+            FunctionName::Percentile => "PERCENTILE",
+            FunctionName::Quartile => "QUARTILE",
+            FunctionName::Rank => "RANK",
+            FunctionName::Correl => "CORREL",
+            FunctionName::Covar => "COVAR",
+            FunctionName::Slope => "SLOPE",
+            FunctionName::Intercept => "INTERCEPT",
+            FunctionName::ForecastLinear => "FORECAST.LINEAR",
+            FunctionName::Npv => "NPV",
+            FunctionName::Irr => "IRR",
+            FunctionName::Pmt => "PMT",
+            FunctionName::Fv => "FV",
+            FunctionName::Pv => "PV",
+            FunctionName::Sparkline => "SPARKLINE",
+        }
+    }
     /// Checks if the function is a copy operation.
     pub fn is_copy(&self) -> bool {
         matches!(self, FunctionName::Copy)
     }
+    /// Checks if the function returns a 2-D result that spills into neighbouring cells.
+    pub fn is_array(&self) -> bool {
+        matches!(
+            self,
+            FunctionName::Sort
+                | FunctionName::Unique
+                | FunctionName::Filter
+                | FunctionName::Sequence
+        )
+    }
+    /// Checks if the function is a statistical or financial function, or SPARKLINE,
+    /// returning a single, possibly fractional, value.
+    pub fn is_analysis(&self) -> bool {
+        matches!(
+            self,
+            FunctionName::Percentile
+                | FunctionName::Quartile
+                | FunctionName::Rank
+                | FunctionName::Correl
+                | FunctionName::Covar
+                | FunctionName::Slope
+                | FunctionName::Intercept
+                | FunctionName::ForecastLinear
+                | FunctionName::Npv
+                | FunctionName::Irr
+                | FunctionName::Pmt
+                | FunctionName::Fv
+                | FunctionName::Pv
+                | FunctionName::Sparkline
+        )
+    }
+    /// Checks if the function is written with an argument list rather than a single range.
+    pub fn takes_argument_list(&self) -> bool {
+        self.is_array() || self.is_analysis()
+    }
+    /// Checks if the parsed arguments form a valid call of this function.
+    ///
+    /// # Accepted Forms
+    /// - `SORT(range[, sort_index[, sort_order]])`
+    /// - `UNIQUE(range)`
+    /// - `FILTER(range, condition)` where the condition covers a single column of the same height
+    /// - `SEQUENCE(rows[, cols[, start[, step]]])`
+    /// - `PERCENTILE(range, k)` and `QUARTILE(range, quart)`
+    /// - `RANK(value, range[, order])`
+    /// - `CORREL`, `COVAR`, `SLOPE` and `INTERCEPT(range, range)` over ranges of the same size
+    /// - `FORECAST.LINEAR(x, known_ys, known_xs)` over ranges of the same size
+    /// - `NPV(rate, value_or_range, ...)`
+    /// - `IRR(range[, guess])`
+    /// - `PMT(rate, nper, pv[, fv[, type]])`, `FV(rate, nper, pmt[, pv[, type]])` and
+    ///   `PV(rate, nper, pmt[, fv[, type]])`
+    /// - `SPARKLINE(range)`
+    pub fn accepts(&self, args: &[Argument]) -> bool {
+        let is_value = |arg: &Argument| matches!(arg, Argument::Value(_));
+        let is_number = |arg: &Argument| matches!(arg, Argument::Value(_) | Argument::Decimal(_));
+        let is_range = |arg: &Argument| matches!(arg, Argument::Range(..));
+        let same_size = |a: &Argument, b: &Argument| match (a, b) {
+            (
+                Argument::Range(Operand::Cell(r1, c1), Operand::Cell(r2, c2)),
+                Argument::Range(Operand::Cell(t1, u1), Operand::Cell(t2, u2)),
+            ) => {
+                (r2 - r1 + 1) as i32 * (c2 - c1 + 1) as i32
+                    == (t2 - t1 + 1) as i32 * (u2 - u1 + 1) as i32
+            }
+            _ => false,
+        };
+        match self {
+            FunctionName::Sort => {
+                (1..=3).contains(&args.len())
+                    && matches!(args[0], Argument::Range(..))
+                    && args[1..].iter().all(is_value)
+            }
+            FunctionName::Unique => args.len() == 1 && matches!(args[0], Argument::Range(..)),
+            FunctionName::Filter => match args {
+                [
+                    Argument::Range(Operand::Cell(r1, _), Operand::Cell(r2, _)),
+                    Argument::Condition {
+                        range: (Operand::Cell(t1, tc1), Operand::Cell(t2, tc2)),
+                        ..
+                    },
+                ] => tc1 == tc2 && r2 - r1 == t2 - t1,
+                _ => false,
+            },
+            FunctionName::Sequence => (1..=4).contains(&args.len()) && args.iter().all(is_value),
+            FunctionName::Percentile | FunctionName::Quartile => {
+                args.len() == 2 && is_range(&args[0]) && is_number(&args[1])
+            }
+            FunctionName::Rank => {
+                (2..=3).contains(&args.len())
+                    && is_number(&args[0])
+                    && is_range(&args[1])
+                    && args[2..].iter().all(is_number)
+            }
+            FunctionName::Correl
+            | FunctionName::Covar
+            | FunctionName::Slope
+            | FunctionName::Intercept => args.len() == 2 && same_size(&args[0], &args[1]),
+            FunctionName::ForecastLinear => {
+                args.len() == 3 && is_number(&args[0]) && same_size(&args[1], &args[2])
+            }
+            FunctionName::Npv => {
+                args.len() >= 2
+                    && is_number(&args[0])
+                    && args[1..].iter().all(|arg| is_number(arg) || is_range(arg))
+            }
+            FunctionName::Irr => {
+                (1..=2).contains(&args.len())
+                    && is_range(&args[0])
+                    && args[1..].iter().all(is_number)
+            }
+            FunctionName::Pmt | FunctionName::Fv | FunctionName::Pv => {
+                (3..=5).contains(&args.len()) && args.iter().all(is_number)
+            }
+            FunctionName::Sparkline => args.len() == 1 && is_range(&args[0]),
+            _ => false,
+        }
+    }
 }
 
 impl Spreadsheet {
@@ -120,6 +453,8 @@ impl Spreadsheet {
             view_col: 0,
             cells: Vec::with_capacity(rows as usize * cols as usize),
             undo_stack: Vec::new(),
+            spill_anchors: BTreeSet::new(),
+            charts: Vec::new(),
         });
 
         for _ in 0..(rows as usize * cols as usize) {
@@ -188,6 +523,97 @@ impl Spreadsheet {
         format!("{}{}", Self::col_to_letter(col), row)
     }
 
+    /// Convert a formula to a displayable string
+    ///
+    /// Converts the internal formula representation to a string that can be displayed
+    /// in the UI or saved to a file.
+    ///
+    /// # Arguments
+    /// * `formula` - The formula to convert
+    ///
+    /// # Returns
+    /// * A string representation of the formula
+    pub fn formula_to_string(formula: &ParsedRHS) -> String {
+        match formula {
+            ParsedRHS::Function {
+                name,
+                args: (arg1, arg2),
+            } => {
+                let func_name = name.as_str();
+
+                let cell1 = match arg1 {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                let cell2 = match arg2 {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                format!("{}({}:{})", func_name, cell1, cell2)
+            }
+            ParsedRHS::Arithmetic { lhs, operator, rhs } => {
+                let left = match lhs {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                let right = match rhs {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                format!("{}{}{}", left, operator, right)
+            }
+            ParsedRHS::Sleep(operand) => {
+                let value = match operand {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                format!("SLEEP({})", value)
+            }
+            ParsedRHS::SingleValue(operand) => match operand {
+                Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                Operand::Number(n) => n.to_string(),
+            },
+            ParsedRHS::FunctionCall { name, args } => {
+                let func_name = name.as_str();
+                let operand_text = |operand: &Operand| match operand {
+                    Operand::Cell(row, col) => Self::get_cell_name(*row, *col),
+                    Operand::Number(n) => n.to_string(),
+                };
+
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Argument::Value(operand) => operand_text(operand),
+                        Argument::Decimal(x) => x.to_string(),
+                        Argument::Range(start, end) => {
+                            format!("{}:{}", operand_text(start), operand_text(end))
+                        }
+                        Argument::Condition {
+                            range: (start, end),
+                            operator,
+                            value,
+                        } => format!(
+                            "{}:{}{}{}",
+                            operand_text(start),
+                            operand_text(end),
+                            operator.symbol(),
+                            operand_text(value)
+                        ),
+                    })
+                    .collect();
+
+                format!("{}({})", func_name, args.join(","))
+            }
+            ParsedRHS::None => String::new(),
+            ParsedRHS::Text(text) => format!("\"{}\"", text.replace('"', "\"\"")),
+        }
+    }
+
     /// Parses a cell name and returns its row and column.
     ///
     /// This function takes a cell reference (e.g., "A1", "B10") and converts it to
@@ -296,14 +722,14 @@ impl Spreadsheet {
                 for i in r1..=r2 {
                     for j in c1..=c2 {
                         let index = (i - 1) as usize * self.cols as usize + (j - 1) as usize;
-                        if index < self.cells.len() {
-                            if let Some(ref c) = self.cells[index] {
-                                if c.error {
-                                    error = true;
-                                    return (0, error);
-                                }
-                                values.push(c.value);
+                        if index < self.cells.len()
+                            && let Some(ref c) = self.cells[index]
+                        {
+                            if c.error {
+                                error = true;
+                                return (0, error);
                             }
+                            values.push(c.value);
                         }
                     }
                 }
@@ -350,25 +776,8 @@ impl Spreadsheet {
                 (0, error)
             }
             ParsedRHS::Sleep(op) => {
-                let mut val = 0;
-                let error = false;
-
-                match op {
-                    Operand::Number(n) => {
-                        val = *n;
-                    }
-                    Operand::Cell(r, c) => {
-                        let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
-                        if let Some(cell) = self.cells.get(index).and_then(|c| c.as_ref()) {
-                            val = cell.value;
-                            if cell.error {
-                                return (val, true);
-                            }
-                        }
-                    }
-                }
-
-                if val > 0 {
+                let (val, error) = self.sleep_seconds(op);
+                if !error && val > 0 {
                     std::thread::sleep(std::time::Duration::from_secs(val as u64));
                 }
 
@@ -427,10 +836,396 @@ impl Spreadsheet {
                 }
                 Operand::Number(x) => (*x, false),
             },
-            ParsedRHS::None => (0, false),
+            ParsedRHS::FunctionCall { name, args } if name.is_analysis() => {
+                match self.evaluate_number(name, args).and_then(round_to_value) {
+                    Some(value) => (value, false),
+                    None => (0, true),
+                }
+            }
+            ParsedRHS::FunctionCall { name, args } => match self.evaluate_array(name, args) {
+                Some(values) => (values[0][0], false),
+                None => (0, true),
+            },
+            ParsedRHS::None | ParsedRHS::Text(_) => (0, false),
+        }
+    }
+
+    /// Returns the value of a `SLEEP` formula, the number of seconds it waits for, without
+    /// waiting.
+    ///
+    /// # Returns
+    /// The number of seconds and whether the cell it is read from is in error
+    fn sleep_seconds(&self, op: &Operand) -> (i32, bool) {
+        match op {
+            Operand::Number(n) => (*n, false),
+            Operand::Cell(r, c) => {
+                let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                self.cells
+                    .get(index)
+                    .and_then(|c| c.as_ref())
+                    .map_or((0, false), |cell| (cell.value, cell.error))
+            }
+        }
+    }
+
+    /// Evaluates a dynamic array function and returns its 2-D result.
+    ///
+    /// # Arguments
+    /// * `name` - The array function (SORT, UNIQUE, FILTER or SEQUENCE)
+    /// * `args` - The parsed argument list
+    ///
+    /// # Returns
+    /// * `Some(rows)` - The result as a non-empty list of equally long rows
+    /// * `None` - If an argument refers to a cell in error, an argument is out of range,
+    ///   or FILTER keeps no rows
+    ///
+    /// # Notes
+    /// SEQUENCE results larger than the sheet are rejected up front instead of being
+    /// generated and then failing to spill.
+    pub fn evaluate_array(&self, name: &FunctionName, args: &[Argument]) -> Option<Vec<Vec<i32>>> {
+        match name {
+            FunctionName::Sort => {
+                let mut rows = self.argument_block(args.first()?)?;
+                let width = rows[0].len();
+                let sort_index = match args.get(1) {
+                    Some(arg) => self.argument_scalar(arg)?,
+                    None => 1,
+                };
+                let sort_order = match args.get(2) {
+                    Some(arg) => self.argument_scalar(arg)?,
+                    None => 1,
+                };
+                if sort_index < 1 || sort_index as usize > width || sort_order.abs() != 1 {
+                    return None;
+                }
+                let key = sort_index as usize - 1;
+                if sort_order == 1 {
+                    rows.sort_by(|a, b| a[key].cmp(&b[key]));
+                } else {
+                    rows.sort_by(|a, b| b[key].cmp(&a[key]));
+                }
+                Some(rows)
+            }
+            FunctionName::Unique => {
+                let rows = self.argument_block(args.first()?)?;
+                let mut seen = BTreeSet::new();
+                Some(
+                    rows.into_iter()
+                        .filter(|line| seen.insert(line.clone()))
+                        .collect(),
+                )
+            }
+            FunctionName::Filter => {
+                let rows = self.argument_block(args.first()?)?;
+                let Some(Argument::Condition {
+                    range: (start, end),
+                    operator,
+                    value,
+                }) = args.get(1)
+                else {
+                    return None;
+                };
+                let tests = self.range_block(start, end)?;
+                let threshold = self.operand_value(value)?;
+                if tests.len() != rows.len() {
+                    return None;
+                }
+                let kept: Vec<Vec<i32>> = rows
+                    .into_iter()
+                    .zip(tests)
+                    .filter(|(_, test)| operator.holds(test[0], threshold))
+                    .map(|(line, _)| line)
+                    .collect();
+                if kept.is_empty() { None } else { Some(kept) }
+            }
+            FunctionName::Sequence => {
+                let mut params = [1; 4];
+                for (param, arg) in params.iter_mut().zip(args) {
+                    *param = self.argument_scalar(arg)?;
+                }
+                let [rows, cols, start, step] = params;
+                if args.is_empty()
+                    || rows < 1
+                    || cols < 1
+                    || rows > self.rows as i32
+                    || cols > self.cols as i32
+                {
+                    return None;
+                }
+                let mut next = start as i64;
+                let mut values = Vec::with_capacity(rows as usize);
+                for _ in 0..rows {
+                    let mut line = Vec::with_capacity(cols as usize);
+                    for _ in 0..cols {
+                        line.push(i32::try_from(next).ok()?);
+                        next += step as i64;
+                    }
+                    values.push(line);
+                }
+                Some(values)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the value of an operand, or `None` if it refers to a cell in error.
+    fn operand_value(&self, operand: &Operand) -> Option<i32> {
+        match operand {
+            Operand::Number(n) => Some(*n),
+            Operand::Cell(r, c) => {
+                let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                match self.cells.get(index).and_then(|c| c.as_ref()) {
+                    Some(cell) if cell.error => None,
+                    Some(cell) => Some(cell.value),
+                    None => Some(0),
+                }
+            }
+        }
+    }
+
+    /// Returns the values of a range row by row, or `None` if any cell is in error.
+    fn range_block(&self, start: &Operand, end: &Operand) -> Option<Vec<Vec<i32>>> {
+        let (Operand::Cell(r1, c1), Operand::Cell(r2, c2)) = (start, end) else {
+            return None;
+        };
+        (*r1..=*r2)
+            .map(|r| {
+                (*c1..=*c2)
+                    .map(|c| self.operand_value(&Operand::Cell(r, c)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Returns an argument as a block of values (a single value becomes a 1x1 block).
+    fn argument_block(&self, arg: &Argument) -> Option<Vec<Vec<i32>>> {
+        match arg {
+            Argument::Range(start, end) => self.range_block(start, end),
+            Argument::Value(operand) => Some(vec![vec![self.operand_value(operand)?]]),
+            Argument::Decimal(_) | Argument::Condition { .. } => None,
+        }
+    }
+
+    /// Returns an argument that must be a single value.
+    fn argument_scalar(&self, arg: &Argument) -> Option<i32> {
+        match arg {
+            Argument::Value(operand) => self.operand_value(operand),
+            _ => None,
+        }
+    }
+
+    /// Evaluates a statistical or financial function.
+    ///
+    /// The functions follow their Excel definitions: PERCENTILE and QUARTILE interpolate
+    /// between ranked values, COVAR is the population covariance, SLOPE, INTERCEPT and
+    /// FORECAST.LINEAR use a least squares fit of the first range against the second, and
+    /// the financial functions use Excel's sign convention (money paid out is negative).
+    /// SPARKLINE takes the last value of its range.
+    ///
+    /// # Arguments
+    /// * `name` - The function to evaluate
+    /// * `args` - The parsed argument list
+    ///
+    /// # Returns
+    /// * `Some(result)` - The result, which may have a fractional part
+    /// * `None` - If an argument refers to a cell in error or lies outside the function's
+    ///   domain, the result is undefined (e.g. SLOPE of a constant range), or IRR does
+    ///   not converge
+    pub fn evaluate_number(&self, name: &FunctionName, args: &[Argument]) -> Option<f64> {
+        let number = |i: usize| -> Option<f64> {
+            match args.get(i) {
+                Some(arg) => self.argument_number(arg),
+                None => Some(0.0),
+            }
+        };
+        match name {
+            FunctionName::Percentile => {
+                let values = self.argument_numbers(args.first()?)?;
+                percentile(values, number(1)?)
+            }
+            FunctionName::Quartile => {
+                let quart = number(1)?.trunc();
+                if !(0.0..=4.0).contains(&quart) {
+                    return None;
+                }
+                let values = self.argument_numbers(args.first()?)?;
+                percentile(values, quart / 4.0)
+            }
+            FunctionName::Rank => {
+                let value = number(0)?;
+                let values = self.argument_numbers(args.get(1)?)?;
+                if !values.contains(&value) {
+                    return None;
+                }
+                let ahead = if number(2)? == 0.0 {
+                    values.iter().filter(|&&v| v > value).count()
+                } else {
+                    values.iter().filter(|&&v| v < value).count()
+                };
+                Some((ahead + 1) as f64)
+            }
+            FunctionName::Correl => {
+                let (ys, xs) = self.paired_numbers(args.first()?, args.get(1)?)?;
+                let deviation = (covariance(&xs, &xs) * covariance(&ys, &ys)).sqrt();
+                if deviation == 0.0 {
+                    return None;
+                }
+                Some(covariance(&xs, &ys) / deviation)
+            }
+            FunctionName::Covar => {
+                let (ys, xs) = self.paired_numbers(args.first()?, args.get(1)?)?;
+                Some(covariance(&xs, &ys))
+            }
+            FunctionName::Slope => {
+                let (ys, xs) = self.paired_numbers(args.first()?, args.get(1)?)?;
+                linear_fit(&xs, &ys).map(|(slope, _)| slope)
+            }
+            FunctionName::Intercept => {
+                let (ys, xs) = self.paired_numbers(args.first()?, args.get(1)?)?;
+                linear_fit(&xs, &ys).map(|(_, intercept)| intercept)
+            }
+            FunctionName::ForecastLinear => {
+                let x = number(0)?;
+                let (ys, xs) = self.paired_numbers(args.get(1)?, args.get(2)?)?;
+                linear_fit(&xs, &ys).map(|(slope, intercept)| intercept + slope * x)
+            }
+            FunctionName::Npv => {
+                let rate = number(0)?;
+                let mut flows = Vec::new();
+                for arg in &args[1..] {
+                    flows.extend(self.argument_numbers(arg)?);
+                }
+                if rate == -1.0 {
+                    return None;
+                }
+                Some(net_present_value(rate, &flows, 1))
+            }
+            FunctionName::Irr => {
+                let flows = self.argument_numbers(args.first()?)?;
+                let guess = if args.len() > 1 { number(1)? } else { 0.1 };
+                internal_rate_of_return(&flows, guess)
+            }
+            FunctionName::Pmt | FunctionName::Fv | FunctionName::Pv => {
+                let (rate, nper) = (number(0)?, number(1)?);
+                let (amount, other) = (number(2)?, number(3)?);
+                let growth = (1.0 + rate).powf(nper);
+                let annuity = annuity_factor(rate, nper, number(4)? != 0.0);
+                match name {
+                    FunctionName::Pmt if annuity != 0.0 => {
+                        Some(-(other + amount * growth) / annuity)
+                    }
+                    FunctionName::Fv => Some(-(other * growth + amount * annuity)),
+                    FunctionName::Pv if growth != 0.0 => Some(-(other + amount * annuity) / growth),
+                    _ => None,
+                }
+            }
+            FunctionName::Sparkline => self.argument_numbers(args.first()?)?.last().copied(),
+            _ => None,
+        }
+    }
+
+    /// Works out the kind of error a formula that failed to evaluate is in.
+    ///
+    /// An error in a cell the formula reads from is passed on unchanged; otherwise the
+    /// kind follows from the function, as Excel would report it.
+    fn error_kind(&self, formula: &ParsedRHS) -> CellError {
+        let passed_on = formula
+            .references()
+            .into_iter()
+            .find_map(|((r1, c1), (r2, c2))| {
+                (r1..=r2)
+                    .flat_map(|r| (c1..=c2).map(move |c| (r, c)))
+                    .find_map(|(r, c)| {
+                        let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                        let cell = self.cells.get(index)?.as_ref()?;
+                        cell.error.then_some(cell.error_kind)
+                    })
+            });
+        if let Some(kind) = passed_on {
+            return kind;
+        }
+        match formula {
+            ParsedRHS::Arithmetic { operator: '/', .. } => CellError::DivZero,
+            ParsedRHS::FunctionCall { name, .. } => match name {
+                FunctionName::Filter => CellError::Calc,
+                FunctionName::Rank => CellError::NotAvailable,
+                FunctionName::Correl
+                | FunctionName::Slope
+                | FunctionName::Intercept
+                | FunctionName::ForecastLinear
+                | FunctionName::Npv => CellError::DivZero,
+                FunctionName::Sort | FunctionName::Unique | FunctionName::Sequence => {
+                    CellError::Value
+                }
+                _ => CellError::Num,
+            },
+            _ => CellError::Value,
+        }
+    }
+
+    /// Returns the values drawn by a `SPARKLINE` cell, in the order of its range.
+    ///
+    /// The cell's own value is the last of them, so formulas referring to it see the
+    /// latest point of the trend.
+    ///
+    /// # Returns
+    /// * `Some(values)` - The values of the range, read row by row
+    /// * `None` - If the cell does not hold a `SPARKLINE` formula or is in error
+    pub fn sparkline_values(&self, row: i16, col: i16) -> Option<Vec<f64>> {
+        let index = (row - 1) as usize * self.cols as usize + (col - 1) as usize;
+        let cell = self.cells.get(index)?.as_ref()?;
+        match &cell.formula {
+            ParsedRHS::FunctionCall {
+                name: FunctionName::Sparkline,
+                args,
+            } if !cell.error => self.argument_numbers(args.first()?),
+            _ => None,
+        }
+    }
+
+    /// Returns the numeric value of a cell, including any fractional part, or `None` if
+    /// the cell is in error.
+    fn cell_number(&self, row: i16, col: i16) -> Option<f64> {
+        let index = (row - 1) as usize * self.cols as usize + (col - 1) as usize;
+        match self.cells.get(index).and_then(|c| c.as_ref()) {
+            Some(cell) if cell.error => None,
+            Some(cell) => Some(cell.number()),
+            None => Some(0.0),
         }
     }
 
+    /// Returns an argument that must be a single number.
+    fn argument_number(&self, arg: &Argument) -> Option<f64> {
+        match arg {
+            Argument::Value(Operand::Number(n)) => Some(*n as f64),
+            Argument::Value(Operand::Cell(r, c)) => self.cell_number(*r, *c),
+            Argument::Decimal(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// Returns the numbers an argument covers, reading ranges row by row.
+    fn argument_numbers(&self, arg: &Argument) -> Option<Vec<f64>> {
+        match arg {
+            Argument::Range(Operand::Cell(r1, c1), Operand::Cell(r2, c2)) => (*r1..=*r2)
+                .flat_map(|r| (*c1..=*c2).map(move |c| (r, c)))
+                .map(|(r, c)| self.cell_number(r, c))
+                .collect(),
+            Argument::Range(..) | Argument::Condition { .. } => None,
+            _ => Some(vec![self.argument_number(arg)?]),
+        }
+    }
+
+    /// Returns the numbers of two equally sized ranges as (first, second).
+    fn paired_numbers(&self, first: &Argument, second: &Argument) -> Option<(Vec<f64>, Vec<f64>)> {
+        let first = self.argument_numbers(first)?;
+        let second = self.argument_numbers(second)?;
+        if first.is_empty() || first.len() != second.len() {
+            return None;
+        }
+        Some((first, second))
+    }
+
     /// Recursively finds cycles in the dependency graph using a stack.
     ///
     /// This function implements cycle detection in the cell dependency graph to prevent
@@ -474,10 +1269,10 @@ impl Spreadsheet {
                         let r = dependent_name.0;
                         let c = dependent_name.1;
                         let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
-                        if index < self.cells.len() {
-                            if let Some(ref neighbor_node) = self.cells[index] {
-                                stack.push(neighbor_node);
-                            }
+                        if index < self.cells.len()
+                            && let Some(ref neighbor_node) = self.cells[index]
+                        {
+                            stack.push(neighbor_node);
                         }
                     }
                 }
@@ -497,12 +1292,29 @@ impl Spreadsheet {
     ///
     /// # Returns
     /// A vector of (row, column) pairs representing the cells that depend on the given cell
+    ///
+    /// # Spill Regions
+    /// The anchor of a dynamic array formula also reports every cell its result spills
+    /// into, so the whole region is recalculated as one unit.
     pub fn get_dependent_names(&self, cell: &Cell) -> Vec<(i16, i16)> {
-        match &cell.dependents {
+        let mut names = match &cell.dependents {
             crate::cell::Dependents::Vector(vec) => vec.clone(),
             crate::cell::Dependents::Set(set) => set.iter().cloned().collect(),
             crate::cell::Dependents::None => Vec::new(),
+        };
+        if let Spill::Anchor { rows, cols } = cell.spill {
+            for r in cell.row..cell.row + rows {
+                for c in cell.col..cell.col + cols {
+                    let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                    if let Some(member) = self.cells.get(index).and_then(|opt| opt.as_ref())
+                        && member.spill == Spill::Member(cell.row, cell.col)
+                    {
+                        names.push((r, c));
+                    }
+                }
+            }
         }
+        names
     }
 
     /// Initiates the cycle detection process for a given cell and range.
@@ -553,28 +1365,26 @@ impl Spreadsheet {
         };
 
         match formula {
-            ParsedRHS::Function { name, args } => {
-                if !name.is_copy() {
-                    let (arg1, arg2) = args;
-                    let (start_row, start_col) = match arg1 {
-                        Operand::Cell(row, col) => (row, col),
-                        Operand::Number(_) => (0, 0),
-                    };
-                    let (end_row, end_col) = match arg2 {
-                        Operand::Cell(row, col) => (row, col),
-                        Operand::Number(_) => (0, 0), // Placeholder
-                    };
+            ParsedRHS::Function { name, args } if !name.is_copy() => {
+                let (arg1, arg2) = args;
+                let (start_row, start_col) = match arg1 {
+                    Operand::Cell(row, col) => (row, col),
+                    Operand::Number(_) => (0, 0),
+                };
+                let (end_row, end_col) = match arg2 {
+                    Operand::Cell(row, col) => (row, col),
+                    Operand::Number(_) => (0, 0), // Placeholder
+                };
 
-                    for dep_r in start_row..=end_row {
-                        for dep_c in start_col..=end_col {
-                            let dep_index =
-                                (dep_r - 1) as usize * self.cols as usize + (dep_c - 1) as usize;
+                for dep_r in start_row..=end_row {
+                    for dep_c in start_col..=end_col {
+                        let dep_index =
+                            (dep_r - 1) as usize * self.cols as usize + (dep_c - 1) as usize;
 
-                            if let Some(dep_cell) =
-                                self.cells.get_mut(dep_index).and_then(|opt| opt.as_mut())
-                            {
-                                crate::cell::cell_dep_remove(dep_cell, r, c);
-                            }
+                        if let Some(dep_cell) =
+                            self.cells.get_mut(dep_index).and_then(|opt| opt.as_mut())
+                        {
+                            crate::cell::cell_dep_remove(dep_cell, r, c);
                         }
                     }
                 }
@@ -610,6 +1420,23 @@ impl Spreadsheet {
                 let dep_cell = self.cells[dep_index].as_mut().unwrap();
                 crate::cell::cell_dep_remove(dep_cell, r, c);
             }
+            ParsedRHS::FunctionCall { args, .. } => {
+                for ((start_row, start_col), (end_row, end_col)) in
+                    args.iter().flat_map(Argument::references)
+                {
+                    for dep_r in start_row..=end_row {
+                        for dep_c in start_col..=end_col {
+                            let dep_index =
+                                (dep_r - 1) as usize * self.cols as usize + (dep_c - 1) as usize;
+                            if let Some(dep_cell) =
+                                self.cells.get_mut(dep_index).and_then(|opt| opt.as_mut())
+                            {
+                                crate::cell::cell_dep_remove(dep_cell, r, c);
+                            }
+                        }
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -663,6 +1490,30 @@ impl Spreadsheet {
         0
     }
 
+    /// Updates the dependencies for a cell whose formula reads several ranges.
+    ///
+    /// This is the counterpart of `update_dependencies` for functions taking an argument
+    /// list. Cells covered by more than one range are only recorded once.
+    ///
+    /// # Arguments
+    /// * `(r, c)` - The cell being updated
+    /// * `ranges` - The (start, end) corners of every range the new formula reads
+    pub fn update_range_dependencies(&mut self, (r, c): (i16, i16), ranges: &[CellRange]) {
+        self.remove_old_dependents(r, c);
+
+        for &((start_row, start_col), (end_row, end_col)) in ranges {
+            for r_it in start_row..=end_row {
+                for c_it in start_col..=end_col {
+                    let dep_index = (r_it - 1) as usize * self.cols as usize + (c_it - 1) as usize;
+                    let dep_cell = self.cells[dep_index].as_mut().unwrap();
+                    if !dep_cell.contains(r, c) {
+                        crate::cell::cell_dep_insert(dep_cell, r, c);
+                    }
+                }
+            }
+        }
+    }
+
     /// Performs a topological sort on the dependency graph starting from a given cell.
     ///
     /// This function sorts cells in dependency order, ensuring that cells are evaluated
@@ -675,17 +1526,20 @@ impl Spreadsheet {
     /// # Returns
     /// A boxed vector of (row, column) pairs in topological order
     pub fn topo_sort(&self, starting: &Cell) -> Box<Vec<(i16, i16)>> {
-        let mut sorted_nodes = Box::new(Vec::new());
-        let mut stack = Box::new(Vec::new());
-        stack.push(starting.clone());
+        Box::new(self.topo_order(&[(starting.row, starting.col)]))
+    }
 
-        let mut visited = Box::new(BTreeSet::new());
+    /// Sorts the given cells and every cell depending on them in dependency order.
+    ///
+    /// This is [`Spreadsheet::topo_sort`] for several starting cells at once; a cell
+    /// reachable from more than one of them appears only once.
+    fn topo_order(&self, starting: &[(i16, i16)]) -> Vec<(i16, i16)> {
+        let mut sorted_nodes = Vec::new();
+        let mut visited = BTreeSet::new();
 
-        let mut work_stack = Box::new(Vec::new());
-        work_stack.push(Box::new((starting.row, starting.col)));
+        let mut work_stack: Vec<(i16, i16)> = starting.iter().rev().copied().collect();
 
         while let Some(current) = work_stack.pop() {
-            let current = *current;
             if visited.contains(&current) {
                 continue;
             }
@@ -698,8 +1552,8 @@ impl Spreadsheet {
                 for dep_key in &dependent_keys {
                     if !visited.contains(dep_key) {
                         let (r, c) = *dep_key;
-                        work_stack.push(Box::new(current));
-                        work_stack.push(Box::new((r, c)));
+                        work_stack.push(current);
+                        work_stack.push((r, c));
                         all_dependents_visited = false;
                         break;
                     }
@@ -724,6 +1578,7 @@ impl Spreadsheet {
     /// 4. Evaluates the new formula
     /// 5. Propagates changes to dependent cells
     /// 6. Updates the undo stack
+    /// 7. Re-spills array formulas whose spill region contains the cell
     ///
     /// # Arguments
     /// * `row` - Row of the cell to update
@@ -756,16 +1611,17 @@ impl Spreadsheet {
             let col_offset = dest_col as isize - start_col as isize;
 
             let mut src_val: Vec<i32> = Vec::new();
-            let mut src_err: Vec<bool> = Vec::new();
+            let mut src_err: Vec<(bool, CellError)> = Vec::new();
             for r in start_row..=end_row {
                 for c in start_col..=end_col {
                     let src_index = ((r - 1) * self.cols + (c - 1)) as usize;
                     let src_cell = self.cells[src_index].as_ref().unwrap();
                     src_val.push(src_cell.value);
-                    src_err.push(src_cell.error);
+                    src_err.push((src_cell.error, src_cell.error_kind));
                 }
             }
             let mut cnter = 0;
+            let mut pasted = Vec::new();
             for r in start_row..=end_row {
                 for c in start_col..=end_col {
                     self.update_dependencies((r, c), (0, 0), (0, 0), false);
@@ -780,82 +1636,464 @@ impl Spreadsheet {
                             dest_cell.col,
                         ));
                         dest_cell.value = src_val[cnter];
+                        dest_cell.decimal = None;
                         dest_cell.formula =
                             ParsedRHS::SingleValue(Operand::Number(dest_cell.value));
-                        dest_cell.error = src_err[cnter];
+                        (dest_cell.error, dest_cell.error_kind) = src_err[cnter];
+                        pasted.push((dest_cell.row, dest_cell.col));
                         cnter += 1;
                     }
                 }
             }
+            for (r, c) in pasted {
+                self.spill_anchors.remove(&(r, c));
+                for (released_row, released_col) in self.release_spill(r, c) {
+                    self.recalculate(released_row, released_col);
+                }
+                self.refresh_spills_covering(r, c);
+            }
             *status_out = "ok".to_string();
             return;
         }
 
-        let mut r1 = 0;
-        let mut r2 = 0;
-        let mut c1 = 0;
-        let mut c2 = 0;
-        let mut is_range = false;
-        match &rhs {
-            ParsedRHS::Function {
-                args: (Operand::Cell(w, x), Operand::Cell(y, z)),
-                ..
-            } => {
-                r1 = *w;
-                r2 = *y;
-                c1 = *x;
-                c2 = *z;
-                is_range = true;
+        if !self.link_dependencies(row, col, &rhs) {
+            *status_out = "Cycle Detected".to_string();
+            return;
+        }
+
+        let cell = self.cells[index].as_mut().unwrap();
+        self.undo_stack
+            .push((cell.formula.clone(), cell.row, cell.col));
+
+        let released = self.release_spill(row, col);
+        if rhs.is_array() {
+            self.spill_anchors.insert((row, col));
+        } else {
+            self.spill_anchors.remove(&(row, col));
+        }
+        self.cells[index].as_mut().unwrap().formula = rhs;
+
+        self.recalculate(row, col);
+        for (released_row, released_col) in released {
+            self.recalculate(released_row, released_col);
+        }
+        self.refresh_spills_covering(row, col);
+
+        *status_out = "ok".to_string();
+    }
+
+    /// Records the cells a new formula for (row, col) reads, replacing its old dependencies.
+    ///
+    /// # Returns
+    /// `false`, leaving the dependencies as they were, if the formula would create a cycle
+    fn link_dependencies(&mut self, row: i16, col: i16, rhs: &ParsedRHS) -> bool {
+        if let ParsedRHS::FunctionCall { args, .. } = rhs {
+            let ranges: Vec<_> = args.iter().flat_map(Argument::references).collect();
+            if ranges
+                .iter()
+                .any(|&(start, end)| self.first_step_find_cycle((row, col), start, end, true))
+            {
+                return false;
             }
-            ParsedRHS::Arithmetic { lhs, rhs, .. } => {
-                if let Operand::Cell(w, x) = lhs {
+            self.update_range_dependencies((row, col), &ranges);
+        } else {
+            let mut r1 = 0;
+            let mut r2 = 0;
+            let mut c1 = 0;
+            let mut c2 = 0;
+            let mut is_range = false;
+            match rhs {
+                ParsedRHS::Function {
+                    args: (Operand::Cell(w, x), Operand::Cell(y, z)),
+                    ..
+                } => {
                     r1 = *w;
-                    c1 = *x;
-                }
-                if let Operand::Cell(y, z) = rhs {
                     r2 = *y;
+                    c1 = *x;
                     c2 = *z;
+                    is_range = true;
                 }
+                ParsedRHS::Arithmetic { lhs, rhs, .. } => {
+                    if let Operand::Cell(w, x) = lhs {
+                        r1 = *w;
+                        c1 = *x;
+                    }
+                    if let Operand::Cell(y, z) = rhs {
+                        r2 = *y;
+                        c2 = *z;
+                    }
+                }
+                ParsedRHS::Sleep(Operand::Cell(r, c)) => {
+                    r1 = *r;
+                    c1 = *c;
+                }
+                ParsedRHS::SingleValue(Operand::Cell(r, c)) => {
+                    r1 = *r;
+                    c1 = *c;
+                }
+                _ => {}
+            };
+
+            if self.first_step_find_cycle((row, col), (r1, c1), (r2, c2), is_range) {
+                return false;
             }
-            ParsedRHS::Sleep(Operand::Cell(r, c)) => {
-                r1 = *r;
-                c1 = *c;
-            }
-            ParsedRHS::SingleValue(Operand::Cell(r, c)) => {
-                r1 = *r;
-                c1 = *c;
-            }
-            _ => {}
-        };
 
-        if self.first_step_find_cycle((row, col), (r1, c1), (r2, c2), is_range) {
-            *status_out = "Cycle Detected".to_string();
-            return;
+            self.update_dependencies((row, col), (r1, c1), (r2, c2), is_range);
         }
+        true
+    }
 
-        self.update_dependencies((row, col), (r1, c1), (r2, c2), is_range);
-        let cell = self.cells[index].as_mut().unwrap();
-        self.undo_stack
-            .push((cell.formula.clone(), cell.row, cell.col));
-
-        cell.formula = rhs;
-        let cell = self.cells[index].as_ref().unwrap();
+    /// Re-evaluates a cell and every cell that depends on it, in topological order.
+    ///
+    /// Array formulas met along the way are re-spilled. Cells that join a spill region
+    /// during this pass were not part of the original ordering, so their dependents are
+    /// recalculated afterwards.
+    ///
+    /// # Arguments
+    /// * `row` - Row of the cell that changed
+    /// * `col` - Column of the cell that changed
+    pub fn recalculate(&mut self, row: i16, col: i16) {
+        self.recalculate_from(&[(row, col)], true);
+    }
 
-        let sorted_cells = self.topo_sort(cell);
+    /// Re-evaluates the given cells and every cell that depends on them, each once, in
+    /// topological order.
+    ///
+    /// # Arguments
+    /// * `cells` - The cells that changed
+    /// * `delay` - Whether `SLEEP` formulas wait; when rebuilding a sheet they only take
+    ///   their value
+    fn recalculate_from(&mut self, cells: &[(i16, i16)], delay: bool) {
+        let sorted_cells = self.topo_order(cells);
+        let mut joined = Vec::new();
 
         for (row, col) in sorted_cells.iter() {
             let sorted_index = (*row - 1) as usize * self.cols as usize + (*col - 1) as usize;
 
-            let formula = &self.cells[sorted_index].as_ref().unwrap().formula;
+            let sorted_cell = self.cells[sorted_index].as_ref().unwrap();
+            if matches!(sorted_cell.spill, Spill::Member(..)) {
+                // Filled in by its anchor, which precedes it in the ordering
+                continue;
+            }
+            if sorted_cell.formula.is_array() {
+                joined.extend(self.spill_array(*row, *col));
+                continue;
+            }
 
-            let (value, error_cell) = self.spreadsheet_evaluate_expression(formula, *row, *col);
+            if let ParsedRHS::FunctionCall { name, args } = &sorted_cell.formula
+                && name.is_analysis()
+            {
+                let number = self.evaluate_number(name, args);
+                let error_kind = match number {
+                    Some(_) => CellError::Num,
+                    None => self.error_kind(&sorted_cell.formula),
+                };
+                let sorted_cell = self.cells[sorted_index].as_mut().unwrap();
+                if number.is_some_and(|number| sorted_cell.set_number(number)) {
+                    sorted_cell.error = false;
+                } else {
+                    sorted_cell.value = 0;
+                    sorted_cell.decimal = None;
+                    sorted_cell.error = true;
+                    sorted_cell.error_kind = error_kind;
+                }
+                continue;
+            }
+
+            let (value, error_cell) = match &sorted_cell.formula {
+                ParsedRHS::Sleep(op) if !delay => self.sleep_seconds(op),
+                formula => self.spreadsheet_evaluate_expression(formula, *row, *col),
+            };
+            let error_kind = error_cell.then(|| self.error_kind(&sorted_cell.formula));
 
             let sorted_cell = self.cells[sorted_index].as_mut().unwrap();
             sorted_cell.value = value;
+            sorted_cell.decimal = None;
             sorted_cell.error = error_cell;
+            if let Some(error_kind) = error_kind {
+                sorted_cell.error_kind = error_kind;
+            }
         }
 
-        *status_out = "ok".to_string();
+        for (row, col) in joined {
+            self.recalculate_from(&[(row, col)], delay);
+        }
+    }
+
+    /// Evaluates the array formula anchored at (row, col) and spills its result.
+    ///
+    /// The previous spill region is released first. If the new region runs off the sheet,
+    /// overlaps one of the formula's own ranges, or contains a non-empty cell or another
+    /// formula's spilled cell, the anchor is marked as blocked and shows `#SPILL!`.
+    ///
+    /// # Returns
+    /// The cells that became part of the spill region and were not part of it before
+    fn spill_array(&mut self, row: i16, col: i16) -> Vec<(i16, i16)> {
+        let index = (row - 1) as usize * self.cols as usize + (col - 1) as usize;
+        let (name, args, old_spill) = {
+            let cell = self.cells[index].as_ref().unwrap();
+            match &cell.formula {
+                ParsedRHS::FunctionCall { name, args } => {
+                    (name.clone(), args.clone(), cell.spill.clone())
+                }
+                _ => return Vec::new(),
+            }
+        };
+
+        let previous: BTreeSet<(i16, i16)> = match old_spill {
+            Spill::Anchor { rows, cols } => self
+                .clear_spill_members(row, col, rows, cols)
+                .into_iter()
+                .collect(),
+            _ => BTreeSet::new(),
+        };
+
+        let values = match self.evaluate_array(&name, &args) {
+            Some(values) => values,
+            None => {
+                let error_kind = self.error_kind(&self.cells[index].as_ref().unwrap().formula);
+                let cell = self.cells[index].as_mut().unwrap();
+                cell.value = 0;
+                cell.decimal = None;
+                cell.error = true;
+                cell.error_kind = error_kind;
+                cell.spill = Spill::Anchor { rows: 1, cols: 1 };
+                return Vec::new();
+            }
+        };
+
+        let (rows, cols) = (values.len(), values[0].len());
+        if !self.spill_region_free((row, col), (rows, cols), &args) {
+            let cell = self.cells[index].as_mut().unwrap();
+            cell.value = 0;
+            cell.decimal = None;
+            cell.error = true;
+            cell.error_kind = CellError::Value;
+            cell.spill = Spill::Blocked {
+                rows: rows.min(i16::MAX as usize) as i16,
+                cols: cols.min(i16::MAX as usize) as i16,
+            };
+            return Vec::new();
+        }
+
+        let mut joined = Vec::new();
+        for (i, line) in values.iter().enumerate() {
+            for (j, value) in line.iter().enumerate() {
+                let (r, c) = (row + i as i16, col + j as i16);
+                let member_index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                let cell = self.cells[member_index].as_mut().unwrap();
+                cell.value = *value;
+                cell.decimal = None;
+                cell.error = false;
+                if (r, c) == (row, col) {
+                    cell.spill = Spill::Anchor {
+                        rows: rows as i16,
+                        cols: cols as i16,
+                    };
+                } else {
+                    cell.spill = Spill::Member(row, col);
+                    if !previous.contains(&(r, c)) {
+                        joined.push((r, c));
+                    }
+                }
+            }
+        }
+        joined
+    }
+
+    /// Checks if an array result of the given size can spill from (row, col).
+    fn spill_region_free(
+        &self,
+        (row, col): (i16, i16),
+        (rows, cols): (usize, usize),
+        args: &[Argument],
+    ) -> bool {
+        if row as usize + rows - 1 > self.rows as usize
+            || col as usize + cols - 1 > self.cols as usize
+        {
+            return false;
+        }
+        let end_row = row + rows as i16 - 1;
+        let end_col = col + cols as i16 - 1;
+
+        let overlaps_source = args.iter().flat_map(Argument::references).any(
+            |((start_row, start_col), (stop_row, stop_col))| {
+                start_row <= end_row && row <= stop_row && start_col <= end_col && col <= stop_col
+            },
+        );
+        if overlaps_source {
+            return false;
+        }
+
+        for r in row..=end_row {
+            for c in col..=end_col {
+                if (r, c) == (row, col) {
+                    continue;
+                }
+                let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                let cell = self.cells[index].as_ref().unwrap();
+                let occupied = cell.formula != ParsedRHS::None
+                    || matches!(cell.spill, Spill::Member(ar, ac) if (ar, ac) != (row, col));
+                if occupied {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Empties the cells the array formula anchored at (row, col) has spilled into.
+    ///
+    /// # Returns
+    /// The cells that were released
+    fn clear_spill_members(&mut self, row: i16, col: i16, rows: i16, cols: i16) -> Vec<(i16, i16)> {
+        let mut released = Vec::new();
+        for r in row..row + rows {
+            for c in col..col + cols {
+                let index = (r - 1) as usize * self.cols as usize + (c - 1) as usize;
+                if let Some(cell) = self.cells.get_mut(index).and_then(|opt| opt.as_mut())
+                    && cell.spill == Spill::Member(row, col)
+                {
+                    cell.spill = Spill::None;
+                    cell.value = 0;
+                    cell.decimal = None;
+                    cell.error = false;
+                    released.push((r, c));
+                }
+            }
+        }
+        released
+    }
+
+    /// Detaches a cell that is about to receive a new formula from any spill region.
+    ///
+    /// A spilled cell simply stops being part of its region, while an anchor releases
+    /// every cell its result spilled into.
+    ///
+    /// # Returns
+    /// The cells that were released and need recalculating
+    fn release_spill(&mut self, row: i16, col: i16) -> Vec<(i16, i16)> {
+        let index = (row - 1) as usize * self.cols as usize + (col - 1) as usize;
+        let cell = self.cells[index].as_mut().unwrap();
+        let old_spill = std::mem::replace(&mut cell.spill, Spill::None);
+        match old_spill {
+            Spill::Anchor { rows, cols } => self.clear_spill_members(row, col, rows, cols),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Re-spills every array formula whose spill region covers (row, col).
+    ///
+    /// Called after a cell's content changes, so that writing into a spill region blocks
+    /// the formula (`#SPILL!`) and emptying the obstructing cell lets it spill again.
+    fn refresh_spills_covering(&mut self, row: i16, col: i16) {
+        let anchors: Vec<(i16, i16)> = self.spill_anchors.iter().copied().collect();
+        for (anchor_row, anchor_col) in anchors {
+            if (anchor_row, anchor_col) == (row, col) {
+                continue;
+            }
+            let index = (anchor_row - 1) as usize * self.cols as usize + (anchor_col - 1) as usize;
+            let Some((rows, cols)) = self.cells[index].as_ref().and_then(|c| c.spill_extent())
+            else {
+                continue;
+            };
+            let covers = row >= anchor_row
+                && (row as i32) < anchor_row as i32 + rows as i32
+                && col >= anchor_col
+                && (col as i32) < anchor_col as i32 + cols as i32;
+            if covers {
+                self.recalculate(anchor_row, anchor_col);
+            }
+        }
+    }
+
+    /// Returns the formula of every non-empty cell as (row, col, formula), row by row.
+    pub fn formulas(&self) -> Vec<(i16, i16, ParsedRHS)> {
+        self.cells
+            .iter()
+            .flatten()
+            .filter(|cell| cell.formula != ParsedRHS::None)
+            .map(|cell| (cell.row, cell.col, cell.formula.clone()))
+            .collect()
+    }
+
+    /// Creates a spreadsheet from the formulas of its non-empty cells.
+    ///
+    /// Every formula and its dependencies are restored first, and the cells are then
+    /// evaluated once in dependency order, spilling array formulas as they go. `SLEEP`
+    /// formulas take their value without waiting. Formulas that would create a cycle are
+    /// left out. The undo stack starts empty.
+    ///
+    /// # Arguments
+    /// * `rows` - Number of rows of the new sheet
+    /// * `cols` - Number of columns of the new sheet
+    /// * `formulas` - (row, col, formula) of each non-empty cell; every cell and every
+    ///   cell a formula reads must lie within the sheet
+    ///
+    /// # Returns
+    /// * `Some(Box<Self>)` - The new sheet
+    /// * `None` - If the sheet could not be created
+    pub fn spreadsheet_from_formulas(
+        rows: i16,
+        cols: i16,
+        formulas: impl IntoIterator<Item = (i16, i16, ParsedRHS)>,
+    ) -> Option<Box<Self>> {
+        let mut sheet = Self::spreadsheet_create(rows, cols)?;
+        let mut restored = Vec::new();
+        for (row, col, formula) in formulas {
+            if !sheet.link_dependencies(row, col, &formula) {
+                continue;
+            }
+            if formula.is_array() {
+                sheet.spill_anchors.insert((row, col));
+            }
+            let index = (row - 1) as usize * cols as usize + (col - 1) as usize;
+            sheet.cells[index].as_mut()?.formula = formula;
+            restored.push((row, col));
+        }
+        sheet.recalculate_from(&restored, false);
+        Some(sheet)
+    }
+
+    /// Creates a copy of the spreadsheet with different dimensions.
+    ///
+    /// Every formula that fits in the new sheet is entered again, so values and
+    /// dependencies are rebuilt from scratch. Formulas in cells outside the new bounds,
+    /// or reading cells outside them, are dropped. Charts are kept as they are.
+    ///
+    /// # Arguments
+    /// * `rows` - Number of rows of the new sheet
+    /// * `cols` - Number of columns of the new sheet
+    ///
+    /// # Returns
+    /// * `Some((sheet, dropped))` - The resized sheet and the number of formulas dropped
+    /// * `None` - If the new sheet could not be created
+    pub fn spreadsheet_resize(&self, rows: i16, cols: i16) -> Option<(Box<Self>, usize)> {
+        let formulas = self.formulas();
+        let total = formulas.len();
+        let kept: Vec<_> = formulas
+            .into_iter()
+            .filter(|(row, col, formula)| Self::formula_fits(*row, *col, formula, rows, cols))
+            .collect();
+        let dropped = total - kept.len();
+
+        let mut resized = Self::spreadsheet_from_formulas(rows, cols, kept)?;
+        resized.view_row = self.view_row.min(rows - 1);
+        resized.view_col = self.view_col.min(cols - 1);
+        resized.charts = self.charts.clone();
+        Some((resized, dropped))
+    }
+
+    /// Returns true if a cell and every cell its formula reads lie within a sheet of the given size.
+    pub fn formula_fits(row: i16, col: i16, formula: &ParsedRHS, rows: i16, cols: i16) -> bool {
+        let fits = |r: i16, c: i16| (1..=rows).contains(&r) && (1..=cols).contains(&c);
+        fits(row, col)
+            && formula
+                .references()
+                .iter()
+                .all(|&((start_row, start_col), (end_row, end_col))| {
+                    fits(start_row, start_col) && fits(end_row, end_col)
+                })
     }
 
     /// Undoes the last operation by restoring the previous cell states.
@@ -891,7 +2129,8 @@ impl Spreadsheet {
     /// - Column headers are shown as letters (A, B, C, ...)
     /// - Row headers are shown as numbers (1, 2, 3, ...)
     /// - Cell values are displayed in the grid
-    /// - Cells with errors show "ERR" instead of their value
+    /// - Cells with errors show "ERR" instead of their value ("#SPILL!" for a blocked array formula)
+    /// - `SPARKLINE` cells show their trend in block characters
     pub fn spreadsheet_display(&self) {
         let end_row = if self.view_row + 10 < self.rows {
             self.view_row + 10
@@ -915,10 +2154,12 @@ impl Spreadsheet {
             for col in (self.view_col + 1)..=end_col {
                 let index = (row - 1) as usize * self.cols as usize + (col - 1) as usize;
                 if let Some(cell) = self.cells.get(index).and_then(|opt| opt.as_ref()) {
-                    if cell.error {
-                        print!("ERR\t\t");
+                    if let Some(text) = cell.error_text() {
+                        print!("{}\t\t", text);
+                    } else if let Some(values) = self.sparkline_values(row, col) {
+                        print!("{:<16}", cell_sparkline(&values, 15, false));
                     } else {
-                        print!("{:<16}", cell.value);
+                        print!("{:<16}", cell.formatted_value());
                     }
                 } else {
                     print!("0\t\t");
@@ -958,10 +2199,32 @@ impl Spreadsheet {
         if formula.is_empty() {
             return ret;
         }
+        if formula.starts_with('"') {
+            if let Some(text) = parse_text_constant(formula) {
+                ret.0 = true;
+                ret.3 = ParsedRHS::Text(text);
+            }
+            return ret;
+        }
         if let Some(captures) = FUNC_REGEX.captures(formula) {
             let func = captures.get(1).unwrap().as_str();
             let args = captures.get(2).unwrap().as_str();
 
+            if let Some(fname) = FunctionName::from_strng(func)
+                && fname.takes_argument_list()
+            {
+                if let Some(arguments) = self.parse_argument_list(args)
+                    && fname.accepts(&arguments)
+                {
+                    ret.0 = true;
+                    ret.3 = ParsedRHS::FunctionCall {
+                        name: fname,
+                        args: arguments,
+                    };
+                }
+                return ret;
+            }
+
             if func.eq_ignore_ascii_case("SLEEP") {
                 if args.is_empty() {
                     return ret;
@@ -985,36 +2248,23 @@ impl Spreadsheet {
                     if let (Some((start_row, start_col)), Some((end_row, end_col))) = (
                         self.spreadsheet_parse_cell_name(start),
                         self.spreadsheet_parse_cell_name(end),
-                    ) {
-                        if start_row <= end_row && start_col <= end_col {
-                            if let Some(fname) = FunctionName::from_strng(func) {
-                                if func == "COPY" {
-                                    let dest_row = ret.1;
-                                    let dest_col = ret.2;
-                                    let row_offset = dest_row - start_row;
-                                    let col_offset = dest_col - start_col;
-                                    let final_row = end_row + row_offset;
-                                    let final_col = end_col + col_offset;
-
-                                    if final_row > 0
-                                        && final_row <= self.rows
-                                        && final_col > 0
-                                        && final_col <= self.cols
-                                    {
-                                        ret.0 = true;
-                                        ret.3 = ParsedRHS::Function {
-                                            name: fname,
-                                            args: (
-                                                Operand::Cell(start_row, start_col),
-                                                Operand::Cell(end_row, end_col),
-                                            ),
-                                        };
-                                        return ret;
-                                    } else {
-                                        ret.0 = false;
-                                        return ret;
-                                    }
-                                }
+                    ) && start_row <= end_row
+                        && start_col <= end_col
+                        && let Some(fname) = FunctionName::from_strng(func)
+                    {
+                        if func == "COPY" {
+                            let dest_row = ret.1;
+                            let dest_col = ret.2;
+                            let row_offset = dest_row - start_row;
+                            let col_offset = dest_col - start_col;
+                            let final_row = end_row + row_offset;
+                            let final_col = end_col + col_offset;
+
+                            if final_row > 0
+                                && final_row <= self.rows
+                                && final_col > 0
+                                && final_col <= self.cols
+                            {
                                 ret.0 = true;
                                 ret.3 = ParsedRHS::Function {
                                     name: fname,
@@ -1024,8 +2274,20 @@ impl Spreadsheet {
                                     ),
                                 };
                                 return ret;
+                            } else {
+                                ret.0 = false;
+                                return ret;
                             }
                         }
+                        ret.0 = true;
+                        ret.3 = ParsedRHS::Function {
+                            name: fname,
+                            args: (
+                                Operand::Cell(start_row, start_col),
+                                Operand::Cell(end_row, end_col),
+                            ),
+                        };
+                        return ret;
                     }
                 }
                 return ret;
@@ -1054,6 +2316,67 @@ impl Spreadsheet {
         }
     }
 
+    /// Parses a comma-separated function argument list.
+    ///
+    /// # Arguments
+    /// * `args` - The text between the function's parentheses, e.g. "A1:C5, C1:C5>10"
+    ///
+    /// # Returns
+    /// * `Some(arguments)` - If every argument is a range, condition, cell or number
+    /// * `None` - If the list is empty or any argument is malformed or out of bounds
+    pub fn parse_argument_list(&self, args: &str) -> Option<Vec<Argument>> {
+        if args.trim().is_empty() {
+            return None;
+        }
+        args.split(',')
+            .map(|arg| self.parse_argument(arg.trim()))
+            .collect()
+    }
+
+    /// Parses a single function argument.
+    fn parse_argument(&self, arg: &str) -> Option<Argument> {
+        if let Some(captures) = CONDITION_REGEX.captures(arg) {
+            let range = self.parse_range(captures.get(1).unwrap().as_str())?;
+            let operator = Comparison::from_symbol(captures.get(2).unwrap().as_str())?;
+            let value = self.parse_operand(captures.get(3).unwrap().as_str())?;
+            return Some(Argument::Condition {
+                range,
+                operator,
+                value,
+            });
+        }
+        if arg.contains(':') {
+            let (start, end) = self.parse_range(arg)?;
+            return Some(Argument::Range(start, end));
+        }
+        if DECIMAL_REGEX.is_match(arg) {
+            return arg.parse::<f64>().ok().map(Argument::Decimal);
+        }
+        self.parse_operand(arg).map(Argument::Value)
+    }
+
+    /// Parses a range such as "A1:B5" whose start lies above and left of its end.
+    fn parse_range(&self, range: &str) -> Option<(Operand, Operand)> {
+        let (start, end) = range.split_once(':')?;
+        let (start_row, start_col) = self.spreadsheet_parse_cell_name(start)?;
+        let (end_row, end_col) = self.spreadsheet_parse_cell_name(end)?;
+        if start_row > end_row || start_col > end_col {
+            return None;
+        }
+        Some((
+            Operand::Cell(start_row, start_col),
+            Operand::Cell(end_row, end_col),
+        ))
+    }
+
+    /// Parses an operand: a cell reference or an integer.
+    fn parse_operand(&self, operand: &str) -> Option<Operand> {
+        if let Some((row, col)) = self.spreadsheet_parse_cell_name(operand) {
+            return Some(Operand::Cell(row, col));
+        }
+        operand.parse::<i32>().ok().map(Operand::Number)
+    }
+
     /// Checks if an arithmetic expression is valid and returns the parsed result.
     ///
     /// This function parses expressions like "A1+B2", "10-5", "C3*D4", or "E5/F6".
@@ -1122,3 +2445,105 @@ impl Spreadsheet {
         }
     }
 }
+
+/// Reads a quoted text constant such as `"Jan"`, where `""` stands for one quote.
+fn parse_text_constant(formula: &str) -> Option<String> {
+    let inner = formula.strip_prefix('"')?.strip_suffix('"')?;
+    if inner.replace("\"\"", "").contains('"') {
+        return None;
+    }
+    Some(inner.replace("\"\"", "\""))
+}
+
+/// Returns the k-th percentile (0 <= k <= 1) of the values, interpolating between
+/// neighbouring ranked values as Excel's PERCENTILE does.
+fn percentile(mut values: Vec<f64>, k: f64) -> Option<f64> {
+    if values.is_empty() || !(0.0..=1.0).contains(&k) {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let position = k * (values.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    Some(values[lower] + (position - lower as f64) * (values[upper] - values[lower]))
+}
+
+/// Returns the population covariance of two equally long, non-empty lists of values.
+fn covariance(xs: &[f64], ys: &[f64]) -> f64 {
+    let n = xs.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = ys.iter().sum::<f64>() / n;
+    xs.iter()
+        .zip(ys)
+        .map(|(x, y)| (x - mean_x) * (y - mean_y))
+        .sum::<f64>()
+        / n
+}
+
+/// Fits the least squares line y = slope * x + intercept.
+///
+/// # Returns
+/// * `Some((slope, intercept))` - The fitted line
+/// * `None` - If all x values are equal
+fn linear_fit(xs: &[f64], ys: &[f64]) -> Option<(f64, f64)> {
+    let variance = covariance(xs, xs);
+    if variance == 0.0 {
+        return None;
+    }
+    let slope = covariance(xs, ys) / variance;
+    let n = xs.len() as f64;
+    let intercept = ys.iter().sum::<f64>() / n - slope * xs.iter().sum::<f64>() / n;
+    Some((slope, intercept))
+}
+
+/// Discounts cash flows at `rate`, the first flow arriving at the end of period `first`.
+fn net_present_value(rate: f64, flows: &[f64], first: i32) -> f64 {
+    flows
+        .iter()
+        .zip(first..)
+        .map(|(flow, period)| flow / (1.0 + rate).powi(period))
+        .sum()
+}
+
+/// Returns the value after `nper` periods of a payment of 1 made every period, i.e. the
+/// factor relating PMT to FV. Payments are made at the start of each period if `due` is set.
+fn annuity_factor(rate: f64, nper: f64, due: bool) -> f64 {
+    if rate == 0.0 {
+        return nper;
+    }
+    let timing = if due { 1.0 + rate } else { 1.0 };
+    timing * ((1.0 + rate).powf(nper) - 1.0) / rate
+}
+
+/// Finds the rate at which the cash flows (starting now) have a net present value of zero,
+/// using Newton's method from `guess`.
+///
+/// # Returns
+/// * `Some(rate)` - The internal rate of return
+/// * `None` - If the flows do not change sign or the iteration does not converge
+fn internal_rate_of_return(flows: &[f64], guess: f64) -> Option<f64> {
+    if !flows.iter().any(|&f| f > 0.0) || !flows.iter().any(|&f| f < 0.0) {
+        return None;
+    }
+    let mut rate = guess;
+    for _ in 0..100 {
+        if rate <= -1.0 {
+            return None;
+        }
+        let value = net_present_value(rate, flows, 0);
+        let derivative: f64 = flows
+            .iter()
+            .zip(0..)
+            .map(|(flow, period)| -(period as f64) * flow / (1.0 + rate).powi(period + 1))
+            .sum();
+        if derivative == 0.0 {
+            return None;
+        }
+        let next = rate - value / derivative;
+        if (next - rate).abs() < 1e-10 {
+            return Some(next);
+        }
+        rate = next;
+    }
+    None
+}
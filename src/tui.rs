@@ -0,0 +1,453 @@
+#![cfg(not(tarpaulin_include))]
+
+//! Full-screen terminal interface.
+//!
+//! Started with `spreadsheet <rows> <cols> --tui`. The grid fills the terminal and
+//! follows a cell cursor moved with the arrow keys. The selected cell's formula is shown
+//! in a formula bar at the top, where it can also be edited in place, and a status line
+//! at the bottom shows the time taken by the last command and its status, as the REPL
+//! prompt does. Every edit goes through the same [`Session`] as the REPL, and `:` opens
+//! a command line that accepts any REPL command.
+
+use crate::cell::Spill;
+use crate::cli::{CommandResult, Session};
+use crate::plot::cell_sparkline;
+use crate::spreadsheet::{ParsedRHS, Spreadsheet};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use std::io::{self, Write};
+use std::time::Instant;
+
+/// Width of a grid column in characters, including the separating space.
+pub const COLUMN_WIDTH: u16 = 12;
+
+/// Number of screen lines that are not grid rows: formula bar, column headers and status line.
+const CHROME_LINES: u16 = 3;
+
+/// How a piece of text on the screen is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Style {
+    /// Ordinary cell contents
+    Plain,
+    /// Row numbers and column letters
+    Header,
+    /// The selected cell
+    Selected,
+    /// A cell in error
+    Error,
+    /// A cell filled by a dynamic array formula
+    Spilled,
+    /// The formula bar and the status line
+    Bar,
+}
+
+/// What key presses currently do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Arrow keys move the cursor
+    Navigate,
+    /// Typing edits the formula of the selected cell
+    Edit,
+    /// Typing edits a REPL command, entered after `:`
+    Command,
+}
+
+/// One line of the screen, as runs of text with their style.
+pub type Line = Vec<(String, Style)>;
+
+/// State of the full-screen interface.
+pub struct Tui {
+    /// The spreadsheet and the command interpreter
+    pub session: Session,
+    /// Selected cell as (row, col), 1-based
+    pub cursor: (i16, i16),
+    /// Cell shown in the top left corner of the grid as (row, col), 1-based
+    pub top_left: (i16, i16),
+    /// What key presses currently do
+    pub mode: Mode,
+    /// Text being edited in the formula bar or command line
+    pub input: String,
+    /// Position of the text cursor in `input`, in characters
+    pub input_cursor: usize,
+    /// Status of the last command, as shown in the REPL prompt
+    pub status: String,
+    /// Time taken by the last command, in seconds
+    pub elapsed: f64,
+    /// Output of the last command (e.g. from `show`), shown until the next key press
+    pub message: Option<String>,
+    /// Terminal size as (width, height)
+    size: (u16, u16),
+}
+
+impl Tui {
+    /// Creates the interface for a session on a terminal of the given size.
+    pub fn new(session: Session, width: u16, height: u16) -> Self {
+        let top_left = (session.sheet.view_row + 1, session.sheet.view_col + 1);
+        Tui {
+            session,
+            cursor: top_left,
+            top_left,
+            mode: Mode::Navigate,
+            input: String::new(),
+            input_cursor: 0,
+            status: String::from("ok"),
+            elapsed: 0.0,
+            message: None,
+            size: (width, height),
+        }
+    }
+
+    /// Adapts the layout to a new terminal size.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.size = (width, height);
+        self.scroll_into_view();
+    }
+
+    /// Number of grid rows that fit on the screen.
+    pub fn visible_rows(&self) -> i16 {
+        (self.size.1.saturating_sub(CHROME_LINES) as i16).max(1)
+    }
+
+    /// Number of grid columns that fit on the screen.
+    pub fn visible_cols(&self) -> i16 {
+        ((self.size.0.saturating_sub(self.header_width()) / COLUMN_WIDTH) as i16).max(1)
+    }
+
+    /// Width of the row number column.
+    fn header_width(&self) -> u16 {
+        (self.session.sheet.rows.to_string().len() as u16 + 1).max(4)
+    }
+
+    /// Handles a key press.
+    ///
+    /// # Returns
+    /// `true` if the interface should close
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        self.message = None;
+        match self.mode {
+            Mode::Navigate => self.navigate_key(key),
+            Mode::Edit | Mode::Command => self.input_key(key),
+        }
+    }
+
+    /// Handles a key press while moving around the grid.
+    fn navigate_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('c') if ctrl => return true,
+            KeyCode::Char('z') if ctrl => return self.run_command("UNDO"),
+            KeyCode::Up => self.move_cursor(-1, 0),
+            KeyCode::Down => self.move_cursor(1, 0),
+            KeyCode::Left => self.move_cursor(0, -1),
+            KeyCode::Right | KeyCode::Tab => self.move_cursor(0, 1),
+            KeyCode::PageUp => self.move_cursor(-self.visible_rows(), 0),
+            KeyCode::PageDown => self.move_cursor(self.visible_rows(), 0),
+            KeyCode::Home => self.move_cursor(0, -self.session.sheet.cols),
+            KeyCode::End => self.move_cursor(0, self.session.sheet.cols),
+            KeyCode::Enter | KeyCode::F(2) => {
+                let text = self.selected_formula();
+                self.start_input(Mode::Edit, text);
+            }
+            KeyCode::Delete | KeyCode::Backspace => {
+                let name = Spreadsheet::get_cell_name(self.cursor.0, self.cursor.1);
+                return self.run_command(&format!("clear {}", name));
+            }
+            KeyCode::Char(':') if !ctrl => self.start_input(Mode::Command, String::new()),
+            KeyCode::Char(c) if !ctrl => self.start_input(Mode::Edit, c.to_string()),
+            _ => {}
+        }
+        false
+    }
+
+    /// Handles a key press while editing the formula bar or command line.
+    fn input_key(&mut self, key: KeyEvent) -> bool {
+        let len = self.input.chars().count();
+        match key.code {
+            KeyCode::Esc => self.mode = Mode::Navigate,
+            KeyCode::Enter | KeyCode::Tab => {
+                let text = std::mem::take(&mut self.input);
+                let mode = std::mem::replace(&mut self.mode, Mode::Navigate);
+                if mode == Mode::Command {
+                    return self.run_command(&text);
+                }
+                let name = Spreadsheet::get_cell_name(self.cursor.0, self.cursor.1);
+                let command = if text.trim().is_empty() {
+                    format!("clear {}", name)
+                } else {
+                    format!("{}={}", name, text.trim())
+                };
+                let quit = self.run_command(&command);
+                if key.code == KeyCode::Tab {
+                    self.move_cursor(0, 1);
+                } else {
+                    self.move_cursor(1, 0);
+                }
+                return quit;
+            }
+            KeyCode::Left => self.input_cursor = self.input_cursor.saturating_sub(1),
+            KeyCode::Right => self.input_cursor = (self.input_cursor + 1).min(len),
+            KeyCode::Home => self.input_cursor = 0,
+            KeyCode::End => self.input_cursor = len,
+            KeyCode::Backspace if self.input_cursor > 0 => {
+                self.input_cursor -= 1;
+                self.remove_input_char();
+            }
+            KeyCode::Delete if self.input_cursor < len => self.remove_input_char(),
+            KeyCode::Char(c) => {
+                let at = self.input_byte_offset();
+                self.input.insert(at, c);
+                self.input_cursor += 1;
+            }
+            _ => {}
+        }
+        false
+    }
+
+    /// Switches to editing `text` in the formula bar or command line.
+    fn start_input(&mut self, mode: Mode, text: String) {
+        self.mode = mode;
+        self.input_cursor = text.chars().count();
+        self.input = text;
+    }
+
+    /// Byte offset in `input` of the text cursor.
+    fn input_byte_offset(&self) -> usize {
+        self.input
+            .char_indices()
+            .nth(self.input_cursor)
+            .map_or(self.input.len(), |(i, _)| i)
+    }
+
+    /// Removes the character after the text cursor.
+    fn remove_input_char(&mut self) {
+        let at = self.input_byte_offset();
+        self.input.remove(at);
+    }
+
+    /// Runs a REPL command and records its status and timing.
+    ///
+    /// # Returns
+    /// `true` if the command asks to quit
+    fn run_command(&mut self, command: &str) -> bool {
+        let view = (self.session.sheet.view_row, self.session.sheet.view_col);
+        let start = Instant::now();
+        let result = self.session.execute(command, &mut self.status);
+        self.elapsed = start.elapsed().as_secs_f64();
+
+        let sheet = &self.session.sheet;
+        if (sheet.view_row, sheet.view_col) != view {
+            // Scrolled by the command (goto, scroll_to, wasd, load, ...)
+            self.top_left = (sheet.view_row + 1, sheet.view_col + 1);
+            self.cursor = self.top_left;
+        }
+        self.move_cursor(0, 0);
+        match result {
+            CommandResult::Quit => true,
+            CommandResult::Output(text) => {
+                self.message = Some(text.lines().map(str::trim).collect::<Vec<_>>().join("  "));
+                false
+            }
+            CommandResult::Continue => false,
+        }
+    }
+
+    /// Moves the cursor by the given number of rows and columns, staying on the sheet.
+    fn move_cursor(&mut self, rows: i16, cols: i16) {
+        let sheet = &self.session.sheet;
+        self.cursor.0 = self.cursor.0.saturating_add(rows).clamp(1, sheet.rows);
+        self.cursor.1 = self.cursor.1.saturating_add(cols).clamp(1, sheet.cols);
+        self.scroll_into_view();
+    }
+
+    /// Scrolls the grid so that the cursor is visible, and records the view in the sheet.
+    fn scroll_into_view(&mut self) {
+        let (rows, cols) = (self.visible_rows(), self.visible_cols());
+        let sheet = &mut self.session.sheet;
+        self.top_left.0 = self.top_left.0.clamp(1, sheet.rows);
+        self.top_left.1 = self.top_left.1.clamp(1, sheet.cols);
+        if self.cursor.0 < self.top_left.0 {
+            self.top_left.0 = self.cursor.0;
+        } else if self.cursor.0 >= self.top_left.0 + rows {
+            self.top_left.0 = self.cursor.0 - rows + 1;
+        }
+        if self.cursor.1 < self.top_left.1 {
+            self.top_left.1 = self.cursor.1;
+        } else if self.cursor.1 >= self.top_left.1 + cols {
+            self.top_left.1 = self.cursor.1 - cols + 1;
+        }
+        sheet.view_row = self.top_left.0 - 1;
+        sheet.view_col = self.top_left.1 - 1;
+    }
+
+    /// Returns the formula of the selected cell as typed by the user.
+    fn selected_formula(&self) -> String {
+        let sheet = &self.session.sheet;
+        let index =
+            (self.cursor.0 - 1) as usize * sheet.cols as usize + (self.cursor.1 - 1) as usize;
+        sheet.cells[index]
+            .as_ref()
+            .map(|cell| Spreadsheet::formula_to_string(&cell.formula))
+            .unwrap_or_default()
+    }
+
+    /// Builds the screen as one [`Line`] per terminal line.
+    ///
+    /// The first line is the formula bar, the second the column headers, the last the
+    /// status line, and the lines in between are grid rows.
+    pub fn frame(&self) -> Vec<Line> {
+        let sheet = &self.session.sheet;
+        let width = self.size.0 as usize;
+        let header_width = self.header_width() as usize;
+        let cell_width = COLUMN_WIDTH as usize;
+        let last_row = (self.top_left.0 + self.visible_rows() - 1).min(sheet.rows);
+        let last_col = (self.top_left.1 + self.visible_cols() - 1).min(sheet.cols);
+        let mut lines = Vec::with_capacity(self.size.1 as usize);
+
+        let bar = match self.mode {
+            Mode::Navigate => format!("{}{}", self.bar_prefix(), self.selected_formula()),
+            Mode::Edit | Mode::Command => format!("{}{}", self.bar_prefix(), self.input),
+        };
+        lines.push(vec![(fit(&bar, width), Style::Bar)]);
+
+        let mut headers = vec![(" ".repeat(header_width), Style::Header)];
+        for col in self.top_left.1..=last_col {
+            let letters = Spreadsheet::col_to_letter(col);
+            headers.push((format!("{:^w$}", letters, w = cell_width), Style::Header));
+        }
+        lines.push(headers);
+
+        for row in self.top_left.0..=last_row {
+            let mut line = vec![(format!("{:>w$} ", row, w = header_width - 1), Style::Header)];
+            for col in self.top_left.1..=last_col {
+                let index = (row - 1) as usize * sheet.cols as usize + (col - 1) as usize;
+                let (text, mut style) = match sheet.cells[index].as_ref() {
+                    Some(cell) => match cell.error_text() {
+                        Some(text) => (text.to_string(), Style::Error),
+                        None if cell.formula == ParsedRHS::None && cell.spill == Spill::None => {
+                            (String::new(), Style::Plain)
+                        }
+                        None if let Some(values) = sheet.sparkline_values(row, col) => {
+                            (cell_sparkline(&values, cell_width - 1, false), Style::Plain)
+                        }
+                        None if matches!(cell.spill, Spill::Member(..)) => {
+                            (cell.formatted_value(), Style::Spilled)
+                        }
+                        None => (cell.formatted_value(), Style::Plain),
+                    },
+                    None => (String::new(), Style::Plain),
+                };
+                if (row, col) == self.cursor {
+                    style = Style::Selected;
+                }
+                let text = if text.chars().count() >= cell_width {
+                    "#".repeat(cell_width - 1)
+                } else {
+                    text
+                };
+                line.push((format!("{:>w$} ", text, w = cell_width - 1), style));
+            }
+            lines.push(line);
+        }
+
+        while lines.len() + 1 < self.size.1 as usize {
+            lines.push(Vec::new());
+        }
+
+        let status = match (&self.message, self.mode) {
+            (Some(message), _) => format!("[{:.1}] ({}) {}", self.elapsed, self.status, message),
+            (None, Mode::Navigate) => format!(
+                "[{:.1}] ({})  arrows: move  Enter: edit  Del: clear  Ctrl-Z: undo  :: command  Ctrl-Q: quit",
+                self.elapsed, self.status
+            ),
+            (None, _) => format!(
+                "[{:.1}] ({})  Enter: confirm  Esc: cancel",
+                self.elapsed, self.status
+            ),
+        };
+        lines.push(vec![(fit(&status, width), Style::Bar)]);
+        lines
+    }
+
+    /// Returns the start of the formula bar, up to where the formula or input begins.
+    fn bar_prefix(&self) -> String {
+        let name = Spreadsheet::get_cell_name(self.cursor.0, self.cursor.1);
+        let w = self.header_width() as usize;
+        match self.mode {
+            Mode::Navigate => format!("{:<w$}| ", name),
+            Mode::Edit => format!("{:<w$}= ", name),
+            Mode::Command => format!("{:<w$}: ", ""),
+        }
+    }
+
+    /// Column of the text cursor on the formula bar, or `None` when nothing is being typed.
+    pub fn input_column(&self) -> Option<u16> {
+        if self.mode == Mode::Navigate {
+            return None;
+        }
+        let column = self.bar_prefix().chars().count() + self.input_cursor;
+        Some(column.min(self.size.0.saturating_sub(1) as usize) as u16)
+    }
+
+    /// Draws the screen.
+    pub fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, cursor::Hide, cursor::MoveTo(0, 0))?;
+        for (y, line) in self.frame().iter().enumerate() {
+            queue!(
+                out,
+                cursor::MoveTo(0, y as u16),
+                terminal::Clear(terminal::ClearType::CurrentLine)
+            )?;
+            for (text, style) in line {
+                match style {
+                    Style::Plain => {}
+                    Style::Header => queue!(out, SetForegroundColor(Color::DarkCyan))?,
+                    Style::Selected => queue!(out, SetAttribute(Attribute::Reverse))?,
+                    Style::Error => queue!(out, SetForegroundColor(Color::Red))?,
+                    Style::Spilled => queue!(out, SetForegroundColor(Color::DarkGrey))?,
+                    Style::Bar => queue!(out, SetAttribute(Attribute::Bold))?,
+                }
+                queue!(out, Print(text), SetAttribute(Attribute::Reset), ResetColor)?;
+            }
+        }
+        if let Some(column) = self.input_column() {
+            queue!(out, cursor::MoveTo(column, 0), cursor::Show)?;
+        }
+        out.flush()
+    }
+}
+
+/// Cuts `text` to at most `width` characters.
+fn fit(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+/// Runs the full-screen interface until the user quits.
+///
+/// The terminal is switched to the alternate screen in raw mode, and restored on exit
+/// even if drawing or reading input fails.
+pub fn run(session: Session) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let mut tui = Tui::new(session, width, height);
+    let mut out = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen)?;
+    let result = (|| -> io::Result<()> {
+        loop {
+            tui.draw(&mut out)?;
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press && tui.handle_key(key) => {
+                    return Ok(());
+                }
+                Event::Resize(width, height) => tui.resize(width, height),
+                _ => {}
+            }
+        }
+    })();
+    // Leave raw mode even if leaving the alternate screen fails
+    let left = execute!(out, cursor::Show, terminal::LeaveAlternateScreen);
+    terminal::disable_raw_mode()?;
+    left?;
+    result
+}
@@ -8,7 +8,9 @@ use cop::app;
 /// It initializes and runs the web server with the specified spreadsheet dimensions.
 ///
 /// # Arguments
-/// * Command line arguments (not directly processed in this function)
+/// * `--autosave <seconds>` - Interval between autosaves of unsaved changes, 0 to disable
+///   (default 30)
+/// * `--restore` - Continue from the most recent recovery file found at startup
 ///
 /// # Default Configuration
 /// * Creates a spreadsheet with 10 rows and 10 columns by default
@@ -23,6 +25,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let rows: i16 = 10;
     let cols: i16 = 10;
 
+    let mut options = app::ServerOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--autosave" => {
+                let seconds: u64 = args
+                    .next()
+                    .and_then(|value| value.parse().ok())
+                    .ok_or("--autosave needs a number of seconds")?;
+                options.autosave_interval =
+                    (seconds > 0).then(|| std::time::Duration::from_secs(seconds));
+            }
+            "--restore" => options.restore_recovery = true,
+            _ => return Err(format!("unknown option '{}'", arg).into()),
+        }
+    }
+
     // Start the web application with the specified dimensions
-    app::run(rows, cols).await
+    app::run_with_options(rows, cols, options).await
 }